@@ -0,0 +1,8 @@
+// Generates the gRPC service (`grpc` module) from proto/poker.proto.
+fn main() {
+    // a vendored protoc, so nothing has to be installed to build
+    let protoc =
+        protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc for this platform");
+    std::env::set_var("PROTOC", protoc);
+    tonic_prost_build::compile_protos("proto/poker.proto").expect("compiling proto/poker.proto");
+}
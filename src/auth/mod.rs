@@ -0,0 +1,273 @@
+use crate::config::GameConfig;
+use crate::models::{AuthSession, AuthTokens, Permission, Role};
+use crate::server::ApiError;
+use crate::store::SharedStore;
+use axum::{
+    extract::{FromRequestParts, Path},
+    http::{header, request::Parts, StatusCode},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+pub mod password;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    Access,
+    Refresh,
+}
+
+/// What a token vouches for. Tokens are `base64(claims).base64(hmac)`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// User id.
+    pub sub: String,
+    /// Session id.
+    pub sid: String,
+    pub typ: TokenKind,
+    /// Session generation a refresh token was issued for.
+    #[serde(default)]
+    pub gen: u32,
+    /// Expiry, seconds since the epoch.
+    pub exp: i64,
+}
+
+pub fn sign(secret: &str, claims: &Claims) -> String {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).expect("claims serialize"));
+    let sig = URL_SAFE_NO_PAD.encode(mac(secret, &payload).finalize().into_bytes());
+    format!("{payload}.{sig}")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum TokenError {
+    /// Malformed, badly signed, or of the wrong kind.
+    #[error("invalid token")]
+    Invalid,
+    #[error("token expired")]
+    Expired,
+}
+
+/// Checks signature, kind and expiry.
+pub fn verify(
+    secret: &str,
+    token: &str,
+    kind: TokenKind,
+    now: DateTime<Utc>,
+) -> Result<Claims, TokenError> {
+    let (payload, sig) = token.split_once('.').ok_or(TokenError::Invalid)?;
+    let sig = URL_SAFE_NO_PAD
+        .decode(sig)
+        .map_err(|_| TokenError::Invalid)?;
+    mac(secret, payload)
+        .verify_slice(&sig)
+        .map_err(|_| TokenError::Invalid)?;
+    let claims: Claims = URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+        .ok_or(TokenError::Invalid)?;
+    if claims.typ != kind {
+        return Err(TokenError::Invalid);
+    }
+    if claims.exp <= now.timestamp() {
+        return Err(TokenError::Expired);
+    }
+    Ok(claims)
+}
+
+/// Compares without stopping at the first differing byte, so response time
+/// doesn't leak how much of a secret was right.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+fn mac(secret: &str, payload: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// Access and refresh token for `session` at its current generation.
+pub fn issue(config: &GameConfig, session: &AuthSession, now: DateTime<Utc>) -> AuthTokens {
+    let access = Claims {
+        sub: session.user_id.clone(),
+        sid: session.id.clone(),
+        typ: TokenKind::Access,
+        gen: session.generation,
+        exp: (now + Duration::seconds(config.access_token_ttl_secs)).timestamp(),
+    };
+    let refresh = Claims {
+        typ: TokenKind::Refresh,
+        exp: session.expires_at.timestamp(),
+        ..access.clone()
+    };
+    AuthTokens {
+        access_token: sign(&config.token_secret, &access),
+        refresh_token: sign(&config.token_secret, &refresh),
+        expires_in: config.access_token_ttl_secs,
+    }
+}
+
+/// The caller, from a valid access token on a session that hasn't been
+/// revoked. Handlers take this instead of a `user_id` from the request.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub user_id: String,
+    pub session_id: String,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let config = parts
+            .extensions
+            .get::<GameConfig>()
+            .ok_or_else(|| ApiError::internal("auth not configured"))?;
+        let store = parts
+            .extensions
+            .get::<SharedStore>()
+            .ok_or_else(|| ApiError::internal("auth not configured"))?;
+
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| ApiError::unauthorized("missing_token", "missing bearer token"))?;
+        authenticate(store, config, token.trim()).await
+    }
+}
+
+/// The caller on a route under their own `{user_id}`. A valid token for
+/// anyone else gets 403.
+#[derive(Debug, Clone)]
+pub struct Owner(pub AuthUser);
+
+impl<S: Send + Sync> FromRequestParts<S> for Owner {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        let Path(user_id) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| ApiError::invalid(e.body_text()))?;
+        if user_id != user.user_id {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "not_your_account",
+                "not your account",
+            ));
+        }
+        Ok(Owner(user))
+    }
+}
+
+/// Checks an access token and that its session is still active.
+pub async fn authenticate(
+    store: &SharedStore,
+    config: &GameConfig,
+    token: &str,
+) -> Result<AuthUser, ApiError> {
+    let now = Utc::now();
+    let claims = verify(&config.token_secret, token, TokenKind::Access, now)?;
+    if session_active(store, &claims.sid, &claims.sub).await {
+        Ok(AuthUser {
+            user_id: claims.sub,
+            session_id: claims.sid,
+        })
+    } else {
+        Err(ApiError::unauthorized("session_revoked", "session revoked"))
+    }
+}
+
+/// Whether session `sid` of `user_id` hasn't been revoked or run out.
+pub async fn session_active(store: &SharedStore, sid: &str, user_id: &str) -> bool {
+    store
+        .get_session(sid)
+        .await
+        .is_some_and(|s| s.is_active(Utc::now()) && s.user_id == user_id)
+}
+
+impl Role {
+    pub fn permissions(self) -> &'static [Permission] {
+        use Permission::*;
+        match self {
+            Role::Player => &[],
+            Role::Support => &[ViewUsers, ManageUsers, PostNotices],
+            Role::Finance => &[ViewUsers, AdjustWallets, ManagePools],
+            Role::Admin => &[
+                ViewUsers,
+                ManageUsers,
+                AdjustWallets,
+                ManagePools,
+                ChangePaytables,
+                ManageRoles,
+                ManageSnapshots,
+                PostNotices,
+            ],
+        }
+    }
+
+    pub fn allows(self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+/// The caller of an operator route: a signed-in user with their current
+/// role, or whoever holds `ADMIN_TOKEN`, who counts as an admin.
+#[derive(Debug, Clone)]
+pub struct Staff {
+    /// `None` for the `ADMIN_TOKEN` holder.
+    pub user_id: Option<String>,
+    pub role: Role,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Staff {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let admin_token = parts
+            .extensions
+            .get::<GameConfig>()
+            .and_then(|c| c.admin_token.as_deref());
+        let presented = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        if let (Some(expected), Some(token)) = (admin_token, presented) {
+            if constant_time_eq(expected, token.trim()) {
+                return Ok(Staff {
+                    user_id: None,
+                    role: Role::Admin,
+                });
+            }
+        }
+
+        let auth = AuthUser::from_request_parts(parts, state).await?;
+        let store = parts
+            .extensions
+            .get::<SharedStore>()
+            .ok_or_else(|| ApiError::internal("auth not configured"))?;
+        let user = store
+            .get_user(&auth.user_id)
+            .await
+            .ok_or_else(|| ApiError::unauthorized("user_not_found", "user not found"))?;
+        Ok(Staff {
+            user_id: Some(user.id),
+            role: user.role,
+        })
+    }
+}
@@ -0,0 +1,57 @@
+use super::constant_time_eq;
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+/// Outcome of checking a password against what the store holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordCheck {
+    Valid,
+    /// Correct, but stored in an outdated form; store a fresh hash.
+    ValidNeedsRehash,
+    Invalid,
+}
+
+/// Argon2id hash of `password` in PHC string form, with a random salt.
+pub fn hash(password: &str) -> Result<String, String> {
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>()).map_err(|e| e.to_string())?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Checks `password` against `stored`. Anything that isn't a PHC string is a
+/// plaintext password from before hashing was introduced; it is compared in
+/// constant time and flagged for rehashing.
+pub fn verify(password: &str, stored: &str) -> PasswordCheck {
+    let Ok(parsed) = PasswordHash::new(stored) else {
+        return if constant_time_eq(password, stored) {
+            PasswordCheck::ValidNeedsRehash
+        } else {
+            PasswordCheck::Invalid
+        };
+    };
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_err()
+    {
+        return PasswordCheck::Invalid;
+    }
+    if parsed.algorithm != argon2::ARGON2ID_IDENT || outdated_params(&parsed) {
+        PasswordCheck::ValidNeedsRehash
+    } else {
+        PasswordCheck::Valid
+    }
+}
+
+/// Whether `parsed` was made with different cost settings than we use now.
+fn outdated_params(parsed: &PasswordHash) -> bool {
+    argon2::Params::try_from(parsed).map_or(true, |p| {
+        let current = argon2::Params::default();
+        p.m_cost() != current.m_cost()
+            || p.t_cost() != current.t_cost()
+            || p.p_cost() != current.p_cost()
+    })
+}
@@ -0,0 +1,232 @@
+use crate::game::GameError;
+use crate::models::{CashierKind, CashierRequest, CashierStatus, Currency};
+use crate::money::Money;
+use crate::store::{SharedStore, StoreError};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Outcome reported by a payment provider for a submitted request.
+#[derive(Debug, Clone)]
+pub struct ProviderCallback {
+    pub request_id: String,
+    pub provider_ref: String,
+    pub success: bool,
+    pub reason: Option<String>,
+}
+
+pub type CallbackSender = mpsc::UnboundedSender<ProviderCallback>;
+
+/// A payment provider processes deposits and withdrawals asynchronously:
+/// `submit` only hands the request over and returns the provider's
+/// reference, the result arrives later through `callbacks`.
+#[async_trait::async_trait]
+pub trait PaymentProvider: Send + Sync {
+    async fn submit(
+        &self,
+        request: &CashierRequest,
+        callbacks: CallbackSender,
+    ) -> Result<String, String>;
+}
+
+/// Local stand-in for a real provider. Approves everything after `delay`,
+/// except amounts above `decline_above`.
+#[derive(Debug, Clone)]
+pub struct MockProvider {
+    pub delay: Duration,
+    pub decline_above: Option<Money>,
+}
+
+impl Default for MockProvider {
+    fn default() -> Self {
+        MockProvider {
+            delay: Duration::from_millis(50),
+            decline_above: None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl PaymentProvider for MockProvider {
+    async fn submit(
+        &self,
+        request: &CashierRequest,
+        callbacks: CallbackSender,
+    ) -> Result<String, String> {
+        let provider_ref = format!("mock-{}", Uuid::new_v4());
+        let declined = self.decline_above.is_some_and(|max| request.amount > max);
+        let callback = ProviderCallback {
+            request_id: request.id.clone(),
+            provider_ref: provider_ref.clone(),
+            success: !declined,
+            reason: declined.then(|| "declined by provider".to_string()),
+        };
+        let delay = self.delay;
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let _ = callbacks.send(callback);
+        });
+        Ok(provider_ref)
+    }
+}
+
+/// Deposit/withdrawal workflow on top of the `Store` and a `PaymentProvider`.
+#[derive(Clone)]
+pub struct Cashier {
+    store: SharedStore,
+    provider: Arc<dyn PaymentProvider>,
+    callbacks: CallbackSender,
+    approval_threshold: Money,
+}
+
+impl Cashier {
+    /// Withdrawals above `approval_threshold` wait for an operator before
+    /// they're sent to the provider. Must be called inside a Tokio runtime:
+    /// provider callbacks are processed on a spawned task.
+    pub fn new(
+        store: SharedStore,
+        provider: Arc<dyn PaymentProvider>,
+        approval_threshold: Money,
+    ) -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel::<ProviderCallback>();
+        let cashier = Cashier {
+            store,
+            provider,
+            callbacks: tx,
+            approval_threshold,
+        };
+
+        let worker = cashier.clone();
+        tokio::spawn(async move {
+            while let Some(cb) = rx.recv().await {
+                if let Err(e) = worker.handle_callback(cb).await {
+                    warn!("cashier callback dropped: {}", e);
+                }
+            }
+        });
+
+        cashier
+    }
+
+    pub async fn deposit(
+        &self,
+        user_id: &str,
+        currency: Currency,
+        amount: Money,
+    ) -> Result<CashierRequest, StoreError> {
+        if !amount.is_positive() {
+            return Err(GameError::InvalidAmount("invalid amount").into());
+        }
+        let req = self
+            .store
+            .create_cashier_request(user_id, CashierKind::Deposit, currency, amount, false)
+            .await?;
+        self.submit(req).await
+    }
+
+    pub async fn withdraw(
+        &self,
+        user_id: &str,
+        currency: Currency,
+        amount: Money,
+    ) -> Result<CashierRequest, StoreError> {
+        if !amount.is_positive() {
+            return Err(GameError::InvalidAmount("invalid amount").into());
+        }
+        let requires_approval = amount > self.approval_threshold;
+        let req = self
+            .store
+            .create_cashier_request(
+                user_id,
+                CashierKind::Withdrawal,
+                currency,
+                amount,
+                requires_approval,
+            )
+            .await?;
+        if requires_approval {
+            return Ok(req);
+        }
+        self.submit(req).await
+    }
+
+    /// Operator sign-off for a withdrawal held for approval.
+    pub async fn approve(&self, id: &str) -> Result<CashierRequest, StoreError> {
+        let req = self.pending(id).await?;
+        if !req.requires_approval {
+            return Err(StoreError::ApprovalNotNeeded);
+        }
+        self.submit(req).await
+    }
+
+    /// Operator rejection of a request the provider hasn't seen yet.
+    pub async fn reject(
+        &self,
+        id: &str,
+        reason: Option<String>,
+    ) -> Result<CashierRequest, StoreError> {
+        let req = self.pending(id).await?;
+        if req.provider_ref.is_some() {
+            return Err(StoreError::AlreadyWithProvider);
+        }
+        self.store
+            .resolve_cashier_request(id, false, reason.or(Some("rejected by operator".into())))
+            .await
+    }
+
+    pub async fn handle_callback(
+        &self,
+        cb: ProviderCallback,
+    ) -> Result<CashierRequest, StoreError> {
+        let req = self.pending(&cb.request_id).await?;
+        match req.provider_ref.as_deref() {
+            Some(r) if r == cb.provider_ref => {}
+            // a fast provider can answer before `submit` has stored its reference
+            None => {}
+            _ => return Err(StoreError::ProviderRefMismatch),
+        }
+        self.store
+            .resolve_cashier_request(&cb.request_id, cb.success, cb.reason)
+            .await
+    }
+
+    async fn pending(&self, id: &str) -> Result<CashierRequest, StoreError> {
+        let req = self
+            .store
+            .get_cashier_request(id)
+            .await
+            .ok_or(StoreError::CashierRequestNotFound)?;
+        if req.status != CashierStatus::Pending {
+            return Err(StoreError::CashierNotPending);
+        }
+        Ok(req)
+    }
+
+    async fn submit(&self, req: CashierRequest) -> Result<CashierRequest, StoreError> {
+        let provider_ref = match self.provider.submit(&req, self.callbacks.clone()).await {
+            Ok(r) => r,
+            Err(e) => {
+                // never reached the provider: release any hold straight away
+                return self
+                    .store
+                    .resolve_cashier_request(&req.id, false, Some(e))
+                    .await;
+            }
+        };
+        match self
+            .store
+            .mark_cashier_submitted(&req.id, &provider_ref)
+            .await
+        {
+            Ok(req) => Ok(req),
+            // callback already settled it
+            Err(_) => self
+                .store
+                .get_cashier_request(&req.id)
+                .await
+                .ok_or(StoreError::CashierRequestNotFound),
+        }
+    }
+}
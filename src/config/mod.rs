@@ -0,0 +1,327 @@
+use crate::game::GameError;
+use crate::models::{Currency, PoolShare};
+use crate::money::{Money, MoneyError, Rounding};
+use std::collections::BTreeMap;
+use std::env;
+use uuid::Uuid;
+
+/// Inclusive bounds on the ante of a round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnteLimits {
+    pub min: Money,
+    pub max: Money,
+}
+
+impl AnteLimits {
+    pub fn check(&self, ante: Money) -> Result<(), GameError> {
+        if ante < self.min || ante > self.max {
+            return Err(GameError::AnteOutOfRange {
+                min: self.min,
+                max: self.max,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Percentage of an amount routed to house profit; the rest feeds the win
+/// pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolSplit {
+    pub house_percent: u32,
+}
+
+/// Where money handed to the pools comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolSource {
+    LosingAnte,
+    DiscardFee,
+    Fold,
+}
+
+/// How antes, fees and folds are divided between the win pool and the house.
+#[derive(Debug, Clone)]
+pub struct PoolPolicy {
+    pub losing_ante: PoolSplit,
+    pub discard_fee: PoolSplit,
+    pub fold: PoolSplit,
+    /// Win pool cap per currency; anything above it overflows into house
+    /// profit. Currencies not listed are uncapped.
+    pub win_pool_ceilings: BTreeMap<Currency, Money>,
+}
+
+impl Default for PoolPolicy {
+    fn default() -> Self {
+        let split = PoolSplit { house_percent: 25 };
+        PoolPolicy {
+            losing_ante: split,
+            discard_fee: split,
+            fold: split,
+            win_pool_ceilings: BTreeMap::new(),
+        }
+    }
+}
+
+impl PoolPolicy {
+    /// Splits `amount` from `source`. The house share is rounded down so the
+    /// win pool never loses a fraction to rounding.
+    pub fn share(
+        &self,
+        source: PoolSource,
+        currency: Currency,
+        amount: Money,
+    ) -> Result<PoolShare, MoneyError> {
+        let split = match source {
+            PoolSource::LosingAnte => self.losing_ante,
+            PoolSource::DiscardFee => self.discard_fee,
+            PoolSource::Fold => self.fold,
+        };
+        let house = amount.percent(split.house_percent, Rounding::Down)?;
+        Ok(PoolShare {
+            win_pool: amount.try_sub(house)?,
+            house,
+            win_pool_ceiling: self.win_pool_ceilings.get(&currency).copied(),
+        })
+    }
+}
+
+/// Runtime knobs for the game server. Defaults are suitable for local dev;
+/// `from_env` lets deployments override them without a rebuild.
+#[derive(Debug, Clone)]
+pub struct GameConfig {
+    /// How many rounds a single user may have in `Active` state at once.
+    pub max_active_rounds_per_user: usize,
+    /// How long a response is kept for replay under its `Idempotency-Key`.
+    pub idempotency_ttl_secs: i64,
+    /// Withdrawals above this amount wait for operator approval.
+    pub withdrawal_approval_threshold: Money,
+    /// How fractional discard fees are rounded to minor units.
+    pub discard_fee_rounding: Rounding,
+    /// Currency used when a request doesn't name one.
+    pub default_currency: Currency,
+    /// Allowed ante per currency; currencies missing here can't be played.
+    pub ante_limits: BTreeMap<Currency, AnteLimits>,
+    pub pool_policy: PoolPolicy,
+    /// Bearer token for `/api/admin`; the admin API is closed when unset.
+    pub admin_token: Option<String>,
+    /// How long a player waits before a raised or removed limit applies.
+    pub limit_increase_delay_secs: i64,
+    /// Shortest break a session limit may impose, and the default one.
+    pub min_session_break_minutes: u32,
+    /// Key signing access and refresh tokens. Defaults to a random key, so
+    /// tokens don't survive a restart unless it is set.
+    pub token_secret: String,
+    pub access_token_ttl_secs: i64,
+    /// Lifetime of a session; each refresh extends it.
+    pub refresh_token_ttl_secs: i64,
+    /// Base URL of an operator's seamless wallet. When set, players' money
+    /// lives there instead of in the store.
+    pub wallet_url: Option<String>,
+    /// Per-attempt timeout for wallet calls.
+    pub wallet_timeout_ms: u64,
+    /// Extra attempts after a wallet call times out or fails with a 5xx.
+    pub wallet_retries: u32,
+    /// How often settlement credits the wallet hasn't taken yet are sent
+    /// again.
+    pub wallet_credit_retry_ms: u64,
+    /// How often `/ws` pings the client. A connection that sends nothing
+    /// for two intervals is closed.
+    pub ws_heartbeat_secs: u64,
+    /// `/ws` pushes each currency's wallet and pools at most this often.
+    pub ws_update_interval_ms: u64,
+    /// Payout, as a multiple of the ante, from which `/api/events`
+    /// announces a round as a big win.
+    pub big_win_multiplier: u32,
+    /// `/api/events` sends each currency's pools at most this often,
+    /// however many rounds move them in between.
+    pub events_coalesce_ms: u64,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            max_active_rounds_per_user: 1,
+            idempotency_ttl_secs: 24 * 60 * 60,
+            withdrawal_approval_threshold: Money::new(500),
+            discard_fee_rounding: Rounding::HalfUp,
+            default_currency: Currency::Play,
+            ante_limits: BTreeMap::from([
+                (
+                    Currency::Eur,
+                    AnteLimits {
+                        min: Money::new(1),
+                        max: Money::new(500),
+                    },
+                ),
+                (
+                    Currency::Usd,
+                    AnteLimits {
+                        min: Money::new(1),
+                        max: Money::new(500),
+                    },
+                ),
+                (
+                    Currency::Play,
+                    AnteLimits {
+                        min: Money::new(1),
+                        max: Money::new(1000),
+                    },
+                ),
+            ]),
+            pool_policy: PoolPolicy::default(),
+            admin_token: None,
+            limit_increase_delay_secs: 24 * 60 * 60,
+            min_session_break_minutes: 15,
+            token_secret: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+            access_token_ttl_secs: 15 * 60,
+            refresh_token_ttl_secs: 30 * 24 * 60 * 60,
+            wallet_url: None,
+            wallet_timeout_ms: 2000,
+            wallet_retries: 2,
+            wallet_credit_retry_ms: 10_000,
+            ws_heartbeat_secs: 30,
+            ws_update_interval_ms: 1000,
+            big_win_multiplier: 25,
+            events_coalesce_ms: 500,
+        }
+    }
+}
+
+impl GameConfig {
+    /// Reads overrides from the environment, falling back to defaults:
+    /// - `MAX_ACTIVE_ROUNDS_PER_USER`
+    /// - `IDEMPOTENCY_TTL_SECS`
+    /// - `WITHDRAWAL_APPROVAL_THRESHOLD`
+    /// - `DISCARD_FEE_ROUNDING`: `down`, `up`, `half_up` or `half_even`
+    /// - `DEFAULT_CURRENCY`, e.g. `EUR`
+    /// - `ANTE_LIMITS`, e.g. `EUR:1-500,USD:1-500`; listed currencies
+    ///   replace their defaults
+    /// - `HOUSE_PERCENT_LOSS`, `HOUSE_PERCENT_DISCARD`, `HOUSE_PERCENT_FOLD`:
+    ///   house share (0-100) of losing antes, discard fees and folds
+    /// - `WIN_POOL_CEILINGS`, e.g. `EUR:100000,USD:100000`
+    /// - `ADMIN_TOKEN`
+    /// - `LIMIT_INCREASE_DELAY_SECS`, `MIN_SESSION_BREAK_MINUTES`
+    /// - `TOKEN_SECRET`, `ACCESS_TOKEN_TTL_SECS`, `REFRESH_TOKEN_TTL_SECS`
+    /// - `WALLET_URL`, `WALLET_TIMEOUT_MS`, `WALLET_RETRIES`
+    /// - `WS_HEARTBEAT_SECS`, `WS_UPDATE_INTERVAL_MS`
+    /// - `BIG_WIN_MULTIPLIER`, `EVENTS_COALESCE_MS`
+    pub fn from_env() -> Self {
+        let mut cfg = GameConfig::default();
+        if let Some(v) = env_parse("MAX_ACTIVE_ROUNDS_PER_USER") {
+            cfg.max_active_rounds_per_user = v;
+        }
+        if let Some(v) = env_parse("IDEMPOTENCY_TTL_SECS") {
+            cfg.idempotency_ttl_secs = v;
+        }
+        if let Some(v) = env_parse("WITHDRAWAL_APPROVAL_THRESHOLD") {
+            cfg.withdrawal_approval_threshold = v;
+        }
+        if let Some(v) = env_parse("DISCARD_FEE_ROUNDING") {
+            cfg.discard_fee_rounding = v;
+        }
+        if let Some(v) = env_parse("DEFAULT_CURRENCY") {
+            cfg.default_currency = v;
+        }
+        if let Ok(v) = env::var("ANTE_LIMITS") {
+            cfg.ante_limits.extend(parse_ante_limits(&v));
+        }
+        let policy = &mut cfg.pool_policy;
+        for (key, split) in [
+            ("HOUSE_PERCENT_LOSS", &mut policy.losing_ante),
+            ("HOUSE_PERCENT_DISCARD", &mut policy.discard_fee),
+            ("HOUSE_PERCENT_FOLD", &mut policy.fold),
+        ] {
+            if let Some(v) = env_parse::<u32>(key).filter(|v| *v <= 100) {
+                split.house_percent = v;
+            }
+        }
+        if let Ok(v) = env::var("WIN_POOL_CEILINGS") {
+            policy.win_pool_ceilings.extend(parse_amounts(&v));
+        }
+        if let Ok(v) = env::var("ADMIN_TOKEN") {
+            cfg.admin_token = Some(v.trim().to_string()).filter(|t| !t.is_empty());
+        }
+        if let Some(v) = env_parse("LIMIT_INCREASE_DELAY_SECS") {
+            cfg.limit_increase_delay_secs = v;
+        }
+        if let Some(v) = env_parse("MIN_SESSION_BREAK_MINUTES") {
+            cfg.min_session_break_minutes = v;
+        }
+        if let Ok(v) = env::var("TOKEN_SECRET") {
+            if !v.trim().is_empty() {
+                cfg.token_secret = v.trim().to_string();
+            }
+        }
+        if let Some(v) = env_parse("ACCESS_TOKEN_TTL_SECS") {
+            cfg.access_token_ttl_secs = v;
+        }
+        if let Some(v) = env_parse("REFRESH_TOKEN_TTL_SECS") {
+            cfg.refresh_token_ttl_secs = v;
+        }
+        if let Ok(v) = env::var("WALLET_URL") {
+            cfg.wallet_url =
+                Some(v.trim().trim_end_matches('/').to_string()).filter(|u| !u.is_empty());
+        }
+        if let Some(v) = env_parse("WALLET_TIMEOUT_MS") {
+            cfg.wallet_timeout_ms = v;
+        }
+        if let Some(v) = env_parse("WALLET_RETRIES") {
+            cfg.wallet_retries = v;
+        }
+        if let Some(v) = env_parse::<u64>("WALLET_CREDIT_RETRY_MS").filter(|v| *v > 0) {
+            cfg.wallet_credit_retry_ms = v;
+        }
+        if let Some(v) = env_parse::<u64>("WS_HEARTBEAT_SECS").filter(|v| *v > 0) {
+            cfg.ws_heartbeat_secs = v;
+        }
+        if let Some(v) = env_parse::<u64>("WS_UPDATE_INTERVAL_MS").filter(|v| *v > 0) {
+            cfg.ws_update_interval_ms = v;
+        }
+        if let Some(v) = env_parse::<u32>("BIG_WIN_MULTIPLIER").filter(|v| *v > 0) {
+            cfg.big_win_multiplier = v;
+        }
+        if let Some(v) = env_parse::<u64>("EVENTS_COALESCE_MS").filter(|v| *v > 0) {
+            cfg.events_coalesce_ms = v;
+        }
+        cfg
+    }
+
+    /// Limits for `currency`, or an error if the server doesn't offer it.
+    pub fn ante_limits_for(&self, currency: Currency) -> Result<AnteLimits, GameError> {
+        self.ante_limits
+            .get(&currency)
+            .copied()
+            .ok_or(GameError::UnsupportedCurrency(currency))
+    }
+}
+
+/// Parses `CUR:min-max` pairs separated by commas; malformed pairs are skipped.
+fn parse_ante_limits(s: &str) -> Vec<(Currency, AnteLimits)> {
+    s.split(',')
+        .filter_map(|pair| {
+            let (cur, range) = pair.split_once(':')?;
+            let (min, max) = range.split_once('-')?;
+            Some((
+                cur.parse().ok()?,
+                AnteLimits {
+                    min: min.trim().parse().ok()?,
+                    max: max.trim().parse().ok()?,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Parses `CUR:amount` pairs separated by commas; malformed pairs are skipped.
+fn parse_amounts(s: &str) -> Vec<(Currency, Money)> {
+    s.split(',')
+        .filter_map(|pair| {
+            let (cur, amount) = pair.split_once(':')?;
+            Some((cur.parse().ok()?, amount.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok().and_then(|v| v.trim().parse().ok())
+}
@@ -1,8 +1,90 @@
-use crate::models::{Card, HandRank, Suit};
+use crate::limits;
+use crate::models::{Card, Currency, HandRank, LimitKind, LimitPeriod, Paytable, Suit};
+use crate::money::{Money, MoneyError};
+use chrono::{DateTime, Utc};
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 use std::collections::HashMap;
 
+/// A move the rules of the game don't allow. The API gives each variant its
+/// own error code; see `server::ApiError`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum GameError {
+    #[error("invalid ante")]
+    InvalidAnte,
+    #[error("ante must be between {min} and {max}")]
+    AnteOutOfRange { min: Money, max: Money },
+    #[error("currency {0} not supported")]
+    UnsupportedCurrency(Currency),
+    #[error("{0}")]
+    InvalidAmount(&'static str),
+    #[error("account frozen")]
+    AccountFrozen,
+    #[error("insufficient wallet")]
+    InsufficientFunds,
+    #[error("too many active rounds, max {max}")]
+    TooManyActiveRounds { max: usize },
+    /// The pool can't cover the round's top payout on top of what it has
+    /// already promised to open rounds.
+    #[error("win pool too small, max ante allowed {max_ante}")]
+    PoolTooSmall { max_ante: Money },
+    #[error("round belongs to another user")]
+    NotYourRound,
+    #[error("round not active")]
+    RoundNotActive,
+    /// The round was discarded on since the caller looked at it.
+    #[error("round changed, retry")]
+    RoundChanged,
+    /// A settlement the handler should never have asked for.
+    #[error("{0}")]
+    InvalidSettlement(&'static str),
+    /// Discard indices that don't name distinct cards of the hand.
+    #[error("{0}")]
+    InvalidDiscard(&'static str),
+    #[error("pools too small to refund discard fees")]
+    PoolsCannotRefund,
+    #[error("self-excluded until {until}")]
+    SelfExcluded { until: DateTime<Utc> },
+    #[error("cooling off until {until}")]
+    CoolingOff { until: DateTime<Utc> },
+    #[error("session limit reached, play resumes at {resume_at}")]
+    SessionLimit { resume_at: DateTime<Utc> },
+    #[error(
+        "{} limit of {amount} {currency} {} reached",
+        limits::describe(*.kind),
+        limits::describe_period(*.period)
+    )]
+    LimitReached {
+        kind: LimitKind,
+        period: LimitPeriod,
+        currency: Currency,
+        amount: Money,
+    },
+    #[error("stronger hands must not pay less than weaker ones")]
+    PaytableOutOfOrder,
+    #[error("multipliers must not exceed {max}")]
+    MultiplierTooHigh { max: u32 },
+    #[error(transparent)]
+    Money(#[from] MoneyError),
+}
+
+/// Cards in a hand.
+pub const HAND_SIZE: usize = 5;
+
+/// Checks that `indices` pick at least one card of the hand, each once.
+pub fn check_discard(indices: &[usize]) -> Result<(), &'static str> {
+    if indices.is_empty() {
+        return Err("discard at least one card");
+    }
+    if indices.iter().any(|&i| i >= HAND_SIZE) {
+        return Err("discard indices must be between 0 and 4");
+    }
+    if (1..indices.len()).any(|i| indices[..i].contains(&indices[i])) {
+        return Err("discard indices must be unique");
+    }
+    Ok(())
+}
+
 pub fn new_deck() -> Vec<Card> {
     let mut deck = Vec::with_capacity(52);
     let suits = [Suit::Hearts, Suit::Diamonds, Suit::Clubs, Suit::Spades];
@@ -69,22 +151,61 @@ pub fn evaluate_hand(cards: &[Card]) -> HandRank {
     HandRank::HighCard
 }
 
-pub fn payout_multiplier(hr: &HandRank) -> u32 {
-    match hr {
-        HandRank::HighCard => 0,
-        HandRank::Pair(rank) => {
-            if *rank >= 11 {
-                1
-            } else {
-                0
+/// Highest multiplier a paytable may pay. A round reserves `ante` times its
+/// table's top multiplier, so this bounds what one round can lock up.
+pub const MAX_MULTIPLIER: u32 = 1000;
+
+impl Paytable {
+    pub fn multiplier(&self, hr: &HandRank) -> u32 {
+        match hr {
+            HandRank::HighCard => 0,
+            HandRank::Pair(rank) => {
+                if *rank >= 11 {
+                    self.jacks_or_better
+                } else {
+                    0
+                }
             }
+            HandRank::TwoPair => self.two_pair,
+            HandRank::Trips => self.three_of_a_kind,
+            HandRank::Straight => self.straight,
+            HandRank::Flush => self.flush,
+            HandRank::FullHouse => self.full_house,
+            HandRank::FourKind => self.four_of_a_kind,
+            HandRank::StraightFlush => self.straight_flush,
         }
-        HandRank::TwoPair => 2,
-        HandRank::Trips => 3,
-        HandRank::Straight => 5,
-        HandRank::Flush => 6,
-        HandRank::FullHouse => 9,
-        HandRank::FourKind => 25,
-        HandRank::StraightFlush => 50,
+    }
+
+    /// Largest multiplier in the table.
+    pub fn max(&self) -> u32 {
+        self.by_strength().into_iter().max().unwrap_or_default()
+    }
+
+    /// A stronger hand must never pay less than a weaker one, and nothing
+    /// may pay more than `MAX_MULTIPLIER`.
+    pub fn validate(&self) -> Result<(), GameError> {
+        let pays = self.by_strength();
+        if pays.windows(2).any(|w| w[0] > w[1]) {
+            return Err(GameError::PaytableOutOfOrder);
+        }
+        if self.max() > MAX_MULTIPLIER {
+            return Err(GameError::MultiplierTooHigh {
+                max: MAX_MULTIPLIER,
+            });
+        }
+        Ok(())
+    }
+
+    fn by_strength(&self) -> [u32; 8] {
+        [
+            self.jacks_or_better,
+            self.two_pair,
+            self.three_of_a_kind,
+            self.straight,
+            self.flush,
+            self.full_house,
+            self.four_of_a_kind,
+            self.straight_flush,
+        ]
     }
 }
@@ -0,0 +1,410 @@
+//! The game over gRPC (`proto/poker.proto`) for internal services. It runs
+//! on its own port beside the HTTP server and calls the same `Play`, so
+//! both work on one store. A failed call carries the API's error code in
+//! its `error-code` metadata, and any details as JSON in `error-details`.
+//! `Start`, `Discard` and `Reveal` take an `idempotency-key` entry with the
+//! same rules as the HTTP header.
+
+use crate::auth::{self, AuthUser};
+use crate::config::GameConfig;
+use crate::models::{self, CachedResponse, Currency, ErrorBody, IdempotencyClaim, Suit};
+use crate::money::Money;
+use crate::server::idempotency::{in_flight, key_reused, scoped_key};
+use crate::server::play::Play;
+use crate::server::{ApiError, IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAY_HEADER};
+use crate::store::SharedStore;
+use axum::http::StatusCode;
+use futures_util::FutureExt;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use tokio::net::TcpListener;
+use tonic::metadata::MetadataMap;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Code, Request, Response, Status};
+
+/// Types generated from `proto/poker.proto`.
+pub mod pb {
+    tonic::include_proto!("poker.v1");
+}
+
+use pb::poker_server::{Poker, PokerServer};
+
+/// The `Poker` service on `store`.
+pub fn service(store: SharedStore, config: GameConfig) -> PokerServer<PokerService> {
+    PokerServer::new(PokerService {
+        play: Play::new(store, config),
+    })
+}
+
+/// Serves the `Poker` service on `listener` until `shutdown` resolves.
+pub async fn serve(
+    listener: TcpListener,
+    store: SharedStore,
+    config: GameConfig,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(service(store, config))
+        .serve_with_incoming_shutdown(TcpIncoming::from(listener), shutdown)
+        .await
+}
+
+pub struct PokerService {
+    play: Play,
+}
+
+impl PokerService {
+    /// The caller, from an `authorization: Bearer` entry in `metadata`.
+    async fn user(&self, metadata: &MetadataMap) -> Result<AuthUser, Status> {
+        let token = metadata
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| ApiError::unauthorized("missing_token", "missing bearer token"))
+            .map_err(status)?;
+        auth::authenticate(&self.play.store, &self.play.config, token.trim())
+            .await
+            .map_err(status)
+    }
+
+    /// Runs `action` for `user`, at most once per `idempotency-key` in
+    /// `metadata`. As over HTTP, a retry gets the first reply back, reusing
+    /// the key for another request or while the first still runs is
+    /// refused, and server errors release the key unless the change went
+    /// through. `method` and `message`
+    /// are what a retry has to match.
+    async fn once<T, M>(
+        &self,
+        user: &AuthUser,
+        metadata: &MetadataMap,
+        method: &str,
+        message: &impl prost::Message,
+        action: impl Future<Output = Result<T, ApiError>> + Send + 'static,
+    ) -> Result<Response<M>, Status>
+    where
+        T: Send + 'static,
+        M: prost::Message + Default + From<T> + 'static,
+    {
+        let key = match metadata.get(IDEMPOTENCY_KEY_HEADER) {
+            None => return reply(action.await),
+            Some(v) => scoped_key(&user.user_id, v.to_str().ok()).map_err(status)?,
+        };
+        let mut hasher = Sha256::new();
+        hasher.update(method.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(message.encode_to_vec());
+        let fingerprint = format!("{:x}", hasher.finalize());
+
+        let store = self.play.store.clone();
+        let ttl = chrono::Duration::seconds(self.play.config.idempotency_ttl_secs);
+        match store
+            .claim_idempotency(&key, &fingerprint, chrono::Utc::now(), ttl)
+            .await
+        {
+            IdempotencyClaim::Claimed => {}
+            IdempotencyClaim::Replay(cached) => return replay(cached),
+            IdempotencyClaim::InFlight => return Err(status(in_flight())),
+            IdempotencyClaim::Mismatch => return Err(status(key_reused())),
+        }
+
+        // a caller that hangs up doesn't cancel the action, so its reply is
+        // still recorded for the retry
+        let run = async move {
+            let Ok(result) = AssertUnwindSafe(action).catch_unwind().await else {
+                store.finish_idempotency(&key, None).await;
+                return Err(status(ApiError::internal("request handler panicked")));
+            };
+            let (cached, result) = match result {
+                Ok(r) => {
+                    let message = M::from(r);
+                    let cached = CachedResponse {
+                        status: StatusCode::OK.as_u16(),
+                        content_type: Some("application/grpc".to_string()),
+                        body: message.encode_to_vec(),
+                    };
+                    (Some(cached), Ok(message))
+                }
+                Err(e) if e.status.is_server_error() && !e.committed => (None, Err(status(e))),
+                Err(e) => {
+                    let http = e.status;
+                    let body = e.into_body();
+                    let cached = CachedResponse {
+                        status: http.as_u16(),
+                        content_type: Some("application/json".to_string()),
+                        body: serde_json::to_vec(&body).expect("error bodies serialize"),
+                    };
+                    (Some(cached), Err(grpc_status(http, body)))
+                }
+            };
+            store.finish_idempotency(&key, cached).await;
+            result
+        };
+        tokio::spawn(run)
+            .await
+            .map_err(|_| status(ApiError::internal("request task failed")))?
+            .map(Response::new)
+    }
+}
+
+#[tonic::async_trait]
+impl Poker for PokerService {
+    async fn sign_up(
+        &self,
+        request: Request<pb::SignUpRequest>,
+    ) -> Result<Response<pb::LoginResponse>, Status> {
+        let req = request.into_inner();
+        let req = models::SignUpRequest {
+            name: req.name,
+            password: req.password,
+        };
+        reply(self.play.signup(req).await)
+    }
+
+    async fn sign_in(
+        &self,
+        request: Request<pb::SignInRequest>,
+    ) -> Result<Response<pb::LoginResponse>, Status> {
+        let req = request.into_inner();
+        let req = models::SignInRequest {
+            name: req.name,
+            password: req.password,
+        };
+        reply(self.play.signin(req).await)
+    }
+
+    async fn start(
+        &self,
+        request: Request<pb::StartRequest>,
+    ) -> Result<Response<pb::StartResponse>, Status> {
+        let user = self.user(request.metadata()).await?;
+        let message = request.get_ref();
+        let req = models::StartRequest {
+            currency: currency(message.currency)?,
+            ante: Money::new(message.ante),
+        };
+        let (play, user_id) = (self.play.clone(), user.user_id.clone());
+        let action = async move { play.start(&user_id, req).await };
+        let method = "/poker.v1.Poker/Start";
+        self.once(&user, request.metadata(), method, message, action)
+            .await
+    }
+
+    async fn discard(
+        &self,
+        request: Request<pb::DiscardRequest>,
+    ) -> Result<Response<pb::DiscardResponse>, Status> {
+        let user = self.user(request.metadata()).await?;
+        let message = request.get_ref();
+        let req = models::DiscardRequest {
+            round_id: message.round_id.clone(),
+            discard_indices: message
+                .discard_indices
+                .iter()
+                .map(|&i| i as usize)
+                .collect(),
+        };
+        let (play, user_id) = (self.play.clone(), user.user_id.clone());
+        let action = async move { play.discard(&user_id, req).await };
+        let method = "/poker.v1.Poker/Discard";
+        self.once(&user, request.metadata(), method, message, action)
+            .await
+    }
+
+    async fn reveal(
+        &self,
+        request: Request<pb::RevealRequest>,
+    ) -> Result<Response<pb::RevealResponse>, Status> {
+        let user = self.user(request.metadata()).await?;
+        let message = request.get_ref();
+        let req = models::RevealRequest {
+            round_id: message.round_id.clone(),
+        };
+        let (play, user_id) = (self.play.clone(), user.user_id.clone());
+        let action = async move { play.reveal(&user_id, req).await };
+        let method = "/poker.v1.Poker/Reveal";
+        self.once(&user, request.metadata(), method, message, action)
+            .await
+    }
+
+    async fn status(
+        &self,
+        request: Request<pb::StatusRequest>,
+    ) -> Result<Response<pb::StatusResponse>, Status> {
+        let user = self.user(request.metadata()).await?;
+        let currency = currency(request.into_inner().currency)?;
+        reply(self.play.status(&user.user_id, currency).await)
+    }
+}
+
+fn reply<T, M: From<T>>(result: Result<T, ApiError>) -> Result<Response<M>, Status> {
+    result.map(|r| Response::new(r.into())).map_err(status)
+}
+
+/// The gRPC status closest to the HTTP one, with the error code and
+/// details in metadata.
+fn status(e: ApiError) -> Status {
+    let http = e.status;
+    grpc_status(http, e.into_body())
+}
+
+fn grpc_status(http: StatusCode, body: ErrorBody) -> Status {
+    let code = match http {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::PAYMENT_REQUIRED | StatusCode::CONFLICT => Code::FailedPrecondition,
+        StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        _ => Code::Internal,
+    };
+    let mut status = Status::new(code, body.message);
+    let metadata = status.metadata_mut();
+    if let Ok(v) = body.code.parse() {
+        metadata.insert("error-code", v);
+    }
+    if let Some(Ok(v)) = body.details.map(|d| d.to_string().parse()) {
+        metadata.insert("error-details", v);
+    }
+    status
+}
+
+/// The reply recorded for an idempotency key, marked as a replay.
+fn replay<M: prost::Message + Default>(cached: CachedResponse) -> Result<Response<M>, Status> {
+    let http = StatusCode::from_u16(cached.status).unwrap_or(StatusCode::OK);
+    let replayed = "true".parse().expect("a valid metadata value");
+    if !http.is_success() {
+        let body = serde_json::from_slice(&cached.body)
+            .map_err(|e| status(ApiError::internal(format!("recorded error: {e}"))))?;
+        let mut status = grpc_status(http, body);
+        status
+            .metadata_mut()
+            .insert(IDEMPOTENT_REPLAY_HEADER, replayed);
+        return Err(status);
+    }
+    let message = M::decode(cached.body.as_slice())
+        .map_err(|e| status(ApiError::internal(format!("recorded reply: {e}"))))?;
+    let mut response = Response::new(message);
+    response
+        .metadata_mut()
+        .insert(IDEMPOTENT_REPLAY_HEADER, replayed);
+    Ok(response)
+}
+
+/// A request's currency; unspecified means the default one.
+fn currency(value: i32) -> Result<Option<Currency>, Status> {
+    match pb::Currency::try_from(value) {
+        Ok(pb::Currency::Unspecified) => Ok(None),
+        Ok(pb::Currency::Eur) => Ok(Some(Currency::Eur)),
+        Ok(pb::Currency::Usd) => Ok(Some(Currency::Usd)),
+        Ok(pb::Currency::Play) => Ok(Some(Currency::Play)),
+        Err(_) => Err(status(ApiError::invalid(format!(
+            "unknown currency {value}"
+        )))),
+    }
+}
+
+impl From<Currency> for pb::Currency {
+    fn from(c: Currency) -> Self {
+        match c {
+            Currency::Eur => pb::Currency::Eur,
+            Currency::Usd => pb::Currency::Usd,
+            Currency::Play => pb::Currency::Play,
+        }
+    }
+}
+
+impl From<models::Card> for pb::Card {
+    fn from(card: models::Card) -> Self {
+        let suit = match card.suit {
+            Suit::Hearts => pb::Suit::Hearts,
+            Suit::Diamonds => pb::Suit::Diamonds,
+            Suit::Clubs => pb::Suit::Clubs,
+            Suit::Spades => pb::Suit::Spades,
+        };
+        pb::Card {
+            rank: u32::from(card.rank),
+            suit: suit.into(),
+        }
+    }
+}
+
+fn cards(cards: Vec<models::Card>) -> Vec<pb::Card> {
+    cards.into_iter().map(pb::Card::from).collect()
+}
+
+fn code(currency: Currency) -> i32 {
+    pb::Currency::from(currency).into()
+}
+
+impl From<models::LoginResponse> for pb::LoginResponse {
+    fn from(r: models::LoginResponse) -> Self {
+        pb::LoginResponse {
+            id: r.id,
+            name: r.name,
+            currency: code(r.currency),
+            wallet: r.wallet.minor(),
+            wallets: r
+                .wallets
+                .into_iter()
+                .map(|(c, m)| (c.code().to_string(), m.minor()))
+                .collect(),
+            tokens: Some(pb::AuthTokens {
+                access_token: r.tokens.access_token,
+                refresh_token: r.tokens.refresh_token,
+                expires_in: r.tokens.expires_in,
+            }),
+        }
+    }
+}
+
+impl From<models::StartResponse> for pb::StartResponse {
+    fn from(r: models::StartResponse) -> Self {
+        pb::StartResponse {
+            round_id: r.round_id,
+            currency: code(r.currency),
+            cards: cards(r.cards),
+            wallet: r.wallet.minor(),
+            win_pool: r.win_pool.minor(),
+        }
+    }
+}
+
+impl From<models::DiscardResponse> for pb::DiscardResponse {
+    fn from(r: models::DiscardResponse) -> Self {
+        pb::DiscardResponse {
+            currency: code(r.currency),
+            cards: cards(r.cards),
+            wallet: r.wallet.minor(),
+            total_bet: r.total_bet.minor(),
+        }
+    }
+}
+
+impl From<models::RevealResponse> for pb::RevealResponse {
+    fn from(r: models::RevealResponse) -> Self {
+        pb::RevealResponse {
+            currency: code(r.currency),
+            wallet: r.wallet.minor(),
+            win_pool: r.win_pool.minor(),
+            house_profit: r.house_profit.minor(),
+            hand_rank: r.hand_rank,
+            multiplier: r.multiplier,
+            payout: r.payout.minor(),
+        }
+    }
+}
+
+impl From<models::StatusResponse> for pb::StatusResponse {
+    fn from(r: models::StatusResponse) -> Self {
+        pb::StatusResponse {
+            currency: code(r.currency),
+            wallet: r.wallet.minor(),
+            win_pool: r.win_pool.minor(),
+            win_pool_reserved: r.win_pool_reserved.minor(),
+            win_pool_available: r.win_pool_available.minor(),
+            house_profit: r.house_profit.minor(),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::models::{
+    Account, BalanceMismatch, Currency, CurrencyTotals, LedgerEntry, LedgerReason, Pools,
+    ReconcileReport, Round, RoundStatus, User,
+};
+use crate::money::Money;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// One leg of a posting before it's numbered: (account, signed amount, reason).
+pub type Leg = (Account, Money, LedgerReason);
+
+/// Turns `legs` into ledger entries in `currency` made at `now`, numbered
+/// from `next_seq` and sharing a `tx_id` taken from that number, so the
+/// same postings always get the same ids. Zero legs are dropped. Panics in debug builds if
+/// the legs don't balance, since that is always a bug in the caller.
+pub fn transaction(
+    next_seq: u64,
+    currency: Currency,
+    round_id: Option<&str>,
+    legs: &[Leg],
+    now: DateTime<Utc>,
+) -> Vec<LedgerEntry> {
+    debug_assert_eq!(
+        legs.iter()
+            .map(|(_, amount, _)| i128::from(amount.minor()))
+            .sum::<i128>(),
+        0,
+        "unbalanced ledger transaction: {legs:?}"
+    );
+
+    let tx_id = format!("tx-{next_seq}");
+    legs.iter()
+        .filter(|(_, amount, _)| *amount != Money::ZERO)
+        .enumerate()
+        .map(|(i, (account, amount, reason))| LedgerEntry {
+            seq: next_seq + i as u64,
+            tx_id: tx_id.clone(),
+            account: account.clone(),
+            currency,
+            amount: *amount,
+            round_id: round_id.map(str::to_string),
+            reason: *reason,
+            created_at: now,
+        })
+        .collect()
+}
+
+/// What the player has staked on a round, ante and discard fees, going by
+/// `entries`, the round's ledger lines.
+pub fn staked<'a>(entries: impl IntoIterator<Item = &'a LedgerEntry>) -> Money {
+    let mut staked = Money::ZERO;
+    for e in entries {
+        let player = matches!(e.account, Account::User(_) | Account::RemoteWallet(_));
+        let stake = matches!(e.reason, LedgerReason::Ante | LedgerReason::DiscardFee);
+        if player && stake {
+            staked -= e.amount;
+        }
+    }
+    staked
+}
+
+/// Balance of every account, per currency.
+pub fn balances<'a>(
+    entries: impl IntoIterator<Item = &'a LedgerEntry>,
+) -> BTreeMap<(Currency, Account), Money> {
+    let mut out = BTreeMap::new();
+    for e in entries {
+        *out.entry((e.currency, e.account.clone())).or_default() += e.amount;
+    }
+    out
+}
+
+/// Checks every transaction balances and that wallets, pools and round stakes
+/// agree with what the ledger says they should hold, currency by currency.
+pub fn reconcile<'a>(
+    entries: &[LedgerEntry],
+    users: impl IntoIterator<Item = &'a User>,
+    rounds: impl IntoIterator<Item = &'a Round>,
+    pools: &BTreeMap<Currency, Pools>,
+) -> ReconcileReport {
+    // a transaction must balance within its currency, not just overall
+    let mut per_tx: BTreeMap<(&str, Currency), Money> = BTreeMap::new();
+    for e in entries {
+        *per_tx.entry((e.tx_id.as_str(), e.currency)).or_default() += e.amount;
+    }
+    let unbalanced_transactions: Vec<String> = per_tx
+        .into_iter()
+        .filter(|(_, sum)| *sum != Money::ZERO)
+        .map(|((tx, _), _)| tx.to_string())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let ledger = balances(entries);
+    let ledger_of = |c: Currency, a: Account| ledger.get(&(c, a)).copied().unwrap_or_default();
+
+    let mut recorded: BTreeMap<(Currency, Account), Money> = BTreeMap::new();
+    for u in users {
+        for (c, amount) in &u.wallets {
+            recorded.insert((*c, Account::User(u.id.clone())), *amount);
+        }
+    }
+    for r in rounds {
+        let stake = if r.status == RoundStatus::Active {
+            r.ante
+        } else {
+            Money::ZERO
+        };
+        recorded.insert((r.currency, Account::InPlay(r.id.clone())), stake);
+    }
+    for (c, p) in pools {
+        recorded.insert((*c, Account::WinPool), p.win_pool);
+        recorded.insert((*c, Account::HouseProfit), p.house_profit);
+    }
+
+    // accounts the ledger knows about but nothing records are checked against 0
+    let accounts: BTreeSet<(Currency, Account)> = ledger
+        .keys()
+        .chain(recorded.keys())
+        .filter(|(_, a)| !a.is_outside())
+        .cloned()
+        .collect();
+    let mismatches: Vec<BalanceMismatch> = accounts
+        .into_iter()
+        .filter_map(|(currency, account)| {
+            let ledger = ledger_of(currency, account.clone());
+            let recorded = recorded
+                .get(&(currency, account.clone()))
+                .copied()
+                .unwrap_or_default();
+            (ledger != recorded).then_some(BalanceMismatch {
+                account,
+                currency,
+                ledger,
+                recorded,
+            })
+        })
+        .collect();
+
+    let currencies: BTreeSet<Currency> = ledger
+        .keys()
+        .chain(recorded.keys())
+        .map(|(c, _)| *c)
+        .collect();
+    let totals: Vec<CurrencyTotals> = currencies
+        .into_iter()
+        .map(|currency| CurrencyTotals {
+            currency,
+            ledger_total: ledger
+                .iter()
+                .filter(|((c, _), _)| *c == currency)
+                .map(|(_, v)| v)
+                .sum(),
+            money_in_game: recorded
+                .iter()
+                .filter(|((c, _), _)| *c == currency)
+                .map(|(_, v)| v)
+                .sum::<Money>(),
+            external_in: -ledger
+                .iter()
+                .filter(|((c, a), _)| *c == currency && a.is_outside())
+                .map(|(_, v)| *v)
+                .sum::<Money>(),
+        })
+        .collect();
+
+    ReconcileReport {
+        ok: unbalanced_transactions.is_empty()
+            && mismatches.is_empty()
+            && totals
+                .iter()
+                .all(|t| t.ledger_total == Money::ZERO && t.money_in_game == t.external_in),
+        entries: entries.len(),
+        totals,
+        unbalanced_transactions,
+        mismatches,
+    }
+}
@@ -1,7 +1,16 @@
-// pub mod auth;
+pub mod auth;
+pub mod cashier;
+pub mod config;
 pub mod game;
+pub mod grpc;
+pub mod ledger;
+pub mod limits;
 pub mod models;
+pub mod money;
 pub mod server;
 pub mod store;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod wallet;
 // pub mod utils;
 // pub use store::AppStore;
@@ -0,0 +1,333 @@
+use crate::game::GameError;
+use crate::models::{
+    Currency, LimitChange, LimitKind, LimitPeriod, MoneyLimit, PendingLimit, PlayBlock,
+    PlayerLimits, SessionLimit, TransactionKind, WalletTransaction,
+};
+use crate::money::Money;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Allowed length of a cool-off, in days.
+pub const COOL_OFF_DAYS: std::ops::RangeInclusive<u32> = 1..=42;
+/// Allowed length of a self-exclusion, in days.
+pub const SELF_EXCLUSION_DAYS: std::ops::RangeInclusive<u32> = 180..=1825;
+
+impl LimitPeriod {
+    pub fn window(self) -> Duration {
+        match self {
+            LimitPeriod::Daily => Duration::days(1),
+            LimitPeriod::Weekly => Duration::days(7),
+            LimitPeriod::Monthly => Duration::days(30),
+        }
+    }
+}
+
+/// Applies queued changes whose cooling period is over.
+pub fn apply_due(limits: &mut PlayerLimits, now: DateTime<Utc>) {
+    let (due, waiting) = std::mem::take(&mut limits.pending)
+        .into_iter()
+        .partition::<Vec<_>, _>(|p| p.effective_at <= now);
+    limits.pending = waiting;
+    for p in due {
+        apply(limits, &p.change);
+    }
+}
+
+/// Takes `change` on board. Anything that makes play stricter applies at
+/// once and cancels a queued loosening of the same limit; anything looser
+/// waits `delay`. Returns when the change takes effect.
+pub fn request(
+    limits: &mut PlayerLimits,
+    change: LimitChange,
+    now: DateTime<Utc>,
+    delay: Duration,
+) -> DateTime<Utc> {
+    apply_due(limits, now);
+    limits.pending.retain(|p| !same_limit(&p.change, &change));
+    if tightens(limits, &change) {
+        apply(limits, &change);
+        return now;
+    }
+    let effective_at = now + delay;
+    limits.pending.push(PendingLimit {
+        change,
+        effective_at,
+    });
+    effective_at
+}
+
+/// Starts or extends a cool-off or self-exclusion; an earlier end than the
+/// one already in place is ignored.
+pub fn block(limits: &mut PlayerLimits, kind: PlayBlock, until: DateTime<Utc>) {
+    let slot = match kind {
+        PlayBlock::CoolOff => &mut limits.cool_off_until,
+        PlayBlock::SelfExclusion => &mut limits.excluded_until,
+    };
+    if slot.is_none_or(|current| current < until) {
+        *slot = Some(until);
+    }
+}
+
+/// Checks that `stake` more in `currency` is allowed right now. `usage`
+/// holds the player's recent stakes and wins.
+pub fn check_stake(
+    limits: &PlayerLimits,
+    usage: &mut Usage,
+    currency: Currency,
+    stake: Money,
+    now: DateTime<Utc>,
+) -> Result<(), GameError> {
+    if let Some(until) = limits.excluded_until.filter(|u| *u > now) {
+        return Err(GameError::SelfExcluded { until });
+    }
+    if let Some(until) = limits.cool_off_until.filter(|u| *u > now) {
+        return Err(GameError::CoolingOff { until });
+    }
+    if let (Some(session), Some(started)) = (limits.session, session_start(limits, now)) {
+        if now - started >= Duration::minutes(i64::from(session.max_minutes)) {
+            let resume_at = limits.last_played_at.unwrap_or(now)
+                + Duration::minutes(i64::from(session.break_minutes));
+            return Err(GameError::SessionLimit { resume_at });
+        }
+    }
+
+    for limit in limits.money.iter().filter(|l| l.currency == currency) {
+        let (wagered, lost) = usage.totals(currency, limit.period, now);
+        let used = match limit.kind {
+            LimitKind::Wager => wagered,
+            LimitKind::Loss => lost,
+        };
+        if used.try_add(stake)? > limit.amount {
+            return Err(GameError::LimitReached {
+                kind: limit.kind,
+                period: limit.period,
+                currency,
+                amount: limit.amount,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Marks play at `now`, opening a new session if the last one has ended.
+pub fn record_play(limits: &mut PlayerLimits, now: DateTime<Utc>) {
+    limits.session_started_at = Some(session_start(limits, now).unwrap_or(now));
+    limits.last_played_at = Some(now);
+}
+
+/// Start of the session still running at `now`. A session ends once the
+/// player has been idle for the break length.
+fn session_start(limits: &PlayerLimits, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let brk = Duration::minutes(i64::from(limits.session?.break_minutes));
+    match (limits.session_started_at, limits.last_played_at) {
+        (Some(start), Some(last)) if now - last < brk => Some(start),
+        _ => None,
+    }
+}
+
+/// One player's stakes and wins over the longest limit window, with a
+/// running total per window, so a check costs no more than the player's
+/// own recent play.
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    /// (time, currency, wagered, lost), oldest first.
+    recent: VecDeque<(DateTime<Utc>, Currency, Money, Money)>,
+    /// How many entries have been dropped off the front of `recent`.
+    dropped: usize,
+    /// One per `LimitPeriod`, in declaration order.
+    windows: [Window; 3],
+}
+
+#[derive(Debug, Clone, Default)]
+struct Window {
+    /// First entry still inside the window, counting dropped ones.
+    from: usize,
+    /// Amount wagered and net amount lost per currency.
+    totals: BTreeMap<Currency, (Money, Money)>,
+}
+
+impl Usage {
+    /// Counts `t` if it stakes or pays out money.
+    pub fn record(&mut self, t: &WalletTransaction) {
+        let (wagered, lost) = match t.kind {
+            TransactionKind::Ante | TransactionKind::DiscardFee | TransactionKind::Refund => {
+                (-t.amount, -t.amount)
+            }
+            TransactionKind::Payout => (Money::ZERO, -t.amount),
+            _ => return,
+        };
+        for window in &mut self.windows {
+            let totals = window.totals.entry(t.currency).or_default();
+            totals.0 += wagered;
+            totals.1 += lost;
+        }
+        self.recent
+            .push_back((t.created_at, t.currency, wagered, lost));
+    }
+
+    /// Amount wagered and net amount lost in `currency` over the `period`
+    /// up to `now`.
+    pub fn totals(
+        &mut self,
+        currency: Currency,
+        period: LimitPeriod,
+        now: DateTime<Utc>,
+    ) -> (Money, Money) {
+        let since = now - period.window();
+        let window = &mut self.windows[period as usize];
+        while let Some(&(at, c, wagered, lost)) = self.recent.get(window.from - self.dropped) {
+            if at >= since {
+                break;
+            }
+            let totals = window.totals.entry(c).or_default();
+            totals.0 -= wagered;
+            totals.1 -= lost;
+            window.from += 1;
+        }
+        let totals = window.totals.get(&currency).copied().unwrap_or_default();
+
+        // entries every window has moved past are no longer needed
+        let keep_from = self.windows.iter().map(|w| w.from).min().unwrap_or(0);
+        while self.dropped < keep_from {
+            self.recent.pop_front();
+            self.dropped += 1;
+        }
+        totals
+    }
+}
+
+fn apply(limits: &mut PlayerLimits, change: &LimitChange) {
+    match *change {
+        LimitChange::Loss {
+            period,
+            currency,
+            amount,
+        } => set_money(limits, LimitKind::Loss, period, currency, amount),
+        LimitChange::Wager {
+            period,
+            currency,
+            amount,
+        } => set_money(limits, LimitKind::Wager, period, currency, amount),
+        LimitChange::Session {
+            max_minutes,
+            break_minutes,
+        } => {
+            limits.session = max_minutes.map(|max_minutes| SessionLimit {
+                max_minutes,
+                break_minutes: break_minutes.unwrap_or_default(),
+            });
+        }
+    }
+}
+
+fn set_money(
+    limits: &mut PlayerLimits,
+    kind: LimitKind,
+    period: LimitPeriod,
+    currency: Currency,
+    amount: Option<Money>,
+) {
+    limits
+        .money
+        .retain(|l| !(l.kind == kind && l.period == period && l.currency == currency));
+    if let Some(amount) = amount {
+        limits.money.push(MoneyLimit {
+            kind,
+            period,
+            currency,
+            amount,
+        });
+    }
+}
+
+fn current_money(
+    limits: &PlayerLimits,
+    kind: LimitKind,
+    period: LimitPeriod,
+    currency: Currency,
+) -> Option<Money> {
+    limits
+        .money
+        .iter()
+        .find(|l| l.kind == kind && l.period == period && l.currency == currency)
+        .map(|l| l.amount)
+}
+
+/// Whether `change` leaves the player no more room than they have now.
+fn tightens(limits: &PlayerLimits, change: &LimitChange) -> bool {
+    let (kind, period, currency, amount) = match *change {
+        LimitChange::Loss {
+            period,
+            currency,
+            amount,
+        } => (LimitKind::Loss, period, currency, amount),
+        LimitChange::Wager {
+            period,
+            currency,
+            amount,
+        } => (LimitKind::Wager, period, currency, amount),
+        LimitChange::Session {
+            max_minutes,
+            break_minutes,
+        } => {
+            return match (limits.session, max_minutes) {
+                (_, None) => limits.session.is_none(),
+                (None, Some(_)) => true,
+                (Some(cur), Some(max)) => {
+                    max <= cur.max_minutes && break_minutes.unwrap_or_default() >= cur.break_minutes
+                }
+            };
+        }
+    };
+    match (current_money(limits, kind, period, currency), amount) {
+        (cur, None) => cur.is_none(),
+        (None, Some(_)) => true,
+        (Some(cur), Some(new)) => new <= cur,
+    }
+}
+
+fn same_limit(a: &LimitChange, b: &LimitChange) -> bool {
+    match (a, b) {
+        (
+            LimitChange::Loss {
+                period: pa,
+                currency: ca,
+                ..
+            },
+            LimitChange::Loss {
+                period: pb,
+                currency: cb,
+                ..
+            },
+        )
+        | (
+            LimitChange::Wager {
+                period: pa,
+                currency: ca,
+                ..
+            },
+            LimitChange::Wager {
+                period: pb,
+                currency: cb,
+                ..
+            },
+        ) => pa == pb && ca == cb,
+        (LimitChange::Session { .. }, LimitChange::Session { .. }) => true,
+        _ => false,
+    }
+}
+
+pub(crate) fn describe(kind: LimitKind) -> &'static str {
+    match kind {
+        LimitKind::Loss => "loss",
+        LimitKind::Wager => "wager",
+    }
+}
+
+pub(crate) fn describe_period(period: LimitPeriod) -> &'static str {
+    match period {
+        LimitPeriod::Daily => "per day",
+        LimitPeriod::Weekly => "per week",
+        LimitPeriod::Monthly => "per month",
+    }
+}
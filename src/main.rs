@@ -2,8 +2,12 @@
 use axum::extract::Extension;
 use tower_http::cors::{Any, CorsLayer};
 
-use poker_server::server::router;
-use poker_server::store::InMem;
+use poker_server::cashier::{Cashier, MockProvider};
+use poker_server::config::GameConfig;
+use poker_server::server::{router_with_cashier, router_with_config};
+use poker_server::store::{InMem, JournalStore, SharedSnapshots, SharedStore, Snapshots};
+use std::sync::Arc;
+use std::time::Duration;
 
 mod middleware;
 use middleware::logging_middleware;
@@ -13,12 +17,49 @@ async fn main() {
     // basic logging
     tracing_subscriber::fmt::init();
 
-    // create in-memory store and convert to shared store
-    let inmem = InMem::new_demo();
-    let shared_store = inmem.into_shared();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mock_payments = args.iter().any(|a| a == "--mock-payments");
+    let (shared_store, snapshots) = open_store(args);
+    let config = GameConfig::from_env();
+
+    // the gRPC API gets its own port and shares the store
+    let grpc_addr = std::env::var("GRPC_ADDR").unwrap_or_else(|_| "0.0.0.0:50051".to_string());
+    let grpc_listener = tokio::net::TcpListener::bind(&grpc_addr).await.unwrap();
+    println!("gRPC listening on {grpc_addr}");
+    let grpc = tokio::spawn(poker_server::grpc::serve(
+        grpc_listener,
+        shared_store.clone(),
+        config.clone(),
+        shutdown_signal(),
+    ));
+
+    // settlement credits a remote wallet missed are sent again until taken
+    if config.wallet_url.is_some() {
+        poker_server::wallet::redeliver_credits_every(
+            shared_store.clone(),
+            poker_server::wallet::from_config(&config, shared_store.clone()),
+            Duration::from_millis(config.wallet_credit_retry_ms),
+        );
+    }
 
     // build router (defined in server::router) and attach layers
-    let app = router(shared_store.clone())
+    // there is no real payment provider yet; without one the cashier
+    // routes answer 503
+    let mut app = if mock_payments {
+        eprintln!("--mock-payments: every deposit is approved with no payment behind it");
+        let cashier = Cashier::new(
+            shared_store.clone(),
+            Arc::new(MockProvider::default()),
+            config.withdrawal_approval_threshold,
+        );
+        router_with_cashier(shared_store.clone(), config, cashier)
+    } else {
+        router_with_config(shared_store.clone(), config)
+    };
+    if let Some(snapshots) = &snapshots {
+        app = app.layer(Extension(snapshots.clone()));
+    }
+    let app = app
         .layer(Extension(logging_middleware))
         // make the store available to handlers via axum's Extension mechanism
         .layer(Extension(shared_store))
@@ -38,6 +79,109 @@ async fn main() {
     println!("Listening on http://{addr}");
 
     axum::serve(listener, app.into_make_service())
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .unwrap();
+    if let Ok(Err(e)) = grpc.await {
+        eprintln!("gRPC server failed: {e}");
+    }
+
+    if let Some(snapshots) = snapshots {
+        match snapshots.save().await {
+            Ok(bytes) => println!(
+                "Saved {bytes} byte snapshot to {}",
+                snapshots.path().display()
+            ),
+            Err(e) => eprintln!("final snapshot failed: {e}"),
+        }
+    }
+}
+
+/// Resolves on Ctrl-C or, on unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.unwrap();
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .unwrap()
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Picks the store from the command line:
+/// `--store memory` (the default; state is lost on exit unless
+/// `--snapshot PATH` is given, which loads it from there, saves it every
+/// `--snapshot-every SECS` (default 60) and on shutdown),
+/// `--store journal [--journal PATH]` (default `poker.journal`) or
+/// `--store sqlite [--db PATH]` (default `poker.db`; needs the `sqlite`
+/// feature).
+fn open_store(args: Vec<String>) -> (SharedStore, Option<SharedSnapshots>) {
+    let flag = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .map(|i| args.get(i + 1).cloned().unwrap_or_default())
+    };
+    match flag("--store").as_deref().unwrap_or("memory") {
+        "memory" => match flag("--snapshot") {
+            Some(path) => {
+                let mem = match InMem::load_snapshot(&path) {
+                    Ok(mem) => mem,
+                    Err(e) => panic!("can't load {path}: {e}"),
+                };
+                let every = match flag("--snapshot-every") {
+                    None => 60,
+                    Some(secs) => match secs.parse::<u64>() {
+                        Ok(secs) if secs > 0 => secs,
+                        _ => usage(&format!(
+                            "--snapshot-every takes a number of seconds above 0, not {secs:?}"
+                        )),
+                    },
+                };
+                let snapshots = Snapshots::new(mem.clone(), path);
+                snapshots.save_every(Duration::from_secs(every));
+                (mem.into_shared(), Some(snapshots))
+            }
+            None => (InMem::new_demo().into_shared(), None),
+        },
+        "journal" => {
+            let path = flag("--journal").unwrap_or_else(|| "poker.journal".to_string());
+            match JournalStore::open(&path) {
+                Ok(store) => (store.into_shared(), None),
+                Err(e) => panic!("can't open {path}: {e}"),
+            }
+        }
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            let path = flag("--db").unwrap_or_else(|| "poker.db".to_string());
+            match poker_server::store::SqliteStore::open(&path) {
+                Ok(store) => (store.into_shared(), None),
+                Err(e) => panic!("can't open {path}: {e}"),
+            }
+        }
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => usage("this build has no sqlite store; rebuild with the sqlite feature"),
+        other => usage(&format!(
+            "unknown store {other:?}; use memory, journal or sqlite"
+        )),
+    }
+}
+
+const USAGE: &str = "\
+usage: poker-server [--store memory] [--snapshot PATH [--snapshot-every SECS]] [--mock-payments]
+       poker-server --store journal [--journal PATH] [--mock-payments]
+       poker-server --store sqlite [--db PATH] [--mock-payments]";
+
+/// Reports a bad command line and exits.
+fn usage(problem: &str) -> ! {
+    eprintln!("{problem}\n\n{USAGE}");
+    std::process::exit(2)
 }
@@ -1,9 +1,5 @@
-use axum::{
-    body::Body,
-    http::{Request, StatusCode},
-    middleware::Next,
-    response::IntoResponse,
-};
+use axum::{body::Body, http::Request, middleware::Next, response::IntoResponse};
+use poker_server::server::ApiError;
 use std::time::Instant;
 use tracing::{error, event, info, Level};
 
@@ -12,7 +8,7 @@ use axum::body::to_bytes;
 pub async fn logging_middleware(
     request: Request<Body>,
     next: Next,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+) -> Result<impl IntoResponse, ApiError> {
     let method = request.method().clone();
     let uri = request.uri().clone();
     let version = request.version();
@@ -53,10 +49,7 @@ pub async fn logging_middleware(
     let (parts, body) = request.into_parts();
     let bytes = to_bytes(body, usize::MAX).await.map_err(|e| {
         error!("Failed to read request body: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to read request body".to_string(),
-        )
+        ApiError::internal("Failed to read request body")
     })?;
 
     let body_str = String::from_utf8_lossy(&bytes);
@@ -1,13 +1,17 @@
+use crate::money::{Money, MoneyError};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
 
 /// Card, Suit, HandRank - simple and serializable
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
 pub struct Card {
     pub rank: u8, // 2..=14
     pub suit: Suit,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub enum Suit {
     Hearts,
     Diamonds,
@@ -28,119 +32,1185 @@ pub enum HandRank {
     StraightFlush,
 }
 
+/// Currencies an operator can run side by side. Money never crosses
+/// currencies: each has its own wallets, pools and ledger balances.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    ToSchema,
+)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    Eur,
+    Usd,
+    /// Play money.
+    #[default]
+    Play,
+}
+
+impl Currency {
+    pub const ALL: [Currency; 3] = [Currency::Eur, Currency::Usd, Currency::Play];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::Eur => "EUR",
+            Currency::Usd => "USD",
+            Currency::Play => "PLAY",
+        }
+    }
+}
+
+impl std::fmt::Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl std::str::FromStr for Currency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Currency::ALL
+            .into_iter()
+            .find(|c| c.code().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown currency {s}"))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
     pub name: String,
-    pub password: String,
-    pub wallet: i64,
+    /// Argon2 PHC string. Accounts created before hashing was introduced
+    /// hold their plaintext password here until their next sign-in.
+    pub password_hash: String,
+    /// Balance per currency; a missing entry means 0.
+    pub wallets: BTreeMap<Currency, Money>,
+    /// Part of each wallet locked by pending withdrawals; not spendable.
+    #[serde(default)]
+    pub held: BTreeMap<Currency, Money>,
+    /// Set by an operator; a frozen account can't start new rounds.
+    #[serde(default)]
+    pub frozen: bool,
+    /// Responsible-gaming limits the player has chosen.
+    #[serde(default)]
+    pub limits: PlayerLimits,
+    #[serde(default)]
+    pub role: Role,
+}
+
+/// What an account may do on the operator API, on top of playing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    #[default]
+    Player,
+    /// Looks after accounts: can see user data, freeze accounts and post
+    /// maintenance notices.
+    Support,
+    /// Moves money: wallet adjustments, cashier approvals, round voids and
+    /// the pools.
+    Finance,
+    Admin,
+}
+
+/// A right checked per operator route; see `Role::permissions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Read users, their rounds and cashier requests.
+    ViewUsers,
+    /// Freeze and unfreeze accounts.
+    ManageUsers,
+    /// Credit or debit wallets, decide on cashier requests and void rounds,
+    /// which refunds them.
+    AdjustWallets,
+    /// Read the pools and ledger reconciliation, top up and withdraw.
+    ManagePools,
+    ChangePaytables,
+    /// Grant and revoke roles.
+    ManageRoles,
+    /// Save and download snapshots of the whole store, password hashes
+    /// included.
+    ManageSnapshots,
+    /// Post maintenance notices to `GET /api/events`.
+    PostNotices,
+}
+
+/// Payout multiplier per winning hand; a pair below jacks and high card
+/// pay nothing. Each round keeps the table it was started under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Paytable {
+    pub jacks_or_better: u32,
+    pub two_pair: u32,
+    pub three_of_a_kind: u32,
+    pub straight: u32,
+    pub flush: u32,
+    pub full_house: u32,
+    pub four_of_a_kind: u32,
+    pub straight_flush: u32,
+}
+
+impl Default for Paytable {
+    fn default() -> Self {
+        Paytable {
+            jacks_or_better: 1,
+            two_pair: 2,
+            three_of_a_kind: 3,
+            straight: 5,
+            flush: 6,
+            full_house: 9,
+            four_of_a_kind: 25,
+            straight_flush: 50,
+        }
+    }
+}
+
+impl User {
+    pub fn balance(&self, currency: Currency) -> Money {
+        self.wallets.get(&currency).copied().unwrap_or_default()
+    }
+
+    pub fn held(&self, currency: Currency) -> Money {
+        self.held.get(&currency).copied().unwrap_or_default()
+    }
+
+    /// Wallet balance not locked by pending withdrawals.
+    pub fn available(&self, currency: Currency) -> Money {
+        self.balance(currency) - self.held(currency)
+    }
+
+    /// Adds `delta` (negative to debit) and returns the new balance.
+    pub fn credit(&mut self, currency: Currency, delta: Money) -> Result<Money, MoneyError> {
+        let balance = self.wallets.entry(currency).or_default();
+        *balance = balance.try_add(delta)?;
+        Ok(*balance)
+    }
+
+    /// Adds `delta` to the held amount in `currency`.
+    pub fn hold(&mut self, currency: Currency, delta: Money) -> Result<(), MoneyError> {
+        let held = self.held.entry(currency).or_default();
+        *held = held.try_add(delta)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitKind {
+    /// Net amount lost: stakes minus payouts and refunds.
+    Loss,
+    /// Total staked: antes and discard fees, less refunds.
+    Wager,
+}
+
+/// Rolling window a money limit applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitPeriod {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MoneyLimit {
+    pub kind: LimitKind,
+    pub period: LimitPeriod,
+    pub currency: Currency,
+    pub amount: Money,
+}
+
+/// After `max_minutes` of play the player must pause for `break_minutes`
+/// before starting or discarding again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SessionLimit {
+    pub max_minutes: u32,
+    pub break_minutes: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+/// A limit the player asked to set, change or remove (`None`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "limit", rename_all = "snake_case")]
+pub enum LimitChange {
+    Loss {
+        period: LimitPeriod,
+        currency: Currency,
+        amount: Option<Money>,
+    },
+    Wager {
+        period: LimitPeriod,
+        currency: Currency,
+        amount: Option<Money>,
+    },
+    Session {
+        max_minutes: Option<u32>,
+        /// Defaults to the server's minimum break.
+        #[serde(default)]
+        break_minutes: Option<u32>,
+    },
+}
+
+/// A loosening change waiting out the cooling period.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PendingLimit {
+    pub change: LimitChange,
+    pub effective_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct PlayerLimits {
+    #[serde(default)]
+    pub money: Vec<MoneyLimit>,
+    pub session: Option<SessionLimit>,
+    #[serde(default)]
+    pub pending: Vec<PendingLimit>,
+    /// No play until then; can be extended but not shortened.
+    pub cool_off_until: Option<DateTime<Utc>>,
+    pub excluded_until: Option<DateTime<Utc>>,
+    /// Start of the current session and the last time money was staked in
+    /// it; used to enforce `session`.
+    pub session_started_at: Option<DateTime<Utc>>,
+    pub last_played_at: Option<DateTime<Utc>>,
+}
+
+/// A signed-in device. Tokens carry the session id, so revoking the session
+/// logs them all out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthSession {
+    pub id: String,
+    pub user_id: String,
+    /// Bumped on every refresh; only the newest refresh token is valid.
+    pub generation: u32,
+    pub revoked: bool,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl AuthSession {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        !self.revoked && self.expires_at > now
+    }
+}
+
+/// Player-requested pause from play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayBlock {
+    CoolOff,
+    SelfExclusion,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub enum RoundStatus {
     Active,
     Discarded,
     Revealed,
     Folded,
+    /// Cancelled by an operator; the stake and any discard fees were refunded.
+    Voided,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Round {
     pub id: String,
     pub user_id: String,
+    pub currency: Currency,
     pub cards: Vec<Card>,
-    pub ante: i64,
+    pub ante: Money,
+    /// Part of the win pool held back for this round's largest possible
+    /// payout while it is `Active`.
+    #[serde(default)]
+    pub reserved: Money,
+    /// Multipliers this round pays by, fixed when it starts.
+    #[serde(default)]
+    pub paytable: Paytable,
+    /// Staked from and paid to an operator's wallet (see `wallet`) rather
+    /// than the local one.
+    #[serde(default)]
+    pub remote_wallet: bool,
     pub status: RoundStatus,
+    pub draws_used: u32,
+    pub created_at: DateTime<Utc>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
 pub struct Pools {
-    pub win_pool: i64,
-    pub house_profit: i64,
+    pub win_pool: Money,
+    pub house_profit: Money,
+    /// Part of `win_pool` reserved for the payouts of open rounds.
+    #[serde(default)]
+    pub reserved: Money,
 }
 
-impl Default for Pools {
-    fn default() -> Self {
-        Pools {
-            win_pool: 0,
-            house_profit: 0,
+impl Pools {
+    /// Win pool not promised to any open round.
+    pub fn available(&self) -> Money {
+        self.win_pool - self.reserved
+    }
+}
+
+/// Ledger account. `External` is the outside world: money entering the game
+/// (signup credits, seeding, manual top-ups) is drawn from it, so its balance
+/// is the negative of everything held inside.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum Account {
+    User(String),
+    /// Stake of a round between start and settlement, keyed by round id.
+    InPlay(String),
+    WinPool,
+    HouseProfit,
+    External,
+    /// The player's balance at an operator's remote wallet, keyed by user
+    /// id. Like `External` it sits outside the game: rounds on a remote
+    /// wallet take stakes from it and pay wins into it.
+    RemoteWallet(String),
+}
+
+impl Account {
+    /// Whether the account is outside the game's own books.
+    pub fn is_outside(&self) -> bool {
+        matches!(self, Account::External | Account::RemoteWallet(_))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerReason {
+    Ante,
+    DiscardFee,
+    Payout,
+    Refund,
+    PoolSplit,
+    HouseCut,
+    SignupBonus,
+    Seed,
+    Adjustment,
+    Deposit,
+    Withdrawal,
+}
+
+/// One leg of a balanced transaction; all entries sharing a `tx_id` sum to 0.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub seq: u64,
+    pub tx_id: String,
+    pub account: Account,
+    pub currency: Currency,
+    pub amount: Money,
+    pub round_id: Option<String>,
+    pub reason: LedgerReason,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BalanceMismatch {
+    pub account: Account,
+    pub currency: Currency,
+    pub ledger: Money,
+    pub recorded: Money,
+}
+
+/// Conservation check for one currency.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CurrencyTotals {
+    pub currency: Currency,
+    /// Sum of every entry; 0 when money is conserved.
+    pub ledger_total: Money,
+    /// Money held by wallets, pools and stakes.
+    pub money_in_game: Money,
+    /// Net money brought in from outside (minus the balance of `External`
+    /// and every `RemoteWallet`).
+    pub external_in: Money,
+}
+
+/// Result of checking stored balances against the ledger.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReconcileReport {
+    pub ok: bool,
+    pub entries: usize,
+    pub totals: Vec<CurrencyTotals>,
+    pub unbalanced_transactions: Vec<String>,
+    pub mismatches: Vec<BalanceMismatch>,
+}
+
+impl ReconcileReport {
+    pub fn totals_for(&self, currency: Currency) -> Option<&CurrencyTotals> {
+        self.totals.iter().find(|t| t.currency == currency)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionKind {
+    Ante,
+    DiscardFee,
+    Payout,
+    Refund,
+    Bonus,
+    Adjustment,
+    Deposit,
+    Withdrawal,
+}
+
+/// A single change to a user's wallet, as shown in their history.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WalletTransaction {
+    pub seq: u64,
+    pub user_id: String,
+    pub currency: Currency,
+    pub kind: TransactionKind,
+    /// Signed: negative for debits.
+    pub amount: Money,
+    /// Local wallet afterwards. Rounds on a remote wallet leave it as is;
+    /// the operator holds that balance.
+    pub balance_after: Money,
+    pub round_id: Option<String>,
+    /// Operator's reason for adjustments and voids.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One movement of a player's money, as sent to the wallet. Ids are derived
+/// from the round, so a retry or rollback always names the same transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletTx {
+    pub transaction_id: String,
+    pub user_id: String,
+    pub round_id: String,
+    pub currency: Currency,
+    /// Always positive; the endpoint says which way it moves.
+    pub amount: Money,
+    pub kind: TransactionKind,
+}
+
+impl WalletTx {
+    pub fn ante(round: &Round) -> Self {
+        WalletTx::for_round(round, "ante".into(), round.ante, TransactionKind::Ante)
+    }
+
+    /// Fee for the discard that brought the round to `round.draws_used`.
+    pub fn discard_fee(round: &Round, fee: Money) -> Self {
+        WalletTx::for_round(
+            round,
+            format!("discard:{}", round.draws_used),
+            fee,
+            TransactionKind::DiscardFee,
+        )
+    }
+
+    /// What settling the round credits back: a win, or the ante when the
+    /// pool couldn't cover the win.
+    pub fn settlement(round: &Round, amount: Money, refunded: bool) -> Self {
+        if refunded {
+            WalletTx::for_round(round, "refund".into(), amount, TransactionKind::Refund)
+        } else {
+            WalletTx::for_round(round, "payout".into(), amount, TransactionKind::Payout)
         }
     }
+
+    /// Ids of every debit taken for `round` so far.
+    pub fn debit_ids(round: &Round) -> Vec<String> {
+        std::iter::once(format!("{}:ante", round.id))
+            .chain((1..=round.draws_used).map(|n| format!("{}:discard:{}", round.id, n)))
+            .collect()
+    }
+
+    fn for_round(round: &Round, step: String, amount: Money, kind: TransactionKind) -> Self {
+        WalletTx {
+            transaction_id: format!("{}:{}", round.id, step),
+            user_id: round.user_id.clone(),
+            round_id: round.id.clone(),
+            currency: round.currency,
+            amount,
+            kind,
+        }
+    }
+}
+
+/// Filter for `Store::list_transactions`. Results are newest first; `before`
+/// is an exclusive `seq` bound used as the page cursor.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionFilter {
+    pub currency: Option<Currency>,
+    pub kind: Option<TransactionKind>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub before: Option<u64>,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CashierKind {
+    Deposit,
+    Withdrawal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CashierStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+/// A deposit or withdrawal moving money between a wallet and a payment
+/// provider. Withdrawals hold their amount in `User::held` while pending.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CashierRequest {
+    pub id: String,
+    pub user_id: String,
+    pub kind: CashierKind,
+    pub currency: Currency,
+    pub amount: Money,
+    pub status: CashierStatus,
+    /// Withdrawal above the approval threshold waiting for an operator.
+    pub requires_approval: bool,
+    /// Provider's reference once the request has been submitted.
+    pub provider_ref: Option<String>,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Opens a round: validated and applied by `Store::start_round` under one lock.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewRound {
+    pub user_id: String,
+    pub currency: Currency,
+    pub ante: Money,
+    pub cards: Vec<Card>,
+    pub max_active_rounds: usize,
+    /// The round pays by this table. `ante` times its largest multiplier is
+    /// reserved from the currency's win pool; the round only opens if that
+    /// much is still available.
+    pub paytable: Paytable,
+    /// Stake from an operator's wallet; the local wallet isn't checked or
+    /// touched.
+    pub remote_wallet: bool,
+}
+
+/// Money handed to the pools by a lost round, a discard or a fold. Whatever
+/// would lift `win_pool` above `win_pool_ceiling` goes to house profit
+/// instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolShare {
+    pub win_pool: Money,
+    pub house: Money,
+    pub win_pool_ceiling: Option<Money>,
+}
+
+impl PoolShare {
+    pub fn total(&self) -> Result<Money, MoneyError> {
+        self.win_pool.try_add(self.house)
+    }
+}
+
+/// Charges `fee.total()` to the player, pays it into the pools and swaps in
+/// `replacements` (hand index, new card).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscardOp {
+    pub user_id: String,
+    pub round_id: String,
+    pub fee: PoolShare,
+    pub replacements: Vec<(usize, Card)>,
+}
+
+/// Result of evaluating a round, applied by `Store::settle_round`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settlement {
+    pub user_id: String,
+    pub round_id: String,
+    /// `draws_used` the outcome was computed from; settling fails if the
+    /// hand changed in between.
+    pub expected_draws: u32,
+    pub payout: Money,
+    /// Where a forfeited ante goes; must add up to the ante when there is
+    /// no payout.
+    pub share: PoolShare,
+    /// The player gave up: the round ends `Folded` and nothing is paid.
+    pub folded: bool,
+}
+
+/// State of a round and its owner's money right after a store operation.
+/// `wallet` and `pools` are in the round's currency.
+#[derive(Debug, Clone)]
+pub struct RoundSnapshot {
+    pub round: Round,
+    pub wallet: Money,
+    pub pools: Pools,
+    /// Set by `settle_round` when the win pool could not cover the payout
+    /// and the ante was returned instead.
+    pub refunded: bool,
+}
+
+/// A response recorded for an `Idempotency-Key`, replayed verbatim on retries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdempotencyRecord {
+    pub key: String,
+    /// Hash of method, path and body of the first request that used the key.
+    pub fingerprint: String,
+    /// `None` while the first request is still being processed.
+    pub response: Option<CachedResponse>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One change recorded by a durable store (`store::Durable`): the
+/// operation that made it, with everything it was given, and when. Replaying
+/// events in order through the same operations rebuilds the store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreEvent {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    /// A new store, seeded with the demo user and win pools.
+    Created,
+    UserRegistered {
+        user_id: String,
+        name: String,
+        password_hash: String,
+    },
+    PasswordChanged {
+        user_id: String,
+        password_hash: String,
+    },
+    UserFrozen {
+        user_id: String,
+        frozen: bool,
+    },
+    RoleChanged {
+        user_id: String,
+        role: Role,
+    },
+    WalletAdjusted {
+        user_id: String,
+        currency: Currency,
+        delta: Money,
+        reason: String,
+    },
+    /// The wallet was set to `wallet` outright.
+    WalletSet {
+        user_id: String,
+        currency: Currency,
+        wallet: Money,
+    },
+    PaytableChanged {
+        paytable: Paytable,
+    },
+    LimitChangeRequested {
+        user_id: String,
+        change: LimitChange,
+        delay_secs: i64,
+    },
+    PlayBlocked {
+        user_id: String,
+        block: PlayBlock,
+        until: DateTime<Utc>,
+    },
+    SessionOpened {
+        session_id: String,
+        user_id: String,
+        expires_at: DateTime<Utc>,
+    },
+    SessionRefreshed {
+        session_id: String,
+        generation: u32,
+        expires_at: DateTime<Utc>,
+    },
+    SessionRevoked {
+        session_id: String,
+    },
+    RoundStarted {
+        round_id: String,
+        #[serde(flatten)]
+        round: NewRound,
+    },
+    CardsDiscarded(DiscardOp),
+    /// Revealed, or folded if `folded` is set.
+    RoundSettled(Settlement),
+    RoundVoided {
+        round_id: String,
+        reason: String,
+    },
+    PoolsFunded {
+        currency: Currency,
+        win: Money,
+        house: Money,
+    },
+    WinPoolWithdrawn {
+        currency: Currency,
+        amount: Money,
+    },
+    HouseProfitWithdrawn {
+        currency: Currency,
+        amount: Money,
+    },
+    CashierRequested(CashierRequest),
+    CashierSubmitted {
+        request_id: String,
+        provider_ref: String,
+    },
+    CashierResolved {
+        request_id: String,
+        approved: bool,
+        reason: Option<String>,
+    },
+    /// A request finished under its idempotency key; claims still running
+    /// and released keys aren't recorded.
+    IdempotencyRecorded(IdempotencyRecord),
+    /// Idempotency records created at or before `before` were dropped.
+    IdempotencyExpired {
+        before: DateTime<Utc>,
+    },
+    /// The remote wallet confirmed the settlement credit `transaction_id`.
+    WalletCreditDelivered {
+        transaction_id: String,
+    },
+}
+
+/// Rows as they stand after an event, plus the ledger and wallet history
+/// lines it appended, for sinks that keep rows rather than events.
+#[derive(Debug, Clone, Default)]
+pub struct StoreChanges {
+    pub users: Vec<User>,
+    pub sessions: Vec<AuthSession>,
+    pub rounds: Vec<Round>,
+    pub cashier: Vec<CashierRequest>,
+    pub pools: BTreeMap<Currency, Pools>,
+    pub paytable: Option<Paytable>,
+    pub ledger: Vec<LedgerEntry>,
+    pub transactions: Vec<WalletTransaction>,
+    pub idempotency: Vec<IdempotencyRecord>,
+    /// Records created at or before this have expired.
+    pub idempotency_expired_before: Option<DateTime<Utc>>,
+    pub wallet_credits: Vec<WalletTx>,
+    /// Transaction ids of credits the remote wallet has confirmed.
+    pub wallet_credits_delivered: Vec<String>,
+}
+
+/// A change announced by a store as it is made, for watchers that don't
+/// want to poll; see `Store::subscribe`.
+#[derive(Debug, Clone)]
+pub enum StoreNotice {
+    /// The pools of `currency` now stand at `pools`.
+    Pools { currency: Currency, pools: Pools },
+    /// The player's local wallet in `currency` now stands at `wallet`.
+    Wallet {
+        user_id: String,
+        currency: Currency,
+        wallet: Money,
+    },
+    /// A revealed round paid out `payout` on a stake of `ante`.
+    Payout {
+        round_id: String,
+        currency: Currency,
+        ante: Money,
+        payout: Money,
+    },
+}
+
+/// Outcome of claiming an idempotency key.
+#[derive(Debug, Clone)]
+pub enum IdempotencyClaim {
+    /// Key was free; caller runs the request and then calls `finish_idempotency`.
+    Claimed,
+    /// Same request is still running under this key.
+    InFlight,
+    /// Same request already completed; replay this response.
+    Replay(CachedResponse),
+    /// Key was already used for a different request.
+    Mismatch,
 }
 
 // Request / Response DTOs
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SignInRequest {
     pub name: String,
     pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SignUpRequest {
     pub name: String,
     pub password: String,
 }
 
-#[derive(Debug, Serialize)]
+/// `wallet` is the balance in `currency` (the server's default currency);
+/// `wallets` lists every currency the user holds.
+#[derive(Debug, Serialize, ToSchema)]
 pub struct LoginResponse {
     pub id: String,
     pub name: String,
-    pub wallet: i64,
+    pub currency: Currency,
+    pub wallet: Money,
+    pub wallets: BTreeMap<Currency, Money>,
+    #[serde(flatten)]
+    pub tokens: AuthTokens,
 }
 
-#[derive(Debug, Deserialize)]
+/// Send `access_token` as `Authorization: Bearer ...`; trade
+/// `refresh_token` at `/api/token/refresh` once it expires.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AuthTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Seconds until `access_token` expires.
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct StartRequest {
-    pub user_id: String,
-    pub ante: i64,
+    /// Defaults to the server's default currency.
+    pub currency: Option<Currency>,
+    pub ante: Money,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct StartResponse {
     pub round_id: String,
+    pub currency: Currency,
     pub cards: Vec<Card>,
-    pub wallet: i64,
-    pub win_pool: i64,
+    pub wallet: Money,
+    pub win_pool: Money,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct DiscardRequest {
-    pub user_id: String,
     pub round_id: String,
     pub discard_indices: Vec<usize>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct DiscardResponse {
+    pub currency: Currency,
     pub cards: Vec<Card>,
-    pub wallet: i64,
-    pub total_bet: i64,
+    pub wallet: Money,
+    pub total_bet: Money,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct FoldRequest {
+    pub round_id: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FoldResponse {
+    pub currency: Currency,
+    pub wallet: Money,
+    pub win_pool: Money,
+    pub house_profit: Money,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RevealRequest {
-    pub user_id: String,
     pub round_id: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct RevealResponse {
-    pub wallet: i64,
-    pub win_pool: i64,
-    pub house_profit: i64,
+    pub currency: Currency,
+    pub wallet: Money,
+    pub win_pool: Money,
+    pub house_profit: Money,
     pub hand_rank: String,
     pub multiplier: u32,
-    pub payout: i64,
+    pub payout: Money,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct StatusResponse {
-    pub wallet: i64,
-    pub win_pool: i64,
-    pub house_profit: i64,
+    pub currency: Currency,
+    pub wallet: Money,
+    pub win_pool: Money,
+    /// Part of `win_pool` reserved for open rounds.
+    pub win_pool_reserved: Money,
+    pub win_pool_available: Money,
+    pub house_profit: Money,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ActiveRound {
+    pub round_id: String,
+    pub currency: Currency,
+    pub cards: Vec<Card>,
+    pub status: RoundStatus,
+    pub draws_used: u32,
+    pub ante: Money,
+    /// The ante plus every discard fee paid so far.
+    pub total_bet: Money,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ActiveRoundsResponse {
+    /// Newest first; empty when nothing is in play.
+    pub rounds: Vec<ActiveRound>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TransactionsQuery {
+    pub currency: Option<Currency>,
+    #[serde(rename = "type")]
+    pub kind: Option<TransactionKind>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TransactionsResponse {
+    pub items: Vec<WalletTransaction>,
+    /// Pass back as `cursor` to fetch the next (older) page.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CashierAmountRequest {
+    /// Defaults to the server's default currency.
+    pub currency: Option<Currency>,
+    pub amount: Money,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CashierListQuery {
+    pub user_id: Option<String>,
+    pub status: Option<CashierStatus>,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct CashierRejectRequest {
+    pub reason: Option<String>,
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CurrencyQuery {
+    pub currency: Option<Currency>,
+}
+
+/// A user as shown to operators; never includes the password.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UserSummary {
+    pub id: String,
+    pub name: String,
+    pub wallets: BTreeMap<Currency, Money>,
+    pub held: BTreeMap<Currency, Money>,
+    pub frozen: bool,
+    pub role: Role,
+}
+
+impl From<User> for UserSummary {
+    fn from(u: User) -> Self {
+        UserSummary {
+            id: u.id,
+            name: u.name,
+            wallets: u.wallets,
+            held: u.held,
+            frozen: u.frozen,
+            role: u.role,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AdminUsersQuery {
+    /// Matches the user id exactly or any part of the name, ignoring case.
+    pub q: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct WalletAdjustRequest {
+    pub currency: Currency,
+    /// Signed: negative to debit.
+    pub amount: Money,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PoolAmountRequest {
+    pub amount: Money,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AdminRoundsQuery {
+    pub user_id: Option<String>,
+    pub status: Option<RoundStatus>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct VoidRoundRequest {
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SnapshotSaved {
+    pub path: String,
+    pub bytes: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct VoidRoundResponse {
+    pub round_id: String,
+    pub currency: Currency,
+    /// Ante plus discard fees handed back.
+    pub refunded: Money,
+    pub wallet: Money,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PlayBlockRequest {
+    pub days: u32,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetRoleRequest {
+    pub role: Role,
+}
+
+/// Body of every error response. `code` is stable and meant to be switched
+/// on; `message` is for people and may change.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    /// Extra fields for the code, e.g. `max_ante` for `pool_too_small`.
+    pub details: Option<serde_json::Value>,
+}
+
+/// A message from a `/ws` client. `id` is echoed on the reply, so several
+/// requests can be in flight at once.
 #[derive(Debug, Deserialize)]
-pub struct StatusRequest {
-    pub user_id: String,
+pub struct WsRequest {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub action: WsAction,
+}
+
+/// What a `/ws` client asks for; the fields are those of the matching HTTP
+/// request, e.g. `{"type": "start", "id": "1", "ante": 10}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsAction {
+    Start(StartRequest),
+    Discard(DiscardRequest),
+    Reveal(RevealRequest),
+    Fold(FoldRequest),
+    Status(CurrencyQuery),
+    Ping,
+}
+
+/// A message from the server on `/ws`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsMessage {
+    /// Sent once the connection is authenticated.
+    Ready {
+        user_id: String,
+        heartbeat_secs: u64,
+    },
+    /// Reply to the request with the same `id`, shaped like the HTTP
+    /// response for that action.
+    Result {
+        id: Option<String>,
+        result: serde_json::Value,
+    },
+    Error {
+        id: Option<String>,
+        error: ErrorBody,
+    },
+    Pong {
+        id: Option<String>,
+    },
+    /// The caller's balance in `currency` changed.
+    Wallet {
+        currency: Currency,
+        wallet: Money,
+    },
+    /// The pools of a currency the caller plays in changed.
+    Pools {
+        currency: Currency,
+        win_pool: Money,
+        win_pool_reserved: Money,
+        win_pool_available: Money,
+    },
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WsQuery {
+    /// Access token, for clients that can't set an `Authorization` header
+    /// on the upgrade request.
+    pub token: Option<String>,
+}
+
+/// One message on `GET /api/events`. `type` doubles as the SSE event name.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LobbyEvent {
+    /// Where the pools of a currency stand. Sent for every currency on
+    /// connect, then at most once per `events_coalesce_ms` as they move.
+    Pools {
+        currency: Currency,
+        win_pool: Money,
+        win_pool_reserved: Money,
+        win_pool_available: Money,
+    },
+    /// A round paid at least `big_win_multiplier` times its ante.
+    BigWin {
+        round_id: String,
+        currency: Currency,
+        ante: Money,
+        payout: Money,
+        multiplier: i64,
+    },
+    /// Posted by an operator; the current one is also sent on connect.
+    Maintenance(MaintenanceNotice),
+}
+
+/// Planned downtime or other news for every player.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceNotice {
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub starts_at: Option<DateTime<Utc>>,
+    /// Once this has passed the notice is no longer sent to new listeners.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<DateTime<Utc>>,
 }
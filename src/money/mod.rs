@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+
+/// An amount in minor units (cents, or whole chips for play money).
+///
+/// Amounts that come from requests or feed a wallet should go through the
+/// `try_*` methods, which report overflow as an error. The operators panic on
+/// overflow instead of wrapping and are meant for sums that are already
+/// bounded, such as adding up ledger legs.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(transparent)]
+#[schema(description = "An amount in minor units (cents, or whole chips for play money).")]
+pub struct Money(i64);
+
+pub const OVERFLOW: &str = "amount overflow";
+
+/// Why an amount couldn't be computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum MoneyError {
+    #[error("amount overflow")]
+    Overflow,
+    #[error("division by zero")]
+    DivisionByZero,
+}
+
+/// How to turn a fractional number of minor units into a whole one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// Towards zero.
+    Down,
+    /// Away from zero.
+    Up,
+    /// To the nearest unit, halves away from zero.
+    #[default]
+    HalfUp,
+    /// To the nearest unit, halves to the even neighbour.
+    HalfEven,
+}
+
+impl std::str::FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "down" => Ok(Rounding::Down),
+            "up" => Ok(Rounding::Up),
+            "half_up" => Ok(Rounding::HalfUp),
+            "half_even" => Ok(Rounding::HalfEven),
+            _ => Err(format!("unknown rounding {s}")),
+        }
+    }
+}
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub const fn new(minor: i64) -> Self {
+        Money(minor)
+    }
+
+    pub const fn minor(self) -> i64 {
+        self.0
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn try_add(self, other: Money) -> Result<Money, MoneyError> {
+        self.0
+            .checked_add(other.0)
+            .map(Money)
+            .ok_or(MoneyError::Overflow)
+    }
+
+    pub fn try_sub(self, other: Money) -> Result<Money, MoneyError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Money)
+            .ok_or(MoneyError::Overflow)
+    }
+
+    pub fn try_mul(self, factor: i64) -> Result<Money, MoneyError> {
+        self.0
+            .checked_mul(factor)
+            .map(Money)
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// `self * num / den`, rounded once at the end with `rounding`.
+    pub fn mul_ratio(self, num: i64, den: i64, rounding: Rounding) -> Result<Money, MoneyError> {
+        if den == 0 {
+            return Err(MoneyError::DivisionByZero);
+        }
+        let n = i128::from(self.0) * i128::from(num);
+        let d = i128::from(den);
+        let (q, r) = (n / d, n % d);
+        let away = if r == 0 {
+            false
+        } else {
+            // compare 2|r| with |d| to find which neighbour is closer
+            let twice = 2 * r.abs();
+            match rounding {
+                Rounding::Down => false,
+                Rounding::Up => true,
+                Rounding::HalfUp => twice >= d.abs(),
+                Rounding::HalfEven => twice > d.abs() || (twice == d.abs() && q % 2 != 0),
+            }
+        };
+        let q = if away {
+            q + if (n < 0) != (d < 0) { -1 } else { 1 }
+        } else {
+            q
+        };
+        i64::try_from(q)
+            .map(Money)
+            .map_err(|_| MoneyError::Overflow)
+    }
+
+    /// `percent`% of `self`.
+    pub fn percent(self, percent: u32, rounding: Rounding) -> Result<Money, MoneyError> {
+        self.mul_ratio(i64::from(percent), 100, rounding)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::str::FromStr for Money {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Money)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        self.try_add(other).expect(OVERFLOW)
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        self.try_sub(other).expect(OVERFLOW)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, other: Money) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(self.0.checked_neg().expect(OVERFLOW))
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Money>>(iter: I) -> Money {
+        iter.copied().sum()
+    }
+}
@@ -0,0 +1,598 @@
+use super::events::Lobby;
+use super::{ApiError, Payments};
+use crate::auth::Staff;
+use crate::game::GameError;
+use crate::models::{
+    AdminRoundsQuery, AdminUsersQuery, CashierListQuery, CashierRejectRequest, CashierRequest,
+    Currency, ErrorBody, MaintenanceNotice, Paytable, Permission, PoolAmountRequest, Pools,
+    ReconcileReport, Round, SetRoleRequest, SnapshotSaved, UserSummary, VoidRoundRequest,
+    VoidRoundResponse, WalletAdjustRequest,
+};
+use crate::money::Money;
+use crate::store::{SharedSnapshots, SharedStore, StoreError};
+use crate::wallet::{self, SharedWallet};
+use axum::{
+    extract::{Extension, Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::collections::BTreeMap;
+use tracing::warn;
+use utoipa::openapi::security::SecurityRequirement;
+use utoipa::openapi::{ContentBuilder, Ref, ResponseBuilder};
+use utoipa_axum::router::{OpenApiRouter, UtoipaMethodRouter};
+use utoipa_axum::routes;
+
+/// Operator endpoints. Each route names the permission it needs; see
+/// `Role::permissions` for who has which.
+pub(super) fn routes() -> OpenApiRouter {
+    use Permission::*;
+    OpenApiRouter::new()
+        .routes(guard(ManagePools, routes!(reconcile_handler)))
+        .routes(guard(ViewUsers, routes!(list_users_handler)))
+        .routes(guard(ViewUsers, routes!(get_user_handler)))
+        .routes(guard(AdjustWallets, routes!(adjust_wallet_handler)))
+        .routes(guard(ManageUsers, routes!(freeze_handler)))
+        .routes(guard(ManageUsers, routes!(unfreeze_handler)))
+        .routes(guard(ManageRoles, routes!(set_role_handler)))
+        .routes(guard(ManagePools, routes!(list_pools_handler)))
+        .routes(guard(ManagePools, routes!(win_pool_top_up_handler)))
+        .routes(guard(ManagePools, routes!(win_pool_withdraw_handler)))
+        .routes(guard(ManagePools, routes!(house_profit_withdraw_handler)))
+        .routes(guard(ChangePaytables, routes!(set_paytable_handler)))
+        .routes(guard(ViewUsers, routes!(list_rounds_handler)))
+        .routes(guard(ViewUsers, routes!(get_round_handler)))
+        .routes(guard(AdjustWallets, routes!(void_round_handler)))
+        .routes(guard(
+            ManageSnapshots,
+            routes!(download_snapshot_handler, save_snapshot_handler),
+        ))
+        .routes(guard(ViewUsers, routes!(admin_cashier_list_handler)))
+        .routes(guard(AdjustWallets, routes!(admin_cashier_approve_handler)))
+        .routes(guard(AdjustWallets, routes!(admin_cashier_reject_handler)))
+        .routes(guard(PostNotices, routes!(post_maintenance_handler)))
+}
+
+/// Puts `route` behind `permission` and says so in its OpenAPI operations,
+/// along with the 401 and 403 the check can answer.
+fn guard(permission: Permission, route: UtoipaMethodRouter) -> UtoipaMethodRouter {
+    let (schemas, mut paths, route) = route;
+    let name = serde_json::to_value(permission).expect("permissions serialize");
+    let needs = format!(
+        "Needs the `{}` permission.",
+        name.as_str().unwrap_or_default()
+    );
+    for item in paths.paths.values_mut() {
+        for op in [&mut item.get, &mut item.post, &mut item.put]
+            .into_iter()
+            .flatten()
+        {
+            op.description = Some(match op.description.take() {
+                Some(d) => format!("{d}\n\n{needs}"),
+                None => needs.clone(),
+            });
+            op.security = Some(vec![SecurityRequirement::new(
+                "bearer",
+                Vec::<String>::new(),
+            )]);
+            for (status, why) in [
+                ("401", "Missing or invalid credentials"),
+                ("403", "The caller's role lacks the permission"),
+            ] {
+                op.responses
+                    .responses
+                    .insert(status.to_string(), error_response(why).into());
+            }
+        }
+    }
+    let route = route.route_layer(middleware::from_fn_with_state(
+        permission,
+        require_permission,
+    ));
+    (schemas, paths, route)
+}
+
+fn error_response(description: &str) -> utoipa::openapi::Response {
+    ResponseBuilder::new()
+        .description(description)
+        .content(
+            "application/json",
+            ContentBuilder::new()
+                .schema(Some(Ref::from_schema_name("ErrorBody")))
+                .build(),
+        )
+        .build()
+}
+
+/// Lets the request through only if the caller's role grants `permission`:
+/// 401 without valid credentials, 403 with the wrong role. Every refusal is
+/// logged.
+async fn require_permission(
+    State(permission): State<Permission>,
+    staff: Result<Staff, ApiError>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let path = request.uri().path();
+    match staff {
+        Err(e) => {
+            warn!(
+                ?permission,
+                path,
+                reason = e.message,
+                "operator request unauthenticated"
+            );
+            Err(e)
+        }
+        Ok(staff) if !staff.role.allows(permission) => {
+            warn!(
+                user_id = staff.user_id.as_deref().unwrap_or("-"),
+                role = ?staff.role,
+                ?permission,
+                path,
+                "operator request denied"
+            );
+            Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "permission_denied",
+                "permission denied",
+            ))
+        }
+        Ok(_) => Ok(next.run(request).await),
+    }
+}
+
+/// GET /api/admin/reconcile
+/// Checks that every wallet, pool and stake matches the ledger and that no
+/// money was created or lost.
+#[utoipa::path(get, path = "/api/admin/reconcile", tag = "admin",
+    responses(
+        (status = 200, body = ReconcileReport),
+    )
+)]
+async fn reconcile_handler(Extension(store): Extension<SharedStore>) -> Json<ReconcileReport> {
+    Json(store.reconcile().await)
+}
+
+/// GET /api/admin/users?q=&limit=
+#[utoipa::path(get, path = "/api/admin/users", tag = "admin",
+    params(AdminUsersQuery),
+    responses(
+        (status = 200, body = Vec<UserSummary>),
+    )
+)]
+async fn list_users_handler(
+    Extension(store): Extension<SharedStore>,
+    Query(q): Query<AdminUsersQuery>,
+) -> Json<Vec<UserSummary>> {
+    let limit = q.limit.unwrap_or(50).clamp(1, 200);
+    let users = store.list_users(q.q.as_deref(), limit).await;
+    Json(users.into_iter().map(UserSummary::from).collect())
+}
+
+/// GET /api/admin/users/{user_id}
+#[utoipa::path(get, path = "/api/admin/users/{user_id}", tag = "admin",
+    params(("user_id" = String, Path)),
+    responses(
+        (status = 200, body = UserSummary),
+        (status = 404, description = "No such user", body = ErrorBody),
+    )
+)]
+async fn get_user_handler(
+    Extension(store): Extension<SharedStore>,
+    Path(user_id): Path<String>,
+) -> Result<Json<UserSummary>, ApiError> {
+    store
+        .get_user(&user_id)
+        .await
+        .map(|u| Json(u.into()))
+        .ok_or_else(|| StoreError::UserNotFound.into())
+}
+
+/// POST /api/admin/users/{user_id}/adjust
+#[utoipa::path(post, path = "/api/admin/users/{user_id}/adjust", tag = "admin",
+    params(("user_id" = String, Path)),
+    request_body = WalletAdjustRequest,
+    responses(
+        (status = 200, body = UserSummary),
+        (status = 400, description = "Missing reason", body = ErrorBody),
+        (status = 402, description = "Debit exceeds the wallet", body = ErrorBody),
+        (status = 404, description = "No such user", body = ErrorBody),
+    )
+)]
+async fn adjust_wallet_handler(
+    Extension(store): Extension<SharedStore>,
+    Path(user_id): Path<String>,
+    Json(req): Json<WalletAdjustRequest>,
+) -> Result<Json<UserSummary>, ApiError> {
+    if req.reason.trim().is_empty() {
+        return Err(ApiError::invalid("reason required"));
+    }
+    store
+        .adjust_wallet(&user_id, req.currency, req.amount, req.reason.trim())
+        .await
+        .map(|u| Json(u.into()))
+        .map_err(ApiError::from)
+}
+
+/// POST /api/admin/users/{user_id}/freeze
+#[utoipa::path(post, path = "/api/admin/users/{user_id}/freeze", tag = "admin",
+    params(("user_id" = String, Path)),
+    responses(
+        (status = 200, body = UserSummary),
+        (status = 404, description = "No such user", body = ErrorBody),
+    )
+)]
+async fn freeze_handler(
+    Extension(store): Extension<SharedStore>,
+    Path(user_id): Path<String>,
+) -> Result<Json<UserSummary>, ApiError> {
+    set_frozen(&store, &user_id, true).await
+}
+
+/// POST /api/admin/users/{user_id}/unfreeze
+#[utoipa::path(post, path = "/api/admin/users/{user_id}/unfreeze", tag = "admin",
+    params(("user_id" = String, Path)),
+    responses(
+        (status = 200, body = UserSummary),
+        (status = 404, description = "No such user", body = ErrorBody),
+    )
+)]
+async fn unfreeze_handler(
+    Extension(store): Extension<SharedStore>,
+    Path(user_id): Path<String>,
+) -> Result<Json<UserSummary>, ApiError> {
+    set_frozen(&store, &user_id, false).await
+}
+
+async fn set_frozen(
+    store: &SharedStore,
+    user_id: &str,
+    frozen: bool,
+) -> Result<Json<UserSummary>, ApiError> {
+    store
+        .set_user_frozen(user_id, frozen)
+        .await
+        .map(|u| Json(u.into()))
+        .map_err(ApiError::from)
+}
+
+/// POST /api/admin/users/{user_id}/role
+#[utoipa::path(post, path = "/api/admin/users/{user_id}/role", tag = "admin",
+    params(("user_id" = String, Path)),
+    request_body = SetRoleRequest,
+    responses(
+        (status = 200, body = UserSummary),
+        (status = 404, description = "No such user", body = ErrorBody),
+    )
+)]
+async fn set_role_handler(
+    Extension(store): Extension<SharedStore>,
+    Path(user_id): Path<String>,
+    Json(req): Json<SetRoleRequest>,
+) -> Result<Json<UserSummary>, ApiError> {
+    store
+        .set_user_role(&user_id, req.role)
+        .await
+        .map(|u| Json(u.into()))
+        .map_err(ApiError::from)
+}
+
+/// GET /api/admin/pools
+#[utoipa::path(get, path = "/api/admin/pools", tag = "admin",
+    responses(
+        (status = 200, body = BTreeMap<Currency, Pools>),
+    )
+)]
+async fn list_pools_handler(
+    Extension(store): Extension<SharedStore>,
+) -> Json<BTreeMap<Currency, Pools>> {
+    let mut pools = BTreeMap::new();
+    for currency in Currency::ALL {
+        pools.insert(currency, store.get_pools(currency).await);
+    }
+    Json(pools)
+}
+
+/// POST /api/admin/pools/{currency}/win-pool/top-up
+#[utoipa::path(post, path = "/api/admin/pools/{currency}/win-pool/top-up", tag = "admin",
+    params(("currency" = Currency, Path)),
+    request_body = PoolAmountRequest,
+    responses(
+        (status = 200, body = Pools),
+        (status = 400, description = "Amount not positive", body = ErrorBody),
+    )
+)]
+async fn win_pool_top_up_handler(
+    Extension(store): Extension<SharedStore>,
+    Path(currency): Path<Currency>,
+    Json(req): Json<PoolAmountRequest>,
+) -> Result<Json<Pools>, ApiError> {
+    require_positive(&req)?;
+    store
+        .add_to_pools(currency, req.amount, Money::ZERO)
+        .await?;
+    Ok(Json(store.get_pools(currency).await))
+}
+
+/// POST /api/admin/pools/{currency}/win-pool/withdraw
+/// Only the part of the win pool not reserved for open rounds can leave.
+#[utoipa::path(post, path = "/api/admin/pools/{currency}/win-pool/withdraw", tag = "admin",
+    params(("currency" = Currency, Path)),
+    request_body = PoolAmountRequest,
+    responses(
+        (status = 200, body = Pools),
+        (status = 400, description = "Amount not positive", body = ErrorBody),
+        (status = 409, description = "More than the unreserved win pool", body = ErrorBody),
+    )
+)]
+async fn win_pool_withdraw_handler(
+    Extension(store): Extension<SharedStore>,
+    Path(currency): Path<Currency>,
+    Json(req): Json<PoolAmountRequest>,
+) -> Result<Json<Pools>, ApiError> {
+    require_positive(&req)?;
+    store.sub_from_win_pool(currency, req.amount).await?;
+    Ok(Json(store.get_pools(currency).await))
+}
+
+/// POST /api/admin/pools/{currency}/house-profit/withdraw
+#[utoipa::path(post, path = "/api/admin/pools/{currency}/house-profit/withdraw", tag = "admin",
+    params(("currency" = Currency, Path)),
+    request_body = PoolAmountRequest,
+    responses(
+        (status = 200, body = Pools),
+        (status = 400, description = "Amount not positive", body = ErrorBody),
+        (status = 409, description = "More than the house profit", body = ErrorBody),
+    )
+)]
+async fn house_profit_withdraw_handler(
+    Extension(store): Extension<SharedStore>,
+    Path(currency): Path<Currency>,
+    Json(req): Json<PoolAmountRequest>,
+) -> Result<Json<Pools>, ApiError> {
+    require_positive(&req)?;
+    store.sub_from_house_profit(currency, req.amount).await?;
+    Ok(Json(store.get_pools(currency).await))
+}
+
+fn require_positive(req: &PoolAmountRequest) -> Result<(), ApiError> {
+    if !req.amount.is_positive() {
+        return Err(GameError::InvalidAmount("amount must be positive").into());
+    }
+    Ok(())
+}
+
+/// PUT /api/admin/paytable
+/// Applies to rounds started afterwards; open rounds keep their table.
+#[utoipa::path(put, path = "/api/admin/paytable", tag = "admin",
+    request_body = Paytable,
+    responses(
+        (status = 200, body = Paytable),
+        (status = 400, description = "Invalid paytable", body = ErrorBody),
+    )
+)]
+async fn set_paytable_handler(
+    Extension(store): Extension<SharedStore>,
+    Json(paytable): Json<Paytable>,
+) -> Result<Json<Paytable>, ApiError> {
+    store
+        .set_paytable(paytable)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+/// GET /api/admin/rounds?user_id=&status=
+#[utoipa::path(get, path = "/api/admin/rounds", tag = "admin",
+    params(AdminRoundsQuery),
+    responses(
+        (status = 200, body = Vec<Round>),
+    )
+)]
+async fn list_rounds_handler(
+    Extension(store): Extension<SharedStore>,
+    Query(q): Query<AdminRoundsQuery>,
+) -> Json<Vec<Round>> {
+    Json(store.list_rounds(q.user_id.as_deref(), q.status).await)
+}
+
+/// GET /api/admin/rounds/{round_id}
+#[utoipa::path(get, path = "/api/admin/rounds/{round_id}", tag = "admin",
+    params(("round_id" = String, Path)),
+    responses(
+        (status = 200, body = Round),
+        (status = 404, description = "No such round", body = ErrorBody),
+    )
+)]
+async fn get_round_handler(
+    Extension(store): Extension<SharedStore>,
+    Path(round_id): Path<String>,
+) -> Result<Json<Round>, ApiError> {
+    store
+        .get_round(&round_id)
+        .await
+        .map(Json)
+        .ok_or_else(|| StoreError::RoundNotFound.into())
+}
+
+/// POST /api/admin/rounds/{round_id}/void
+/// On a remote wallet the round's debits are rolled back with the operator.
+#[utoipa::path(post, path = "/api/admin/rounds/{round_id}/void", tag = "admin",
+    params(("round_id" = String, Path)),
+    request_body = VoidRoundRequest,
+    responses(
+        (status = 200, body = VoidRoundResponse),
+        (status = 400, description = "Missing reason", body = ErrorBody),
+        (status = 404, description = "No such round", body = ErrorBody),
+        (status = 409, description = "Round no longer active", body = ErrorBody),
+    )
+)]
+async fn void_round_handler(
+    Extension(store): Extension<SharedStore>,
+    Extension(wallet): Extension<SharedWallet>,
+    Path(round_id): Path<String>,
+    Json(req): Json<VoidRoundRequest>,
+) -> Result<Json<VoidRoundResponse>, ApiError> {
+    if req.reason.trim().is_empty() {
+        return Err(ApiError::invalid("reason required"));
+    }
+    let (voided, refunded) =
+        wallet::void_and_roll_back(&store, wallet.as_ref(), &round_id, req.reason.trim()).await?;
+    Ok(Json(VoidRoundResponse {
+        round_id: voided.round.id,
+        currency: voided.round.currency,
+        refunded,
+        wallet: voided.wallet,
+    }))
+}
+
+/// Snapshots are only there when main runs the in-memory store with
+/// `--snapshot`.
+fn snapshots(snapshots: Option<Extension<SharedSnapshots>>) -> Result<SharedSnapshots, ApiError> {
+    snapshots.map(|Extension(s)| s).ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "snapshots_disabled",
+            "snapshots are not enabled",
+        )
+    })
+}
+
+/// POST /api/admin/snapshot
+#[utoipa::path(post, path = "/api/admin/snapshot", tag = "admin",
+    responses(
+        (status = 200, body = SnapshotSaved),
+        (status = 404, description = "Snapshots not enabled", body = ErrorBody),
+    )
+)]
+async fn save_snapshot_handler(
+    ext: Option<Extension<SharedSnapshots>>,
+) -> Result<Json<SnapshotSaved>, ApiError> {
+    let snapshots = snapshots(ext)?;
+    let bytes = snapshots.save().await.map_err(ApiError::internal)?;
+    Ok(Json(SnapshotSaved {
+        path: snapshots.path().display().to_string(),
+        bytes,
+    }))
+}
+
+/// GET /api/admin/snapshot
+/// A fresh snapshot as a download, without password hashes or sessions;
+/// the file on disk is left alone.
+#[utoipa::path(get, path = "/api/admin/snapshot", tag = "admin",
+    responses(
+        (status = 200, body = Object),
+        (status = 404, description = "Snapshots not enabled", body = ErrorBody),
+    )
+)]
+async fn download_snapshot_handler(
+    ext: Option<Extension<SharedSnapshots>>,
+) -> Result<Response, ApiError> {
+    let data = snapshots(ext)?.export();
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"snapshot.json\"",
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// GET /api/admin/cashier/requests?user_id=&status=
+#[utoipa::path(get, path = "/api/admin/cashier/requests", tag = "admin",
+    params(CashierListQuery),
+    responses(
+        (status = 200, body = Vec<CashierRequest>),
+    )
+)]
+async fn admin_cashier_list_handler(
+    Extension(store): Extension<SharedStore>,
+    Query(q): Query<CashierListQuery>,
+) -> Json<Vec<CashierRequest>> {
+    Json(
+        store
+            .list_cashier_requests(q.user_id.as_deref(), q.status)
+            .await,
+    )
+}
+
+/// POST /api/admin/cashier/requests/{id}/approve
+#[utoipa::path(post, path = "/api/admin/cashier/requests/{id}/approve", tag = "admin",
+    params(("id" = String, Path, description = "Cashier request id")),
+    responses(
+        (status = 200, body = CashierRequest),
+        (status = 404, description = "No such request", body = ErrorBody),
+        (status = 409, description = "Not pending approval", body = ErrorBody),
+        (status = 503, description = "No payment provider is configured", body = ErrorBody),
+    )
+)]
+async fn admin_cashier_approve_handler(
+    Extension(payments): Extension<Payments>,
+    Path(id): Path<String>,
+) -> Result<Json<CashierRequest>, ApiError> {
+    payments
+        .cashier()?
+        .approve(&id)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+/// POST /api/admin/cashier/requests/{id}/reject
+#[utoipa::path(post, path = "/api/admin/cashier/requests/{id}/reject", tag = "admin",
+    params(("id" = String, Path, description = "Cashier request id")),
+    request_body = Option<CashierRejectRequest>,
+    responses(
+        (status = 200, body = CashierRequest),
+        (status = 404, description = "No such request", body = ErrorBody),
+        (status = 409, description = "Not pending", body = ErrorBody),
+        (status = 503, description = "No payment provider is configured", body = ErrorBody),
+    )
+)]
+async fn admin_cashier_reject_handler(
+    Extension(payments): Extension<Payments>,
+    Path(id): Path<String>,
+    body: Option<Json<CashierRejectRequest>>,
+) -> Result<Json<CashierRequest>, ApiError> {
+    let reason = body.and_then(|Json(b)| b.reason);
+    payments
+        .cashier()?
+        .reject(&id, reason)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+/// POST /api/admin/maintenance
+/// Sends a notice to everyone on `GET /api/events`. It replaces the last
+/// one and is shown to new listeners until `ends_at`.
+#[utoipa::path(post, path = "/api/admin/maintenance", tag = "admin",
+    request_body = MaintenanceNotice,
+    responses(
+        (status = 200, body = MaintenanceNotice),
+        (status = 400, description = "Empty message, or it ends before it starts", body = ErrorBody),
+    )
+)]
+async fn post_maintenance_handler(
+    Extension(lobby): Extension<Lobby>,
+    Json(mut notice): Json<MaintenanceNotice>,
+) -> Result<Json<MaintenanceNotice>, ApiError> {
+    notice.message = notice.message.trim().to_string();
+    if notice.message.is_empty() {
+        return Err(ApiError::invalid("message must not be empty"));
+    }
+    if let (Some(starts_at), Some(ends_at)) = (notice.starts_at, notice.ends_at) {
+        if ends_at <= starts_at {
+            return Err(ApiError::invalid("ends_at must be after starts_at"));
+        }
+    }
+    lobby.post(notice.clone());
+    Ok(Json(notice))
+}
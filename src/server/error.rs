@@ -0,0 +1,241 @@
+use crate::auth::TokenError;
+use crate::game::GameError;
+use crate::models::ErrorBody;
+use crate::money::MoneyError;
+use crate::store::StoreError;
+use crate::wallet::WalletError;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Value};
+use tracing::error;
+
+/// An error as the API reports it: a status and an `ErrorBody`. Store,
+/// game and wallet errors convert into it, so handlers can use `?` and the
+/// status for each kind of failure is decided here, in one place.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    pub details: Option<Value>,
+    /// The request's change went through before it failed; see `committed`.
+    pub committed: bool,
+}
+
+/// Marks a response whose request changed the store even though it
+/// failed, so an idempotency key keeps it rather than letting a retry run.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Committed;
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+            committed: false,
+        }
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// For a failure after the request's change was made, such as a payout
+    /// the wallet didn't take: a retry under the same idempotency key gets
+    /// this error back instead of running again.
+    pub fn committed(mut self) -> Self {
+        self.committed = true;
+        self
+    }
+
+    /// 400 for a request that is wrong on its face.
+    pub fn invalid(message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, "invalid_request", message)
+    }
+
+    pub fn unauthorized(code: &'static str, message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::UNAUTHORIZED, code, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", message)
+    }
+
+    /// The body the client is sent, over HTTP or the WebSocket. Server
+    /// errors are logged here.
+    pub fn into_body(self) -> ErrorBody {
+        if self.status.is_server_error() {
+            error!(code = self.code, "{}", self.message);
+        }
+        ErrorBody {
+            code: self.code.to_string(),
+            message: self.message,
+            details: self.details,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let committed = self.committed;
+        let mut response = (self.status, Json(self.into_body())).into_response();
+        if committed {
+            response.extensions_mut().insert(Committed);
+        }
+        response
+    }
+}
+
+impl From<GameError> for ApiError {
+    fn from(e: GameError) -> Self {
+        use GameError::*;
+        let (status, code, details) = match &e {
+            InvalidAnte => (StatusCode::BAD_REQUEST, "invalid_ante", None),
+            AnteOutOfRange { min, max } => (
+                StatusCode::BAD_REQUEST,
+                "ante_out_of_range",
+                Some(json!({ "min": min, "max": max })),
+            ),
+            UnsupportedCurrency(currency) => (
+                StatusCode::BAD_REQUEST,
+                "unsupported_currency",
+                Some(json!({ "currency": currency })),
+            ),
+            InvalidAmount(_) => (StatusCode::BAD_REQUEST, "invalid_amount", None),
+            AccountFrozen => (StatusCode::FORBIDDEN, "account_frozen", None),
+            InsufficientFunds => (StatusCode::PAYMENT_REQUIRED, "insufficient_funds", None),
+            TooManyActiveRounds { max } => (
+                StatusCode::CONFLICT,
+                "too_many_active_rounds",
+                Some(json!({ "max": max })),
+            ),
+            PoolTooSmall { max_ante } => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "pool_too_small",
+                Some(json!({ "max_ante": max_ante })),
+            ),
+            NotYourRound => (StatusCode::FORBIDDEN, "not_your_round", None),
+            RoundNotActive => (StatusCode::CONFLICT, "round_not_active", None),
+            RoundChanged => (StatusCode::CONFLICT, "round_changed", None),
+            InvalidSettlement(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_settlement",
+                None,
+            ),
+            InvalidDiscard(_) => (StatusCode::BAD_REQUEST, "invalid_request", None),
+            PoolsCannotRefund => (StatusCode::CONFLICT, "pools_cannot_refund", None),
+            SelfExcluded { until } => (
+                StatusCode::FORBIDDEN,
+                "self_excluded",
+                Some(json!({ "until": until })),
+            ),
+            CoolingOff { until } => (
+                StatusCode::FORBIDDEN,
+                "cooling_off",
+                Some(json!({ "until": until })),
+            ),
+            SessionLimit { resume_at } => (
+                StatusCode::FORBIDDEN,
+                "session_limit_reached",
+                Some(json!({ "resume_at": resume_at })),
+            ),
+            LimitReached {
+                kind,
+                period,
+                currency,
+                amount,
+            } => (
+                StatusCode::FORBIDDEN,
+                "limit_reached",
+                Some(json!({
+                    "kind": kind,
+                    "period": period,
+                    "currency": currency,
+                    "amount": amount,
+                })),
+            ),
+            PaytableOutOfOrder => (StatusCode::BAD_REQUEST, "invalid_paytable", None),
+            MultiplierTooHigh { max } => (
+                StatusCode::BAD_REQUEST,
+                "invalid_paytable",
+                Some(json!({ "max": max })),
+            ),
+            Money(MoneyError::Overflow) => (StatusCode::BAD_REQUEST, "amount_overflow", None),
+            Money(MoneyError::DivisionByZero) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal_error", None)
+            }
+        };
+        ApiError {
+            status,
+            code,
+            message: e.to_string(),
+            details,
+            committed: false,
+        }
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(e: StoreError) -> Self {
+        use StoreError::*;
+        let (status, code) = match &e {
+            UserNotFound => (StatusCode::NOT_FOUND, "user_not_found"),
+            RoundNotFound => (StatusCode::NOT_FOUND, "round_not_found"),
+            CashierRequestNotFound => (StatusCode::NOT_FOUND, "cashier_request_not_found"),
+            SessionNotFound => (StatusCode::UNAUTHORIZED, "session_not_found"),
+            SessionExpired => (StatusCode::UNAUTHORIZED, "session_expired"),
+            RefreshTokenReused => (StatusCode::UNAUTHORIZED, "refresh_token_reused"),
+            NameTaken => (StatusCode::CONFLICT, "name_taken"),
+            CashierNotPending => (StatusCode::CONFLICT, "cashier_request_not_pending"),
+            ApprovalNotNeeded => (StatusCode::CONFLICT, "approval_not_needed"),
+            AlreadyWithProvider => (StatusCode::CONFLICT, "already_with_provider"),
+            ProviderRefMismatch => (StatusCode::CONFLICT, "provider_ref_mismatch"),
+            WinPoolShort => (StatusCode::CONFLICT, "win_pool_short"),
+            HouseProfitShort => (StatusCode::CONFLICT, "house_profit_short"),
+            Game(e) => return e.clone().into(),
+            // the cause is logged; it can name files on the server
+            Storage(cause) => {
+                error!("store write failed: {}", cause);
+                return ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "storage_error",
+                    "the change could not be saved",
+                );
+            }
+        };
+        ApiError::new(status, code, e.to_string())
+    }
+}
+
+impl From<MoneyError> for ApiError {
+    fn from(e: MoneyError) -> Self {
+        GameError::from(e).into()
+    }
+}
+
+/// A refusal is the player's problem; an unreachable wallet is ours.
+impl From<WalletError> for ApiError {
+    fn from(e: WalletError) -> Self {
+        let (status, code) = match e {
+            WalletError::Declined(_) => (StatusCode::PAYMENT_REQUIRED, "wallet_declined"),
+            WalletError::Unavailable(_) => (StatusCode::BAD_GATEWAY, "wallet_unavailable"),
+        };
+        ApiError::new(status, code, e.to_string())
+    }
+}
+
+impl From<TokenError> for ApiError {
+    fn from(e: TokenError) -> Self {
+        let code = match e {
+            TokenError::Invalid => "invalid_token",
+            TokenError::Expired => "token_expired",
+        };
+        ApiError::unauthorized(code, e.to_string())
+    }
+}
@@ -0,0 +1,190 @@
+//! `/api/events`: a public server-sent event stream for lobby screens.
+//! Pool changes and payouts come from `Store::subscribe`, so they are seen
+//! whichever route made them; maintenance notices are posted by operators.
+//! Pool updates are coalesced per currency, so a busy table costs each
+//! listener one message per `events_coalesce_ms`.
+
+use crate::config::GameConfig;
+use crate::models::{Currency, LobbyEvent, MaintenanceNotice, Pools, StoreNotice};
+use crate::store::SharedStore;
+use axum::extract::Extension;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use chrono::Utc;
+use futures_util::stream::{self, Stream};
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{interval, Interval, MissedTickBehavior};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+pub(super) fn routes() -> OpenApiRouter {
+    OpenApiRouter::new().routes(routes!(events_handler))
+}
+
+/// The current maintenance notice and the listeners to tell about new ones.
+#[derive(Clone)]
+pub(super) struct Lobby {
+    notices: broadcast::Sender<MaintenanceNotice>,
+    current: Arc<Mutex<Option<MaintenanceNotice>>>,
+}
+
+impl Lobby {
+    pub(super) fn new() -> Self {
+        Lobby {
+            notices: broadcast::channel(16).0,
+            current: Arc::default(),
+        }
+    }
+
+    /// Replaces the current notice and sends it to every listener.
+    pub(super) fn post(&self, notice: MaintenanceNotice) {
+        *self.current.lock() = Some(notice.clone());
+        // no listeners is fine
+        let _ = self.notices.send(notice);
+    }
+
+    /// The latest notice, unless it has ended.
+    fn current(&self) -> Option<MaintenanceNotice> {
+        let now = Utc::now();
+        self.current
+            .lock()
+            .clone()
+            .filter(|n| n.ends_at.is_none_or(|end| end > now))
+    }
+}
+
+/// GET /api/events
+/// Server-sent events for lobby screens; no token needed. Each event is
+/// named after its `type`. The pools of every currency come first, then
+/// the current maintenance notice if there is one.
+#[utoipa::path(get, path = "/api/events", tag = "lobby",
+    responses(
+        (status = 200, description = "A `text/event-stream` of these, kept open",
+            body = LobbyEvent, content_type = "text/event-stream"),
+    )
+)]
+async fn events_handler(
+    Extension(store): Extension<SharedStore>,
+    Extension(config): Extension<GameConfig>,
+    Extension(lobby): Extension<Lobby>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let feed = Feed::new(store, &config, lobby).await;
+    let events = stream::unfold(feed, |mut feed| async move {
+        let event = feed.next().await?;
+        Some((Ok(sse_event(&event)), feed))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+fn sse_event(event: &LobbyEvent) -> Event {
+    let name = match event {
+        LobbyEvent::Pools { .. } => "pools",
+        LobbyEvent::BigWin { .. } => "big_win",
+        LobbyEvent::Maintenance(_) => "maintenance",
+    };
+    Event::default()
+        .event(name)
+        .json_data(event)
+        .expect("lobby events serialize")
+}
+
+fn pools_event(currency: Currency, pools: &Pools) -> LobbyEvent {
+    LobbyEvent::Pools {
+        currency,
+        win_pool: pools.win_pool,
+        win_pool_reserved: pools.reserved,
+        win_pool_available: pools.available(),
+    }
+}
+
+/// One listener's view of the store and the lobby.
+struct Feed {
+    store: SharedStore,
+    lobby: Lobby,
+    changes: broadcast::Receiver<StoreNotice>,
+    notices: broadcast::Receiver<MaintenanceNotice>,
+    big_win_multiplier: i64,
+    /// Latest pools per currency not sent yet.
+    pending: BTreeMap<Currency, Pools>,
+    /// Events to send before waiting for more.
+    ready: VecDeque<LobbyEvent>,
+    flush: Interval,
+}
+
+impl Feed {
+    async fn new(store: SharedStore, config: &GameConfig, lobby: Lobby) -> Self {
+        // subscribe before reading, so no change falls in between
+        let changes = store.subscribe();
+        let notices = lobby.notices.subscribe();
+        let mut ready = VecDeque::new();
+        for currency in Currency::ALL {
+            ready.push_back(pools_event(currency, &store.get_pools(currency).await));
+        }
+        ready.extend(lobby.current().map(LobbyEvent::Maintenance));
+        let mut flush = interval(Duration::from_millis(config.events_coalesce_ms));
+        // after a quiet spell the first change goes out straight away
+        flush.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Feed {
+            store,
+            lobby,
+            changes,
+            notices,
+            big_win_multiplier: i64::from(config.big_win_multiplier),
+            pending: BTreeMap::new(),
+            ready,
+            flush,
+        }
+    }
+
+    /// The next event to send; `None` once there can be no more.
+    async fn next(&mut self) -> Option<LobbyEvent> {
+        loop {
+            if let Some(event) = self.ready.pop_front() {
+                return Some(event);
+            }
+            tokio::select! {
+                change = self.changes.recv() => match change {
+                    Ok(StoreNotice::Pools { currency, pools }) => {
+                        self.pending.insert(currency, pools);
+                    }
+                    Ok(StoreNotice::Payout { round_id, currency, ante, payout }) => {
+                        let multiplier = payout.minor() / ante.minor().max(1);
+                        if multiplier >= self.big_win_multiplier {
+                            self.ready.push_back(LobbyEvent::BigWin {
+                                round_id,
+                                currency,
+                                ante,
+                                payout,
+                                multiplier,
+                            });
+                        }
+                    }
+                    Ok(StoreNotice::Wallet { .. }) => {}
+                    // some changes were missed; the current pools make up for them
+                    Err(RecvError::Lagged(_)) => {
+                        for currency in Currency::ALL {
+                            let pools = self.store.get_pools(currency).await;
+                            self.pending.insert(currency, pools);
+                        }
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+                notice = self.notices.recv() => match notice {
+                    Ok(notice) => self.ready.push_back(LobbyEvent::Maintenance(notice)),
+                    Err(RecvError::Lagged(_)) => {
+                        self.ready.extend(self.lobby.current().map(LobbyEvent::Maintenance));
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = self.flush.tick(), if !self.pending.is_empty() => {
+                    for (currency, pools) in std::mem::take(&mut self.pending) {
+                        self.ready.push_back(pools_event(currency, &pools));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use super::error::Committed;
+use super::ApiError;
+use crate::auth::AuthUser;
+use crate::config::GameConfig;
+use crate::models::{CachedResponse, IdempotencyClaim};
+use crate::store::SharedStore;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Extension, Request},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::FutureExt;
+use http_body_util::LengthLimitError;
+use sha2::{Digest, Sha256};
+use std::panic::AssertUnwindSafe;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+pub const IDEMPOTENT_REPLAY_HEADER: &str = "idempotent-replayed";
+
+const MAX_KEY_LEN: usize = 255;
+/// Bodies are buffered to fingerprint and replay them; these bound how much.
+const MAX_REQUEST_BODY: usize = 64 * 1024;
+const MAX_RESPONSE_BODY: usize = 1024 * 1024;
+
+/// Route layer for money-moving endpoints. Requests carrying an
+/// `Idempotency-Key` run at most once per retention window: retries get the
+/// first response back verbatim, reuse of the key for a different request is
+/// rejected with 422, and a retry racing the original gets 409. Keys are
+/// scoped to the caller, so two players can't collide on the same key.
+///
+/// Once claimed, the request runs on its own task: a client that hangs up
+/// or times out doesn't cancel it, and its response is still recorded for
+/// the retry. A handler panic releases the key.
+pub async fn idempotency_middleware(
+    Extension(store): Extension<SharedStore>,
+    Extension(config): Extension<GameConfig>,
+    auth: AuthUser,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let key = match request.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        None => return Ok(next.run(request).await),
+        Some(v) => scoped_key(&auth.user_id, v.to_str().ok())?,
+    };
+
+    let (parts, body) = request.into_parts();
+    let bytes = to_bytes(body, MAX_REQUEST_BODY).await.map_err(|e| {
+        if e.into_inner().is::<LengthLimitError>() {
+            ApiError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "payload_too_large",
+                format!("request body over {MAX_REQUEST_BODY} bytes"),
+            )
+        } else {
+            ApiError::invalid("failed to read request body")
+        }
+    })?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(parts.method.as_str().as_bytes());
+    hasher.update(b" ");
+    hasher.update(parts.uri.path().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(&bytes);
+    let fingerprint = format!("{:x}", hasher.finalize());
+
+    let ttl = chrono::Duration::seconds(config.idempotency_ttl_secs);
+    match store
+        .claim_idempotency(&key, &fingerprint, chrono::Utc::now(), ttl)
+        .await
+    {
+        IdempotencyClaim::Claimed => {}
+        IdempotencyClaim::Replay(cached) => return Ok(replay(cached)),
+        IdempotencyClaim::InFlight => return Err(in_flight()),
+        IdempotencyClaim::Mismatch => return Err(key_reused()),
+    }
+
+    let request = Request::from_parts(parts, Body::from(bytes));
+    tokio::spawn(run_claimed(store, key, request, next))
+        .await
+        .map_err(|_| ApiError::internal("request task failed"))?
+}
+
+/// The key an `Idempotency-Key` value is claimed under, scoped to the
+/// caller. `None` is a value that isn't text.
+pub(crate) fn scoped_key(user_id: &str, value: Option<&str>) -> Result<String, ApiError> {
+    let key = value
+        .map(str::trim)
+        .filter(|k| !k.is_empty() && k.len() <= MAX_KEY_LEN)
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_idempotency_key",
+                "invalid idempotency key",
+            )
+        })?;
+    Ok(format!("{user_id}:{key}"))
+}
+
+pub(crate) fn in_flight() -> ApiError {
+    ApiError::new(
+        StatusCode::CONFLICT,
+        "idempotency_in_flight",
+        "request with this idempotency key is still in progress",
+    )
+}
+
+pub(crate) fn key_reused() -> ApiError {
+    ApiError::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "idempotency_key_reused",
+        "idempotency key already used for a different request",
+    )
+}
+
+/// Runs a claimed request to the end and records its response under `key`.
+async fn run_claimed(
+    store: SharedStore,
+    key: String,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let response = match AssertUnwindSafe(next.run(request)).catch_unwind().await {
+        Ok(response) => response,
+        Err(_) => {
+            store.finish_idempotency(&key, None).await;
+            return Err(ApiError::internal("request handler panicked"));
+        }
+    };
+
+    // server errors are not cached so the client can retry them, unless
+    // the change went through anyway
+    if response.status().is_server_error() && response.extensions().get::<Committed>().is_none() {
+        store.finish_idempotency(&key, None).await;
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_RESPONSE_BODY).await {
+        Ok(b) => b,
+        Err(_) => {
+            store.finish_idempotency(&key, None).await;
+            return Err(ApiError::internal("failed to read response body"));
+        }
+    };
+
+    let cached = CachedResponse {
+        status: parts.status.as_u16(),
+        content_type: parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        body: bytes.to_vec(),
+    };
+    store.finish_idempotency(&key, Some(cached)).await;
+
+    Ok(Response::from_parts(parts, Body::from(bytes)))
+}
+
+fn replay(cached: CachedResponse) -> Response {
+    let status = StatusCode::from_u16(cached.status).unwrap_or(StatusCode::OK);
+    let mut response = (status, cached.body).into_response();
+    let headers = response.headers_mut();
+    match cached
+        .content_type
+        .and_then(|ct| HeaderValue::from_str(&ct).ok())
+    {
+        Some(ct) => {
+            headers.insert(header::CONTENT_TYPE, ct);
+        }
+        None => {
+            headers.remove(header::CONTENT_TYPE);
+        }
+    }
+    headers.insert(IDEMPOTENT_REPLAY_HEADER, HeaderValue::from_static("true"));
+    response
+}
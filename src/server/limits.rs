@@ -0,0 +1,150 @@
+use super::ApiError;
+use crate::auth::Owner;
+use crate::config::GameConfig;
+use crate::limits::{COOL_OFF_DAYS, SELF_EXCLUSION_DAYS};
+use crate::models::{ErrorBody, LimitChange, PlayBlock, PlayBlockRequest, PlayerLimits};
+use crate::store::{SharedStore, StoreError};
+use axum::{extract::Extension, Json};
+use chrono::{Duration, Utc};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+/// Player-set responsible-gaming limits. Enforcement lives in the store's
+/// start and discard steps.
+pub(super) fn routes() -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(get_limits_handler, set_limit_handler))
+        .routes(routes!(cool_off_handler))
+        .routes(routes!(self_exclusion_handler))
+}
+
+/// GET /api/users/{user_id}/limits
+#[utoipa::path(get, path = "/api/users/{user_id}/limits", tag = "limits", security(("bearer" = [])),
+    params(("user_id" = String, Path)),
+    responses(
+        (status = 200, body = PlayerLimits),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 403, description = "The id is not the caller's", body = ErrorBody),
+    )
+)]
+async fn get_limits_handler(
+    Extension(store): Extension<SharedStore>,
+    Owner(user): Owner,
+) -> Result<Json<PlayerLimits>, ApiError> {
+    store
+        .get_limits(&user.user_id)
+        .await
+        .map(Json)
+        .ok_or_else(|| StoreError::UserNotFound.into())
+}
+
+/// POST /api/users/{user_id}/limits
+/// Lowering a limit applies at once; raising or removing one waits out the
+/// configured cooling period and shows under `pending` until then.
+#[utoipa::path(post, path = "/api/users/{user_id}/limits", tag = "limits", security(("bearer" = [])),
+    params(("user_id" = String, Path)),
+    request_body = LimitChange,
+    responses(
+        (status = 200, body = PlayerLimits),
+        (status = 400, description = "Limit out of range", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 403, description = "The id is not the caller's", body = ErrorBody),
+    )
+)]
+async fn set_limit_handler(
+    Extension(store): Extension<SharedStore>,
+    Extension(config): Extension<GameConfig>,
+    Owner(user): Owner,
+    Json(mut change): Json<LimitChange>,
+) -> Result<Json<PlayerLimits>, ApiError> {
+    match &mut change {
+        LimitChange::Loss { amount, .. } | LimitChange::Wager { amount, .. } => {
+            if amount.is_some_and(|a| !a.is_positive()) {
+                return Err(ApiError::invalid("limit must be positive"));
+            }
+        }
+        LimitChange::Session {
+            max_minutes,
+            break_minutes,
+        } => {
+            if *max_minutes == Some(0) {
+                return Err(ApiError::invalid("session limit must be positive"));
+            }
+            let min = config.min_session_break_minutes;
+            if *break_minutes.get_or_insert(min) < min {
+                return Err(ApiError::invalid(format!(
+                    "break must be at least {min} minutes"
+                )));
+            }
+        }
+    }
+
+    let delay = Duration::seconds(config.limit_increase_delay_secs);
+    store
+        .request_limit_change(&user.user_id, change, delay)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+/// POST /api/users/{user_id}/cool-off
+#[utoipa::path(post, path = "/api/users/{user_id}/cool-off", tag = "limits", security(("bearer" = [])),
+    params(("user_id" = String, Path)),
+    request_body = PlayBlockRequest,
+    responses(
+        (status = 200, body = PlayerLimits),
+        (status = 400, description = "Days out of range", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 403, description = "The id is not the caller's", body = ErrorBody),
+    )
+)]
+async fn cool_off_handler(
+    Extension(store): Extension<SharedStore>,
+    Owner(user): Owner,
+    Json(req): Json<PlayBlockRequest>,
+) -> Result<Json<PlayerLimits>, ApiError> {
+    block(&store, &user.user_id, PlayBlock::CoolOff, req.days).await
+}
+
+/// POST /api/users/{user_id}/self-exclusion
+#[utoipa::path(post, path = "/api/users/{user_id}/self-exclusion", tag = "limits", security(("bearer" = [])),
+    params(("user_id" = String, Path)),
+    request_body = PlayBlockRequest,
+    responses(
+        (status = 200, body = PlayerLimits),
+        (status = 400, description = "Days out of range", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 403, description = "The id is not the caller's", body = ErrorBody),
+    )
+)]
+async fn self_exclusion_handler(
+    Extension(store): Extension<SharedStore>,
+    Owner(user): Owner,
+    Json(req): Json<PlayBlockRequest>,
+) -> Result<Json<PlayerLimits>, ApiError> {
+    block(&store, &user.user_id, PlayBlock::SelfExclusion, req.days).await
+}
+
+async fn block(
+    store: &SharedStore,
+    user_id: &str,
+    kind: PlayBlock,
+    days: u32,
+) -> Result<Json<PlayerLimits>, ApiError> {
+    let allowed = match kind {
+        PlayBlock::CoolOff => COOL_OFF_DAYS,
+        PlayBlock::SelfExclusion => SELF_EXCLUSION_DAYS,
+    };
+    if !allowed.contains(&days) {
+        return Err(ApiError::invalid(format!(
+            "days must be between {} and {}",
+            allowed.start(),
+            allowed.end()
+        )));
+    }
+    let until = Utc::now() + Duration::days(i64::from(days));
+    store
+        .block_play(user_id, kind, until)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
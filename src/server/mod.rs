@@ -1,309 +1,463 @@
-use crate::game;
+mod admin;
+mod error;
+mod events;
+pub(crate) mod idempotency;
+mod limits;
+mod openapi;
+pub mod play;
+mod ws;
+
+use crate::auth::{self, AuthUser, Owner, TokenKind};
+use crate::cashier::Cashier;
+use crate::config::GameConfig;
+use crate::ledger;
 use crate::models::{
-    DiscardRequest, DiscardResponse, LoginResponse, RevealRequest, RevealResponse, RoundStatus,
-    SignInRequest, SignUpRequest, StartRequest, StartResponse, StatusResponse,
+    ActiveRound, ActiveRoundsResponse, AuthTokens, CashierAmountRequest, CashierRequest,
+    CurrencyQuery, DiscardRequest, DiscardResponse, ErrorBody, FoldRequest, FoldResponse,
+    LoginResponse, Paytable, RefreshRequest, RevealRequest, RevealResponse, SignInRequest,
+    SignUpRequest, StartRequest, StartResponse, StatusResponse, TransactionFilter,
+    TransactionsQuery, TransactionsResponse,
 };
-use crate::store::SharedStore;
+use crate::store::{SharedStore, StoreError};
 use axum::{
-    extract::Extension,
-    extract::Path,
-    http::StatusCode,
-    routing::{get, post},
-    Json, Router,
+    extract::Extension, extract::Path, extract::Query, http::StatusCode, middleware, Json, Router,
 };
+use events::Lobby;
+use idempotency::idempotency_middleware;
+use play::Play;
+use utoipa_axum::router::{OpenApiRouter, UtoipaMethodRouter, UtoipaMethodRouterExt};
+use utoipa_axum::routes;
+use utoipa_swagger_ui::SwaggerUi;
+
+pub use error::ApiError;
+pub use idempotency::{IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAY_HEADER};
 use serde_json::json;
 
+/// The API with no payment provider; see `router_with_config`.
 pub fn router(store: SharedStore) -> Router {
-    Router::new()
-        .route("/", get(root_health))
-        .route("/api/signup", post(signup_handler))
-        .route("/api/signin", post(signin_handler))
-        .route("/api/status/{user_id}", get(status_handler))
-        .route("/api/start", post(start_handler))
-        .route("/api/discard", post(discard_handler))
-        .route("/api/reveal", post(reveal_handler))
+    router_with_config(store, GameConfig::default())
+}
+
+/// The API with no payment provider: deposits, withdrawals and their
+/// approval answer 503 `payments_unavailable`.
+pub fn router_with_config(store: SharedStore, config: GameConfig) -> Router {
+    build(store, config, Payments(None))
+}
+
+/// The API with deposits and withdrawals going through `cashier`.
+pub fn router_with_cashier(store: SharedStore, config: GameConfig, cashier: Cashier) -> Router {
+    build(store, config, Payments(Some(cashier)))
+}
+
+fn build(store: SharedStore, config: GameConfig, payments: Payments) -> Router {
+    let play = Play::new(store.clone(), config.clone());
+    let (api, spec) = api().split_for_parts();
+    api.merge(SwaggerUi::new("/docs").url("/openapi.json", spec))
         .layer(Extension(store))
+        .layer(Extension(payments))
+        .layer(Extension(play.wallet.clone()))
+        .layer(Extension(play))
+        .layer(Extension(Lobby::new()))
+        .layer(Extension(config))
+}
+
+/// The cashier, if the server was given a payment provider.
+#[derive(Clone)]
+struct Payments(Option<Cashier>);
+
+impl Payments {
+    fn cashier(&self) -> Result<&Cashier, ApiError> {
+        self.0.as_ref().ok_or_else(|| {
+            ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "payments_unavailable",
+                "no payment provider is configured",
+            )
+        })
+    }
+}
+
+/// The OpenAPI document served at `/openapi.json`.
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    api().into_openapi()
+}
+
+/// Every API route, each documented by its handler's `utoipa::path`.
+fn api() -> OpenApiRouter {
+    OpenApiRouter::with_openapi(openapi::doc())
+        .routes(routes!(root_health))
+        .routes(routes!(signup_handler))
+        .routes(routes!(signin_handler))
+        .routes(routes!(refresh_handler))
+        .routes(routes!(logout_handler))
+        .routes(routes!(status_handler))
+        .routes(idempotent(routes!(start_handler)))
+        .routes(idempotent(routes!(discard_handler)))
+        .routes(idempotent(routes!(reveal_handler)))
+        .routes(idempotent(routes!(fold_handler)))
+        .routes(routes!(active_rounds_handler))
+        .routes(routes!(transactions_handler))
+        .routes(routes!(paytable_handler))
+        .routes(idempotent(routes!(deposit_handler)))
+        .routes(idempotent(routes!(withdraw_handler)))
+        .routes(routes!(cashier_request_handler))
+        .merge(limits::routes())
+        .merge(admin::routes())
+        .merge(ws::routes())
+        .merge(events::routes())
+}
+
+fn idempotent(route: UtoipaMethodRouter) -> UtoipaMethodRouter {
+    route.map(|route| route.route_layer(middleware::from_fn(idempotency_middleware)))
 }
 
 /// GET /
+#[utoipa::path(get, path = "/", tag = "health", responses(
+    (status = 200, description = "Service is up", body = Object),
+))]
 async fn root_health() -> Json<serde_json::Value> {
     Json(json!({"status":"ok","service":"poker-server","version":"0.1"}))
 }
 
 /// POST /api/signup
+#[utoipa::path(post, path = "/api/signup", tag = "session", request_body = SignUpRequest, responses(
+    (status = 200, body = LoginResponse),
+    (status = 400, description = "Empty password", body = ErrorBody),
+    (status = 409, description = "Name taken", body = ErrorBody),
+))]
 async fn signup_handler(
-    Extension(store): Extension<SharedStore>,
+    Extension(play): Extension<Play>,
     Json(req): Json<SignUpRequest>,
-) -> Result<Json<LoginResponse>, (StatusCode, String)> {
-    let user = store
-        .create_user_if_unique(&req.name, &req.password)
-        .await
-        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
-    Ok(Json(LoginResponse {
-        id: user.id,
-        name: user.name,
-        wallet: user.wallet,
-    }))
+) -> Result<Json<LoginResponse>, ApiError> {
+    play.signup(req).await.map(Json)
 }
 
 /// POST /api/signin
+#[utoipa::path(post, path = "/api/signin", tag = "session", request_body = SignInRequest, responses(
+    (status = 200, body = LoginResponse),
+    (status = 401, description = "Invalid credentials", body = ErrorBody),
+))]
 async fn signin_handler(
-    Extension(store): Extension<SharedStore>,
+    Extension(play): Extension<Play>,
     Json(req): Json<SignInRequest>,
-) -> Result<Json<LoginResponse>, (StatusCode, String)> {
-    let user = store
-        .login_user_if_exists(&req.name, &req.password)
-        .await
-        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
-    Ok(Json(LoginResponse {
-        id: user.id,
-        name: user.name,
-        wallet: user.wallet,
-    }))
+) -> Result<Json<LoginResponse>, ApiError> {
+    play.signin(req).await.map(Json)
 }
 
-/// POST /api/start
-async fn start_handler(
+/// POST /api/token/refresh
+/// Trades a refresh token for a new token pair. Each refresh token works
+/// once; replaying an old one ends the session.
+#[utoipa::path(post, path = "/api/token/refresh", tag = "session", request_body = RefreshRequest, responses(
+    (status = 200, body = AuthTokens),
+    (status = 401, description = "Invalid, expired or reused refresh token", body = ErrorBody),
+))]
+async fn refresh_handler(
     Extension(store): Extension<SharedStore>,
-    Json(req): Json<StartRequest>,
-) -> Result<Json<StartResponse>, (StatusCode, String)> {
-    // ensure user exists
-    let user = store
-        .get_user(&req.user_id)
-        .await
-        .ok_or((StatusCode::BAD_REQUEST, "user not found".to_string()))?;
-
-    if req.ante <= 0 {
-        return Err((StatusCode::BAD_REQUEST, "invalid ante".to_string()));
-    }
-    if req.ante > user.wallet {
-        return Err((StatusCode::BAD_REQUEST, "insufficient wallet".to_string()));
-    }
-
-    // check win_pool capacity: max_multiplier = 50
-    let pools = store.get_pools().await;
-    let max_possible = req.ante * 50;
-    if pools.win_pool < max_possible {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            format!(
-                "win pool too small, max ante allowed {}",
-                pools.win_pool / 50
-            ),
-        ));
-    }
-
-    // deduct wallet (short critical section)
-    store
-        .update_user_wallet(&req.user_id, user.wallet - req.ante)
-        .await
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "wallet update failed".to_string(),
-            )
-        })?;
-
-    // deal 5 cards (pure)
-    let mut deck = game::new_deck();
-    let hand = game::deal_hand(&mut deck, 5);
+    Extension(config): Extension<GameConfig>,
+    Json(req): Json<RefreshRequest>,
+) -> Result<Json<AuthTokens>, ApiError> {
+    let now = chrono::Utc::now();
+    let claims = auth::verify(
+        &config.token_secret,
+        &req.refresh_token,
+        TokenKind::Refresh,
+        now,
+    )?;
+    let session = store
+        .refresh_session(
+            &claims.sid,
+            claims.gen,
+            now + chrono::Duration::seconds(config.refresh_token_ttl_secs),
+        )
+        .await?;
+    Ok(Json(auth::issue(&config, &session, now)))
+}
 
-    // create round
-    let round_id = store
-        .create_round(req.user_id.clone(), req.ante, hand.clone())
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+/// POST /api/logout
+/// Revokes the caller's session; its access and refresh tokens stop working.
+#[utoipa::path(post, path = "/api/logout", tag = "session", security(("bearer" = [])), responses(
+    (status = 204, description = "Session revoked"),
+    (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+))]
+async fn logout_handler(
+    Extension(store): Extension<SharedStore>,
+    user: AuthUser,
+) -> Result<StatusCode, ApiError> {
+    store.revoke_session(&user.session_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    let current_user = store.get_user(&req.user_id).await.unwrap();
-    let pools_after = store.get_pools().await;
-    Ok(Json(StartResponse {
-        round_id,
-        cards: hand,
-        wallet: current_user.wallet,
-        win_pool: pools_after.win_pool,
-    }))
+/// POST /api/start
+#[utoipa::path(post, path = "/api/start", tag = "game", security(("bearer" = [])),
+    params(("Idempotency-Key" = Option<String>, Header, description = "Replays the first response for a retry with the same key and body")),
+    request_body = StartRequest,
+    responses(
+        (status = 200, body = StartResponse),
+        (status = 400, description = "Invalid ante or currency", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 402, description = "Insufficient funds or declined by the wallet", body = ErrorBody),
+        (status = 403, description = "Account frozen or a limit reached", body = ErrorBody),
+        (status = 409, description = "Too many active rounds, or a request with this idempotency key is still running", body = ErrorBody),
+        (status = 422, description = "Idempotency key reused for a different request", body = ErrorBody),
+        (status = 503, description = "Win pool can't cover the ante's largest payout", body = ErrorBody),
+    )
+)]
+async fn start_handler(
+    Extension(play): Extension<Play>,
+    user: AuthUser,
+    Json(req): Json<StartRequest>,
+) -> Result<Json<StartResponse>, ApiError> {
+    play.start(&user.user_id, req).await.map(Json)
 }
 
 /// POST /api/discard
+#[utoipa::path(post, path = "/api/discard", tag = "game", security(("bearer" = [])),
+    params(("Idempotency-Key" = Option<String>, Header, description = "Replays the first response for a retry with the same key and body")),
+    request_body = DiscardRequest,
+    responses(
+        (status = 200, body = DiscardResponse),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 402, description = "Insufficient funds for the fee", body = ErrorBody),
+        (status = 403, description = "Not the caller's round, or a limit reached", body = ErrorBody),
+        (status = 404, description = "No such round", body = ErrorBody),
+        (status = 409, description = "Round no longer active, or a request with this idempotency key is still running", body = ErrorBody),
+        (status = 422, description = "Idempotency key reused for a different request", body = ErrorBody),
+    )
+)]
 async fn discard_handler(
-    Extension(store): Extension<SharedStore>,
+    Extension(play): Extension<Play>,
+    user: AuthUser,
     Json(req): Json<DiscardRequest>,
-) -> Result<Json<DiscardResponse>, (StatusCode, String)> {
-    // basic validations
-    let round = store
-        .get_round(&req.round_id)
-        .await
-        .ok_or((StatusCode::BAD_REQUEST, "round not found".to_string()))?;
-    if round.user_id != req.user_id {
-        return Err((StatusCode::BAD_REQUEST, "user mismatch".to_string()));
-    }
-    if round.status != RoundStatus::Active {
-        return Err((StatusCode::BAD_REQUEST, "round not active".to_string()));
-    }
+) -> Result<Json<DiscardResponse>, ApiError> {
+    play.discard(&user.user_id, req).await.map(Json)
+}
 
-    // cost: 50% ante per card
-    let discard_count = req.discard_indices.len();
-    let cost = ((round.ante as f64) * 0.5 * (discard_count as f64)) as i64;
+/// POST /api/reveal
+#[utoipa::path(post, path = "/api/reveal", tag = "game", security(("bearer" = [])),
+    params(("Idempotency-Key" = Option<String>, Header, description = "Replays the first response for a retry with the same key and body")),
+    request_body = RevealRequest,
+    responses(
+        (status = 200, body = RevealResponse),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 403, description = "Not the caller's round", body = ErrorBody),
+        (status = 404, description = "No such round", body = ErrorBody),
+        (status = 409, description = "Round no longer active, or a request with this idempotency key is still running", body = ErrorBody),
+        (status = 502, description = "Wallet unreachable; the credit is replayed by id", body = ErrorBody),
+        (status = 503, description = "Win pool short; the ante was refunded", body = ErrorBody),
+        (status = 422, description = "Idempotency key reused for a different request", body = ErrorBody),
+    )
+)]
+async fn reveal_handler(
+    Extension(play): Extension<Play>,
+    user: AuthUser,
+    Json(req): Json<RevealRequest>,
+) -> Result<Json<RevealResponse>, ApiError> {
+    play.reveal(&user.user_id, req).await.map(Json)
+}
 
-    let user = store
-        .get_user(&req.user_id)
-        .await
-        .ok_or((StatusCode::BAD_REQUEST, "user not found".to_string()))?;
-    if user.wallet < cost {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            "insufficient wallet for discard".to_string(),
-        ));
-    }
+/// POST /api/fold
+/// Gives up an active round; the ante is forfeited to the pools.
+#[utoipa::path(post, path = "/api/fold", tag = "game", security(("bearer" = [])),
+    params(("Idempotency-Key" = Option<String>, Header, description = "Replays the first response for a retry with the same key and body")),
+    request_body = FoldRequest,
+    responses(
+        (status = 200, body = FoldResponse),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 403, description = "Not the caller's round", body = ErrorBody),
+        (status = 404, description = "No such round", body = ErrorBody),
+        (status = 409, description = "Round no longer active, or a request with this idempotency key is still running", body = ErrorBody),
+        (status = 422, description = "Idempotency key reused for a different request", body = ErrorBody),
+    )
+)]
+async fn fold_handler(
+    Extension(play): Extension<Play>,
+    user: AuthUser,
+    Json(req): Json<FoldRequest>,
+) -> Result<Json<FoldResponse>, ApiError> {
+    play.fold(&user.user_id, req).await.map(Json)
+}
 
-    // deduct
-    store
-        .update_user_wallet(&req.user_id, user.wallet - cost)
-        .await
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "wallet update failed".to_string(),
-            )
-        })?;
+/// GET /api/status/{user_id}?currency=...
+#[utoipa::path(get, path = "/api/status/{user_id}", tag = "player", security(("bearer" = [])),
+    params(("user_id" = String, Path), CurrencyQuery),
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 403, description = "The id is not the caller's", body = ErrorBody),
+    )
+)]
+async fn status_handler(
+    Extension(play): Extension<Play>,
+    Owner(auth): Owner,
+    Query(q): Query<CurrencyQuery>,
+) -> Result<Json<StatusResponse>, ApiError> {
+    play.status(&auth.user_id, q.currency).await.map(Json)
+}
 
-    // replace cards
-    let mut deck = game::new_deck();
-    // remove existing cards from deck (naive approach - just shuffle and deal new ones)
-    let mut newcards = round.cards.clone();
-    // replace indices with new cards
-    let dealt = game::deal_hand(&mut deck, discard_count);
-    for (i, idx) in req.discard_indices.iter().enumerate() {
-        if *idx < newcards.len() {
-            newcards[*idx] = dealt[i].clone();
-        }
+/// GET /api/users/{user_id}/active-rounds
+/// Lets a client that lost its `round_id`s pick its unfinished rounds back up.
+#[utoipa::path(get, path = "/api/users/{user_id}/active-rounds", tag = "game", security(("bearer" = [])),
+    params(("user_id" = String, Path)),
+    responses(
+        (status = 200, body = ActiveRoundsResponse),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 403, description = "The id is not the caller's", body = ErrorBody),
+    )
+)]
+async fn active_rounds_handler(
+    Extension(store): Extension<SharedStore>,
+    Owner(user): Owner,
+) -> Json<ActiveRoundsResponse> {
+    let mut rounds = Vec::new();
+    for round in store.get_active_rounds(&user.user_id).await {
+        let total_bet = ledger::staked(&store.round_ledger(&round.id).await);
+        rounds.push(ActiveRound {
+            round_id: round.id,
+            currency: round.currency,
+            cards: round.cards,
+            status: round.status,
+            draws_used: round.draws_used,
+            ante: round.ante,
+            total_bet,
+            created_at: round.created_at,
+        });
     }
+    Json(ActiveRoundsResponse { rounds })
+}
 
-    store
-        .update_round_cards(&req.round_id, newcards.clone())
-        .await
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "failed update round".to_string(),
-            )
-        })?;
-
-    // compute total bet (ante + raise) - here raise 0
-    let total_bet = round.ante;
-
-    let new_user = store.get_user(&req.user_id).await.unwrap();
-    Ok(Json(DiscardResponse {
-        cards: newcards,
-        wallet: new_user.wallet,
-        total_bet,
-    }))
+/// GET /api/paytable
+/// Multipliers new rounds are paid by.
+#[utoipa::path(get, path = "/api/paytable", tag = "game", responses(
+    (status = 200, body = Paytable),
+))]
+async fn paytable_handler(Extension(store): Extension<SharedStore>) -> Json<Paytable> {
+    Json(store.get_paytable().await)
 }
 
-/// POST /api/reveal
-async fn reveal_handler(
+/// GET /api/users/{user_id}/transactions?currency=&type=&from=&to=&cursor=&limit=
+#[utoipa::path(get, path = "/api/users/{user_id}/transactions", tag = "player", security(("bearer" = [])),
+    params(("user_id" = String, Path), TransactionsQuery),
+    responses(
+        (status = 200, body = TransactionsResponse),
+        (status = 400, description = "Invalid cursor", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 403, description = "The id is not the caller's", body = ErrorBody),
+    )
+)]
+async fn transactions_handler(
     Extension(store): Extension<SharedStore>,
-    Json(req): Json<RevealRequest>,
-) -> Result<Json<RevealResponse>, (StatusCode, String)> {
-    let round = store
-        .get_round(&req.round_id)
-        .await
-        .ok_or((StatusCode::BAD_REQUEST, "round not found".to_string()))?;
-    if round.user_id != req.user_id {
-        return Err((StatusCode::BAD_REQUEST, "user mismatch".to_string()));
-    }
-    if round.status != RoundStatus::Active {
-        return Err((StatusCode::BAD_REQUEST, "round not active".to_string()));
-    }
+    Owner(user): Owner,
+    Query(q): Query<TransactionsQuery>,
+) -> Result<Json<TransactionsResponse>, ApiError> {
+    let before = match q.cursor {
+        Some(c) => Some(
+            c.parse::<u64>()
+                .map_err(|_| ApiError::invalid("invalid cursor"))?,
+        ),
+        None => None,
+    };
+    let limit = q.limit.unwrap_or(50).clamp(1, 200);
 
-    let total_bet = round.ante;
-    let hr = game::evaluate_hand(&round.cards);
-    let mult = game::payout_multiplier(&hr);
-    let payout = (total_bet as i64) * (mult as i64);
-
-    if mult == 0 {
-        // losing: split 25% house, 75% win_pool
-        let house = (total_bet * 25) / 100;
-        let win = total_bet - house;
-        store.add_to_pools(win, house).await;
-        store
-            .set_round_status(&req.round_id, RoundStatus::Revealed)
-            .await
-            .ok();
-        let user = store.get_user(&req.user_id).await.unwrap();
-        let pools = store.get_pools().await;
-        return Ok(Json(RevealResponse {
-            wallet: user.wallet,
-            win_pool: pools.win_pool,
-            house_profit: pools.house_profit,
-            hand_rank: format!("{:?}", hr),
-            multiplier: mult,
-            payout: 0,
-        }));
-    }
+    // fetch one extra row to know whether another page exists
+    let mut items = store
+        .list_transactions(
+            &user.user_id,
+            TransactionFilter {
+                currency: q.currency,
+                kind: q.kind,
+                from: q.from,
+                to: q.to,
+                before,
+                limit: limit + 1,
+            },
+        )
+        .await;
+    let next_cursor = if items.len() > limit {
+        items.truncate(limit);
+        items.last().map(|t| t.seq.to_string())
+    } else {
+        None
+    };
 
-    // winning hand: check pool
-    let pools = store.get_pools().await;
-    if pools.win_pool < payout {
-        // refund total_bet to user
-        let user = store.get_user(&req.user_id).await.unwrap();
-        let _ = store
-            .update_user_wallet(&req.user_id, user.wallet + total_bet)
-            .await;
-        store
-            .set_round_status(&req.round_id, RoundStatus::Revealed)
-            .await
-            .ok();
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "win_pool short, refunded".to_string(),
-        ));
-    }
+    Ok(Json(TransactionsResponse { items, next_cursor }))
+}
 
-    // pay out
-    store
-        .sub_from_win_pool(payout)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
-    let user = store.get_user(&req.user_id).await.unwrap();
-    let _ = store
-        .update_user_wallet(&req.user_id, user.wallet + payout)
-        .await;
-    store
-        .set_round_status(&req.round_id, RoundStatus::Revealed)
+/// POST /api/cashier/deposit
+#[utoipa::path(post, path = "/api/cashier/deposit", tag = "cashier", security(("bearer" = [])),
+    params(("Idempotency-Key" = Option<String>, Header, description = "Replays the first response for a retry with the same key and body")),
+    request_body = CashierAmountRequest,
+    responses(
+        (status = 200, body = CashierRequest),
+        (status = 400, description = "Invalid amount", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 409, description = "A request with this idempotency key is still running", body = ErrorBody),
+        (status = 422, description = "Idempotency key reused for a different request", body = ErrorBody),
+        (status = 503, description = "No payment provider is configured", body = ErrorBody),
+    )
+)]
+async fn deposit_handler(
+    Extension(payments): Extension<Payments>,
+    Extension(config): Extension<GameConfig>,
+    user: AuthUser,
+    Json(req): Json<CashierAmountRequest>,
+) -> Result<Json<CashierRequest>, ApiError> {
+    let currency = req.currency.unwrap_or(config.default_currency);
+    payments
+        .cashier()?
+        .deposit(&user.user_id, currency, req.amount)
         .await
-        .ok();
-    let pools_after = store.get_pools().await;
-    let new_user = store.get_user(&req.user_id).await.unwrap();
+        .map(Json)
+        .map_err(ApiError::from)
+}
 
-    Ok(Json(RevealResponse {
-        wallet: new_user.wallet,
-        win_pool: pools_after.win_pool,
-        house_profit: pools_after.house_profit,
-        hand_rank: format!("{:?}", hr),
-        multiplier: mult,
-        payout,
-    }))
+/// POST /api/cashier/withdraw
+#[utoipa::path(post, path = "/api/cashier/withdraw", tag = "cashier", security(("bearer" = [])),
+    params(("Idempotency-Key" = Option<String>, Header, description = "Replays the first response for a retry with the same key and body")),
+    request_body = CashierAmountRequest,
+    responses(
+        (status = 200, body = CashierRequest),
+        (status = 400, description = "Invalid amount", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 402, description = "Insufficient funds", body = ErrorBody),
+        (status = 409, description = "A request with this idempotency key is still running", body = ErrorBody),
+        (status = 422, description = "Idempotency key reused for a different request", body = ErrorBody),
+        (status = 503, description = "No payment provider is configured", body = ErrorBody),
+    )
+)]
+async fn withdraw_handler(
+    Extension(payments): Extension<Payments>,
+    Extension(config): Extension<GameConfig>,
+    user: AuthUser,
+    Json(req): Json<CashierAmountRequest>,
+) -> Result<Json<CashierRequest>, ApiError> {
+    let currency = req.currency.unwrap_or(config.default_currency);
+    payments
+        .cashier()?
+        .withdraw(&user.user_id, currency, req.amount)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
 }
 
-/// GET /api/status?user_id=...
-async fn status_handler(
+/// GET /api/cashier/requests/{id}
+/// Other users' requests look the same as missing ones.
+#[utoipa::path(get, path = "/api/cashier/requests/{id}", tag = "cashier", security(("bearer" = [])),
+    params(("id" = String, Path, description = "Cashier request id")),
+    responses(
+        (status = 200, body = CashierRequest),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+        (status = 404, description = "No such request of the caller's", body = ErrorBody),
+    )
+)]
+async fn cashier_request_handler(
     Extension(store): Extension<SharedStore>,
-    Path(user_id): Path<String>,
-) -> Result<Json<StatusResponse>, (StatusCode, String)> {
-    let user = store
-        .get_user(&user_id)
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<CashierRequest>, ApiError> {
+    store
+        .get_cashier_request(&id)
         .await
-        .ok_or((StatusCode::BAD_REQUEST, "user not found".to_string()))?;
-
-    // optional redundant check removed because query already carries the user_id:
-    // if user.id != req.user_id { ... }
-
-    let pools = store.get_pools().await;
-
-    Ok(Json(StatusResponse {
-        wallet: user.wallet,
-        win_pool: pools.win_pool,
-        house_profit: pools.house_profit,
-    }))
+        .filter(|r| r.user_id == user.user_id)
+        .map(Json)
+        .ok_or_else(|| StoreError::CashierRequestNotFound.into())
 }
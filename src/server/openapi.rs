@@ -0,0 +1,49 @@
+use crate::models::ErrorBody;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/// Top of the OpenAPI document. Operations are added by `super::api` as it
+/// registers each annotated handler, so a route can't be served without
+/// being documented.
+pub(super) fn doc() -> utoipa::openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    // the package has no license to name
+    doc.info.license = None;
+    doc
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "poker-server",
+        description = "Five-card draw poker slot with per-currency wallets and pools. \
+                       Amounts are integers in minor units."
+    ),
+    modifiers(&BearerAuth),
+    components(schemas(ErrorBody)),
+    tags(
+        (name = "health"),
+        (name = "session", description = "Accounts and tokens"),
+        (name = "game", description = "Playing rounds"),
+        (name = "player", description = "The caller's wallet and history"),
+        (name = "limits", description = "Responsible-gaming limits"),
+        (name = "cashier", description = "Deposits and withdrawals"),
+        (name = "lobby", description = "Public live feed of pools, big wins and notices"),
+        (name = "admin", description = "Operator endpoints, each guarded by a permission"),
+    )
+)]
+struct ApiDoc;
+
+/// `bearer`: an access token from signup, signin or refresh, or
+/// `ADMIN_TOKEN` on the operator routes.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
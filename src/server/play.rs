@@ -0,0 +1,395 @@
+//! The game actions and sign-in, whichever transport asks for them. HTTP
+//! handlers, the WebSocket and gRPC all call these; the game actions take
+//! an already authenticated user.
+
+use super::ApiError;
+use crate::auth::{
+    self,
+    password::{self, PasswordCheck},
+};
+use crate::config::{GameConfig, PoolSource};
+use crate::game::{self, GameError};
+use crate::models::{
+    Currency, DiscardOp, DiscardRequest, DiscardResponse, FoldRequest, FoldResponse, LoginResponse,
+    NewRound, PoolShare, RevealRequest, RevealResponse, RoundSnapshot, RoundStatus, Settlement,
+    SignInRequest, SignUpRequest, StartRequest, StartResponse, StatusResponse, User, WalletTx,
+};
+use crate::money::Money;
+use crate::store::{SharedStore, StoreError};
+use crate::wallet::{self, SharedWallet};
+use axum::http::StatusCode;
+use serde_json::json;
+use std::sync::LazyLock;
+use uuid::Uuid;
+
+/// Everything a game action needs. Built once by the router and handed to
+/// handlers as an extension.
+#[derive(Clone)]
+pub struct Play {
+    pub store: SharedStore,
+    pub config: GameConfig,
+    pub wallet: SharedWallet,
+}
+
+impl Play {
+    pub fn new(store: SharedStore, config: GameConfig) -> Self {
+        let wallet = wallet::from_config(&config, store.clone());
+        Play {
+            store,
+            config,
+            wallet,
+        }
+    }
+
+    /// Creates an account and signs it in.
+    pub async fn signup(&self, req: SignUpRequest) -> Result<LoginResponse, ApiError> {
+        if req.password.is_empty() {
+            return Err(ApiError::invalid("password must not be empty"));
+        }
+        if self.store.find_user_by_name(&req.name).await.is_some() {
+            return Err(StoreError::NameTaken.into());
+        }
+        let hash = blocking(move || password::hash(&req.password)).await?;
+        let user = self.store.create_user_if_unique(&req.name, &hash).await?;
+        self.login(user).await
+    }
+
+    /// Checks a name and password and opens a session for them.
+    pub async fn signin(&self, req: SignInRequest) -> Result<LoginResponse, ApiError> {
+        let store = &self.store;
+        let user = store.find_user_by_name(&req.name).await;
+        // Unknown names still pay for a hash check so timing doesn't reveal
+        // which accounts exist.
+        let stored = user
+            .as_ref()
+            .map_or_else(|| UNKNOWN_USER_HASH.clone(), |u| u.password_hash.clone());
+        let pw = req.password.clone();
+        let check = blocking(move || Ok(password::verify(&pw, &stored))).await?;
+        let user = match (user, check) {
+            (Some(user), PasswordCheck::Valid | PasswordCheck::ValidNeedsRehash) => user,
+            _ => {
+                return Err(ApiError::unauthorized(
+                    "invalid_credentials",
+                    "invalid credentials",
+                ))
+            }
+        };
+
+        if check == PasswordCheck::ValidNeedsRehash {
+            let hash = blocking(move || password::hash(&req.password)).await?;
+            store.set_password_hash(&user.id, &hash).await?;
+        }
+        self.login(user).await
+    }
+
+    /// Opens a new session for `user` and reports its wallets along with
+    /// the session's tokens.
+    async fn login(&self, user: User) -> Result<LoginResponse, ApiError> {
+        let config = &self.config;
+        let now = chrono::Utc::now();
+        let session = self
+            .store
+            .create_session(
+                &user.id,
+                now + chrono::Duration::seconds(config.refresh_token_ttl_secs),
+            )
+            .await?;
+        let currency = config.default_currency;
+        Ok(LoginResponse {
+            wallet: user.balance(currency),
+            id: user.id,
+            name: user.name,
+            currency,
+            wallets: user.wallets,
+            tokens: auth::issue(config, &session, now),
+        })
+    }
+
+    /// Deals a new round and debits its ante.
+    pub async fn start(&self, user_id: &str, req: StartRequest) -> Result<StartResponse, ApiError> {
+        let Play {
+            store,
+            config,
+            wallet,
+        } = self;
+        if !req.ante.is_positive() {
+            return Err(GameError::InvalidAnte.into());
+        }
+        let currency = req.currency.unwrap_or(config.default_currency);
+        config
+            .ante_limits_for(currency)
+            .and_then(|limits| limits.check(req.ante))?;
+        // the store re-checks under its lock; this just gives a clearer status
+        if store.get_user(user_id).await.is_some_and(|u| u.frozen) {
+            return Err(GameError::AccountFrozen.into());
+        }
+
+        // deal 5 cards (pure)
+        let mut deck = game::new_deck();
+        let hand = game::deal_hand(&mut deck, game::HAND_SIZE);
+
+        // wallet, active-round cap and pool capacity are checked and the ante
+        // debited in one store step, so parallel starts can't all slip through
+        let started = store
+            .start_round(NewRound {
+                user_id: user_id.to_string(),
+                currency,
+                ante: req.ante,
+                cards: hand,
+                max_active_rounds: config.max_active_rounds_per_user,
+                paytable: store.get_paytable().await,
+                remote_wallet: wallet.is_remote(),
+            })
+            .await?;
+        let balance =
+            wallet::debit_or_void(store, wallet.as_ref(), &WalletTx::ante(&started.round)).await?;
+
+        Ok(StartResponse {
+            round_id: started.round.id,
+            currency,
+            cards: started.round.cards,
+            wallet: balance,
+            win_pool: started.pools.win_pool,
+        })
+    }
+
+    /// Replaces the chosen cards for a fee of half the ante each.
+    pub async fn discard(
+        &self,
+        user_id: &str,
+        req: DiscardRequest,
+    ) -> Result<DiscardResponse, ApiError> {
+        let Play {
+            store,
+            config,
+            wallet,
+        } = self;
+        game::check_discard(&req.discard_indices).map_err(ApiError::invalid)?;
+        // ante is fixed for the life of a round, so pricing off a snapshot is safe
+        let round = store
+            .get_round(&req.round_id)
+            .await
+            .ok_or(StoreError::RoundNotFound)?;
+
+        // cost: 50% ante per card, rounded once over the whole discard
+        let discard_count = req.discard_indices.len();
+        let fee = round
+            .ante
+            .mul_ratio(discard_count as i64, 2, config.discard_fee_rounding)
+            .and_then(|cost| {
+                config
+                    .pool_policy
+                    .share(PoolSource::DiscardFee, round.currency, cost)
+            })?;
+
+        // replace cards
+        let mut deck = game::new_deck();
+        // naive approach - just shuffle and deal new ones
+        let dealt = game::deal_hand(&mut deck, discard_count);
+        let replacements = req.discard_indices.iter().copied().zip(dealt).collect();
+
+        let fee_total = fee.total()?;
+        let discarded = store
+            .apply_discard(DiscardOp {
+                user_id: user_id.to_string(),
+                round_id: req.round_id,
+                fee,
+                replacements,
+            })
+            .await?;
+        let fee_tx = WalletTx::discard_fee(&discarded.round, fee_total);
+        let balance = wallet::debit_or_void(store, wallet.as_ref(), &fee_tx).await?;
+
+        // compute total bet (ante + raise) - here raise 0
+        let total_bet = discarded.round.ante;
+
+        Ok(DiscardResponse {
+            currency: discarded.round.currency,
+            cards: discarded.round.cards,
+            wallet: balance,
+            total_bet,
+        })
+    }
+
+    /// Scores the hand and pays out, or splits a losing ante between the
+    /// pools.
+    pub async fn reveal(
+        &self,
+        user_id: &str,
+        req: RevealRequest,
+    ) -> Result<RevealResponse, ApiError> {
+        let Play {
+            store,
+            config,
+            wallet,
+        } = self;
+        let round = store
+            .get_round(&req.round_id)
+            .await
+            .ok_or(StoreError::RoundNotFound)?;
+        if round.user_id != user_id {
+            return Err(GameError::NotYourRound.into());
+        }
+        if round.status != RoundStatus::Active {
+            return Err(GameError::RoundNotActive.into());
+        }
+
+        let total_bet = round.ante;
+        let hr = game::evaluate_hand(&round.cards);
+        let mult = round.paytable.multiplier(&hr);
+        let payout = total_bet.try_mul(i64::from(mult))?;
+
+        // losing: the ante is split between the pools per policy
+        let share = if mult == 0 {
+            config
+                .pool_policy
+                .share(PoolSource::LosingAnte, round.currency, total_bet)?
+        } else {
+            PoolShare::default()
+        };
+
+        let settled = store
+            .settle_round(Settlement {
+                user_id: user_id.to_string(),
+                round_id: req.round_id,
+                expected_draws: round.draws_used,
+                payout,
+                share,
+                folded: false,
+            })
+            .await?;
+        let credited = if settled.refunded { round.ante } else { payout };
+        // the round is settled, so failures from here on are the outcome
+        let balance = credit_settlement(store, wallet.as_ref(), &settled, credited)
+            .await
+            .map_err(ApiError::committed)?;
+
+        // the round is over either way; the client gets its ante back and the
+        // balance that leaves
+        if settled.refunded {
+            return Err(ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "pool_exhausted",
+                "win pool short, ante refunded",
+            )
+            .with_details(json!({ "refunded": round.ante, "wallet": balance }))
+            .committed());
+        }
+
+        Ok(RevealResponse {
+            currency: round.currency,
+            wallet: balance,
+            win_pool: settled.pools.win_pool,
+            house_profit: settled.pools.house_profit,
+            hand_rank: format!("{:?}", hr),
+            multiplier: mult,
+            payout,
+        })
+    }
+
+    /// Gives up an active round; the ante is forfeited to the pools.
+    pub async fn fold(&self, user_id: &str, req: FoldRequest) -> Result<FoldResponse, ApiError> {
+        let Play {
+            store,
+            config,
+            wallet,
+        } = self;
+        let round = store
+            .get_round(&req.round_id)
+            .await
+            .ok_or(StoreError::RoundNotFound)?;
+
+        let share = config
+            .pool_policy
+            .share(PoolSource::Fold, round.currency, round.ante)?;
+
+        let folded = store
+            .settle_round(Settlement {
+                user_id: user_id.to_string(),
+                round_id: req.round_id,
+                expected_draws: round.draws_used,
+                payout: Money::ZERO,
+                share,
+                folded: true,
+            })
+            .await?;
+
+        Ok(FoldResponse {
+            currency: round.currency,
+            wallet: wallet
+                .balance(&folded.round.user_id, folded.round.currency)
+                .await
+                .map_err(|e| ApiError::from(e).committed())?,
+            win_pool: folded.pools.win_pool,
+            house_profit: folded.pools.house_profit,
+        })
+    }
+
+    /// The caller's wallet and the pools in `currency`, or the default one.
+    pub async fn status(
+        &self,
+        user_id: &str,
+        currency: Option<Currency>,
+    ) -> Result<StatusResponse, ApiError> {
+        let currency = currency.unwrap_or(self.config.default_currency);
+        let balance = self.wallet.balance(user_id, currency).await?;
+
+        let pools = self.store.get_pools(currency).await;
+
+        Ok(StatusResponse {
+            currency,
+            wallet: balance,
+            win_pool: pools.win_pool,
+            win_pool_reserved: pools.reserved,
+            win_pool_available: pools.available(),
+            house_profit: pools.house_profit,
+        })
+    }
+}
+
+/// Pays out a settled round through the wallet. The store has already
+/// booked it and kept the credit as pending, so a wallet that stays
+/// unreachable is reported as 502 and the credit is sent again later by
+/// `wallet::redeliver_credits`.
+async fn credit_settlement(
+    store: &SharedStore,
+    wallet: &dyn wallet::WalletProvider,
+    settled: &RoundSnapshot,
+    amount: Money,
+) -> Result<Money, ApiError> {
+    let round = &settled.round;
+    if !amount.is_positive() {
+        return Ok(wallet.balance(&round.user_id, round.currency).await?);
+    }
+    let tx = WalletTx::settlement(round, amount, settled.refunded);
+    let balance = wallet.credit(&tx).await.map_err(|e| {
+        tracing::error!(
+            "wallet credit {} failed, will retry: {}",
+            tx.transaction_id,
+            e
+        );
+        ApiError::from(e)
+    })?;
+    if let Err(e) = store.wallet_credit_delivered(&tx.transaction_id).await {
+        // sent again later; the wallet applies each id once
+        tracing::error!("wallet credit {} left pending: {}", tx.transaction_id, e);
+    }
+    Ok(balance)
+}
+
+/// Hash of a random password, checked against when the sign-in name is
+/// unknown.
+static UNKNOWN_USER_HASH: LazyLock<String> = LazyLock::new(|| {
+    password::hash(&Uuid::new_v4().to_string()).expect("hashing a fixed-size password")
+});
+
+/// Runs password hashing off the async workers.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, ApiError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r)
+        .map_err(ApiError::internal)
+}
@@ -0,0 +1,330 @@
+//! `/ws`: the game over one WebSocket. Clients send JSON requests tagged by
+//! `type` with an optional `id`; each gets a `result` or `error` back with
+//! the same `id`. Wallet and pool changes come from `Store::subscribe` and
+//! are pushed at most once per `ws_update_interval_ms`; a remote wallet's
+//! balance is re-read after each of the connection's own actions. The
+//! server pings every `ws_heartbeat_secs`.
+
+use super::play::Play;
+use super::ApiError;
+use crate::auth::{self, AuthUser};
+use crate::models::{
+    Currency, ErrorBody, Pools, StoreNotice, WsAction, WsMessage, WsQuery, WsRequest,
+};
+use crate::money::Money;
+use axum::{
+    extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+    extract::{Extension, Query},
+    http::{header, HeaderMap},
+    response::Response,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{interval, Instant, MissedTickBehavior};
+use tracing::debug;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+pub(super) fn routes() -> OpenApiRouter {
+    OpenApiRouter::new().routes(routes!(ws_handler))
+}
+
+/// GET /ws
+/// Upgrades to the WebSocket game protocol. The access token is checked
+/// once here; the session is re-checked before every request, so logging
+/// out elsewhere ends the connection.
+#[utoipa::path(get, path = "/ws", tag = "game",
+    params(WsQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol"),
+        (status = 401, description = "Missing, invalid or expired access token", body = ErrorBody),
+    )
+)]
+async fn ws_handler(
+    Extension(play): Extension<Play>,
+    Query(q): Query<WsQuery>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_string)
+        .or(q.token)
+        .ok_or_else(|| ApiError::unauthorized("missing_token", "missing bearer token"))?;
+    let user = auth::authenticate(&play.store, &play.config, token.trim()).await?;
+    Ok(upgrade.on_upgrade(move |socket| Connection::new(play, user).run(socket)))
+}
+
+/// Wallet and pools of one currency, as last pushed or as noticed since.
+#[derive(Default)]
+struct Numbers {
+    wallet: Option<Money>,
+    pools: Option<Pools>,
+}
+
+struct Connection {
+    play: Play,
+    user: AuthUser,
+    /// Currencies the client has played or asked about, with what it was
+    /// last sent.
+    watched: BTreeMap<Currency, Numbers>,
+    /// Changes to watched currencies not pushed yet.
+    pending: BTreeMap<Currency, Numbers>,
+}
+
+impl Connection {
+    fn new(play: Play, user: AuthUser) -> Self {
+        Connection {
+            play,
+            user,
+            watched: BTreeMap::new(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    async fn run(mut self, mut socket: WebSocket) {
+        let heartbeat = Duration::from_secs(self.play.config.ws_heartbeat_secs);
+        let mut pings = interval(heartbeat);
+        pings.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // the first tick fires straight away
+        pings.tick().await;
+        let mut flush = interval(Duration::from_millis(
+            self.play.config.ws_update_interval_ms,
+        ));
+        // after a quiet spell the first change goes out straight away
+        flush.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut last_heard = Instant::now();
+        // subscribe before reading, so no change falls in between
+        let mut changes = self.play.store.subscribe();
+
+        let ready = WsMessage::Ready {
+            user_id: self.user.user_id.clone(),
+            heartbeat_secs: heartbeat.as_secs(),
+        };
+        let default = self.play.config.default_currency;
+        if send(&mut socket, &ready).await.is_err() || !self.catch_up(&mut socket, default).await {
+            return;
+        }
+
+        loop {
+            let open = tokio::select! {
+                msg = socket.recv() => {
+                    let Some(Ok(msg)) = msg else { break };
+                    last_heard = Instant::now();
+                    match msg {
+                        Message::Text(text) => self.request(&mut socket, text.as_str()).await,
+                        Message::Binary(_) => {
+                            let error = ApiError::invalid("messages must be JSON text").into_body();
+                            let reply = WsMessage::Error { id: None, error };
+                            send(&mut socket, &reply).await.is_ok()
+                        }
+                        Message::Close(_) => false,
+                        // pings are answered by axum; pongs only count as a sign of life
+                        Message::Ping(_) | Message::Pong(_) => true,
+                    }
+                }
+                _ = pings.tick() => {
+                    if last_heard.elapsed() > heartbeat * 2 {
+                        debug!(user_id = self.user.user_id, "websocket client went quiet");
+                        close(&mut socket, close_code::AWAY, "heartbeat timeout").await;
+                        false
+                    } else {
+                        self.session_active(&mut socket).await
+                            && socket.send(Message::Ping(Default::default())).await.is_ok()
+                    }
+                }
+                change = changes.recv() => self.notice(change).await,
+                _ = flush.tick(), if !self.pending.is_empty() => {
+                    let mut open = true;
+                    for (currency, numbers) in std::mem::take(&mut self.pending) {
+                        open = open && self.push(&mut socket, currency, numbers).await;
+                    }
+                    open
+                }
+            };
+            if !open {
+                break;
+            }
+        }
+    }
+
+    /// Answers one client message; `false` once the connection should end.
+    async fn request(&mut self, socket: &mut WebSocket, text: &str) -> bool {
+        if !self.session_active(socket).await {
+            return false;
+        }
+        let (reply, currency) = match serde_json::from_str::<WsRequest>(text) {
+            Ok(req) => self.act(req).await,
+            Err(e) => {
+                let error = ApiError::invalid(e.to_string()).into_body();
+                (WsMessage::Error { id: None, error }, None)
+            }
+        };
+        if send(socket, &reply).await.is_err() {
+            return false;
+        }
+        match currency {
+            Some(currency) => self.catch_up(socket, currency).await,
+            None => true,
+        }
+    }
+
+    /// Runs one request; the reply comes with the currency it played in.
+    async fn act(&self, req: WsRequest) -> (WsMessage, Option<Currency>) {
+        let play = &self.play;
+        let user_id = self.user.user_id.as_str();
+        let result = match req.action {
+            WsAction::Start(r) => reply(play.start(user_id, r).await, |r| r.currency),
+            WsAction::Discard(r) => reply(play.discard(user_id, r).await, |r| r.currency),
+            WsAction::Reveal(r) => reply(play.reveal(user_id, r).await, |r| r.currency),
+            WsAction::Fold(r) => reply(play.fold(user_id, r).await, |r| r.currency),
+            WsAction::Status(q) => reply(play.status(user_id, q.currency).await, |r| r.currency),
+            WsAction::Ping => return (WsMessage::Pong { id: req.id }, None),
+        };
+        match result {
+            Ok((currency, result)) => (WsMessage::Result { id: req.id, result }, Some(currency)),
+            Err(e) => {
+                let error = e.into_body();
+                (WsMessage::Error { id: req.id, error }, None)
+            }
+        }
+    }
+
+    /// Notes a store change to push at the next flush; `false` once the
+    /// store has gone away.
+    async fn notice(&mut self, change: Result<StoreNotice, RecvError>) -> bool {
+        let remote = self.play.wallet.is_remote();
+        match change {
+            Ok(StoreNotice::Pools { currency, pools }) if self.watched.contains_key(&currency) => {
+                self.pending.entry(currency).or_default().pools = Some(pools);
+            }
+            // a remote balance isn't the store's to announce
+            Ok(StoreNotice::Wallet {
+                user_id,
+                currency,
+                wallet,
+            }) if !remote
+                && user_id == self.user.user_id
+                && self.watched.contains_key(&currency) =>
+            {
+                self.pending.entry(currency).or_default().wallet = Some(wallet);
+            }
+            Ok(_) => {}
+            // some changes were missed; the current numbers make up for them
+            Err(RecvError::Lagged(_)) => {
+                let store = &self.play.store;
+                let user = store.get_user(&self.user.user_id).await;
+                for &currency in self.watched.keys() {
+                    let pending = self.pending.entry(currency).or_default();
+                    pending.pools = Some(store.get_pools(currency).await);
+                    if !remote {
+                        pending.wallet = user.as_ref().map(|u| u.balance(currency));
+                    }
+                }
+            }
+            Err(RecvError::Closed) => return false,
+        }
+        true
+    }
+
+    /// Brings the client up to date on `currency` after it played or asked
+    /// about it: everything the first time, and afterwards a remote balance,
+    /// whose changes the store never hears of.
+    async fn catch_up(&mut self, socket: &mut WebSocket, currency: Currency) -> bool {
+        let play = &self.play;
+        let first = !self.watched.contains_key(&currency);
+        let mut fresh = Numbers::default();
+        if first || play.wallet.is_remote() {
+            match play.wallet.balance(&self.user.user_id, currency).await {
+                Ok(wallet) => fresh.wallet = Some(wallet),
+                // an unreachable remote wallet is tried again after the next action
+                Err(e) => debug!(%currency, "websocket balance check failed: {}", e),
+            }
+        }
+        if first {
+            fresh.pools = Some(play.store.get_pools(currency).await);
+        }
+        self.watched.entry(currency).or_default();
+        self.push(socket, currency, fresh).await
+    }
+
+    /// Sends a `wallet` or `pools` message for whichever of `numbers` moved
+    /// since the client last heard about `currency`.
+    async fn push(&mut self, socket: &mut WebSocket, currency: Currency, numbers: Numbers) -> bool {
+        let seen = self.watched.entry(currency).or_default();
+        if let Some(wallet) = numbers.wallet.filter(|&w| seen.wallet != Some(w)) {
+            seen.wallet = Some(wallet);
+            if send(socket, &WsMessage::Wallet { currency, wallet })
+                .await
+                .is_err()
+            {
+                return false;
+            }
+        }
+        let moved =
+            |old: &Pools, new: &Pools| (old.win_pool, old.reserved) != (new.win_pool, new.reserved);
+        if let Some(pools) = numbers
+            .pools
+            .filter(|p| seen.pools.as_ref().is_none_or(|old| moved(old, p)))
+        {
+            let msg = WsMessage::Pools {
+                currency,
+                win_pool: pools.win_pool,
+                win_pool_reserved: pools.reserved,
+                win_pool_available: pools.available(),
+            };
+            seen.pools = Some(pools);
+            if send(socket, &msg).await.is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Ends the connection with an error once its session is revoked or
+    /// has run out.
+    async fn session_active(&self, socket: &mut WebSocket) -> bool {
+        let user = &self.user;
+        if auth::session_active(&self.play.store, &user.session_id, &user.user_id).await {
+            return true;
+        }
+        let error = ApiError::unauthorized("session_revoked", "session revoked");
+        let _ = send(
+            socket,
+            &WsMessage::Error {
+                id: None,
+                error: error.into_body(),
+            },
+        )
+        .await;
+        close(socket, close_code::POLICY, "session revoked").await;
+        false
+    }
+}
+
+/// A successful action as its JSON result and the currency it was in.
+fn reply<T: Serialize>(
+    result: Result<T, ApiError>,
+    currency: impl FnOnce(&T) -> Currency,
+) -> Result<(Currency, serde_json::Value), ApiError> {
+    let response = result?;
+    let value = serde_json::to_value(&response).map_err(|e| ApiError::internal(e.to_string()))?;
+    Ok((currency(&response), value))
+}
+
+async fn send(socket: &mut WebSocket, msg: &WsMessage) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(msg).expect("websocket messages serialize");
+    socket.send(Message::Text(text.into())).await
+}
+
+async fn close(socket: &mut WebSocket, code: u16, reason: &'static str) {
+    let frame = CloseFrame {
+        code,
+        reason: reason.into(),
+    };
+    let _ = socket.send(Message::Close(Some(frame))).await;
+}
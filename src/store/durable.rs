@@ -0,0 +1,1028 @@
+//! Persistence shared by the durable stores. The working state stays in an
+//! `InMem`, which keeps every rule in one place. Each change is then handed
+//! to a `Sink` as a `StoreEvent`, naming the operation and what it was
+//! given, along with the rows it touched; one at a time and in the order
+//! the changes were made.
+
+use super::{limits, new_id, pending_request, InMem, InMemState, SharedStore, Store, StoreError};
+use crate::models::{
+    AuthSession, CachedResponse, CashierKind, CashierRequest, CashierStatus, Currency, DiscardOp,
+    EventKind, IdempotencyClaim, IdempotencyRecord, LedgerEntry, LimitChange, NewRound, Paytable,
+    PlayBlock, PlayerLimits, Pools, ReconcileReport, Role, Round, RoundSnapshot, RoundStatus,
+    Settlement, StoreChanges, StoreEvent, StoreNotice, TransactionFilter, User, WalletTransaction,
+    WalletTx,
+};
+use crate::money::Money;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex, OwnedMutexGuard};
+use tracing::error;
+
+/// Where a `Durable` store writes its events.
+pub trait Sink: Send + 'static {
+    /// Whether writing `event` needs the whole state along with it.
+    fn wants_state(&self, _event: &StoreEvent) -> bool {
+        false
+    }
+
+    /// Makes `event` durable. `changes` are the rows it left behind, and
+    /// `state` already includes it; `state` is only passed when
+    /// `wants_state` asked for it. Runs on a blocking thread.
+    fn write(
+        &mut self,
+        event: &StoreEvent,
+        changes: &StoreChanges,
+        state: Option<&InMemState>,
+    ) -> Result<(), String>;
+}
+
+/// A `Store` that hands every change to a `Sink` before answering. Reads
+/// are served from memory. A failed operation is undone and never reaches
+/// the sink. The sink writes on a blocking thread, without the state lock;
+/// if it fails, the change is undone in memory and the caller gets the
+/// error. Notices go out once the change is written.
+pub struct Durable<S> {
+    mem: InMem,
+    /// Held from the start of an operation until the sink has written it,
+    /// so changes reach the sink one at a time.
+    sink: Arc<Mutex<S>>,
+}
+
+/// Rows a change may have touched. The owners of touched rounds and
+/// cashier requests, and the pools of touched rounds, come along.
+#[derive(Clone, Default)]
+struct Touched {
+    users: Vec<String>,
+    sessions: Vec<String>,
+    rounds: Vec<String>,
+    cashier: Vec<String>,
+    pools: Vec<Currency>,
+    paytable: bool,
+    idempotency: Vec<String>,
+    idempotency_expired_before: Option<DateTime<Utc>>,
+    wallet_credits: Vec<String>,
+}
+
+impl Touched {
+    fn user(id: &str) -> Self {
+        Touched {
+            users: vec![id.to_string()],
+            ..Touched::default()
+        }
+    }
+
+    fn session(id: &str) -> Self {
+        Touched {
+            sessions: vec![id.to_string()],
+            ..Touched::default()
+        }
+    }
+
+    fn round(id: &str) -> Self {
+        Touched {
+            rounds: vec![id.to_string()],
+            ..Touched::default()
+        }
+    }
+
+    fn cashier(id: &str) -> Self {
+        Touched {
+            cashier: vec![id.to_string()],
+            ..Touched::default()
+        }
+    }
+
+    fn pools(currency: Currency) -> Self {
+        Touched {
+            pools: vec![currency],
+            ..Touched::default()
+        }
+    }
+
+    fn and(mut self, other: Touched) -> Self {
+        self.users.extend(other.users);
+        self.sessions.extend(other.sessions);
+        self.rounds.extend(other.rounds);
+        self.cashier.extend(other.cashier);
+        self.pools.extend(other.pools);
+        self.paytable |= other.paytable;
+        self.idempotency.extend(other.idempotency);
+        self.idempotency_expired_before = self
+            .idempotency_expired_before
+            .or(other.idempotency_expired_before);
+        self.wallet_credits.extend(other.wallet_credits);
+        self
+    }
+}
+
+/// Memory as it was before an operation, for the rows it was going to
+/// touch. Put back if the operation fails or the sink can't write it.
+struct Undo {
+    users: HashMap<String, Option<User>>,
+    usage: HashMap<String, Option<limits::Usage>>,
+    sessions: HashMap<String, Option<AuthSession>>,
+    rounds: HashMap<String, Option<Round>>,
+    cashier: HashMap<String, Option<CashierRequest>>,
+    pools: BTreeMap<Currency, Pools>,
+    wallet_credits: BTreeMap<String, WalletTx>,
+    paytable: Paytable,
+    ledger_len: usize,
+    transactions_len: usize,
+}
+
+fn before<T: Clone>(rows: &HashMap<String, T>, ids: &[String]) -> HashMap<String, Option<T>> {
+    ids.iter()
+        .map(|id| (id.clone(), rows.get(id).cloned()))
+        .collect()
+}
+
+fn restore<T>(
+    rows: &mut HashMap<String, T>,
+    before: HashMap<String, Option<T>>,
+    created: &[String],
+) {
+    for (id, row) in before {
+        match row {
+            Some(row) => rows.insert(id, row),
+            None => rows.remove(&id),
+        };
+    }
+    for id in created {
+        rows.remove(id);
+    }
+}
+
+impl InMemState {
+    /// Adds the owners and pools of the touched rounds and cashier
+    /// requests, and the touched rounds' pending wallet credits.
+    fn expand(&self, mut touched: Touched) -> Touched {
+        for round in touched.rounds.iter().filter_map(|id| self.rounds.get(id)) {
+            touched.users.push(round.user_id.clone());
+            touched.pools.push(round.currency);
+        }
+        for tx in self.wallet_credits.values() {
+            if touched.rounds.contains(&tx.round_id) {
+                touched.wallet_credits.push(tx.transaction_id.clone());
+            }
+        }
+        for req in touched.cashier.iter().filter_map(|id| self.cashier.get(id)) {
+            touched.users.push(req.user_id.clone());
+        }
+        for ids in [
+            &mut touched.users,
+            &mut touched.sessions,
+            &mut touched.rounds,
+            &mut touched.cashier,
+            &mut touched.wallet_credits,
+        ] {
+            ids.sort();
+            ids.dedup();
+        }
+        touched.pools.sort();
+        touched.pools.dedup();
+        touched
+    }
+
+    fn undo(&self, touched: &Touched) -> Undo {
+        Undo {
+            users: before(&self.users, &touched.users),
+            usage: before(&self.usage, &touched.users),
+            sessions: before(&self.sessions, &touched.sessions),
+            rounds: before(&self.rounds, &touched.rounds),
+            cashier: before(&self.cashier, &touched.cashier),
+            pools: self.pools.clone(),
+            wallet_credits: self.wallet_credits.clone(),
+            paytable: self.paytable,
+            ledger_len: self.ledger.len(),
+            transactions_len: self.transactions.len(),
+        }
+    }
+
+    /// Puts back what `undo` saved and drops the rows in `created`.
+    fn rollback(&mut self, undo: Undo, created: &Touched) {
+        restore(&mut self.users, undo.users, &created.users);
+        restore(&mut self.usage, undo.usage, &created.users);
+        restore(&mut self.sessions, undo.sessions, &created.sessions);
+        restore(&mut self.rounds, undo.rounds, &created.rounds);
+        restore(&mut self.cashier, undo.cashier, &created.cashier);
+        self.pools = undo.pools;
+        self.wallet_credits = undo.wallet_credits;
+        self.paytable = undo.paytable;
+        self.ledger.truncate(undo.ledger_len);
+        self.transactions.truncate(undo.transactions_len);
+        self.notices.clear();
+    }
+
+    fn changes(
+        &self,
+        touched: Touched,
+        ledger_from: usize,
+        transactions_from: usize,
+    ) -> StoreChanges {
+        let touched = self.expand(touched);
+        StoreChanges {
+            users: touched
+                .users
+                .iter()
+                .filter_map(|id| self.users.get(id).cloned())
+                .collect(),
+            sessions: touched
+                .sessions
+                .iter()
+                .filter_map(|id| self.sessions.get(id).cloned())
+                .collect(),
+            rounds: touched
+                .rounds
+                .iter()
+                .filter_map(|id| self.rounds.get(id).cloned())
+                .collect(),
+            cashier: touched
+                .cashier
+                .iter()
+                .filter_map(|id| self.cashier.get(id).cloned())
+                .collect(),
+            pools: touched
+                .pools
+                .into_iter()
+                .map(|c| (c, self.pools(c)))
+                .collect(),
+            paytable: touched.paytable.then_some(self.paytable),
+            ledger: self.ledger[ledger_from..].to_vec(),
+            transactions: self.transactions[transactions_from..].to_vec(),
+            idempotency: touched
+                .idempotency
+                .iter()
+                .filter_map(|key| self.idempotency.get(key).cloned())
+                .collect(),
+            idempotency_expired_before: touched.idempotency_expired_before,
+            wallet_credits: (touched.wallet_credits.iter())
+                .filter_map(|id| self.wallet_credits.get(id).cloned())
+                .collect(),
+            wallet_credits_delivered: (touched.wallet_credits.into_iter())
+                .filter(|id| !self.wallet_credits.contains_key(id))
+                .collect(),
+        }
+    }
+
+    /// All of it, as the changes of a `Created` event.
+    pub(super) fn everything(&self) -> StoreChanges {
+        StoreChanges {
+            users: self.users.values().cloned().collect(),
+            sessions: self.sessions.values().cloned().collect(),
+            rounds: self.rounds.values().cloned().collect(),
+            cashier: self.cashier.values().cloned().collect(),
+            pools: self.pools.clone(),
+            paytable: Some(self.paytable),
+            ledger: self.ledger.clone(),
+            transactions: self.transactions.clone(),
+            idempotency: self.idempotency.values().cloned().collect(),
+            wallet_credits: self.wallet_credits.values().cloned().collect(),
+            ..StoreChanges::default()
+        }
+    }
+}
+
+impl InMemState {
+    /// Applies a recorded event through the operation that made it, as it
+    /// stood at the time.
+    pub(super) fn replay(&mut self, event: StoreEvent) -> Result<(), StoreError> {
+        let now = event.at;
+        match event.kind {
+            EventKind::Created => self.seed(now),
+            EventKind::UserRegistered {
+                user_id,
+                name,
+                password_hash,
+            } => {
+                self.create_user(user_id, &name, &password_hash, now)?;
+            }
+            EventKind::PasswordChanged {
+                user_id,
+                password_hash,
+            } => self.set_password_hash(&user_id, &password_hash)?,
+            EventKind::UserFrozen { user_id, frozen } => {
+                self.set_user_frozen(&user_id, frozen)?;
+            }
+            EventKind::RoleChanged { user_id, role } => {
+                self.set_user_role(&user_id, role)?;
+            }
+            EventKind::WalletAdjusted {
+                user_id,
+                currency,
+                delta,
+                reason,
+            } => {
+                self.adjust_wallet(&user_id, currency, delta, &reason, now)?;
+            }
+            EventKind::WalletSet {
+                user_id,
+                currency,
+                wallet,
+            } => self.update_user_wallet(&user_id, currency, wallet, now)?,
+            EventKind::PaytableChanged { paytable } => {
+                self.set_paytable(paytable)?;
+            }
+            EventKind::LimitChangeRequested {
+                user_id,
+                change,
+                delay_secs,
+            } => {
+                self.request_limit_change(&user_id, change, Duration::seconds(delay_secs), now)?;
+            }
+            EventKind::PlayBlocked {
+                user_id,
+                block,
+                until,
+            } => {
+                self.block_play(&user_id, block, until)?;
+            }
+            EventKind::SessionOpened {
+                session_id,
+                user_id,
+                expires_at,
+            } => {
+                self.create_session(session_id, &user_id, expires_at, now)?;
+            }
+            EventKind::SessionRefreshed {
+                session_id,
+                generation,
+                expires_at,
+            } => {
+                self.refresh_session(&session_id, generation, expires_at, now)?;
+            }
+            EventKind::SessionRevoked { session_id } => self.revoke_session(&session_id)?,
+            EventKind::RoundStarted { round_id, round } => {
+                self.start_round(round_id, round, now)?;
+            }
+            EventKind::CardsDiscarded(op) => {
+                self.apply_discard(op, now)?;
+            }
+            EventKind::RoundSettled(settlement) => {
+                self.settle_round(settlement, now)?;
+            }
+            EventKind::RoundVoided { round_id, reason } => {
+                self.void_round(&round_id, &reason, now)?;
+            }
+            EventKind::PoolsFunded {
+                currency,
+                win,
+                house,
+            } => self.add_to_pools(currency, win, house, now)?,
+            EventKind::WinPoolWithdrawn { currency, amount } => {
+                self.sub_from_win_pool(currency, amount, now)?
+            }
+            EventKind::HouseProfitWithdrawn { currency, amount } => {
+                self.sub_from_house_profit(currency, amount, now)?
+            }
+            EventKind::CashierRequested(req) => {
+                self.create_cashier_request(req)?;
+            }
+            EventKind::CashierSubmitted {
+                request_id,
+                provider_ref,
+            } => {
+                self.mark_cashier_submitted(&request_id, &provider_ref, now)?;
+            }
+            EventKind::CashierResolved {
+                request_id,
+                approved,
+                reason,
+            } => {
+                self.resolve_cashier_request(&request_id, approved, reason, now)?;
+            }
+            EventKind::IdempotencyRecorded(record) => {
+                self.idempotency.insert(record.key.clone(), record);
+            }
+            EventKind::IdempotencyExpired { before } => self.expire_idempotency(before),
+            EventKind::WalletCreditDelivered { transaction_id } => {
+                self.wallet_credit_delivered(&transaction_id)
+            }
+        }
+        // nobody is listening yet
+        self.notices.clear();
+        Ok(())
+    }
+}
+
+impl<S: Sink> Durable<S> {
+    /// Serves `state`, which `sink` already holds. Without one, a new demo
+    /// state is made and written first as a `Created` event.
+    pub(super) fn new(state: Option<InMemState>, mut sink: S) -> Result<Self, String> {
+        let state = match state {
+            Some(state) => state,
+            None => {
+                let event = StoreEvent {
+                    at: Utc::now(),
+                    kind: EventKind::Created,
+                };
+                let mut state = InMemState::default();
+                state
+                    .replay(event.clone())
+                    .map_err(|e| format!("can't seed the store: {e}"))?;
+                let wanted = sink.wants_state(&event);
+                sink.write(&event, &state.everything(), wanted.then_some(&state))?;
+                state
+            }
+        };
+        Ok(Durable {
+            mem: InMem::from_state(state),
+            sink: Arc::new(Mutex::new(sink)),
+        })
+    }
+
+    /// Runs `op` on memory at the event's time and writes the event, with
+    /// the rows it changed, to the sink. `touched` lists the existing rows
+    /// `op` may change. A failed operation is undone and not written.
+    async fn record<T>(
+        &self,
+        kind: EventKind,
+        touched: Touched,
+        op: impl FnOnce(&mut InMemState, DateTime<Utc>) -> Result<T, StoreError>,
+    ) -> Result<T, StoreError> {
+        self.record_created(kind, touched, op, |_| Touched::default())
+            .await
+    }
+
+    /// As `record`, for operations that also create the rows `created`
+    /// names.
+    async fn record_created<T>(
+        &self,
+        kind: EventKind,
+        touched: Touched,
+        op: impl FnOnce(&mut InMemState, DateTime<Utc>) -> Result<T, StoreError>,
+        created: impl FnOnce(&T) -> Touched,
+    ) -> Result<T, StoreError> {
+        let sink = self.sink.clone().lock_owned().await;
+        let event = StoreEvent {
+            at: Utc::now(),
+            kind,
+        };
+        let (out, undo, created, changes, notices, state) = {
+            let mut s = self.mem.inner.lock();
+            let touched = s.expand(touched);
+            let undo = s.undo(&touched);
+            let out = match op(&mut s, event.at) {
+                Ok(out) => out,
+                Err(e) => {
+                    s.rollback(undo, &Touched::default());
+                    return Err(e);
+                }
+            };
+            let created = created(&out);
+            let changes = s.changes(
+                touched.and(created.clone()),
+                undo.ledger_len,
+                undo.transactions_len,
+            );
+            let notices = std::mem::take(&mut s.notices);
+            let state = sink.wants_state(&event).then(|| s.clone());
+            (out, undo, created, changes, notices, state)
+        };
+
+        let mem = self.mem.clone();
+        write(sink, move |sink| {
+            let written = sink.write(&event, &changes, state.as_ref());
+            match written {
+                Ok(()) => mem.send(notices),
+                Err(_) => mem.inner.lock().rollback(undo, &created),
+            }
+            written
+        })
+        .await?;
+        Ok(out)
+    }
+
+    /// As `record`, for bookkeeping that can't fail and that callers carry
+    /// on without; a write error is logged.
+    async fn record_or_log<T>(
+        &self,
+        event: StoreEvent,
+        touched: Touched,
+        op: impl FnOnce(&mut InMemState) -> T,
+    ) -> T {
+        let sink = self.sink.clone().lock_owned().await;
+        let (out, changes, state) = {
+            let mut s = self.mem.inner.lock();
+            let (ledger_from, transactions_from) = (s.ledger.len(), s.transactions.len());
+            let out = op(&mut s);
+            let changes = s.changes(touched, ledger_from, transactions_from);
+            let state = sink.wants_state(&event).then(|| s.clone());
+            (out, changes, state)
+        };
+        let written = write(sink, move |sink| {
+            sink.write(&event, &changes, state.as_ref())
+        });
+        if let Err(e) = written.await {
+            error!("store write failed: {}", e);
+        }
+        out
+    }
+}
+
+/// Runs `f` on a blocking thread with the sink, which stays locked until
+/// it is done. It runs to the end even if the caller goes away.
+async fn write<S: Sink>(
+    mut sink: OwnedMutexGuard<S>,
+    f: impl FnOnce(&mut S) -> Result<(), String> + Send + 'static,
+) -> Result<(), StoreError> {
+    tokio::task::spawn_blocking(move || f(&mut sink))
+        .await
+        .map_err(|e| StoreError::Storage(format!("store write task failed: {e}")))?
+        .map_err(StoreError::Storage)
+}
+
+impl<S: Sink + 'static> Durable<S> {
+    pub fn into_shared(self) -> SharedStore {
+        Arc::new(self)
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: Sink + 'static> Store for Durable<S> {
+    async fn create_user_if_unique(
+        &self,
+        name: &str,
+        password_hash: &str,
+    ) -> Result<User, StoreError> {
+        let user_id = new_id();
+        let kind = EventKind::UserRegistered {
+            user_id: user_id.clone(),
+            name: name.to_string(),
+            password_hash: password_hash.to_string(),
+        };
+        self.record_created(
+            kind,
+            Touched::default(),
+            |s, now| s.create_user(user_id, name, password_hash, now),
+            |u| Touched::user(&u.id),
+        )
+        .await
+    }
+
+    async fn find_user_by_name(&self, name: &str) -> Option<User> {
+        self.mem.find_user_by_name(name).await
+    }
+
+    async fn set_password_hash(
+        &self,
+        user_id: &str,
+        password_hash: &str,
+    ) -> Result<(), StoreError> {
+        let kind = EventKind::PasswordChanged {
+            user_id: user_id.to_string(),
+            password_hash: password_hash.to_string(),
+        };
+        self.record(kind, Touched::user(user_id), |s, _| {
+            s.set_password_hash(user_id, password_hash)
+        })
+        .await
+    }
+
+    async fn create_session(
+        &self,
+        user_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AuthSession, StoreError> {
+        let session_id = new_id();
+        let kind = EventKind::SessionOpened {
+            session_id: session_id.clone(),
+            user_id: user_id.to_string(),
+            expires_at,
+        };
+        self.record_created(
+            kind,
+            Touched::default(),
+            |s, now| s.create_session(session_id, user_id, expires_at, now),
+            |s| Touched::session(&s.id),
+        )
+        .await
+    }
+
+    async fn get_session(&self, session_id: &str) -> Option<AuthSession> {
+        self.mem.get_session(session_id).await
+    }
+
+    async fn refresh_session(
+        &self,
+        session_id: &str,
+        generation: u32,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AuthSession, StoreError> {
+        let kind = EventKind::SessionRefreshed {
+            session_id: session_id.to_string(),
+            generation,
+            expires_at,
+        };
+        let out = self
+            .record(kind, Touched::session(session_id), |s, now| {
+                s.refresh_session(session_id, generation, expires_at, now)
+            })
+            .await;
+        if out
+            .as_ref()
+            .is_err_and(|e| *e == StoreError::RefreshTokenReused)
+        {
+            self.revoke_session(session_id).await?;
+        }
+        out
+    }
+
+    async fn revoke_session(&self, session_id: &str) -> Result<(), StoreError> {
+        let kind = EventKind::SessionRevoked {
+            session_id: session_id.to_string(),
+        };
+        self.record(kind, Touched::session(session_id), |s, _| {
+            s.revoke_session(session_id)
+        })
+        .await
+    }
+
+    async fn get_user(&self, user_id: &str) -> Option<User> {
+        self.mem.get_user(user_id).await
+    }
+
+    async fn list_users(&self, query: Option<&str>, limit: usize) -> Vec<User> {
+        self.mem.list_users(query, limit).await
+    }
+
+    async fn set_user_frozen(&self, user_id: &str, frozen: bool) -> Result<User, StoreError> {
+        let kind = EventKind::UserFrozen {
+            user_id: user_id.to_string(),
+            frozen,
+        };
+        self.record(kind, Touched::user(user_id), |s, _| {
+            s.set_user_frozen(user_id, frozen)
+        })
+        .await
+    }
+
+    async fn set_user_role(&self, user_id: &str, role: Role) -> Result<User, StoreError> {
+        let kind = EventKind::RoleChanged {
+            user_id: user_id.to_string(),
+            role,
+        };
+        self.record(kind, Touched::user(user_id), |s, _| {
+            s.set_user_role(user_id, role)
+        })
+        .await
+    }
+
+    async fn adjust_wallet(
+        &self,
+        user_id: &str,
+        currency: Currency,
+        delta: Money,
+        reason: &str,
+    ) -> Result<User, StoreError> {
+        let kind = EventKind::WalletAdjusted {
+            user_id: user_id.to_string(),
+            currency,
+            delta,
+            reason: reason.to_string(),
+        };
+        self.record(kind, Touched::user(user_id), |s, now| {
+            s.adjust_wallet(user_id, currency, delta, reason, now)
+        })
+        .await
+    }
+
+    async fn get_paytable(&self) -> Paytable {
+        self.mem.get_paytable().await
+    }
+
+    async fn set_paytable(&self, paytable: Paytable) -> Result<Paytable, StoreError> {
+        let touched = Touched {
+            paytable: true,
+            ..Touched::default()
+        };
+        self.record(EventKind::PaytableChanged { paytable }, touched, |s, _| {
+            s.set_paytable(paytable)
+        })
+        .await
+    }
+
+    async fn get_limits(&self, user_id: &str) -> Option<PlayerLimits> {
+        self.mem.get_limits(user_id).await
+    }
+
+    async fn request_limit_change(
+        &self,
+        user_id: &str,
+        change: LimitChange,
+        delay: Duration,
+    ) -> Result<PlayerLimits, StoreError> {
+        let kind = EventKind::LimitChangeRequested {
+            user_id: user_id.to_string(),
+            change: change.clone(),
+            delay_secs: delay.num_seconds(),
+        };
+        self.record(kind, Touched::user(user_id), |s, now| {
+            s.request_limit_change(user_id, change, delay, now)
+        })
+        .await
+    }
+
+    async fn block_play(
+        &self,
+        user_id: &str,
+        kind: PlayBlock,
+        until: DateTime<Utc>,
+    ) -> Result<PlayerLimits, StoreError> {
+        let event = EventKind::PlayBlocked {
+            user_id: user_id.to_string(),
+            block: kind,
+            until,
+        };
+        self.record(event, Touched::user(user_id), |s, _| {
+            s.block_play(user_id, kind, until)
+        })
+        .await
+    }
+
+    async fn update_user_wallet(
+        &self,
+        user_id: &str,
+        currency: Currency,
+        new_wallet: Money,
+    ) -> Result<(), StoreError> {
+        let kind = EventKind::WalletSet {
+            user_id: user_id.to_string(),
+            currency,
+            wallet: new_wallet,
+        };
+        self.record(kind, Touched::user(user_id), |s, now| {
+            s.update_user_wallet(user_id, currency, new_wallet, now)
+        })
+        .await
+    }
+
+    async fn get_round(&self, round_id: &str) -> Option<Round> {
+        self.mem.get_round(round_id).await
+    }
+
+    async fn list_rounds(&self, user_id: Option<&str>, status: Option<RoundStatus>) -> Vec<Round> {
+        self.mem.list_rounds(user_id, status).await
+    }
+
+    async fn get_active_rounds(&self, user_id: &str) -> Vec<Round> {
+        self.mem.get_active_rounds(user_id).await
+    }
+
+    async fn round_ledger(&self, round_id: &str) -> Vec<LedgerEntry> {
+        self.mem.round_ledger(round_id).await
+    }
+
+    async fn start_round(&self, new: NewRound) -> Result<RoundSnapshot, StoreError> {
+        let round_id = new_id();
+        let touched = Touched::user(&new.user_id).and(Touched::pools(new.currency));
+        let kind = EventKind::RoundStarted {
+            round_id: round_id.clone(),
+            round: new.clone(),
+        };
+        self.record_created(
+            kind,
+            touched,
+            |s, now| s.start_round(round_id, new, now),
+            |r| Touched::round(&r.round.id),
+        )
+        .await
+    }
+
+    async fn apply_discard(&self, op: DiscardOp) -> Result<RoundSnapshot, StoreError> {
+        let touched = Touched::round(&op.round_id);
+        let kind = EventKind::CardsDiscarded(op.clone());
+        self.record(kind, touched, |s, now| s.apply_discard(op, now))
+            .await
+    }
+
+    async fn settle_round(&self, settlement: Settlement) -> Result<RoundSnapshot, StoreError> {
+        let touched = Touched::round(&settlement.round_id);
+        let kind = EventKind::RoundSettled(settlement.clone());
+        self.record(kind, touched, |s, now| s.settle_round(settlement, now))
+            .await
+    }
+
+    async fn void_round(
+        &self,
+        round_id: &str,
+        reason: &str,
+    ) -> Result<(RoundSnapshot, Money), StoreError> {
+        let kind = EventKind::RoundVoided {
+            round_id: round_id.to_string(),
+            reason: reason.to_string(),
+        };
+        self.record(kind, Touched::round(round_id), |s, now| {
+            s.void_round(round_id, reason, now)
+        })
+        .await
+    }
+
+    async fn get_pools(&self, currency: Currency) -> Pools {
+        self.mem.get_pools(currency).await
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<StoreNotice> {
+        self.mem.subscribe()
+    }
+
+    async fn add_to_pools(
+        &self,
+        currency: Currency,
+        win: Money,
+        house: Money,
+    ) -> Result<(), StoreError> {
+        let kind = EventKind::PoolsFunded {
+            currency,
+            win,
+            house,
+        };
+        self.record(kind, Touched::pools(currency), |s, now| {
+            s.add_to_pools(currency, win, house, now)
+        })
+        .await
+    }
+
+    async fn sub_from_win_pool(&self, currency: Currency, amount: Money) -> Result<(), StoreError> {
+        let kind = EventKind::WinPoolWithdrawn { currency, amount };
+        self.record(kind, Touched::pools(currency), |s, now| {
+            s.sub_from_win_pool(currency, amount, now)
+        })
+        .await
+    }
+
+    async fn sub_from_house_profit(
+        &self,
+        currency: Currency,
+        amount: Money,
+    ) -> Result<(), StoreError> {
+        let kind = EventKind::HouseProfitWithdrawn { currency, amount };
+        self.record(kind, Touched::pools(currency), |s, now| {
+            s.sub_from_house_profit(currency, amount, now)
+        })
+        .await
+    }
+
+    async fn list_transactions(
+        &self,
+        user_id: &str,
+        filter: TransactionFilter,
+    ) -> Vec<WalletTransaction> {
+        self.mem.list_transactions(user_id, filter).await
+    }
+
+    async fn create_cashier_request(
+        &self,
+        user_id: &str,
+        kind: CashierKind,
+        currency: Currency,
+        amount: Money,
+        requires_approval: bool,
+    ) -> Result<CashierRequest, StoreError> {
+        let req = pending_request(
+            new_id(),
+            user_id,
+            kind,
+            currency,
+            amount,
+            requires_approval,
+            Utc::now(),
+        );
+        self.record_created(
+            EventKind::CashierRequested(req.clone()),
+            Touched::user(user_id),
+            |s, _| s.create_cashier_request(req),
+            |r| Touched::cashier(&r.id),
+        )
+        .await
+    }
+
+    async fn get_cashier_request(&self, id: &str) -> Option<CashierRequest> {
+        self.mem.get_cashier_request(id).await
+    }
+
+    async fn list_cashier_requests(
+        &self,
+        user_id: Option<&str>,
+        status: Option<CashierStatus>,
+    ) -> Vec<CashierRequest> {
+        self.mem.list_cashier_requests(user_id, status).await
+    }
+
+    async fn mark_cashier_submitted(
+        &self,
+        id: &str,
+        provider_ref: &str,
+    ) -> Result<CashierRequest, StoreError> {
+        let kind = EventKind::CashierSubmitted {
+            request_id: id.to_string(),
+            provider_ref: provider_ref.to_string(),
+        };
+        self.record(kind, Touched::cashier(id), |s, now| {
+            s.mark_cashier_submitted(id, provider_ref, now)
+        })
+        .await
+    }
+
+    async fn resolve_cashier_request(
+        &self,
+        id: &str,
+        approved: bool,
+        reason: Option<String>,
+    ) -> Result<CashierRequest, StoreError> {
+        let kind = EventKind::CashierResolved {
+            request_id: id.to_string(),
+            approved,
+            reason: reason.clone(),
+        };
+        self.record(kind, Touched::cashier(id), |s, now| {
+            s.resolve_cashier_request(id, approved, reason, now)
+        })
+        .await
+    }
+
+    async fn pending_wallet_credits(&self) -> Vec<WalletTx> {
+        self.mem.pending_wallet_credits().await
+    }
+
+    /// Only a credit that is still pending is written.
+    async fn wallet_credit_delivered(&self, transaction_id: &str) -> Result<(), StoreError> {
+        if !(self.mem.inner.lock().wallet_credits).contains_key(transaction_id) {
+            return Ok(());
+        }
+        let kind = EventKind::WalletCreditDelivered {
+            transaction_id: transaction_id.to_string(),
+        };
+        let touched = Touched {
+            wallet_credits: vec![transaction_id.to_string()],
+            ..Touched::default()
+        };
+        self.record(kind, touched, |s, _| {
+            s.wallet_credit_delivered(transaction_id);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn reconcile(&self) -> ReconcileReport {
+        self.mem.reconcile().await
+    }
+
+    /// Claims live in memory only: after a restart a request that was
+    /// still running can run again. Expiry is written when it drops
+    /// something.
+    async fn claim_idempotency(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        ttl: Duration,
+    ) -> IdempotencyClaim {
+        let before = now - ttl;
+        let expired = (self.mem.inner.lock().idempotency.values()).any(|r| r.created_at <= before);
+        if expired {
+            let event = StoreEvent {
+                at: now,
+                kind: EventKind::IdempotencyExpired { before },
+            };
+            let touched = Touched {
+                idempotency_expired_before: Some(before),
+                ..Touched::default()
+            };
+            self.record_or_log(event, touched, |s| s.expire_idempotency(before))
+                .await;
+        }
+        self.mem.claim_idempotency(key, fingerprint, now, ttl).await
+    }
+
+    /// Records the response a retry will get; a released key is only
+    /// forgotten in memory, as its claim never was written.
+    async fn finish_idempotency(&self, key: &str, response: Option<CachedResponse>) {
+        let record = {
+            let mut s = self.mem.inner.lock();
+            let Some(response) = response else {
+                s.finish_idempotency(key, None);
+                return;
+            };
+            match s.idempotency.get(key) {
+                Some(rec) => IdempotencyRecord {
+                    response: Some(response),
+                    ..rec.clone()
+                },
+                None => return,
+            }
+        };
+        let event = StoreEvent {
+            at: Utc::now(),
+            kind: EventKind::IdempotencyRecorded(record.clone()),
+        };
+        let touched = Touched {
+            idempotency: vec![key.to_string()],
+            ..Touched::default()
+        };
+        self.record_or_log(event, touched, |s| {
+            s.idempotency.insert(record.key.clone(), record);
+        })
+        .await
+    }
+}
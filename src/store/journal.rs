@@ -0,0 +1,239 @@
+//! Append-only journal of store events, for audits. Each line is
+//!
+//! ```text
+//! <crc32 of the json, 8 hex digits> {"seq":N,"event":{"at":...,"type":"cards_discarded",...}}
+//! ```
+//!
+//! and is synced to disk before the change is acknowledged. An event names
+//! the store operation and everything it was given; startup runs them
+//! through the same operations again. Every so many
+//! events the whole state is written to `<path>.snapshot`, together with
+//! the journal offset it covers, so startup only replays what came after.
+//! Finished idempotency records are journaled and snapshotted too, so a
+//! retry after a restart still gets its first response.
+
+use super::snapshot::write_atomic;
+use super::{Durable, InMemState, Sink};
+use crate::models::{StoreChanges, StoreEvent};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{error, warn};
+
+/// Events between snapshots when `JournalStore::open` is used.
+const DEFAULT_SNAPSHOT_EVERY: u64 = 1000;
+
+/// A `Store` whose state is rebuilt from its journal on startup.
+pub type JournalStore = Durable<JournalSink>;
+
+/// Appends events to the journal file and takes the snapshots.
+pub struct JournalSink {
+    file: File,
+    snapshot_path: PathBuf,
+    snapshot_every: u64,
+    /// Sequence number of the last event written.
+    seq: u64,
+    /// Length of the journal, which is where the next event goes.
+    offset: u64,
+    /// Sequence number the latest snapshot includes.
+    snapshot_seq: u64,
+}
+
+#[derive(Serialize)]
+struct EntryRef<'a> {
+    seq: u64,
+    event: &'a StoreEvent,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    seq: u64,
+    event: StoreEvent,
+}
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    seq: u64,
+    offset: u64,
+    state: &'a InMemState,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    seq: u64,
+    offset: u64,
+    state: InMemState,
+}
+
+fn io_err(e: std::io::Error) -> String {
+    format!("journal error: {e}")
+}
+
+fn snapshot_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".snapshot");
+    PathBuf::from(name)
+}
+
+fn encode(seq: u64, event: &StoreEvent) -> Vec<u8> {
+    let json = serde_json::to_string(&EntryRef { seq, event }).expect("models serialize");
+    format!("{:08x} {}\n", crc32fast::hash(json.as_bytes()), json).into_bytes()
+}
+
+fn decode(line: &[u8]) -> Result<Entry, String> {
+    let line = std::str::from_utf8(line).map_err(|_| "not utf-8".to_string())?;
+    let (crc, json) = line.split_once(' ').ok_or("no checksum")?;
+    let crc = u32::from_str_radix(crc, 16).map_err(|_| "bad checksum")?;
+    if crc != crc32fast::hash(json.as_bytes()) {
+        return Err("checksum mismatch".into());
+    }
+    serde_json::from_str(json).map_err(|e| format!("bad entry: {e}"))
+}
+
+impl JournalStore {
+    /// Opens (or creates) the journal at `path`, snapshotting every
+    /// `DEFAULT_SNAPSHOT_EVERY` events.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        Self::open_with(path, DEFAULT_SNAPSHOT_EVERY)
+    }
+
+    /// Opens (or creates) the journal at `path` and replays it on top of
+    /// the latest snapshot. A torn last line, left by a crash mid-write, is
+    /// cut off with a warning; damage anywhere before it is an error. A new
+    /// journal starts from the same demo state as `InMem::new_demo`.
+    pub fn open_with(path: impl AsRef<Path>, snapshot_every: u64) -> Result<Self, String> {
+        let path = path.as_ref();
+        let snapshot_path = snapshot_path(path);
+        let (mut state, mut seq, offset) = match std::fs::read(&snapshot_path) {
+            Ok(data) => {
+                let snap: Snapshot = serde_json::from_slice(&data)
+                    .map_err(|e| format!("corrupt snapshot {}: {e}", snapshot_path.display()))?;
+                let mut state = snap.state;
+                state.reindex();
+                (state, snap.seq, snap.offset)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (InMemState::default(), 0, 0),
+            Err(e) => return Err(io_err(e)),
+        };
+        let snapshot_seq = seq;
+
+        let journal = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(io_err(e)),
+        };
+        let tail = journal
+            .get(offset as usize..)
+            .ok_or("journal is shorter than its snapshot")?;
+
+        let mut end = offset;
+        let mut rest = tail;
+        while !rest.is_empty() {
+            let (line, after, torn) = match rest.iter().position(|&b| b == b'\n') {
+                Some(i) => (&rest[..i], &rest[i + 1..], false),
+                None => (rest, &rest[rest.len()..], true),
+            };
+            let entry = if torn {
+                Err("incomplete line".to_string())
+            } else {
+                decode(line)
+            };
+            let entry = match entry {
+                Ok(entry) if entry.seq == seq + 1 => entry,
+                Ok(entry) => {
+                    return Err(format!(
+                        "journal skips from event {seq} to {} at byte {end}",
+                        entry.seq
+                    ))
+                }
+                Err(e) if after.is_empty() => {
+                    warn!(
+                        "dropping damaged last journal line at byte {} ({}): {}",
+                        end,
+                        path.display(),
+                        e
+                    );
+                    break;
+                }
+                Err(e) => return Err(format!("journal damaged at byte {end}: {e}")),
+            };
+            state
+                .replay(entry.event)
+                .map_err(|e| format!("journal event {} doesn't replay: {e}", entry.seq))?;
+            seq = entry.seq;
+            end += (line.len() + 1) as u64;
+            rest = after;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(io_err)?;
+        if end < journal.len() as u64 {
+            file.set_len(end).map_err(io_err)?;
+            file.sync_data().map_err(io_err)?;
+        }
+
+        let sink = JournalSink {
+            file,
+            snapshot_path,
+            snapshot_every: snapshot_every.max(1),
+            seq,
+            offset: end,
+            snapshot_seq,
+        };
+        Durable::new((seq > 0).then_some(state), sink)
+    }
+}
+
+impl JournalSink {
+    /// Writes the snapshot beside the journal.
+    fn snapshot(&mut self, state: &InMemState) -> Result<(), String> {
+        let data = serde_json::to_vec(&SnapshotRef {
+            seq: self.seq,
+            offset: self.offset,
+            state,
+        })
+        .expect("models serialize");
+        write_atomic(&self.snapshot_path, &data)?;
+        self.snapshot_seq = self.seq;
+        Ok(())
+    }
+}
+
+impl Sink for JournalSink {
+    fn wants_state(&self, _event: &StoreEvent) -> bool {
+        self.seq + 1 - self.snapshot_seq >= self.snapshot_every
+    }
+
+    fn write(
+        &mut self,
+        event: &StoreEvent,
+        _changes: &StoreChanges,
+        state: Option<&InMemState>,
+    ) -> Result<(), String> {
+        let line = encode(self.seq + 1, event);
+        if let Err(e) = self
+            .file
+            .write_all(&line)
+            .and_then(|_| self.file.sync_data())
+        {
+            // don't leave half a line for the next event to land after
+            let _ = self.file.set_len(self.offset);
+            return Err(io_err(e));
+        }
+        self.seq += 1;
+        self.offset += line.len() as u64;
+
+        if let Some(state) = state {
+            // the event is already safe; a failed snapshot only means a
+            // longer replay
+            if let Err(e) = self.snapshot(state) {
+                error!("journal snapshot failed: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
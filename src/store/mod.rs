@@ -1,37 +1,1264 @@
-use crate::models::{Card, Pools, Round, RoundStatus, User};
+use crate::game::{self, GameError};
+use crate::ledger::{self, Leg};
+use crate::limits;
+use crate::models::{
+    Account, AuthSession, CachedResponse, CashierKind, CashierRequest, CashierStatus, Currency,
+    DiscardOp, IdempotencyClaim, IdempotencyRecord, LedgerEntry, LedgerReason, LimitChange,
+    NewRound, Paytable, PlayBlock, PlayerLimits, PoolShare, Pools, ReconcileReport, Role, Round,
+    RoundSnapshot, RoundStatus, Settlement, StoreNotice, TransactionFilter, TransactionKind, User,
+    WalletTransaction, WalletTx,
+};
+use crate::money::{Money, MoneyError, Rounding};
+use chrono::{DateTime, Duration, Utc};
 use parking_lot::Mutex;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
+mod durable;
+mod journal;
+mod snapshot;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use durable::{Durable, Sink};
+pub use journal::{JournalSink, JournalStore};
+pub use snapshot::{SharedSnapshots, Snapshots};
+#[cfg(feature = "sqlite")]
+pub use sqlite::{SqliteSink, SqliteStore};
+
 pub type SharedStore = Arc<dyn Store + Send + Sync>;
 
+/// Why a store operation failed. Rule violations come through as `Game`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum StoreError {
+    #[error("user not found")]
+    UserNotFound,
+    #[error("session not found")]
+    SessionNotFound,
+    #[error("round not found")]
+    RoundNotFound,
+    #[error("cashier request not found")]
+    CashierRequestNotFound,
+    #[error("name already exists")]
+    NameTaken,
+    #[error("session expired")]
+    SessionExpired,
+    /// An older refresh token came back; the session has been revoked.
+    #[error("refresh token already used")]
+    RefreshTokenReused,
+    #[error("cashier request not pending")]
+    CashierNotPending,
+    #[error("cashier request does not need approval")]
+    ApprovalNotNeeded,
+    #[error("cashier request already with provider")]
+    AlreadyWithProvider,
+    #[error("provider reference mismatch")]
+    ProviderRefMismatch,
+    #[error("win_pool short")]
+    WinPoolShort,
+    #[error("house_profit short")]
+    HouseProfitShort,
+    #[error(transparent)]
+    Game(#[from] GameError),
+    /// A durable store couldn't write the change, so it was undone.
+    #[error("storage failed: {0}")]
+    Storage(String),
+}
+
+impl From<MoneyError> for StoreError {
+    fn from(e: MoneyError) -> Self {
+        StoreError::Game(e.into())
+    }
+}
+
+/// Play money credited to every new account.
+const SIGNUP_BONUS: Money = Money::new(1000);
+
+/// Notices a subscriber can fall behind by before it misses some.
+const NOTICE_BUFFER: usize = 1024;
+
 #[derive(Clone)]
 pub struct InMem {
     inner: Arc<Mutex<InMemState>>,
+    notices: broadcast::Sender<StoreNotice>,
 }
 
-#[derive(Default)]
-struct InMemState {
+/// Everything an `InMem` holds. Durable stores keep it on disk: as rows,
+/// as a journal of `StoreEvent`s, or as a snapshot.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct InMemState {
     users: HashMap<String, User>,
     rounds: HashMap<String, Round>,
-    pools: Pools,
+    pools: BTreeMap<Currency, Pools>,
+    /// Finished requests by idempotency key, kept until they expire so a
+    /// retry after a restart still gets the first response.
+    #[serde(default)]
+    idempotency: HashMap<String, IdempotencyRecord>,
+    ledger: Vec<LedgerEntry>,
+    transactions: Vec<WalletTransaction>,
+    /// Settlement credits booked for a remote wallet that it hasn't
+    /// confirmed yet, by transaction id.
+    #[serde(default)]
+    wallet_credits: BTreeMap<String, WalletTx>,
+    cashier: HashMap<String, CashierRequest>,
+    sessions: HashMap<String, AuthSession>,
+    /// Table new rounds are started under.
+    paytable: Paytable,
+    /// Each player's recent stakes and wins, for limit checks. Rebuilt from
+    /// `transactions` on load.
+    #[serde(skip)]
+    usage: HashMap<String, limits::Usage>,
+    /// Announcements made by the operation in progress, sent once it has
+    /// gone through.
+    #[serde(skip)]
+    notices: Vec<StoreNotice>,
 }
 
-impl InMem {
-    pub fn new_demo() -> Self {
+impl InMemState {
+    /// Appends a balanced transaction to the ledger. Callers update the
+    /// matching wallet/pool fields themselves under the same lock.
+    fn post(
+        &mut self,
+        currency: Currency,
+        round_id: Option<&str>,
+        legs: &[Leg],
+        now: DateTime<Utc>,
+    ) {
+        let next_seq = self.ledger.len() as u64 + 1;
+        let entries = ledger::transaction(next_seq, currency, round_id, legs, now);
+        self.ledger.extend(entries);
+    }
+
+    fn pools(&self, currency: Currency) -> Pools {
+        self.pools.get(&currency).cloned().unwrap_or_default()
+    }
+
+    fn pools_mut(&mut self, currency: Currency) -> &mut Pools {
+        self.pools.entry(currency).or_default()
+    }
+
+    /// Announces where the pools of `currency` stand.
+    fn announce_pools(&mut self, currency: Currency) {
+        let pools = self.pools(currency);
+        self.notices.push(StoreNotice::Pools { currency, pools });
+    }
+
+    /// Pays `share` into `pools`, diverting whatever would lift the win pool
+    /// above its ceiling to house profit. Returns the amounts actually added
+    /// as (win_pool, house); on error `pools` is left as it was.
+    fn fund_pools(pools: &mut Pools, share: &PoolShare) -> Result<(Money, Money), MoneyError> {
+        let (mut win, mut house) = (share.win_pool, share.house);
+        if let Some(ceiling) = share.win_pool_ceiling {
+            let room = ceiling.try_sub(pools.win_pool)?.max(Money::ZERO);
+            if win > room {
+                house = house.try_add(win.try_sub(room)?)?;
+                win = room;
+            }
+        }
+        let win_pool = pools.win_pool.try_add(win)?;
+        let house_profit = pools.house_profit.try_add(house)?;
+        pools.win_pool = win_pool;
+        pools.house_profit = house_profit;
+        Ok((win, house))
+    }
+
+    /// Creates a user holding the signup bonus in play money.
+    fn insert_user(
+        &mut self,
+        id: String,
+        name: &str,
+        password_hash: &str,
+        now: DateTime<Utc>,
+    ) -> User {
+        let currency = Currency::Play;
+        let user = User {
+            id: id.clone(),
+            name: name.to_string(),
+            password_hash: password_hash.to_string(),
+            wallets: BTreeMap::from([(currency, SIGNUP_BONUS)]),
+            held: BTreeMap::new(),
+            frozen: false,
+            limits: PlayerLimits::default(),
+            role: Role::default(),
+        };
+        self.post(
+            currency,
+            None,
+            &[
+                (Account::External, -SIGNUP_BONUS, LedgerReason::SignupBonus),
+                (
+                    Account::User(id.clone()),
+                    SIGNUP_BONUS,
+                    LedgerReason::SignupBonus,
+                ),
+            ],
+            now,
+        );
+        self.users.insert(id.clone(), user.clone());
+        self.record_tx(
+            &id,
+            currency,
+            TransactionKind::Bonus,
+            SIGNUP_BONUS,
+            None,
+            now,
+        );
+        user
+    }
+
+    /// Brings the player's limits up to date and checks they may stake
+    /// `stake` more in `currency` at `now`.
+    fn check_limits(
+        &mut self,
+        user_id: &str,
+        currency: Currency,
+        stake: Money,
+        now: DateTime<Utc>,
+    ) -> Result<(), StoreError> {
+        let InMemState { users, usage, .. } = self;
+        let user = users.get_mut(user_id).ok_or(StoreError::UserNotFound)?;
+        limits::apply_due(&mut user.limits, now);
+        limits::check_stake(
+            &user.limits,
+            usage.entry(user_id.to_string()).or_default(),
+            currency,
+            stake,
+            now,
+        )?;
+        Ok(())
+    }
+
+    /// Attaches an operator's note to the newest wallet history line.
+    fn note_last_tx(&mut self, note: &str) {
+        if let Some(tx) = self.transactions.last_mut() {
+            tx.note = Some(note.to_string());
+        }
+    }
+
+    /// The ledger account to book `delta` in or out of the player's wallet
+    /// against for a round, with the local balance once it has moved. A
+    /// round on a remote wallet leaves the local wallet alone. Changes
+    /// nothing; `set_player_funds` applies it.
+    fn player_funds_after(
+        &self,
+        user_id: &str,
+        currency: Currency,
+        delta: Money,
+        remote_wallet: bool,
+    ) -> Result<(Account, Money), StoreError> {
+        let user = self.users.get(user_id).ok_or(StoreError::UserNotFound)?;
+        if remote_wallet {
+            return Ok((
+                Account::RemoteWallet(user_id.to_string()),
+                user.balance(currency),
+            ));
+        }
+        Ok((
+            Account::User(user_id.to_string()),
+            user.balance(currency).try_add(delta)?,
+        ))
+    }
+
+    /// Sets the local balance `player_funds_after` worked out.
+    fn set_player_funds(
+        &mut self,
+        user_id: &str,
+        currency: Currency,
+        wallet: Money,
+        remote_wallet: bool,
+    ) {
+        if remote_wallet {
+            return;
+        }
+        if let Some(user) = self.users.get_mut(user_id) {
+            user.wallets.insert(currency, wallet);
+        }
+    }
+
+    /// Adds a line to the user's wallet history and announces the balance.
+    /// Call it once `amount` is in the wallet, which it reads the balance
+    /// after from.
+    fn record_tx(
+        &mut self,
+        user_id: &str,
+        currency: Currency,
+        kind: TransactionKind,
+        amount: Money,
+        round_id: Option<&str>,
+        now: DateTime<Utc>,
+    ) {
+        if amount == Money::ZERO {
+            return;
+        }
+        let balance_after = self
+            .users
+            .get(user_id)
+            .map_or(Money::ZERO, |u| u.balance(currency));
+        let seq = self.transactions.len() as u64 + 1;
+        let tx = WalletTransaction {
+            seq,
+            user_id: user_id.to_string(),
+            currency,
+            kind,
+            amount,
+            balance_after,
+            round_id: round_id.map(str::to_string),
+            note: None,
+            created_at: now,
+        };
+        self.usage
+            .entry(tx.user_id.clone())
+            .or_default()
+            .record(&tx);
+        self.notices.push(StoreNotice::Wallet {
+            user_id: tx.user_id.clone(),
+            currency,
+            wallet: balance_after,
+        });
+        self.transactions.push(tx);
+    }
+
+    /// Rebuilds what isn't saved with the state.
+    fn reindex(&mut self) {
+        self.usage.clear();
+        for tx in &self.transactions {
+            self.usage.entry(tx.user_id.clone()).or_default().record(tx);
+        }
+    }
+}
+
+impl InMemState {
+    /// A demo user and a seeded win pool in every currency.
+    fn demo(now: DateTime<Utc>) -> Self {
         let mut state = InMemState::default();
-        let u = User {
-            id: "user1".to_string(),
-            name: "user1".to_string(),
-            password: "pass1".to_string(),
-            wallet: 1000,
+        state.seed(now);
+        state
+    }
+
+    /// Adds what `demo` starts with.
+    fn seed(&mut self, now: DateTime<Utc>) {
+        self.insert_user("user1".to_string(), "user1", "pass1", now);
+        let seed = Money::new(50_000);
+        for currency in Currency::ALL {
+            self.pools_mut(currency).win_pool = seed;
+            self.post(
+                currency,
+                None,
+                &[
+                    (Account::External, -seed, LedgerReason::Seed),
+                    (Account::WinPool, seed, LedgerReason::Seed),
+                ],
+                now,
+            );
+        }
+    }
+}
+
+/// A fresh id for a user, session, round or cashier request.
+fn new_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// A new cashier request, waiting for the provider or an operator.
+fn pending_request(
+    id: String,
+    user_id: &str,
+    kind: CashierKind,
+    currency: Currency,
+    amount: Money,
+    requires_approval: bool,
+    now: DateTime<Utc>,
+) -> CashierRequest {
+    CashierRequest {
+        id,
+        user_id: user_id.to_string(),
+        kind,
+        currency,
+        amount,
+        status: CashierStatus::Pending,
+        requires_approval,
+        provider_ref: None,
+        reason: None,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+/// The store's operations. Ids and the time come from the caller, so a
+/// durable store can replay a recorded operation and get the same result.
+impl InMemState {
+    fn create_user(
+        &mut self,
+        id: String,
+        name: &str,
+        password_hash: &str,
+        now: DateTime<Utc>,
+    ) -> Result<User, StoreError> {
+        if self.users.values().any(|u| u.name == name) {
+            return Err(StoreError::NameTaken);
+        }
+        Ok(self.insert_user(id, name, password_hash, now))
+    }
+
+    fn set_password_hash(&mut self, user_id: &str, password_hash: &str) -> Result<(), StoreError> {
+        let user = self
+            .users
+            .get_mut(user_id)
+            .ok_or(StoreError::UserNotFound)?;
+        user.password_hash = password_hash.to_string();
+        Ok(())
+    }
+
+    fn create_session(
+        &mut self,
+        id: String,
+        user_id: &str,
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<AuthSession, StoreError> {
+        if !self.users.contains_key(user_id) {
+            return Err(StoreError::UserNotFound);
+        }
+        let session = AuthSession {
+            id,
+            user_id: user_id.to_string(),
+            generation: 0,
+            revoked: false,
+            created_at: now,
+            expires_at,
+        };
+        self.sessions.insert(session.id.clone(), session.clone());
+        Ok(session)
+    }
+
+    /// Moves the session on a generation. A stale `generation` is refused
+    /// with `RefreshTokenReused`; revoking the session is up to the caller.
+    fn refresh_session(
+        &mut self,
+        session_id: &str,
+        generation: u32,
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<AuthSession, StoreError> {
+        let session = self
+            .sessions
+            .get_mut(session_id)
+            .ok_or(StoreError::SessionNotFound)?;
+        if !session.is_active(now) {
+            return Err(StoreError::SessionExpired);
+        }
+        if session.generation != generation {
+            return Err(StoreError::RefreshTokenReused);
+        }
+        session.generation += 1;
+        session.expires_at = expires_at;
+        Ok(session.clone())
+    }
+
+    fn revoke_session(&mut self, session_id: &str) -> Result<(), StoreError> {
+        let session = self
+            .sessions
+            .get_mut(session_id)
+            .ok_or(StoreError::SessionNotFound)?;
+        session.revoked = true;
+        Ok(())
+    }
+
+    fn set_user_frozen(&mut self, user_id: &str, frozen: bool) -> Result<User, StoreError> {
+        let u = self
+            .users
+            .get_mut(user_id)
+            .ok_or(StoreError::UserNotFound)?;
+        u.frozen = frozen;
+        Ok(u.clone())
+    }
+
+    fn set_user_role(&mut self, user_id: &str, role: Role) -> Result<User, StoreError> {
+        let u = self
+            .users
+            .get_mut(user_id)
+            .ok_or(StoreError::UserNotFound)?;
+        u.role = role;
+        Ok(u.clone())
+    }
+
+    fn set_paytable(&mut self, paytable: Paytable) -> Result<Paytable, StoreError> {
+        paytable.validate()?;
+        self.paytable = paytable;
+        Ok(paytable)
+    }
+
+    fn adjust_wallet(
+        &mut self,
+        user_id: &str,
+        currency: Currency,
+        delta: Money,
+        reason: &str,
+        now: DateTime<Utc>,
+    ) -> Result<User, StoreError> {
+        if delta == Money::ZERO {
+            return Err(GameError::InvalidAmount("amount must not be zero").into());
+        }
+        let u = self
+            .users
+            .get_mut(user_id)
+            .ok_or(StoreError::UserNotFound)?;
+        if delta.is_negative() && u.available(currency).try_add(delta)?.is_negative() {
+            return Err(GameError::InsufficientFunds.into());
+        }
+        u.credit(currency, delta)?;
+        let user = u.clone();
+        self.post(
+            currency,
+            None,
+            &[
+                (Account::External, -delta, LedgerReason::Adjustment),
+                (
+                    Account::User(user_id.to_string()),
+                    delta,
+                    LedgerReason::Adjustment,
+                ),
+            ],
+            now,
+        );
+        self.record_tx(
+            user_id,
+            currency,
+            TransactionKind::Adjustment,
+            delta,
+            None,
+            now,
+        );
+        self.note_last_tx(reason);
+        Ok(user)
+    }
+
+    fn update_user_wallet(
+        &mut self,
+        user_id: &str,
+        currency: Currency,
+        new_wallet: Money,
+        now: DateTime<Utc>,
+    ) -> Result<(), StoreError> {
+        let u = self
+            .users
+            .get_mut(user_id)
+            .ok_or(StoreError::UserNotFound)?;
+        let delta = new_wallet.try_sub(u.balance(currency))?;
+        u.credit(currency, delta)?;
+        self.post(
+            currency,
+            None,
+            &[
+                (Account::External, -delta, LedgerReason::Adjustment),
+                (
+                    Account::User(user_id.to_string()),
+                    delta,
+                    LedgerReason::Adjustment,
+                ),
+            ],
+            now,
+        );
+        self.record_tx(
+            user_id,
+            currency,
+            TransactionKind::Adjustment,
+            delta,
+            None,
+            now,
+        );
+        Ok(())
+    }
+
+    fn request_limit_change(
+        &mut self,
+        user_id: &str,
+        change: LimitChange,
+        delay: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<PlayerLimits, StoreError> {
+        let u = self
+            .users
+            .get_mut(user_id)
+            .ok_or(StoreError::UserNotFound)?;
+        limits::request(&mut u.limits, change, now, delay);
+        Ok(u.limits.clone())
+    }
+
+    fn block_play(
+        &mut self,
+        user_id: &str,
+        kind: PlayBlock,
+        until: DateTime<Utc>,
+    ) -> Result<PlayerLimits, StoreError> {
+        let u = self
+            .users
+            .get_mut(user_id)
+            .ok_or(StoreError::UserNotFound)?;
+        limits::block(&mut u.limits, kind, until);
+        Ok(u.limits.clone())
+    }
+
+    fn start_round(
+        &mut self,
+        id: String,
+        new: NewRound,
+        now: DateTime<Utc>,
+    ) -> Result<RoundSnapshot, StoreError> {
+        let user = self
+            .users
+            .get(&new.user_id)
+            .ok_or(StoreError::UserNotFound)?;
+        if user.frozen {
+            return Err(GameError::AccountFrozen.into());
+        }
+        // a remote wallet answers for its own balance when it is debited
+        if !new.remote_wallet && new.ante > user.available(new.currency) {
+            return Err(GameError::InsufficientFunds.into());
+        }
+        self.check_limits(&new.user_id, new.currency, new.ante, now)?;
+
+        let active = self
+            .rounds
+            .values()
+            .filter(|r| r.user_id == new.user_id && r.status == RoundStatus::Active)
+            .count();
+        if active >= new.max_active_rounds {
+            return Err(GameError::TooManyActiveRounds {
+                max: new.max_active_rounds,
+            }
+            .into());
+        }
+
+        // only the pool of the round's own currency can pay it out, and only
+        // the part not already promised to other open rounds
+        let max_multiplier = i64::from(new.paytable.max());
+        let reserve = new.ante.try_mul(max_multiplier)?;
+        let available = self.pools(new.currency).available();
+        if available < reserve {
+            return Err(GameError::PoolTooSmall {
+                max_ante: available.mul_ratio(1, max_multiplier, Rounding::Down)?,
+            }
+            .into());
+        }
+
+        let round = Round {
+            id,
+            user_id: new.user_id,
+            currency: new.currency,
+            cards: new.cards,
+            ante: new.ante,
+            reserved: reserve,
+            paytable: new.paytable,
+            remote_wallet: new.remote_wallet,
+            status: RoundStatus::Active,
+            draws_used: 0,
+            created_at: now,
+        };
+        let (player, wallet) = self.player_funds_after(
+            &round.user_id,
+            round.currency,
+            -round.ante,
+            round.remote_wallet,
+        )?;
+
+        // nothing can fail from here on
+        self.set_player_funds(&round.user_id, round.currency, wallet, round.remote_wallet);
+        if let Some(user) = self.users.get_mut(&round.user_id) {
+            limits::record_play(&mut user.limits, now);
+        }
+        self.pools_mut(round.currency).reserved += reserve;
+        self.post(
+            round.currency,
+            Some(&round.id),
+            &[
+                (player, -round.ante, LedgerReason::Ante),
+                (
+                    Account::InPlay(round.id.clone()),
+                    round.ante,
+                    LedgerReason::Ante,
+                ),
+            ],
+            now,
+        );
+        self.record_tx(
+            &round.user_id,
+            round.currency,
+            TransactionKind::Ante,
+            -round.ante,
+            Some(&round.id),
+            now,
+        );
+        self.rounds.insert(round.id.clone(), round.clone());
+        self.announce_pools(round.currency);
+
+        Ok(RoundSnapshot {
+            pools: self.pools(round.currency),
+            round,
+            wallet,
+            refunded: false,
+        })
+    }
+
+    fn apply_discard(
+        &mut self,
+        op: DiscardOp,
+        now: DateTime<Utc>,
+    ) -> Result<RoundSnapshot, StoreError> {
+        let mut round = self
+            .rounds
+            .get(&op.round_id)
+            .ok_or(StoreError::RoundNotFound)?
+            .clone();
+        if round.user_id != op.user_id {
+            return Err(GameError::NotYourRound.into());
+        }
+        if round.status != RoundStatus::Active {
+            return Err(GameError::RoundNotActive.into());
+        }
+        let indices: Vec<usize> = op.replacements.iter().map(|(idx, _)| *idx).collect();
+        game::check_discard(&indices).map_err(GameError::InvalidDiscard)?;
+        let currency = round.currency;
+        let remote_wallet = round.remote_wallet;
+        let cost = op.fee.total()?;
+
+        let user = self
+            .users
+            .get(&op.user_id)
+            .ok_or(StoreError::UserNotFound)?;
+        if !remote_wallet && user.available(currency) < cost {
+            return Err(GameError::InsufficientFunds.into());
+        }
+        self.check_limits(&op.user_id, currency, cost, now)?;
+        let mut pools = self.pools(currency);
+        let (win, house) = Self::fund_pools(&mut pools, &op.fee)?;
+        let (player, wallet) =
+            self.player_funds_after(&op.user_id, currency, -cost, remote_wallet)?;
+
+        // nothing can fail from here on
+        self.pools.insert(currency, pools);
+        self.set_player_funds(&op.user_id, currency, wallet, remote_wallet);
+        if let Some(user) = self.users.get_mut(&op.user_id) {
+            limits::record_play(&mut user.limits, now);
+        }
+        self.post(
+            currency,
+            Some(&op.round_id),
+            &[
+                (player, -cost, LedgerReason::DiscardFee),
+                (Account::WinPool, win, LedgerReason::DiscardFee),
+                (Account::HouseProfit, house, LedgerReason::DiscardFee),
+            ],
+            now,
+        );
+        self.record_tx(
+            &op.user_id,
+            currency,
+            TransactionKind::DiscardFee,
+            -cost,
+            Some(&op.round_id),
+            now,
+        );
+
+        for (idx, card) in op.replacements {
+            round.cards[idx] = card;
+        }
+        round.draws_used += 1;
+        self.rounds.insert(round.id.clone(), round.clone());
+        self.announce_pools(currency);
+
+        Ok(RoundSnapshot {
+            pools: self.pools(round.currency),
+            round,
+            wallet,
+            refunded: false,
+        })
+    }
+
+    fn settle_round(
+        &mut self,
+        settlement: Settlement,
+        now: DateTime<Utc>,
+    ) -> Result<RoundSnapshot, StoreError> {
+        let mut round = self
+            .rounds
+            .get(&settlement.round_id)
+            .ok_or(StoreError::RoundNotFound)?
+            .clone();
+        if round.user_id != settlement.user_id {
+            return Err(GameError::NotYourRound.into());
+        }
+        if round.status != RoundStatus::Active {
+            return Err(GameError::RoundNotActive.into());
+        }
+        if round.draws_used != settlement.expected_draws {
+            return Err(GameError::RoundChanged.into());
+        }
+        let ante = round.ante;
+        let currency = round.currency;
+        let remote_wallet = round.remote_wallet;
+        if !self.users.contains_key(&settlement.user_id) {
+            return Err(StoreError::UserNotFound);
+        }
+        if settlement.folded && settlement.payout != Money::ZERO {
+            return Err(GameError::InvalidSettlement("a folded round pays nothing").into());
+        }
+        if !settlement.payout.is_positive() && settlement.share.total()? != ante {
+            return Err(
+                GameError::InvalidSettlement("settlement does not split the full stake").into(),
+            );
+        }
+
+        // the pools as they will stand, worked out on a copy with the
+        // round's reservation released
+        let mut pools = self.pools(currency);
+        pools.reserved -= round.reserved;
+
+        // only a payout above the reservation can outrun the pool; hand the
+        // ante back instead
+        let refunded = pools.available().try_add(ante)? < settlement.payout;
+        let credit = if refunded {
+            ante
+        } else if settlement.payout.is_positive() {
+            settlement.payout
+        } else {
+            Money::ZERO
+        };
+        let (player, wallet) =
+            self.player_funds_after(&settlement.user_id, currency, credit, remote_wallet)?;
+        let stake = Account::InPlay(settlement.round_id.clone());
+        let legs = if refunded {
+            vec![
+                (stake, -ante, LedgerReason::Refund),
+                (player, ante, LedgerReason::Refund),
+            ]
+        } else if settlement.payout.is_positive() {
+            // the stake is forfeited to the pool, which then pays the win
+            pools.win_pool = pools.win_pool.try_add(ante)?.try_sub(settlement.payout)?;
+            vec![
+                (stake, -ante, LedgerReason::PoolSplit),
+                (Account::WinPool, ante, LedgerReason::PoolSplit),
+                (Account::WinPool, -settlement.payout, LedgerReason::Payout),
+                (player, settlement.payout, LedgerReason::Payout),
+            ]
+        } else {
+            let (win, house) = Self::fund_pools(&mut pools, &settlement.share)?;
+            vec![
+                (stake, -ante, LedgerReason::PoolSplit),
+                (Account::WinPool, win, LedgerReason::PoolSplit),
+                (Account::HouseProfit, house, LedgerReason::HouseCut),
+            ]
+        };
+
+        // nothing can fail from here on
+        self.pools.insert(currency, pools);
+        self.post(currency, Some(&settlement.round_id), &legs, now);
+        self.set_player_funds(&settlement.user_id, currency, wallet, remote_wallet);
+        let kind = if refunded {
+            TransactionKind::Refund
+        } else {
+            TransactionKind::Payout
+        };
+        self.record_tx(
+            &settlement.user_id,
+            currency,
+            kind,
+            credit,
+            Some(&settlement.round_id),
+            now,
+        );
+
+        round.status = if settlement.folded {
+            RoundStatus::Folded
+        } else {
+            RoundStatus::Revealed
+        };
+        self.rounds.insert(round.id.clone(), round.clone());
+        if remote_wallet && credit.is_positive() {
+            let tx = WalletTx::settlement(&round, credit, refunded);
+            self.wallet_credits.insert(tx.transaction_id.clone(), tx);
+        }
+        self.announce_pools(currency);
+        if !refunded && credit.is_positive() {
+            self.notices.push(StoreNotice::Payout {
+                round_id: round.id.clone(),
+                currency,
+                ante,
+                payout: credit,
+            });
+        }
+
+        Ok(RoundSnapshot {
+            round,
+            wallet,
+            pools: self.pools(currency),
+            refunded,
+        })
+    }
+
+    fn void_round(
+        &mut self,
+        round_id: &str,
+        reason: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(RoundSnapshot, Money), StoreError> {
+        let mut round = self
+            .rounds
+            .get(round_id)
+            .ok_or(StoreError::RoundNotFound)?
+            .clone();
+        if round.status != RoundStatus::Active {
+            return Err(GameError::RoundNotActive.into());
+        }
+        let (user_id, currency, ante, reserved, remote_wallet) = (
+            round.user_id.clone(),
+            round.currency,
+            round.ante,
+            round.reserved,
+            round.remote_wallet,
+        );
+
+        // discard fees are taken back from wherever the ledger says they went
+        let (mut fee_win, mut fee_house) = (Money::ZERO, Money::ZERO);
+        for e in self.ledger.iter().filter(|e| {
+            e.round_id.as_deref() == Some(round_id) && e.reason == LedgerReason::DiscardFee
+        }) {
+            match e.account {
+                Account::WinPool => fee_win = fee_win.try_add(e.amount)?,
+                Account::HouseProfit => fee_house = fee_house.try_add(e.amount)?,
+                _ => {}
+            }
+        }
+        let fees = fee_win.try_add(fee_house)?;
+        let refund = ante.try_add(fees)?;
+
+        let mut pools = self.pools(currency);
+        // the round's own reservation is released first, so only other
+        // rounds' reservations can stand in the way
+        if pools.available().try_add(reserved)? < fee_win || pools.house_profit < fee_house {
+            return Err(GameError::PoolsCannotRefund.into());
+        }
+        pools.reserved -= reserved;
+        pools.win_pool -= fee_win;
+        pools.house_profit -= fee_house;
+        let (player, wallet) =
+            self.player_funds_after(&user_id, currency, refund, remote_wallet)?;
+
+        // nothing can fail from here on
+        self.pools.insert(currency, pools);
+        self.set_player_funds(&user_id, currency, wallet, remote_wallet);
+        self.post(
+            currency,
+            Some(round_id),
+            &[
+                (
+                    Account::InPlay(round_id.to_string()),
+                    -ante,
+                    LedgerReason::Refund,
+                ),
+                (Account::WinPool, -fee_win, LedgerReason::Refund),
+                (Account::HouseProfit, -fee_house, LedgerReason::Refund),
+                (player, refund, LedgerReason::Refund),
+            ],
+            now,
+        );
+        self.record_tx(
+            &user_id,
+            currency,
+            TransactionKind::Refund,
+            refund,
+            Some(round_id),
+            now,
+        );
+        self.note_last_tx(reason);
+
+        round.status = RoundStatus::Voided;
+        self.rounds.insert(round.id.clone(), round.clone());
+        self.announce_pools(currency);
+
+        Ok((
+            RoundSnapshot {
+                round,
+                wallet,
+                pools: self.pools(currency),
+                refunded: true,
+            },
+            refund,
+        ))
+    }
+
+    fn add_to_pools(
+        &mut self,
+        currency: Currency,
+        win: Money,
+        house: Money,
+        now: DateTime<Utc>,
+    ) -> Result<(), StoreError> {
+        let total = win.try_add(house)?;
+        let pools = self.pools_mut(currency);
+        let win_pool = pools.win_pool.try_add(win)?;
+        let house_profit = pools.house_profit.try_add(house)?;
+        pools.win_pool = win_pool;
+        pools.house_profit = house_profit;
+        self.post(
+            currency,
+            None,
+            &[
+                (Account::External, -total, LedgerReason::Adjustment),
+                (Account::WinPool, win, LedgerReason::Adjustment),
+                (Account::HouseProfit, house, LedgerReason::Adjustment),
+            ],
+            now,
+        );
+        self.announce_pools(currency);
+        Ok(())
+    }
+
+    fn sub_from_win_pool(
+        &mut self,
+        currency: Currency,
+        amount: Money,
+        now: DateTime<Utc>,
+    ) -> Result<(), StoreError> {
+        let pools = self.pools_mut(currency);
+        // money reserved for open rounds stays put
+        if pools.available() < amount {
+            return Err(StoreError::WinPoolShort);
+        }
+        pools.win_pool -= amount;
+        self.post(
+            currency,
+            None,
+            &[
+                (Account::WinPool, -amount, LedgerReason::Adjustment),
+                (Account::External, amount, LedgerReason::Adjustment),
+            ],
+            now,
+        );
+        self.announce_pools(currency);
+        Ok(())
+    }
+
+    fn sub_from_house_profit(
+        &mut self,
+        currency: Currency,
+        amount: Money,
+        now: DateTime<Utc>,
+    ) -> Result<(), StoreError> {
+        if !amount.is_positive() {
+            return Err(GameError::InvalidAmount("amount must be positive").into());
+        }
+        let pools = self.pools_mut(currency);
+        if pools.house_profit < amount {
+            return Err(StoreError::HouseProfitShort);
+        }
+        pools.house_profit -= amount;
+        self.post(
+            currency,
+            None,
+            &[
+                (Account::HouseProfit, -amount, LedgerReason::Withdrawal),
+                (Account::External, amount, LedgerReason::Withdrawal),
+            ],
+            now,
+        );
+        self.announce_pools(currency);
+        Ok(())
+    }
+
+    /// Records `req`, a new pending request. Withdrawals hold its amount.
+    fn create_cashier_request(
+        &mut self,
+        req: CashierRequest,
+    ) -> Result<CashierRequest, StoreError> {
+        let user = self
+            .users
+            .get_mut(&req.user_id)
+            .ok_or(StoreError::UserNotFound)?;
+        if req.kind == CashierKind::Withdrawal {
+            if user.available(req.currency) < req.amount {
+                return Err(GameError::InsufficientFunds.into());
+            }
+            user.hold(req.currency, req.amount)?;
+        }
+        self.cashier.insert(req.id.clone(), req.clone());
+        Ok(req)
+    }
+
+    fn mark_cashier_submitted(
+        &mut self,
+        id: &str,
+        provider_ref: &str,
+        now: DateTime<Utc>,
+    ) -> Result<CashierRequest, StoreError> {
+        let req = self
+            .cashier
+            .get_mut(id)
+            .ok_or(StoreError::CashierRequestNotFound)?;
+        if req.status != CashierStatus::Pending {
+            return Err(StoreError::CashierNotPending);
+        }
+        req.provider_ref = Some(provider_ref.to_string());
+        req.requires_approval = false;
+        req.updated_at = now;
+        Ok(req.clone())
+    }
+
+    fn resolve_cashier_request(
+        &mut self,
+        id: &str,
+        approved: bool,
+        reason: Option<String>,
+        now: DateTime<Utc>,
+    ) -> Result<CashierRequest, StoreError> {
+        let mut req = self
+            .cashier
+            .get(id)
+            .ok_or(StoreError::CashierRequestNotFound)?
+            .clone();
+        if req.status != CashierStatus::Pending {
+            return Err(StoreError::CashierNotPending);
+        }
+        // changed on a copy, so a failure leaves the user as they were
+        let mut user = self
+            .users
+            .get(&req.user_id)
+            .ok_or(StoreError::UserNotFound)?
+            .clone();
+        if req.kind == CashierKind::Withdrawal {
+            user.hold(req.currency, -req.amount)?;
+        }
+        let (delta, ledger_reason, kind) = match req.kind {
+            CashierKind::Deposit => (req.amount, LedgerReason::Deposit, TransactionKind::Deposit),
+            CashierKind::Withdrawal => (
+                -req.amount,
+                LedgerReason::Withdrawal,
+                TransactionKind::Withdrawal,
+            ),
+        };
+        if approved {
+            user.credit(req.currency, delta)?;
+        }
+
+        // nothing can fail from here on
+        self.users.insert(user.id.clone(), user);
+        if approved {
+            self.post(
+                req.currency,
+                None,
+                &[
+                    (Account::External, -delta, ledger_reason),
+                    (Account::User(req.user_id.clone()), delta, ledger_reason),
+                ],
+                now,
+            );
+            self.record_tx(&req.user_id, req.currency, kind, delta, None, now);
+        }
+
+        req.status = if approved {
+            CashierStatus::Approved
+        } else {
+            CashierStatus::Rejected
         };
-        state.users.insert(u.id.clone(), u);
-        state.pools.win_pool = 50_000;
-        state.pools.house_profit = 0;
+        req.reason = reason;
+        req.updated_at = now;
+        self.cashier.insert(req.id.clone(), req.clone());
+        Ok(req)
+    }
+
+    fn wallet_credit_delivered(&mut self, transaction_id: &str) {
+        self.wallet_credits.remove(transaction_id);
+    }
+
+    fn claim_idempotency(
+        &mut self,
+        key: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        ttl: Duration,
+    ) -> IdempotencyClaim {
+        self.expire_idempotency(now - ttl);
+
+        if let Some(rec) = self.idempotency.get(key) {
+            if rec.fingerprint != fingerprint {
+                return IdempotencyClaim::Mismatch;
+            }
+            return match &rec.response {
+                Some(resp) => IdempotencyClaim::Replay(resp.clone()),
+                None => IdempotencyClaim::InFlight,
+            };
+        }
+
+        self.idempotency.insert(
+            key.to_string(),
+            IdempotencyRecord {
+                key: key.to_string(),
+                fingerprint: fingerprint.to_string(),
+                response: None,
+                created_at: now,
+            },
+        );
+        IdempotencyClaim::Claimed
+    }
+
+    /// Drops the idempotency records created at or before `before`.
+    fn expire_idempotency(&mut self, before: DateTime<Utc>) {
+        self.idempotency.retain(|_, r| r.created_at > before);
+    }
+
+    fn finish_idempotency(&mut self, key: &str, response: Option<CachedResponse>) {
+        match response {
+            Some(resp) => {
+                if let Some(rec) = self.idempotency.get_mut(key) {
+                    rec.response = Some(resp);
+                }
+            }
+            None => {
+                self.idempotency.remove(key);
+            }
+        }
+    }
+}
+
+impl InMem {
+    pub fn new_demo() -> Self {
+        InMem::from_state(InMemState::demo(Utc::now()))
+    }
+
+    fn from_state(mut state: InMemState) -> Self {
+        state.reindex();
+        // seeding announced to nobody
+        state.notices.clear();
         InMem {
             inner: Arc::new(Mutex::new(state)),
+            notices: broadcast::channel(NOTICE_BUFFER).0,
+        }
+    }
+
+    /// Runs `op` on the state and sends the notices it left. The lock is
+    /// held throughout, so notices go out in the order the changes were
+    /// made. Nothing is undone here: an `op` does everything that can fail
+    /// before it changes anything, so an error leaves the state as it was.
+    fn update<T>(
+        &self,
+        op: impl FnOnce(&mut InMemState) -> Result<T, StoreError>,
+    ) -> Result<T, StoreError> {
+        let mut s = self.inner.lock();
+        let out = op(&mut s);
+        let notices = std::mem::take(&mut s.notices);
+        if out.is_ok() {
+            self.send(notices);
+        }
+        out
+    }
+
+    fn send(&self, notices: Vec<StoreNotice>) {
+        for notice in notices {
+            // no subscribers is fine
+            let _ = self.notices.send(notice);
         }
     }
 
@@ -42,65 +1269,236 @@ impl InMem {
 
 #[async_trait::async_trait]
 pub trait Store {
-    async fn create_user_if_unique(&self, name: &str, password: &str) -> Result<User, String>;
-    async fn login_user_if_exists(&self, name: &str, password: &str) -> Result<User, String>;
+    /// Creates a user; `password_hash` comes from `auth::password::hash`.
+    async fn create_user_if_unique(
+        &self,
+        name: &str,
+        password_hash: &str,
+    ) -> Result<User, StoreError>;
+    async fn find_user_by_name(&self, name: &str) -> Option<User>;
+    /// Replaces the stored password hash, e.g. after upgrading a legacy entry.
+    async fn set_password_hash(&self, user_id: &str, password_hash: &str)
+        -> Result<(), StoreError>;
+    async fn create_session(
+        &self,
+        user_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AuthSession, StoreError>;
+    async fn get_session(&self, session_id: &str) -> Option<AuthSession>;
+    /// Moves an active session to the next refresh generation and extends
+    /// it to `expires_at`. Presenting an older generation means the refresh
+    /// token leaked, so the session is revoked instead.
+    async fn refresh_session(
+        &self,
+        session_id: &str,
+        generation: u32,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AuthSession, StoreError>;
+    async fn revoke_session(&self, session_id: &str) -> Result<(), StoreError>;
     async fn get_user(&self, user_id: &str) -> Option<User>;
-    async fn update_user_wallet(&self, user_id: &str, new_wallet: i64) -> Result<(), String>;
-    async fn create_round(
+    /// Users whose id equals `query` or whose name contains it (ignoring
+    /// case), sorted by name; all users when `query` is `None`.
+    async fn list_users(&self, query: Option<&str>, limit: usize) -> Vec<User>;
+    async fn set_user_frozen(&self, user_id: &str, frozen: bool) -> Result<User, StoreError>;
+    async fn set_user_role(&self, user_id: &str, role: Role) -> Result<User, StoreError>;
+    /// Operator credit (positive `delta`) or debit of a wallet, recorded with
+    /// `reason`. A debit can't touch money held by pending withdrawals.
+    async fn adjust_wallet(
+        &self,
+        user_id: &str,
+        currency: Currency,
+        delta: Money,
+        reason: &str,
+    ) -> Result<User, StoreError>;
+    /// Table new rounds are started under.
+    async fn get_paytable(&self) -> Paytable;
+    /// Replaces the table for rounds started from now on; open rounds keep
+    /// theirs.
+    async fn set_paytable(&self, paytable: Paytable) -> Result<Paytable, StoreError>;
+    /// The player's limits with any changes that have come due applied.
+    async fn get_limits(&self, user_id: &str) -> Option<PlayerLimits>;
+    /// Tightening changes apply at once; loosening ones after `delay`.
+    async fn request_limit_change(
         &self,
-        user_id: String,
-        ante: i64,
-        cards: Vec<Card>,
-    ) -> Result<String, String>;
+        user_id: &str,
+        change: LimitChange,
+        delay: Duration,
+    ) -> Result<PlayerLimits, StoreError>;
+    /// Starts or extends a cool-off or self-exclusion ending at `until`.
+    async fn block_play(
+        &self,
+        user_id: &str,
+        kind: PlayBlock,
+        until: DateTime<Utc>,
+    ) -> Result<PlayerLimits, StoreError>;
+    async fn update_user_wallet(
+        &self,
+        user_id: &str,
+        currency: Currency,
+        new_wallet: Money,
+    ) -> Result<(), StoreError>;
     async fn get_round(&self, round_id: &str) -> Option<Round>;
-    async fn update_round_cards(&self, round_id: &str, cards: Vec<Card>) -> Result<(), String>;
-    async fn set_round_status(&self, round_id: &str, status: RoundStatus) -> Result<(), String>;
-    async fn get_pools(&self) -> Pools;
-    async fn add_to_pools(&self, win: i64, house: i64);
-    async fn sub_from_win_pool(&self, amount: i64) -> Result<(), String>;
+    /// Newest first.
+    async fn list_rounds(&self, user_id: Option<&str>, status: Option<RoundStatus>) -> Vec<Round>;
+    /// Rounds of `user_id` still in `Active` state, newest first.
+    async fn get_active_rounds(&self, user_id: &str) -> Vec<Round>;
+    /// Ledger lines booked for `round_id`, oldest first.
+    async fn round_ledger(&self, round_id: &str) -> Vec<LedgerEntry>;
+    /// Checks the account isn't frozen, the player's limits, wallet,
+    /// active-round cap and pool capacity, then debits the ante, reserves the
+    /// round's maximum payout and creates the round, all as one step.
+    async fn start_round(&self, new: NewRound) -> Result<RoundSnapshot, StoreError>;
+    /// Checks ownership, status, wallet and the player's limits, then
+    /// charges the discard fee into the pools, replaces the cards and bumps
+    /// `draws_used`, all as one step.
+    async fn apply_discard(&self, op: DiscardOp) -> Result<RoundSnapshot, StoreError>;
+    /// Moves an `Active` round to `Revealed` (or `Folded`), releases its
+    /// reservation and pays it out. Only one caller can ever settle a given
+    /// round.
+    async fn settle_round(&self, settlement: Settlement) -> Result<RoundSnapshot, StoreError>;
+    /// Cancels an `Active` round: the ante and any discard fees go back to
+    /// the player and the reservation is released. Returns the round after
+    /// the void along with the amount refunded.
+    async fn void_round(
+        &self,
+        round_id: &str,
+        reason: &str,
+    ) -> Result<(RoundSnapshot, Money), StoreError>;
+    async fn get_pools(&self, currency: Currency) -> Pools;
+    /// Notices of pool, wallet and payout changes from now on, in the order
+    /// they were made. A subscriber that falls behind gets `Lagged` and
+    /// should re-read what it watches.
+    fn subscribe(&self) -> broadcast::Receiver<StoreNotice>;
+    async fn add_to_pools(
+        &self,
+        currency: Currency,
+        win: Money,
+        house: Money,
+    ) -> Result<(), StoreError>;
+    async fn sub_from_win_pool(&self, currency: Currency, amount: Money) -> Result<(), StoreError>;
+    /// Moves `amount` of house profit out of the game.
+    async fn sub_from_house_profit(
+        &self,
+        currency: Currency,
+        amount: Money,
+    ) -> Result<(), StoreError>;
+    /// Wallet history of `user_id`, newest first.
+    async fn list_transactions(
+        &self,
+        user_id: &str,
+        filter: TransactionFilter,
+    ) -> Vec<WalletTransaction>;
+    /// Records a new cashier request. Withdrawals place a hold on the
+    /// wallet and fail if the available balance is too low.
+    async fn create_cashier_request(
+        &self,
+        user_id: &str,
+        kind: CashierKind,
+        currency: Currency,
+        amount: Money,
+        requires_approval: bool,
+    ) -> Result<CashierRequest, StoreError>;
+    async fn get_cashier_request(&self, id: &str) -> Option<CashierRequest>;
+    /// Newest first.
+    async fn list_cashier_requests(
+        &self,
+        user_id: Option<&str>,
+        status: Option<CashierStatus>,
+    ) -> Vec<CashierRequest>;
+    /// Marks a pending request as handed to the provider, clearing any
+    /// outstanding approval requirement.
+    async fn mark_cashier_submitted(
+        &self,
+        id: &str,
+        provider_ref: &str,
+    ) -> Result<CashierRequest, StoreError>;
+    /// Finalises a pending request: approved deposits credit the wallet,
+    /// approved withdrawals debit it, and rejected withdrawals release the
+    /// hold.
+    async fn resolve_cashier_request(
+        &self,
+        id: &str,
+        approved: bool,
+        reason: Option<String>,
+    ) -> Result<CashierRequest, StoreError>;
+    /// Settlement credits a remote wallet hasn't confirmed yet. Settling a
+    /// round on a remote wallet with something to pay adds its credit here.
+    async fn pending_wallet_credits(&self) -> Vec<WalletTx>;
+    /// Forgets the pending credit `transaction_id` once the wallet has
+    /// taken it. Unknown ids are ignored.
+    async fn wallet_credit_delivered(&self, transaction_id: &str) -> Result<(), StoreError>;
+    /// Verifies wallets, pools and stakes against the ledger.
+    async fn reconcile(&self) -> ReconcileReport;
+    /// Claims `key` for a request with `fingerprint`. Records older than `ttl`
+    /// are treated as absent.
+    async fn claim_idempotency(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        ttl: Duration,
+    ) -> IdempotencyClaim;
+    /// Stores the response for a claimed key, or releases the claim when
+    /// `response` is `None` so the request can be retried.
+    async fn finish_idempotency(&self, key: &str, response: Option<CachedResponse>);
 }
 
 /// In-memory implementation
 #[async_trait::async_trait]
 impl Store for InMem {
-    async fn create_user_if_unique(&self, name: &str, password: &str) -> Result<User, String> {
-        let mut s = self.inner.lock();
-        if s.users.values().any(|u| u.name == name) {
-            return Err("name already exists".into());
-        }
+    async fn create_user_if_unique(
+        &self,
+        name: &str,
+        password_hash: &str,
+    ) -> Result<User, StoreError> {
+        self.update(|s| s.create_user(new_id(), name, password_hash, Utc::now()))
+    }
 
-        let id = Uuid::new_v4().to_string();
-        let user = User {
-            id: id.clone(),
-            name: name.to_string(),
-            password: password.to_string(),
-            wallet: 1000,
-        };
+    async fn find_user_by_name(&self, name: &str) -> Option<User> {
+        let s = self.inner.lock();
+        s.users.values().find(|u| u.name == name).cloned()
+    }
 
-        s.users.insert(id.clone(), user.clone());
-        Ok(user)
+    async fn set_password_hash(
+        &self,
+        user_id: &str,
+        password_hash: &str,
+    ) -> Result<(), StoreError> {
+        self.update(|s| s.set_password_hash(user_id, password_hash))
     }
 
-    async fn login_user_if_exists(&self, name: &str, password: &str) -> Result<User, String> {
+    async fn create_session(
+        &self,
+        user_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AuthSession, StoreError> {
+        self.update(|s| s.create_session(new_id(), user_id, expires_at, Utc::now()))
+    }
+
+    async fn get_session(&self, session_id: &str) -> Option<AuthSession> {
+        let s = self.inner.lock();
+        s.sessions.get(session_id).cloned()
+    }
+
+    async fn refresh_session(
+        &self,
+        session_id: &str,
+        generation: u32,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AuthSession, StoreError> {
         let mut s = self.inner.lock();
-        if !s
-            .users
-            .values()
-            .any(|u| u.name == name && u.password == password)
+        let out = s.refresh_session(session_id, generation, expires_at, Utc::now());
+        if out
+            .as_ref()
+            .is_err_and(|e| *e == StoreError::RefreshTokenReused)
         {
-            return Err("invalid credentials".into());
+            s.revoke_session(session_id)?;
         }
+        out
+    }
 
-        let id = Uuid::new_v4().to_string();
-        let user = User {
-            id: id.clone(),
-            name: name.to_string(),
-            password: password.to_string(),
-            wallet: 1000,
-        };
-
-        s.users.insert(id.clone(), user.clone());
-        Ok(user)
+    async fn revoke_session(&self, session_id: &str) -> Result<(), StoreError> {
+        self.update(|s| s.revoke_session(session_id))
     }
 
     async fn get_user(&self, user_id: &str) -> Option<User> {
@@ -108,34 +1506,82 @@ impl Store for InMem {
         s.users.get(user_id).cloned()
     }
 
-    async fn update_user_wallet(&self, user_id: &str, new_wallet: i64) -> Result<(), String> {
-        let mut s = self.inner.lock();
-        match s.users.get_mut(user_id) {
-            Some(u) => {
-                u.wallet = new_wallet;
-                Ok(())
-            }
-            None => Err("user not found".into()),
-        }
+    async fn list_users(&self, query: Option<&str>, limit: usize) -> Vec<User> {
+        let s = self.inner.lock();
+        let query = query.map(str::to_lowercase);
+        let mut users: Vec<User> = s
+            .users
+            .values()
+            .filter(|u| {
+                query
+                    .as_deref()
+                    .is_none_or(|q| u.id == q || u.name.to_lowercase().contains(q))
+            })
+            .cloned()
+            .collect();
+        users.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+        users.truncate(limit);
+        users
     }
 
-    async fn create_round(
+    async fn set_user_frozen(&self, user_id: &str, frozen: bool) -> Result<User, StoreError> {
+        self.update(|s| s.set_user_frozen(user_id, frozen))
+    }
+
+    async fn set_user_role(&self, user_id: &str, role: Role) -> Result<User, StoreError> {
+        self.update(|s| s.set_user_role(user_id, role))
+    }
+
+    async fn get_paytable(&self) -> Paytable {
+        self.inner.lock().paytable
+    }
+
+    async fn set_paytable(&self, paytable: Paytable) -> Result<Paytable, StoreError> {
+        self.update(|s| s.set_paytable(paytable))
+    }
+
+    async fn adjust_wallet(
         &self,
-        user_id: String,
-        ante: i64,
-        cards: Vec<Card>,
-    ) -> Result<String, String> {
-        let mut s = self.inner.lock();
-        let id = Uuid::new_v4().to_string();
-        let r = Round {
-            id: id.clone(),
-            user_id,
-            cards,
-            ante,
-            status: crate::models::RoundStatus::Active,
-        };
-        s.rounds.insert(id.clone(), r);
-        Ok(id)
+        user_id: &str,
+        currency: Currency,
+        delta: Money,
+        reason: &str,
+    ) -> Result<User, StoreError> {
+        self.update(|s| s.adjust_wallet(user_id, currency, delta, reason, Utc::now()))
+    }
+
+    async fn update_user_wallet(
+        &self,
+        user_id: &str,
+        currency: Currency,
+        new_wallet: Money,
+    ) -> Result<(), StoreError> {
+        self.update(|s| s.update_user_wallet(user_id, currency, new_wallet, Utc::now()))
+    }
+
+    async fn get_limits(&self, user_id: &str) -> Option<PlayerLimits> {
+        let s = self.inner.lock();
+        let mut player = s.users.get(user_id)?.limits.clone();
+        limits::apply_due(&mut player, Utc::now());
+        Some(player)
+    }
+
+    async fn request_limit_change(
+        &self,
+        user_id: &str,
+        change: LimitChange,
+        delay: Duration,
+    ) -> Result<PlayerLimits, StoreError> {
+        self.update(|s| s.request_limit_change(user_id, change, delay, Utc::now()))
+    }
+
+    async fn block_play(
+        &self,
+        user_id: &str,
+        kind: PlayBlock,
+        until: DateTime<Utc>,
+    ) -> Result<PlayerLimits, StoreError> {
+        self.update(|s| s.block_play(user_id, kind, until))
     }
 
     async fn get_round(&self, round_id: &str) -> Option<Round> {
@@ -143,45 +1589,198 @@ impl Store for InMem {
         s.rounds.get(round_id).cloned()
     }
 
-    async fn update_round_cards(&self, round_id: &str, cards: Vec<Card>) -> Result<(), String> {
-        let mut s = self.inner.lock();
-        match s.rounds.get_mut(round_id) {
-            Some(r) => {
-                r.cards = cards;
-                Ok(())
-            }
-            None => Err("round not found".into()),
-        }
+    async fn list_rounds(&self, user_id: Option<&str>, status: Option<RoundStatus>) -> Vec<Round> {
+        let s = self.inner.lock();
+        let mut rounds: Vec<Round> = s
+            .rounds
+            .values()
+            .filter(|r| user_id.is_none_or(|u| r.user_id == u))
+            .filter(|r| status.as_ref().is_none_or(|st| r.status == *st))
+            .cloned()
+            .collect();
+        rounds.sort_by_key(|r| std::cmp::Reverse(r.created_at));
+        rounds
     }
 
-    async fn set_round_status(&self, round_id: &str, status: RoundStatus) -> Result<(), String> {
-        let mut s = self.inner.lock();
-        match s.rounds.get_mut(round_id) {
-            Some(r) => {
-                r.status = status;
-                Ok(())
-            }
-            None => Err("round not found".into()),
-        }
+    async fn get_active_rounds(&self, user_id: &str) -> Vec<Round> {
+        let s = self.inner.lock();
+        let mut rounds: Vec<Round> = s
+            .rounds
+            .values()
+            .filter(|r| r.user_id == user_id && r.status == RoundStatus::Active)
+            .cloned()
+            .collect();
+        rounds.sort_by_key(|r| std::cmp::Reverse(r.created_at));
+        rounds
     }
 
-    async fn get_pools(&self) -> Pools {
+    async fn round_ledger(&self, round_id: &str) -> Vec<LedgerEntry> {
         let s = self.inner.lock();
-        s.pools.clone()
+        (s.ledger.iter())
+            .filter(|e| e.round_id.as_deref() == Some(round_id))
+            .cloned()
+            .collect()
     }
 
-    async fn add_to_pools(&self, win: i64, house: i64) {
-        let mut s = self.inner.lock();
-        s.pools.win_pool += win;
-        s.pools.house_profit += house;
+    async fn start_round(&self, new: NewRound) -> Result<RoundSnapshot, StoreError> {
+        self.update(|s| s.start_round(new_id(), new, Utc::now()))
     }
 
-    async fn sub_from_win_pool(&self, amount: i64) -> Result<(), String> {
-        let mut s = self.inner.lock();
-        if s.pools.win_pool < amount {
-            return Err("win_pool short".into());
-        }
-        s.pools.win_pool -= amount;
-        Ok(())
+    async fn apply_discard(&self, op: DiscardOp) -> Result<RoundSnapshot, StoreError> {
+        self.update(|s| s.apply_discard(op, Utc::now()))
+    }
+
+    async fn settle_round(&self, settlement: Settlement) -> Result<RoundSnapshot, StoreError> {
+        self.update(|s| s.settle_round(settlement, Utc::now()))
+    }
+
+    async fn void_round(
+        &self,
+        round_id: &str,
+        reason: &str,
+    ) -> Result<(RoundSnapshot, Money), StoreError> {
+        self.update(|s| s.void_round(round_id, reason, Utc::now()))
+    }
+
+    async fn get_pools(&self, currency: Currency) -> Pools {
+        let s = self.inner.lock();
+        s.pools(currency)
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<StoreNotice> {
+        self.notices.subscribe()
+    }
+
+    async fn add_to_pools(
+        &self,
+        currency: Currency,
+        win: Money,
+        house: Money,
+    ) -> Result<(), StoreError> {
+        self.update(|s| s.add_to_pools(currency, win, house, Utc::now()))
+    }
+
+    async fn sub_from_win_pool(&self, currency: Currency, amount: Money) -> Result<(), StoreError> {
+        self.update(|s| s.sub_from_win_pool(currency, amount, Utc::now()))
+    }
+
+    async fn sub_from_house_profit(
+        &self,
+        currency: Currency,
+        amount: Money,
+    ) -> Result<(), StoreError> {
+        self.update(|s| s.sub_from_house_profit(currency, amount, Utc::now()))
+    }
+
+    async fn list_transactions(
+        &self,
+        user_id: &str,
+        filter: TransactionFilter,
+    ) -> Vec<WalletTransaction> {
+        let s = self.inner.lock();
+        s.transactions
+            .iter()
+            .rev()
+            .filter(|t| t.user_id == user_id)
+            .filter(|t| filter.before.is_none_or(|b| t.seq < b))
+            .filter(|t| filter.currency.is_none_or(|c| t.currency == c))
+            .filter(|t| filter.kind.is_none_or(|k| t.kind == k))
+            .filter(|t| filter.from.is_none_or(|f| t.created_at >= f))
+            .filter(|t| filter.to.is_none_or(|to| t.created_at < to))
+            .take(filter.limit)
+            .cloned()
+            .collect()
+    }
+
+    async fn create_cashier_request(
+        &self,
+        user_id: &str,
+        kind: CashierKind,
+        currency: Currency,
+        amount: Money,
+        requires_approval: bool,
+    ) -> Result<CashierRequest, StoreError> {
+        let req = pending_request(
+            new_id(),
+            user_id,
+            kind,
+            currency,
+            amount,
+            requires_approval,
+            Utc::now(),
+        );
+        self.update(|s| s.create_cashier_request(req))
+    }
+
+    async fn get_cashier_request(&self, id: &str) -> Option<CashierRequest> {
+        let s = self.inner.lock();
+        s.cashier.get(id).cloned()
+    }
+
+    async fn list_cashier_requests(
+        &self,
+        user_id: Option<&str>,
+        status: Option<CashierStatus>,
+    ) -> Vec<CashierRequest> {
+        let s = self.inner.lock();
+        let mut out: Vec<CashierRequest> = s
+            .cashier
+            .values()
+            .filter(|r| user_id.is_none_or(|u| r.user_id == u))
+            .filter(|r| status.is_none_or(|st| r.status == st))
+            .cloned()
+            .collect();
+        out.sort_by_key(|r| std::cmp::Reverse(r.created_at));
+        out
+    }
+
+    async fn mark_cashier_submitted(
+        &self,
+        id: &str,
+        provider_ref: &str,
+    ) -> Result<CashierRequest, StoreError> {
+        self.update(|s| s.mark_cashier_submitted(id, provider_ref, Utc::now()))
+    }
+
+    async fn resolve_cashier_request(
+        &self,
+        id: &str,
+        approved: bool,
+        reason: Option<String>,
+    ) -> Result<CashierRequest, StoreError> {
+        self.update(|s| s.resolve_cashier_request(id, approved, reason, Utc::now()))
+    }
+
+    async fn pending_wallet_credits(&self) -> Vec<WalletTx> {
+        let s = self.inner.lock();
+        s.wallet_credits.values().cloned().collect()
+    }
+
+    async fn wallet_credit_delivered(&self, transaction_id: &str) -> Result<(), StoreError> {
+        self.update(|s| {
+            s.wallet_credit_delivered(transaction_id);
+            Ok(())
+        })
+    }
+
+    async fn reconcile(&self) -> ReconcileReport {
+        let s = self.inner.lock();
+        ledger::reconcile(&s.ledger, s.users.values(), s.rounds.values(), &s.pools)
+    }
+
+    async fn claim_idempotency(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        ttl: Duration,
+    ) -> IdempotencyClaim {
+        self.inner
+            .lock()
+            .claim_idempotency(key, fingerprint, now, ttl)
+    }
+
+    async fn finish_idempotency(&self, key: &str, response: Option<CachedResponse>) {
+        self.inner.lock().finish_idempotency(key, response)
     }
 }
@@ -0,0 +1,122 @@
+//! JSON snapshots of an `InMem`, so the in-memory store can survive a
+//! restart without a database.
+
+use super::{InMem, InMemState};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Writes `data` to `path` through a temporary file that is synced and
+/// then renamed over it, so a crash leaves either the old file or the new
+/// one, never half of one.
+pub(super) fn write_atomic(path: &Path, data: &[u8]) -> Result<(), String> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_name);
+    let io_err = |e: std::io::Error| format!("can't write {}: {e}", path.display());
+    let mut tmp = File::create(&tmp_path).map_err(io_err)?;
+    tmp.write_all(data).map_err(io_err)?;
+    tmp.sync_all().map_err(io_err)?;
+    std::fs::rename(&tmp_path, path).map_err(io_err)
+}
+
+impl InMem {
+    /// Loads the snapshot at `path`, or starts from the demo state if
+    /// there is none yet.
+    pub fn load_snapshot(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        match std::fs::read(path) {
+            Ok(data) => {
+                let state: InMemState = serde_json::from_slice(&data)
+                    .map_err(|e| format!("corrupt snapshot {}: {e}", path.display()))?;
+                Ok(InMem::from_state(state))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(InMem::new_demo()),
+            Err(e) => Err(format!("can't read {}: {e}", path.display())),
+        }
+    }
+
+    /// The whole state as JSON, taken in one go so it is consistent.
+    pub fn snapshot(&self) -> Vec<u8> {
+        serde_json::to_vec(&*self.inner.lock()).expect("models serialize")
+    }
+
+    /// The snapshot without password hashes or sessions, for handing to
+    /// an operator. It can't be loaded back.
+    pub fn redacted_snapshot(&self) -> Vec<u8> {
+        let mut state = serde_json::to_value(&*self.inner.lock()).expect("models serialize");
+        if let Some(users) = state["users"].as_object_mut() {
+            for user in users.values_mut().filter_map(|u| u.as_object_mut()) {
+                user.remove("password_hash");
+            }
+        }
+        if let Some(state) = state.as_object_mut() {
+            state.remove("sessions");
+        }
+        serde_json::to_vec(&state).expect("models serialize")
+    }
+
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        write_atomic(path.as_ref(), &self.snapshot())
+    }
+}
+
+/// An `InMem` and the file its snapshots go to. Handed to the admin routes
+/// as an extension, so it can be saved or downloaded on demand.
+pub struct Snapshots {
+    mem: InMem,
+    path: PathBuf,
+}
+
+pub type SharedSnapshots = Arc<Snapshots>;
+
+impl Snapshots {
+    pub fn new(mem: InMem, path: impl Into<PathBuf>) -> SharedSnapshots {
+        Arc::new(Snapshots {
+            mem,
+            path: path.into(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes a snapshot to the file and returns its size in bytes.
+    pub async fn save(&self) -> Result<usize, String> {
+        let data = self.mem.snapshot();
+        let path = self.path.clone();
+        let len = data.len();
+        tokio::task::spawn_blocking(move || write_atomic(&path, &data))
+            .await
+            .map_err(|e| format!("snapshot task failed: {e}"))??;
+        Ok(len)
+    }
+
+    /// A fresh snapshot with password hashes and sessions left out, without
+    /// touching the file.
+    pub fn export(&self) -> Vec<u8> {
+        self.mem.redacted_snapshot()
+    }
+
+    /// Saves every `every` until the process exits. Failures are logged
+    /// and tried again next time. Panics if `every` is zero.
+    pub fn save_every(self: &Arc<Self>, every: Duration) -> tokio::task::JoinHandle<()> {
+        let snapshots = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            // the first tick fires straight away
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                match snapshots.save().await {
+                    Ok(len) => info!("saved {} byte snapshot", len),
+                    Err(e) => error!("snapshot failed: {}", e),
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,311 @@
+//! A snapshot store on SQLite: the in-memory state kept as rows, one JSON
+//! `data` blob per record. It is not queried. `open` loads every row into
+//! memory, all reads are served from there, and each change's rows are
+//! written back in a single SQLite transaction before the change is
+//! acknowledged. The only other columns are the ones the schema itself
+//! uses: keys, references to users, the unique user name and the
+//! idempotency expiry.
+
+use super::{Durable, InMemState, Sink};
+use crate::models::{
+    AuthSession, CashierRequest, Currency, IdempotencyRecord, LedgerEntry, Pools, Round,
+    StoreChanges, StoreEvent, User, WalletTransaction, WalletTx,
+};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::Path;
+
+/// Schema changes in order; `PRAGMA user_version` counts how many have run.
+/// Append new steps, never edit old ones.
+const MIGRATIONS: &[&str] = &[r#"
+    CREATE TABLE users (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE UNIQUE INDEX users_name ON users (name);
+
+    CREATE TABLE sessions (
+        id TEXT PRIMARY KEY,
+        user_id TEXT NOT NULL REFERENCES users (id),
+        data TEXT NOT NULL
+    );
+
+    CREATE TABLE rounds (
+        id TEXT PRIMARY KEY,
+        user_id TEXT NOT NULL REFERENCES users (id),
+        data TEXT NOT NULL
+    );
+
+    CREATE TABLE pools (
+        currency TEXT PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+
+    CREATE TABLE ledger (
+        seq INTEGER PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+
+    CREATE TABLE transactions (
+        seq INTEGER PRIMARY KEY,
+        user_id TEXT NOT NULL REFERENCES users (id),
+        data TEXT NOT NULL
+    );
+
+    CREATE TABLE cashier_requests (
+        id TEXT PRIMARY KEY,
+        user_id TEXT NOT NULL REFERENCES users (id),
+        data TEXT NOT NULL
+    );
+
+    CREATE TABLE idempotency (
+        key TEXT PRIMARY KEY,
+        created_at INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX idempotency_created ON idempotency (created_at);
+
+    CREATE TABLE wallet_credits (
+        transaction_id TEXT PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+
+    CREATE TABLE settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+"#];
+
+/// A `Store` that survives restarts. Writes return once they are
+/// committed to disk, or fail with the SQLite error and are undone.
+pub type SqliteStore = Durable<SqliteSink>;
+
+/// Writes each event's rows to SQLite tables. The event itself isn't kept.
+pub struct SqliteSink {
+    conn: Connection,
+}
+
+fn db_err(e: rusqlite::Error) -> String {
+    format!("storage error: {e}")
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("models serialize")
+}
+
+/// A unit enum's serde name, e.g. `"active"`, for plain-text columns.
+fn to_key<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value).expect("models serialize") {
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+fn load<T: DeserializeOwned>(conn: &Connection, sql: &str) -> Result<Vec<T>, String> {
+    let mut stmt = conn.prepare(sql).map_err(db_err)?;
+    let rows = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(db_err)?;
+    rows.map(|data| {
+        let data = data.map_err(db_err)?;
+        serde_json::from_str(&data).map_err(|e| format!("corrupt row: {e}"))
+    })
+    .collect()
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), String> {
+    let version: usize = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(db_err)?;
+    for (i, step) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction().map_err(db_err)?;
+        tx.execute_batch(step).map_err(db_err)?;
+        tx.pragma_update(None, "user_version", i + 1)
+            .map_err(db_err)?;
+        tx.commit().map_err(db_err)?;
+    }
+    Ok(())
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the database at `path` and brings its schema up
+    /// to date. A new database starts from the same demo state as
+    /// `InMem::new_demo`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let mut conn = Connection::open(path).map_err(db_err)?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(db_err)?;
+        conn.pragma_update(None, "foreign_keys", true)
+            .map_err(db_err)?;
+        migrate(&mut conn)?;
+
+        let pools: Vec<(String, String)> = {
+            let mut stmt = conn
+                .prepare("SELECT currency, data FROM pools")
+                .map_err(db_err)?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(db_err)?;
+            rows.collect::<Result<_, _>>().map_err(db_err)?
+        };
+        let state = if pools.is_empty() {
+            None
+        } else {
+            let mut state = InMemState::default();
+            for (currency, data) in pools {
+                let currency: Currency = serde_json::from_value(currency.into())
+                    .map_err(|e| format!("corrupt row: {e}"))?;
+                let pools: Pools =
+                    serde_json::from_str(&data).map_err(|e| format!("corrupt row: {e}"))?;
+                state.pools.insert(currency, pools);
+            }
+            let users: Vec<User> = load(&conn, "SELECT data FROM users")?;
+            state.users = users.into_iter().map(|u| (u.id.clone(), u)).collect();
+            let sessions: Vec<AuthSession> = load(&conn, "SELECT data FROM sessions")?;
+            state.sessions = sessions.into_iter().map(|s| (s.id.clone(), s)).collect();
+            let rounds: Vec<Round> = load(&conn, "SELECT data FROM rounds")?;
+            state.rounds = rounds.into_iter().map(|r| (r.id.clone(), r)).collect();
+            let cashier: Vec<CashierRequest> = load(&conn, "SELECT data FROM cashier_requests")?;
+            state.cashier = cashier.into_iter().map(|r| (r.id.clone(), r)).collect();
+            let idempotency: Vec<IdempotencyRecord> = load(&conn, "SELECT data FROM idempotency")?;
+            // claims left running by an earlier version would block retries
+            state.idempotency = idempotency
+                .into_iter()
+                .filter(|r| r.response.is_some())
+                .map(|r| (r.key.clone(), r))
+                .collect();
+            let credits: Vec<WalletTx> = load(&conn, "SELECT data FROM wallet_credits")?;
+            state.wallet_credits = (credits.into_iter())
+                .map(|tx| (tx.transaction_id.clone(), tx))
+                .collect();
+            state.ledger = load::<LedgerEntry>(&conn, "SELECT data FROM ledger ORDER BY seq")?;
+            state.transactions =
+                load::<WalletTransaction>(&conn, "SELECT data FROM transactions ORDER BY seq")?;
+            if let Some(paytable) = conn
+                .query_row(
+                    "SELECT value FROM settings WHERE key = 'paytable'",
+                    [],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()
+                .map_err(db_err)?
+            {
+                state.paytable =
+                    serde_json::from_str(&paytable).map_err(|e| format!("corrupt row: {e}"))?;
+            }
+            Some(state)
+        };
+        Durable::new(state, SqliteSink { conn })
+    }
+}
+
+impl Sink for SqliteSink {
+    /// Writes the event's rows and new ledger and wallet history lines as
+    /// one transaction.
+    fn write(
+        &mut self,
+        _event: &StoreEvent,
+        changes: &StoreChanges,
+        _state: Option<&InMemState>,
+    ) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(db_err)?;
+
+        for user in &changes.users {
+            tx.execute(
+                "INSERT INTO users (id, name, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (id) DO UPDATE SET name = excluded.name, data = excluded.data",
+                params![user.id, user.name, to_json(user)],
+            )
+            .map_err(db_err)?;
+        }
+        for session in &changes.sessions {
+            tx.execute(
+                "INSERT INTO sessions (id, user_id, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (id) DO UPDATE SET data = excluded.data",
+                params![session.id, session.user_id, to_json(session)],
+            )
+            .map_err(db_err)?;
+        }
+        for round in &changes.rounds {
+            tx.execute(
+                "INSERT INTO rounds (id, user_id, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (id) DO UPDATE SET data = excluded.data",
+                params![round.id, round.user_id, to_json(round)],
+            )
+            .map_err(db_err)?;
+        }
+        for req in &changes.cashier {
+            tx.execute(
+                "INSERT INTO cashier_requests (id, user_id, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (id) DO UPDATE SET data = excluded.data",
+                params![req.id, req.user_id, to_json(req)],
+            )
+            .map_err(db_err)?;
+        }
+        if let Some(cutoff) = changes.idempotency_expired_before {
+            tx.execute(
+                "DELETE FROM idempotency WHERE created_at <= ?1",
+                params![cutoff.timestamp_millis()],
+            )
+            .map_err(db_err)?;
+        }
+        for rec in &changes.idempotency {
+            tx.execute(
+                "INSERT INTO idempotency (key, created_at, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (key) DO UPDATE SET data = excluded.data",
+                params![rec.key, rec.created_at.timestamp_millis(), to_json(rec)],
+            )
+            .map_err(db_err)?;
+        }
+        for credit in &changes.wallet_credits {
+            tx.execute(
+                "INSERT INTO wallet_credits (transaction_id, data) VALUES (?1, ?2)
+                 ON CONFLICT (transaction_id) DO UPDATE SET data = excluded.data",
+                params![credit.transaction_id, to_json(credit)],
+            )
+            .map_err(db_err)?;
+        }
+        for id in &changes.wallet_credits_delivered {
+            tx.execute(
+                "DELETE FROM wallet_credits WHERE transaction_id = ?1",
+                params![id],
+            )
+            .map_err(db_err)?;
+        }
+
+        for (currency, pools) in &changes.pools {
+            tx.execute(
+                "INSERT INTO pools (currency, data) VALUES (?1, ?2)
+                 ON CONFLICT (currency) DO UPDATE SET data = excluded.data",
+                params![to_key(currency), to_json(pools)],
+            )
+            .map_err(db_err)?;
+        }
+        if let Some(paytable) = &changes.paytable {
+            tx.execute(
+                "INSERT INTO settings (key, value) VALUES ('paytable', ?1)
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                params![to_json(paytable)],
+            )
+            .map_err(db_err)?;
+        }
+
+        for entry in &changes.ledger {
+            tx.execute(
+                "INSERT INTO ledger (seq, data) VALUES (?1, ?2)",
+                params![entry.seq, to_json(entry)],
+            )
+            .map_err(db_err)?;
+        }
+        for line in &changes.transactions {
+            tx.execute(
+                "INSERT INTO transactions (seq, user_id, data) VALUES (?1, ?2, ?3)",
+                params![line.seq, line.user_id, to_json(line)],
+            )
+            .map_err(db_err)?;
+        }
+
+        tx.commit().map_err(db_err)
+    }
+}
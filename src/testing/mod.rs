@@ -0,0 +1,695 @@
+//! Behaviour every `Store` backend has to share with `InMem`, as a suite a
+//! backend's tests run against its own constructor:
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn test_my_store_conforms() {
+//!     poker_server::testing::store_conformance(|| async { MyStore::new_demo().into_shared() })
+//!         .await;
+//! }
+//! ```
+//!
+//! The constructor is called once per check and must return a store in the
+//! demo state (`user1` with 1000 play credits, 50 000 in every win pool).
+//! Every check runs even if an earlier one fails; the panic lists all
+//! failures. Enabled with the `testing` feature.
+
+use crate::game;
+use crate::models::{
+    CachedResponse, CashierKind, CashierStatus, Currency, DiscardOp, IdempotencyClaim, NewRound,
+    Paytable, PoolShare, RoundStatus, Settlement, StoreNotice, TransactionFilter, TransactionKind,
+};
+use crate::money::Money;
+use crate::store::SharedStore;
+use chrono::{Duration, Utc};
+use std::future::Future;
+use std::pin::Pin;
+
+type Check = fn(SharedStore) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Tasks racing each other in the concurrency checks.
+const TASKS: usize = 32;
+
+const CHECKS: &[(&str, Check)] = &[
+    ("names_are_unique", |s| Box::pin(names_are_unique(s))),
+    ("missing_rows_are_errors", |s| {
+        Box::pin(missing_rows_are_errors(s))
+    }),
+    ("start_debits_ante_and_reserves", |s| {
+        Box::pin(start_debits_ante_and_reserves(s))
+    }),
+    ("start_refuses_what_it_cant_cover", |s| {
+        Box::pin(start_refuses_what_it_cant_cover(s))
+    }),
+    ("discard_charges_fee", |s| Box::pin(discard_charges_fee(s))),
+    ("settle_pays_once", |s| Box::pin(settle_pays_once(s))),
+    ("remote_credits_wait_for_delivery", |s| {
+        Box::pin(remote_credits_wait_for_delivery(s))
+    }),
+    ("settle_rejects_stale_hand", |s| {
+        Box::pin(settle_rejects_stale_hand(s))
+    }),
+    ("void_refunds_stake", |s| Box::pin(void_refunds_stake(s))),
+    ("failed_steps_change_nothing", |s| {
+        Box::pin(failed_steps_change_nothing(s))
+    }),
+    ("pools_refuse_overdraft", |s| {
+        Box::pin(pools_refuse_overdraft(s))
+    }),
+    ("changes_are_announced", |s| {
+        Box::pin(changes_are_announced(s))
+    }),
+    ("withdrawals_hold_funds", |s| {
+        Box::pin(withdrawals_hold_funds(s))
+    }),
+    ("reused_refresh_revokes_session", |s| {
+        Box::pin(reused_refresh_revokes_session(s))
+    }),
+    ("idempotency_keys", |s| Box::pin(idempotency_keys(s))),
+    ("concurrent_signups_make_one_user", |s| {
+        Box::pin(concurrent_signups_make_one_user(s))
+    }),
+    ("concurrent_starts_respect_cap", |s| {
+        Box::pin(concurrent_starts_respect_cap(s))
+    }),
+    ("concurrent_settles_pay_once", |s| {
+        Box::pin(concurrent_settles_pay_once(s))
+    }),
+    ("concurrent_discards_never_overdraw", |s| {
+        Box::pin(concurrent_discards_never_overdraw(s))
+    }),
+];
+
+/// Runs every check against a fresh store from `new_store` and panics
+/// with the names and messages of the ones that failed.
+pub async fn store_conformance<F, Fut>(new_store: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = SharedStore>,
+{
+    let mut failed = Vec::new();
+    for (name, check) in CHECKS {
+        let store = new_store().await;
+        if let Err(e) = tokio::spawn(check(store)).await {
+            let message = match e.try_into_panic() {
+                Ok(panic) => panic
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or_default(),
+                Err(e) => e.to_string(),
+            };
+            failed.push(format!("{name}: {message}"));
+        }
+    }
+    assert!(
+        failed.is_empty(),
+        "{} of {} store checks failed:\n{}",
+        failed.len(),
+        CHECKS.len(),
+        failed.join("\n")
+    );
+}
+
+const PLAYER: &str = "user1";
+
+fn new_round(max_active_rounds: usize) -> NewRound {
+    let mut deck = game::new_deck();
+    NewRound {
+        user_id: PLAYER.to_string(),
+        currency: Currency::Play,
+        ante: Money::new(10),
+        cards: game::deal_hand(&mut deck, game::HAND_SIZE),
+        max_active_rounds,
+        paytable: Paytable::default(),
+        remote_wallet: false,
+    }
+}
+
+async fn start(store: &SharedStore) -> String {
+    store.start_round(new_round(10)).await.unwrap().round.id
+}
+
+fn discard(round_id: &str, fee: i64) -> DiscardOp {
+    DiscardOp {
+        user_id: PLAYER.to_string(),
+        round_id: round_id.to_string(),
+        fee: PoolShare {
+            win_pool: Money::new(fee),
+            ..Default::default()
+        },
+        replacements: game::deal_hand(&mut game::new_deck(), 1)
+            .into_iter()
+            .map(|card| (0, card))
+            .collect(),
+    }
+}
+
+fn win(round_id: &str, payout: i64) -> Settlement {
+    Settlement {
+        user_id: PLAYER.to_string(),
+        round_id: round_id.to_string(),
+        expected_draws: 0,
+        payout: Money::new(payout),
+        share: PoolShare::default(),
+        folded: false,
+    }
+}
+
+async fn balance(store: &SharedStore) -> Money {
+    store
+        .get_user(PLAYER)
+        .await
+        .expect("demo user")
+        .balance(Currency::Play)
+}
+
+async fn assert_reconciles(store: &SharedStore) {
+    let report = store.reconcile().await;
+    assert!(report.ok, "ledger doesn't reconcile: {report:?}");
+}
+
+async fn names_are_unique(store: SharedStore) {
+    let user = store.create_user_if_unique("alice", "hash").await.unwrap();
+    assert_eq!(user.balance(Currency::Play), Money::new(1000));
+    assert!(store.create_user_if_unique("alice", "hash").await.is_err());
+    assert!(store.create_user_if_unique(PLAYER, "hash").await.is_err());
+    let found = store.find_user_by_name("alice").await.unwrap();
+    assert_eq!(found.id, user.id);
+    assert!(store.find_user_by_name("bob").await.is_none());
+    assert_reconciles(&store).await;
+}
+
+async fn missing_rows_are_errors(store: SharedStore) {
+    assert!(store.get_round("missing").await.is_none());
+    assert!(store.apply_discard(discard("missing", 0)).await.is_err());
+    assert!(store.settle_round(win("missing", 0)).await.is_err());
+    assert!(store.void_round("missing", "test").await.is_err());
+    assert!(store.get_user("missing").await.is_none());
+    assert!(store
+        .adjust_wallet("missing", Currency::Play, Money::new(1), "test")
+        .await
+        .is_err());
+    assert!(store.set_user_frozen("missing", true).await.is_err());
+    assert!(store.revoke_session("missing").await.is_err());
+    assert!(store
+        .resolve_cashier_request("missing", true, None)
+        .await
+        .is_err());
+    let mut round = new_round(1);
+    round.user_id = "missing".to_string();
+    assert!(store.start_round(round).await.is_err());
+}
+
+async fn start_debits_ante_and_reserves(store: SharedStore) {
+    let pools = store.get_pools(Currency::Play).await;
+    let started = store.start_round(new_round(1)).await.unwrap();
+    let round = &started.round;
+    assert_eq!(round.status, RoundStatus::Active);
+    assert_eq!(round.draws_used, 0);
+    assert_eq!(started.wallet, Money::new(990));
+    assert_eq!(balance(&store).await, Money::new(990));
+
+    let reserve = Money::new(10 * i64::from(Paytable::default().max()));
+    assert_eq!(round.reserved, reserve);
+    let after = store.get_pools(Currency::Play).await;
+    assert_eq!(after.reserved, pools.reserved + reserve);
+    assert_eq!(after.win_pool, pools.win_pool);
+
+    let stored = store.get_round(&round.id).await.unwrap();
+    assert_eq!(stored.ante, Money::new(10));
+    assert_eq!(store.get_active_rounds(PLAYER).await.len(), 1);
+    let history = store
+        .list_transactions(
+            PLAYER,
+            TransactionFilter {
+                limit: 10,
+                ..TransactionFilter::default()
+            },
+        )
+        .await;
+    assert_eq!(history[0].kind, TransactionKind::Ante);
+    assert_eq!(history[0].amount, Money::new(-10));
+    assert_reconciles(&store).await;
+}
+
+async fn start_refuses_what_it_cant_cover(store: SharedStore) {
+    let mut round = new_round(1);
+    round.ante = Money::new(1001);
+    assert!(store.start_round(round).await.is_err());
+
+    // more than the win pool can pay at the top multiplier
+    let mut round = new_round(1);
+    round.ante = Money::new(1001);
+    store
+        .adjust_wallet(PLAYER, Currency::Play, Money::new(10_000), "test")
+        .await
+        .unwrap();
+    assert!(store.start_round(round).await.is_err());
+
+    store.start_round(new_round(1)).await.unwrap();
+    assert!(store.start_round(new_round(1)).await.is_err());
+
+    store.set_user_frozen(PLAYER, true).await.unwrap();
+    assert!(store.start_round(new_round(5)).await.is_err());
+    assert_eq!(store.get_active_rounds(PLAYER).await.len(), 1);
+    assert_eq!(balance(&store).await, Money::new(10_990));
+    assert_reconciles(&store).await;
+}
+
+async fn discard_charges_fee(store: SharedStore) {
+    let round_id = start(&store).await;
+    let pools = store.get_pools(Currency::Play).await;
+    let snap = store.apply_discard(discard(&round_id, 5)).await.unwrap();
+    assert_eq!(snap.round.draws_used, 1);
+    assert_eq!(snap.wallet, Money::new(985));
+    assert_eq!(
+        store.get_pools(Currency::Play).await.win_pool,
+        pools.win_pool + Money::new(5)
+    );
+    assert!(store.apply_discard(discard(&round_id, 1000)).await.is_err());
+
+    let mut other = discard(&round_id, 1);
+    other.user_id = "someone-else".to_string();
+    assert!(store.apply_discard(other).await.is_err());
+    let mut off_hand = discard(&round_id, 5);
+    off_hand.replacements[0].0 = game::HAND_SIZE;
+    assert!(store.apply_discard(off_hand).await.is_err());
+    assert_eq!(balance(&store).await, Money::new(985));
+    assert_eq!(store.get_round(&round_id).await.unwrap().draws_used, 1);
+    assert_reconciles(&store).await;
+}
+
+async fn settle_pays_once(store: SharedStore) {
+    let round_id = start(&store).await;
+    let snap = store.settle_round(win(&round_id, 30)).await.unwrap();
+    assert_eq!(snap.round.status, RoundStatus::Revealed);
+    assert_eq!(snap.wallet, Money::new(1020));
+    assert_eq!(store.get_pools(Currency::Play).await.reserved, Money::ZERO);
+    assert!(store.settle_round(win(&round_id, 30)).await.is_err());
+    assert!(store.apply_discard(discard(&round_id, 0)).await.is_err());
+    assert_eq!(balance(&store).await, Money::new(1020));
+    assert!(store.get_active_rounds(PLAYER).await.is_empty());
+    assert_reconciles(&store).await;
+}
+
+async fn remote_credits_wait_for_delivery(store: SharedStore) {
+    let local = start(&store).await;
+    store.settle_round(win(&local, 30)).await.unwrap();
+    assert!(store.pending_wallet_credits().await.is_empty());
+
+    let round = NewRound {
+        remote_wallet: true,
+        ..new_round(10)
+    };
+    let round_id = store.start_round(round).await.unwrap().round.id;
+    store.settle_round(win(&round_id, 30)).await.unwrap();
+    let pending = store.pending_wallet_credits().await;
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].transaction_id, format!("{round_id}:payout"));
+    assert_eq!(pending[0].amount, Money::new(30));
+
+    let id = pending[0].transaction_id.clone();
+    store.wallet_credit_delivered(&id).await.unwrap();
+    assert!(store.pending_wallet_credits().await.is_empty());
+    // a second confirmation changes nothing
+    store.wallet_credit_delivered(&id).await.unwrap();
+}
+
+async fn settle_rejects_stale_hand(store: SharedStore) {
+    let round_id = start(&store).await;
+    store.apply_discard(discard(&round_id, 0)).await.unwrap();
+    // computed before the discard landed
+    assert!(store.settle_round(win(&round_id, 30)).await.is_err());
+    assert_eq!(
+        store.get_round(&round_id).await.unwrap().status,
+        RoundStatus::Active
+    );
+    let mut current = win(&round_id, 30);
+    current.expected_draws = 1;
+    store.settle_round(current).await.unwrap();
+    assert_reconciles(&store).await;
+}
+
+async fn void_refunds_stake(store: SharedStore) {
+    let round_id = start(&store).await;
+    store.apply_discard(discard(&round_id, 5)).await.unwrap();
+    let (snap, refunded) = store.void_round(&round_id, "test").await.unwrap();
+    assert_eq!(snap.round.status, RoundStatus::Voided);
+    assert_eq!(refunded, Money::new(15));
+    assert_eq!(balance(&store).await, Money::new(1000));
+    assert_eq!(store.get_pools(Currency::Play).await.reserved, Money::ZERO);
+    assert!(store.void_round(&round_id, "test").await.is_err());
+    assert_reconciles(&store).await;
+}
+
+/// The pools as (win pool, reserved, house profit), for comparing.
+async fn pool_totals(store: &SharedStore) -> (Money, Money, Money) {
+    let pools = store.get_pools(Currency::Play).await;
+    (pools.win_pool, pools.reserved, pools.house_profit)
+}
+
+async fn failed_steps_change_nothing(store: SharedStore) {
+    // a wallet this full can't take back a discard that would credit it
+    let full = Money::new(i64::MAX - 5);
+    store
+        .update_user_wallet(PLAYER, Currency::Play, full)
+        .await
+        .unwrap();
+    let round_id = start(&store).await;
+    let before = pool_totals(&store).await;
+    let wallet = balance(&store).await;
+
+    let mut refund = discard(&round_id, 10);
+    refund.fee.house = Money::new(-30);
+    assert!(store.apply_discard(refund).await.is_err());
+    assert_eq!(pool_totals(&store).await, before);
+    assert_eq!(balance(&store).await, wallet);
+    let round = store.get_round(&round_id).await.unwrap();
+    assert_eq!(round.draws_used, 0);
+
+    // splits the stake, but more than the win pool can hold
+    let mut settlement = win(&round_id, 0);
+    settlement.share = PoolShare {
+        win_pool: Money::new(i64::MAX - 100),
+        house: Money::new(110 - i64::MAX),
+        win_pool_ceiling: None,
+    };
+    assert!(store.settle_round(settlement).await.is_err());
+    assert_eq!(pool_totals(&store).await, before);
+    assert_eq!(balance(&store).await, wallet);
+    let round = store.get_round(&round_id).await.unwrap();
+    assert_eq!(round.status, RoundStatus::Active);
+
+    // the round still settles as usual afterwards
+    let mut settlement = win(&round_id, 0);
+    settlement.share.win_pool = Money::new(10);
+    store.settle_round(settlement).await.unwrap();
+    assert_eq!(store.get_pools(Currency::Play).await.reserved, Money::ZERO);
+}
+
+async fn pools_refuse_overdraft(store: SharedStore) {
+    start(&store).await;
+    let pools = store.get_pools(Currency::Play).await;
+    // reserved money can't be taken out
+    assert!(store
+        .sub_from_win_pool(Currency::Play, pools.available() + Money::new(1))
+        .await
+        .is_err());
+    assert_eq!(
+        store.get_pools(Currency::Play).await.win_pool,
+        pools.win_pool
+    );
+    store
+        .sub_from_win_pool(Currency::Play, pools.available())
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_pools(Currency::Play).await.available(),
+        Money::ZERO
+    );
+
+    store
+        .add_to_pools(Currency::Play, Money::ZERO, Money::new(7))
+        .await
+        .unwrap();
+    assert!(store
+        .sub_from_house_profit(Currency::Play, Money::new(8))
+        .await
+        .is_err());
+    assert!(store
+        .sub_from_house_profit(Currency::Play, Money::ZERO)
+        .await
+        .is_err());
+    store
+        .sub_from_house_profit(Currency::Play, Money::new(7))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_pools(Currency::Play).await.house_profit,
+        Money::ZERO
+    );
+    assert_reconciles(&store).await;
+}
+
+async fn changes_are_announced(store: SharedStore) {
+    let mut notices = store.subscribe();
+    let round_id = start(&store).await;
+    let balance = store
+        .get_user(PLAYER)
+        .await
+        .unwrap()
+        .balance(Currency::Play);
+    match notices.try_recv() {
+        Ok(StoreNotice::Wallet {
+            user_id,
+            currency,
+            wallet,
+        }) => {
+            assert_eq!((user_id.as_str(), currency), (PLAYER, Currency::Play));
+            assert_eq!(wallet, balance);
+        }
+        other => panic!("expected the wallet after a start, got {other:?}"),
+    }
+    match notices.try_recv() {
+        Ok(StoreNotice::Pools { currency, pools }) => {
+            assert_eq!(currency, Currency::Play);
+            assert_eq!(
+                pools.reserved,
+                Money::new(10 * i64::from(Paytable::default().max()))
+            );
+        }
+        other => panic!("expected the pools after a start, got {other:?}"),
+    }
+    // refused changes announce nothing
+    assert!(store
+        .sub_from_house_profit(Currency::Play, Money::new(1))
+        .await
+        .is_err());
+    store.settle_round(win(&round_id, 30)).await.unwrap();
+    match notices.try_recv() {
+        Ok(StoreNotice::Wallet { wallet, .. }) => {
+            assert_eq!(wallet, balance.try_add(Money::new(30)).unwrap())
+        }
+        other => panic!("expected the wallet after a settle, got {other:?}"),
+    }
+    match notices.try_recv() {
+        Ok(StoreNotice::Pools { pools, .. }) => assert_eq!(pools.reserved, Money::ZERO),
+        other => panic!("expected the pools after a settle, got {other:?}"),
+    }
+    match notices.try_recv() {
+        Ok(StoreNotice::Payout {
+            round_id: id,
+            payout,
+            ante,
+            ..
+        }) => {
+            assert_eq!(id, round_id);
+            assert_eq!((ante, payout), (Money::new(10), Money::new(30)));
+        }
+        other => panic!("expected the payout, got {other:?}"),
+    }
+    assert!(notices.try_recv().is_err());
+}
+
+async fn withdrawals_hold_funds(store: SharedStore) {
+    let req = store
+        .create_cashier_request(
+            PLAYER,
+            CashierKind::Withdrawal,
+            Currency::Play,
+            Money::new(600),
+            true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(req.status, CashierStatus::Pending);
+    // the held 600 is still in the wallet but can't be spent
+    assert!(store
+        .create_cashier_request(
+            PLAYER,
+            CashierKind::Withdrawal,
+            Currency::Play,
+            Money::new(600),
+            true,
+        )
+        .await
+        .is_err());
+    let mut round = new_round(1);
+    round.ante = Money::new(401);
+    assert!(store.start_round(round).await.is_err());
+    assert!(store
+        .adjust_wallet(PLAYER, Currency::Play, Money::new(-401), "test")
+        .await
+        .is_err());
+
+    let rejected = store
+        .resolve_cashier_request(&req.id, false, Some("test".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(rejected.status, CashierStatus::Rejected);
+    assert!(store
+        .resolve_cashier_request(&req.id, true, None)
+        .await
+        .is_err());
+    let user = store.get_user(PLAYER).await.unwrap();
+    assert_eq!(user.available(Currency::Play), Money::new(1000));
+
+    let req = store
+        .create_cashier_request(
+            PLAYER,
+            CashierKind::Withdrawal,
+            Currency::Play,
+            Money::new(600),
+            false,
+        )
+        .await
+        .unwrap();
+    store
+        .resolve_cashier_request(&req.id, true, None)
+        .await
+        .unwrap();
+    assert_eq!(balance(&store).await, Money::new(400));
+    assert_reconciles(&store).await;
+}
+
+async fn reused_refresh_revokes_session(store: SharedStore) {
+    let expires_at = Utc::now() + Duration::hours(1);
+    let session = store.create_session(PLAYER, expires_at).await.unwrap();
+    assert_eq!(session.generation, 0);
+    let refreshed = store
+        .refresh_session(&session.id, 0, expires_at)
+        .await
+        .unwrap();
+    assert_eq!(refreshed.generation, 1);
+    assert!(store
+        .refresh_session(&session.id, 0, expires_at)
+        .await
+        .is_err());
+    let session = store.get_session(&session.id).await.unwrap();
+    assert!(session.revoked);
+    assert!(store
+        .refresh_session(&session.id, 1, expires_at)
+        .await
+        .is_err());
+}
+
+async fn idempotency_keys(store: SharedStore) {
+    let now = Utc::now();
+    let ttl = Duration::hours(24);
+    let claim = |key: &'static str, fingerprint: &'static str| {
+        let store = store.clone();
+        async move { store.claim_idempotency(key, fingerprint, now, ttl).await }
+    };
+    assert!(matches!(claim("a", "one").await, IdempotencyClaim::Claimed));
+    assert!(matches!(
+        claim("a", "one").await,
+        IdempotencyClaim::InFlight
+    ));
+    assert!(matches!(
+        claim("a", "two").await,
+        IdempotencyClaim::Mismatch
+    ));
+    let response = CachedResponse {
+        status: 200,
+        content_type: None,
+        body: b"done".to_vec(),
+    };
+    store.finish_idempotency("a", Some(response)).await;
+    match claim("a", "one").await {
+        IdempotencyClaim::Replay(r) => assert_eq!(r.body, b"done"),
+        other => panic!("expected a replay, got {other:?}"),
+    }
+
+    // a released claim can be taken again
+    assert!(matches!(claim("b", "one").await, IdempotencyClaim::Claimed));
+    store.finish_idempotency("b", None).await;
+    assert!(matches!(claim("b", "one").await, IdempotencyClaim::Claimed));
+
+    // expired keys are free
+    let later = now + ttl + Duration::seconds(1);
+    assert!(matches!(
+        store.claim_idempotency("a", "two", later, ttl).await,
+        IdempotencyClaim::Claimed
+    ));
+}
+
+async fn concurrent_signups_make_one_user(store: SharedStore) {
+    let mut handles = Vec::new();
+    for _ in 0..TASKS {
+        let store = store.clone();
+        handles.push(tokio::spawn(async move {
+            store.create_user_if_unique("racer", "hash").await
+        }));
+    }
+    let mut created = 0;
+    for h in handles {
+        if h.await.unwrap().is_ok() {
+            created += 1;
+        }
+    }
+    assert_eq!(created, 1);
+    assert_eq!(store.list_users(Some("racer"), 10).await.len(), 1);
+}
+
+async fn concurrent_starts_respect_cap(store: SharedStore) {
+    let mut handles = Vec::new();
+    for _ in 0..TASKS {
+        let store = store.clone();
+        handles.push(tokio::spawn(async move {
+            store.start_round(new_round(3)).await
+        }));
+    }
+    let mut started = 0;
+    for h in handles {
+        if h.await.unwrap().is_ok() {
+            started += 1;
+        }
+    }
+    assert_eq!(started, 3);
+    assert_eq!(balance(&store).await, Money::new(970));
+    assert_eq!(store.get_active_rounds(PLAYER).await.len(), 3);
+    assert_reconciles(&store).await;
+}
+
+async fn concurrent_settles_pay_once(store: SharedStore) {
+    let round_id = start(&store).await;
+    let mut handles = Vec::new();
+    for _ in 0..TASKS {
+        let store = store.clone();
+        let settlement = win(&round_id, 30);
+        handles.push(tokio::spawn(
+            async move { store.settle_round(settlement).await },
+        ));
+    }
+    let mut settled = 0;
+    for h in handles {
+        if h.await.unwrap().is_ok() {
+            settled += 1;
+        }
+    }
+    assert_eq!(settled, 1);
+    assert_eq!(balance(&store).await, Money::new(1020));
+    assert_reconciles(&store).await;
+}
+
+async fn concurrent_discards_never_overdraw(store: SharedStore) {
+    let round_id = start(&store).await;
+    let mut handles = Vec::new();
+    for _ in 0..TASKS {
+        let store = store.clone();
+        let op = discard(&round_id, 100);
+        handles.push(tokio::spawn(async move { store.apply_discard(op).await }));
+    }
+    let mut applied = 0;
+    for h in handles {
+        if h.await.unwrap().is_ok() {
+            applied += 1;
+        }
+    }
+    // 990 left after the ante covers nine 100-credit discards
+    assert_eq!(applied, 9);
+    assert_eq!(balance(&store).await, Money::new(90));
+    assert_eq!(store.get_round(&round_id).await.unwrap().draws_used, 9);
+    assert_reconciles(&store).await;
+}
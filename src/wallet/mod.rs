@@ -0,0 +1,283 @@
+use crate::config::GameConfig;
+use crate::models::{Currency, RoundSnapshot, WalletTx};
+use crate::money::Money;
+use crate::store::{SharedStore, StoreError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalletError {
+    /// The wallet answered and refused, e.g. for insufficient funds.
+    Declined(String),
+    /// No usable answer, even after retrying.
+    Unavailable(String),
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalletError::Declined(e) => write!(f, "wallet declined: {e}"),
+            WalletError::Unavailable(e) => write!(f, "wallet unavailable: {e}"),
+        }
+    }
+}
+
+/// Where players' money lives. Handlers book each step in the store first
+/// and then settle it with the wallet: `debit` after a start or discard,
+/// `credit` after a round pays out. Every call returns the balance after.
+#[async_trait::async_trait]
+pub trait WalletProvider: Send + Sync {
+    /// Whether balances are held outside the store. Rounds then book stakes
+    /// and wins against `Account::RemoteWallet` instead of the local wallet.
+    fn is_remote(&self) -> bool;
+    async fn balance(&self, user_id: &str, currency: Currency) -> Result<Money, WalletError>;
+    async fn debit(&self, tx: &WalletTx) -> Result<Money, WalletError>;
+    async fn credit(&self, tx: &WalletTx) -> Result<Money, WalletError>;
+    /// Undoes the debit `transaction_id`; a debit the wallet never saw is
+    /// nothing to undo.
+    async fn rollback(&self, user_id: &str, transaction_id: &str) -> Result<Money, WalletError>;
+}
+
+pub type SharedWallet = Arc<dyn WalletProvider>;
+
+/// The seamless wallet at `config.wallet_url` if one is set, otherwise the
+/// store's own wallets.
+pub fn from_config(config: &GameConfig, store: SharedStore) -> SharedWallet {
+    match &config.wallet_url {
+        Some(url) => Arc::new(SeamlessWallet::new(
+            url,
+            Duration::from_millis(config.wallet_timeout_ms),
+            config.wallet_retries,
+        )),
+        None => Arc::new(StoreWallet::new(store)),
+    }
+}
+
+/// The store's own wallets. The store's round steps already move the money,
+/// so the calls here only report the balance.
+pub struct StoreWallet {
+    store: SharedStore,
+}
+
+impl StoreWallet {
+    pub fn new(store: SharedStore) -> Self {
+        StoreWallet { store }
+    }
+}
+
+#[async_trait::async_trait]
+impl WalletProvider for StoreWallet {
+    fn is_remote(&self) -> bool {
+        false
+    }
+
+    async fn balance(&self, user_id: &str, currency: Currency) -> Result<Money, WalletError> {
+        self.store
+            .get_user(user_id)
+            .await
+            .map(|u| u.balance(currency))
+            .ok_or_else(|| WalletError::Declined("user not found".into()))
+    }
+
+    async fn debit(&self, tx: &WalletTx) -> Result<Money, WalletError> {
+        self.balance(&tx.user_id, tx.currency).await
+    }
+
+    async fn credit(&self, tx: &WalletTx) -> Result<Money, WalletError> {
+        self.balance(&tx.user_id, tx.currency).await
+    }
+
+    async fn rollback(&self, _user_id: &str, _transaction_id: &str) -> Result<Money, WalletError> {
+        Err(WalletError::Declined(
+            "the store wallet refunds through voids".into(),
+        ))
+    }
+}
+
+/// Client for an operator's "seamless wallet" API. Each call is a JSON POST
+/// to `{base_url}/{balance,debit,credit,rollback}` answered with
+/// `{"balance": ...}`. Timeouts and 5xx answers are retried with the same
+/// transaction id, so the operator must treat repeats as one transaction;
+/// 4xx answers are final.
+pub struct SeamlessWallet {
+    client: reqwest::Client,
+    base_url: String,
+    retries: u32,
+}
+
+#[derive(Serialize)]
+struct BalanceRequest<'a> {
+    user_id: &'a str,
+    currency: Currency,
+}
+
+#[derive(Serialize)]
+struct RollbackRequest<'a> {
+    user_id: &'a str,
+    transaction_id: &'a str,
+}
+
+#[derive(Deserialize)]
+struct BalanceResponse {
+    balance: Money,
+}
+
+impl SeamlessWallet {
+    pub fn new(base_url: &str, timeout: Duration, retries: u32) -> Self {
+        SeamlessWallet {
+            client: reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .expect("http client"),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            retries,
+        }
+    }
+
+    async fn call(&self, endpoint: &str, body: &impl Serialize) -> Result<Money, WalletError> {
+        let url = format!("{}/{}", self.base_url, endpoint);
+        let mut last = String::new();
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                tokio::time::sleep(Duration::from_millis(100 * u64::from(attempt))).await;
+            }
+            match self.client.post(&url).json(body).send().await {
+                Ok(resp) if resp.status().is_success() => {
+                    return resp
+                        .json::<BalanceResponse>()
+                        .await
+                        .map(|b| b.balance)
+                        .map_err(|e| WalletError::Unavailable(e.to_string()));
+                }
+                Ok(resp) if resp.status().is_client_error() => {
+                    let status = resp.status();
+                    let text = resp.text().await.unwrap_or_default();
+                    return Err(WalletError::Declined(if text.is_empty() {
+                        status.to_string()
+                    } else {
+                        text
+                    }));
+                }
+                Ok(resp) => last = resp.status().to_string(),
+                Err(e) => last = e.to_string(),
+            }
+            warn!(
+                "wallet {} attempt {} failed: {}",
+                endpoint,
+                attempt + 1,
+                last
+            );
+        }
+        Err(WalletError::Unavailable(last))
+    }
+}
+
+#[async_trait::async_trait]
+impl WalletProvider for SeamlessWallet {
+    fn is_remote(&self) -> bool {
+        true
+    }
+
+    async fn balance(&self, user_id: &str, currency: Currency) -> Result<Money, WalletError> {
+        self.call("balance", &BalanceRequest { user_id, currency })
+            .await
+    }
+
+    async fn debit(&self, tx: &WalletTx) -> Result<Money, WalletError> {
+        self.call("debit", tx).await
+    }
+
+    async fn credit(&self, tx: &WalletTx) -> Result<Money, WalletError> {
+        self.call("credit", tx).await
+    }
+
+    async fn rollback(&self, user_id: &str, transaction_id: &str) -> Result<Money, WalletError> {
+        self.call(
+            "rollback",
+            &RollbackRequest {
+                user_id,
+                transaction_id,
+            },
+        )
+        .await
+    }
+}
+
+/// Takes `tx` from the wallet once the store has booked it. If the wallet
+/// refuses or can't be reached, the round is voided and every debit taken
+/// for it rolled back, so neither side keeps the money.
+pub async fn debit_or_void(
+    store: &SharedStore,
+    wallet: &dyn WalletProvider,
+    tx: &WalletTx,
+) -> Result<Money, WalletError> {
+    match wallet.debit(tx).await {
+        Ok(balance) => Ok(balance),
+        Err(e) => {
+            if let Err(void) = void_and_roll_back(store, wallet, &tx.round_id, &e.to_string()).await
+            {
+                error!(
+                    "round {} left open after wallet failure: {}",
+                    tx.round_id, void
+                );
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Voids the round in the store and, for a remote wallet, rolls back each
+/// debit taken for it. Rollbacks that fail are logged for follow-up; the
+/// operator can replay them by transaction id.
+pub async fn void_and_roll_back(
+    store: &SharedStore,
+    wallet: &dyn WalletProvider,
+    round_id: &str,
+    reason: &str,
+) -> Result<(RoundSnapshot, Money), StoreError> {
+    let (snapshot, refund) = store.void_round(round_id, reason).await?;
+    let round = &snapshot.round;
+    if round.remote_wallet {
+        for id in WalletTx::debit_ids(round) {
+            if let Err(e) = wallet.rollback(&round.user_id, &id).await {
+                error!("wallet rollback of {} failed: {}", id, e);
+            }
+        }
+    }
+    Ok((snapshot, refund))
+}
+
+/// Sends each pending settlement credit to the wallet again and forgets
+/// the ones it takes. Ids repeat those of the first attempt, so a credit
+/// the wallet did get before is applied once.
+pub async fn redeliver_credits(store: &SharedStore, wallet: &dyn WalletProvider) {
+    for tx in store.pending_wallet_credits().await {
+        match wallet.credit(&tx).await {
+            Ok(_) => {
+                if let Err(e) = store.wallet_credit_delivered(&tx.transaction_id).await {
+                    error!("wallet credit {} left pending: {}", tx.transaction_id, e);
+                }
+            }
+            Err(e) => warn!("wallet credit {} still failing: {}", tx.transaction_id, e),
+        }
+    }
+}
+
+/// Runs `redeliver_credits` every `every` until the process exits. Panics
+/// if `every` is zero.
+pub fn redeliver_credits_every(
+    store: SharedStore,
+    wallet: SharedWallet,
+    every: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(every);
+        loop {
+            ticker.tick().await;
+            redeliver_credits(&store, wallet.as_ref()).await;
+        }
+    })
+}
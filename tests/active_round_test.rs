@@ -0,0 +1,231 @@
+mod common;
+use common::*;
+use poker_server::config::GameConfig;
+use serde_json::json;
+
+async fn create_test_user(server: &TestServer, client: &reqwest::Client) -> TestUser {
+    let response = client
+        .post(server.url("/api/signup"))
+        .json(&json!({
+            "name": "active_round_user",
+            "password": "secret"
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    TestUser {
+        id: json["id"].as_str().unwrap().to_string(),
+        token: json["access_token"].as_str().unwrap().to_string(),
+    }
+}
+
+async fn start_round(
+    server: &TestServer,
+    client: &reqwest::Client,
+    token: &str,
+) -> reqwest::Response {
+    client
+        .post(server.url("/api/start"))
+        .bearer_auth(token)
+        .json(&json!({
+            "ante": 10
+        }))
+        .send()
+        .await
+        .expect("Failed to send request")
+}
+
+#[tokio::test]
+async fn test_active_round_resume() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+
+    let response = start_round(&server, &client, &user.token).await;
+    let started: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+
+    let response = client
+        .get(server.url(&format!("/api/users/{}/active-rounds", user.id)))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(json["rounds"].as_array().unwrap().len(), 1);
+    let round = &json["rounds"][0];
+    assert_eq!(round["round_id"], started["round_id"]);
+    assert_eq!(round["cards"], started["cards"]);
+    assert_eq!(round["status"], "Active");
+    assert_eq!(round["draws_used"], 0);
+    assert_eq!(round["ante"], 10);
+    assert_eq!(round["total_bet"], 10);
+}
+
+#[tokio::test]
+async fn test_active_round_none() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+
+    let response = client
+        .get(server.url(&format!("/api/users/{}/active-rounds", user.id)))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(json["rounds"], json!([]));
+}
+
+#[tokio::test]
+async fn test_active_round_counts_draws_and_clears_on_reveal() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+
+    let response = start_round(&server, &client, &user.token).await;
+    let started: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    let round_id = started["round_id"].as_str().unwrap();
+
+    client
+        .post(server.url("/api/discard"))
+        .bearer_auth(&user.token)
+        .json(&json!({
+            "round_id": round_id,
+            "discard_indices": [1]
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    let json: serde_json::Value = client
+        .get(server.url(&format!("/api/users/{}/active-rounds", user.id)))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(json["rounds"][0]["draws_used"], 1);
+    // the ante and a fee of half the ante for the one card
+    assert_eq!(json["rounds"][0]["total_bet"], 15);
+
+    client
+        .post(server.url("/api/reveal"))
+        .bearer_auth(&user.token)
+        .json(&json!({
+            "round_id": round_id
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    let json: serde_json::Value = client
+        .get(server.url(&format!("/api/users/{}/active-rounds", user.id)))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(json["rounds"], json!([]));
+}
+
+#[tokio::test]
+async fn test_start_rejects_too_many_active_rounds() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+
+    let response = start_round(&server, &client, &user.token).await;
+    assert_eq!(response.status(), 200);
+
+    // default config allows a single active round per user
+    let response = start_round(&server, &client, &user.token).await;
+    expect_error(response, 409, "too_many_active_rounds").await;
+}
+
+#[tokio::test]
+async fn test_active_rounds_lists_every_open_round() {
+    let server = TestServer::with_config(GameConfig {
+        max_active_rounds_per_user: 2,
+        ..GameConfig::default()
+    })
+    .await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+
+    let first: serde_json::Value = start_round(&server, &client, &user.token)
+        .await
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let second: serde_json::Value = start_round(&server, &client, &user.token)
+        .await
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let response = client
+        .post(server.url("/api/discard"))
+        .bearer_auth(&user.token)
+        .json(&json!({
+            "round_id": first["round_id"],
+            "discard_indices": [0, 1]
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    let json: serde_json::Value = client
+        .get(server.url(&format!("/api/users/{}/active-rounds", user.id)))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let rounds = json["rounds"].as_array().unwrap();
+    assert_eq!(rounds.len(), 2);
+    let bet = |round: &serde_json::Value| {
+        rounds
+            .iter()
+            .find(|r| r["round_id"] == round["round_id"])
+            .map(|r| r["total_bet"].clone())
+            .expect("round listed")
+    };
+    // two cards at half the ante each
+    assert_eq!(bet(&first), 20);
+    assert_eq!(bet(&second), 10);
+}
+
+#[tokio::test]
+async fn test_active_round_of_another_user_is_forbidden() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+    let other = signup(&server, &client, "someone_else").await;
+
+    let response = client
+        .get(server.url(&format!("/api/users/{}/active-rounds", other.id)))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request");
+    expect_error(response, 403, "not_your_account").await;
+
+    let response = client
+        .get(server.url(&format!("/api/users/{}/active-rounds", user.id)))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 401);
+}
@@ -0,0 +1,262 @@
+mod common;
+use common::*;
+use serde_json::json;
+
+async fn admin_post(
+    server: &TestServer,
+    client: &reqwest::Client,
+    path: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    client
+        .post(server.url(path))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to send request")
+}
+
+async fn admin_get(server: &TestServer, client: &reqwest::Client, path: &str) -> serde_json::Value {
+    let response = client
+        .get(server.url(path))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    response.json().await.expect("Failed to parse JSON")
+}
+
+async fn start(client: &reqwest::Client, server: &TestServer, token: &str) -> reqwest::Response {
+    client
+        .post(server.url("/api/start"))
+        .bearer_auth(token)
+        .json(&json!({ "ante": 20 }))
+        .send()
+        .await
+        .expect("Failed to send request")
+}
+
+#[tokio::test]
+async fn test_admin_requires_token() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+
+    let response = client
+        .get(server.url("/api/admin/users"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 401);
+
+    let response = client
+        .get(server.url("/api/admin/reconcile"))
+        .bearer_auth("not-the-token")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 401);
+
+    let users = admin_get(&server, &client, "/api/admin/users?q=USER1").await;
+    assert_eq!(users.as_array().unwrap().len(), 1);
+    assert_eq!(users[0]["id"], "user1");
+    assert!(users[0].get("password").is_none());
+}
+
+#[tokio::test]
+async fn test_frozen_user_cannot_start() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = signup(&server, &client, "frozen_user").await;
+
+    let response = admin_post(
+        &server,
+        &client,
+        &format!("/api/admin/users/{}/freeze", user.id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(json["frozen"], true);
+
+    let response = start(&client, &server, &user.token).await;
+    assert_eq!(response.status(), 403);
+
+    let response = admin_post(
+        &server,
+        &client,
+        &format!("/api/admin/users/{}/unfreeze", user.id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let response = start(&client, &server, &user.token).await;
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_wallet_adjustment_is_recorded_with_reason() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = signup(&server, &client, "adjusted_user").await;
+    let path = format!("/api/admin/users/{}/adjust", user.id);
+
+    let response = admin_post(
+        &server,
+        &client,
+        &path,
+        json!({ "currency": "EUR", "amount": 75, "reason": "goodwill" }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(json["wallets"]["EUR"], 75);
+
+    // can't debit below zero, and a reason is mandatory
+    let response = admin_post(
+        &server,
+        &client,
+        &path,
+        json!({ "currency": "EUR", "amount": -76, "reason": "chargeback" }),
+    )
+    .await;
+    expect_error(response, 402, "insufficient_funds").await;
+    let response = admin_post(
+        &server,
+        &client,
+        &path,
+        json!({ "currency": "EUR", "amount": -5, "reason": " " }),
+    )
+    .await;
+    assert_eq!(response.status(), 400);
+
+    let json: serde_json::Value = client
+        .get(server.url(&format!(
+            "/api/users/{}/transactions?type=adjustment",
+            user.id
+        )))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(json["items"].as_array().unwrap().len(), 1);
+    assert_eq!(json["items"][0]["amount"], 75);
+    assert_eq!(json["items"][0]["note"], "goodwill");
+}
+
+#[tokio::test]
+async fn test_void_round_refunds_ante_and_fees() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = signup(&server, &client, "void_user").await;
+    let before = admin_get(&server, &client, "/api/admin/pools").await;
+
+    let json: serde_json::Value = start(&client, &server, &user.token)
+        .await
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let round_id = json["round_id"].as_str().unwrap().to_string();
+    let response = client
+        .post(server.url("/api/discard"))
+        .bearer_auth(&user.token)
+        .json(&json!({
+            "round_id": &round_id,
+            "discard_indices": [0, 1]
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    let rounds = admin_get(
+        &server,
+        &client,
+        &format!("/api/admin/rounds?user_id={}&status=Active", user.id),
+    )
+    .await;
+    assert_eq!(rounds[0]["id"], round_id.as_str());
+
+    let response = admin_post(
+        &server,
+        &client,
+        &format!("/api/admin/rounds/{}/void", round_id),
+        json!({ "reason": "dealer error" }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(json["refunded"], 20 + 20);
+    assert_eq!(json["wallet"], 1000);
+
+    let round = admin_get(&server, &client, &format!("/api/admin/rounds/{}", round_id)).await;
+    assert_eq!(round["status"], "Voided");
+
+    // pools are back where they started, reservation included
+    let after = admin_get(&server, &client, "/api/admin/pools").await;
+    assert_eq!(after["PLAY"], before["PLAY"]);
+
+    let response = admin_post(
+        &server,
+        &client,
+        &format!("/api/admin/rounds/{}/void", round_id),
+        json!({ "reason": "again" }),
+    )
+    .await;
+    expect_error(response, 409, "round_not_active").await;
+
+    let report = admin_get(&server, &client, "/api/admin/reconcile").await;
+    assert_eq!(report["ok"], true, "{report}");
+}
+
+#[tokio::test]
+async fn test_pool_top_up_and_withdrawals() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+
+    let response = admin_post(
+        &server,
+        &client,
+        "/api/admin/pools/USD/win-pool/top-up",
+        json!({ "amount": 1_000 }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(json["win_pool"], 51_000);
+
+    let response = admin_post(
+        &server,
+        &client,
+        "/api/admin/pools/USD/win-pool/withdraw",
+        json!({ "amount": 51_001 }),
+    )
+    .await;
+    expect_error(response, 409, "win_pool_short").await;
+    let response = admin_post(
+        &server,
+        &client,
+        "/api/admin/pools/USD/win-pool/withdraw",
+        json!({ "amount": 1_000 }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+
+    // the demo store starts with no house profit to take out
+    let response = admin_post(
+        &server,
+        &client,
+        "/api/admin/pools/USD/house-profit/withdraw",
+        json!({ "amount": 1 }),
+    )
+    .await;
+    expect_error(response, 409, "house_profit_short").await;
+
+    let report = admin_get(&server, &client, "/api/admin/reconcile").await;
+    assert_eq!(report["ok"], true, "{report}");
+}
@@ -0,0 +1,196 @@
+mod common;
+use common::*;
+use serde_json::json;
+
+async fn refresh(
+    server: &TestServer,
+    client: &reqwest::Client,
+    refresh_token: &str,
+) -> reqwest::Response {
+    client
+        .post(server.url("/api/token/refresh"))
+        .json(&json!({ "refresh_token": refresh_token }))
+        .send()
+        .await
+        .expect("Failed to send request")
+}
+
+async fn status(
+    server: &TestServer,
+    client: &reqwest::Client,
+    user_id: &str,
+    token: &str,
+) -> reqwest::Response {
+    client
+        .get(server.url(&format!("/api/status/{}", user_id)))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to send request")
+}
+
+#[tokio::test]
+async fn test_requests_need_a_valid_token() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+
+    let response = client
+        .get(server.url("/api/status/nobody"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 401);
+
+    assert_eq!(
+        status(&server, &client, "nobody", "garbage").await.status(),
+        401
+    );
+
+    // a refresh token can't stand in for an access token
+    let json: serde_json::Value = client
+        .post(server.url("/api/signup"))
+        .json(&json!({ "name": "token_kind_user", "password": "secret" }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let user_id = json["id"].as_str().unwrap();
+    let refresh_token = json["refresh_token"].as_str().unwrap();
+    assert_eq!(
+        status(&server, &client, user_id, refresh_token)
+            .await
+            .status(),
+        401
+    );
+}
+
+#[tokio::test]
+async fn test_signin_opens_session_for_existing_user() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = signup(&server, &client, "returning_user").await;
+
+    let response = client
+        .post(server.url("/api/signin"))
+        .json(&json!({ "name": "returning_user", "password": "wrong" }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 401);
+
+    let json: serde_json::Value = client
+        .post(server.url("/api/signin"))
+        .json(&json!({ "name": "returning_user", "password": "secret" }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(json["id"], user.id.as_str());
+    assert!(json["expires_in"].as_i64().unwrap() > 0);
+
+    let token = json["access_token"].as_str().unwrap();
+    let me: serde_json::Value = status(&server, &client, &user.id, token)
+        .await
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(me["wallet"], 1000);
+}
+
+#[tokio::test]
+async fn test_refresh_rotates_and_reuse_revokes_session() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let json: serde_json::Value = client
+        .post(server.url("/api/signup"))
+        .json(&json!({ "name": "rotating_user", "password": "secret" }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let user_id = json["id"].as_str().unwrap();
+    let first = json["refresh_token"].as_str().unwrap().to_string();
+
+    let response = refresh(&server, &client, &first).await;
+    assert_eq!(response.status(), 200);
+    let rotated: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    let second = rotated["refresh_token"].as_str().unwrap();
+    assert_ne!(second, first);
+    let access = rotated["access_token"].as_str().unwrap();
+    assert_eq!(
+        status(&server, &client, user_id, access).await.status(),
+        200
+    );
+
+    // replaying the spent token ends the whole session
+    assert_eq!(refresh(&server, &client, &first).await.status(), 401);
+    assert_eq!(refresh(&server, &client, second).await.status(), 401);
+    assert_eq!(
+        status(&server, &client, user_id, access).await.status(),
+        401
+    );
+}
+
+#[tokio::test]
+async fn test_logout_revokes_session() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = signup(&server, &client, "leaving_user").await;
+
+    let response = client
+        .post(server.url("/api/logout"))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 204);
+
+    assert_eq!(
+        status(&server, &client, &user.id, &user.token)
+            .await
+            .status(),
+        401
+    );
+}
+
+#[tokio::test]
+async fn test_cashier_requests_are_private() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let owner = signup(&server, &client, "cashier_owner").await;
+    let other = signup(&server, &client, "cashier_snoop").await;
+
+    let json: serde_json::Value = client
+        .post(server.url("/api/cashier/deposit"))
+        .bearer_auth(&owner.token)
+        .json(&json!({ "amount": 50 }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let path = format!("/api/cashier/requests/{}", json["id"].as_str().unwrap());
+
+    let response = client
+        .get(server.url(&path))
+        .bearer_auth(&other.token)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 404);
+
+    let response = client
+        .get(server.url(&path))
+        .bearer_auth(&owner.token)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+}
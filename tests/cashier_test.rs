@@ -0,0 +1,310 @@
+mod common;
+use common::*;
+use poker_server::cashier::{Cashier, MockProvider};
+use poker_server::models::{CashierStatus, Currency};
+use poker_server::money::Money;
+use poker_server::store::{InMem, SharedStore};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn create_test_user(server: &TestServer, client: &reqwest::Client) -> TestUser {
+    let json: serde_json::Value = client
+        .post(server.url("/api/signup"))
+        .json(&json!({
+            "name": "cashier_user",
+            "password": "secret"
+        }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    TestUser {
+        id: json["id"].as_str().unwrap().to_string(),
+        token: json["access_token"].as_str().unwrap().to_string(),
+    }
+}
+
+async fn cashier_post(
+    server: &TestServer,
+    client: &reqwest::Client,
+    token: &str,
+    path: &str,
+    body: serde_json::Value,
+) -> serde_json::Value {
+    let response = client
+        .post(server.url(path))
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    response.json().await.expect("Failed to parse JSON")
+}
+
+/// Polls until the provider callback has moved the request out of `pending`.
+async fn wait_resolved(
+    server: &TestServer,
+    client: &reqwest::Client,
+    token: &str,
+    id: &str,
+) -> serde_json::Value {
+    for _ in 0..50 {
+        let json: serde_json::Value = client
+            .get(server.url(&format!("/api/cashier/requests/{}", id)))
+            .bearer_auth(token)
+            .send()
+            .await
+            .expect("Failed to send request")
+            .json()
+            .await
+            .expect("Failed to parse JSON");
+        if json["status"] != "pending" {
+            return json;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("cashier request {id} never resolved");
+}
+
+async fn status(
+    server: &TestServer,
+    client: &reqwest::Client,
+    user: &TestUser,
+) -> serde_json::Value {
+    client
+        .get(server.url(&format!("/api/status/{}", user.id)))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON")
+}
+
+#[tokio::test]
+async fn test_deposit_credits_wallet_after_callback() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+    let token = user.token.clone();
+
+    let req = cashier_post(
+        &server,
+        &client,
+        &token,
+        "/api/cashier/deposit",
+        json!({ "amount": 250 }),
+    )
+    .await;
+    assert_eq!(req["status"], "pending");
+    assert_eq!(req["kind"], "deposit");
+
+    let req = wait_resolved(&server, &client, &token, req["id"].as_str().unwrap()).await;
+    assert_eq!(req["status"], "approved");
+    assert_eq!(status(&server, &client, &user).await["wallet"], 1250);
+
+    let json: serde_json::Value = client
+        .get(server.url(&format!("/api/users/{}/transactions?type=deposit", user.id)))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(json["items"][0]["amount"], 250);
+}
+
+#[tokio::test]
+async fn test_no_provider_refuses_deposits() {
+    let server = TestServer::without_payments().await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+
+    for path in ["/api/cashier/deposit", "/api/cashier/withdraw"] {
+        let response = client
+            .post(server.url(path))
+            .bearer_auth(&user.token)
+            .json(&json!({ "amount": 250 }))
+            .send()
+            .await
+            .expect("Failed to send request");
+        expect_error(response, 503, "payments_unavailable").await;
+    }
+    assert_eq!(status(&server, &client, &user).await["wallet"], 1000);
+}
+
+#[tokio::test]
+async fn test_withdrawal_holds_funds_until_paid_out() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+    let token = user.token.clone();
+
+    let req = cashier_post(
+        &server,
+        &client,
+        &token,
+        "/api/cashier/withdraw",
+        json!({ "amount": 400 }),
+    )
+    .await;
+    assert_eq!(req["status"], "pending");
+    assert_eq!(req["requires_approval"], false);
+
+    // only 600 is spendable while the withdrawal is in flight
+    let response = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&token)
+        .json(&json!({ "ante": 700 }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    expect_error(response, 402, "insufficient_funds").await;
+
+    let req = wait_resolved(&server, &client, &token, req["id"].as_str().unwrap()).await;
+    assert_eq!(req["status"], "approved");
+    assert_eq!(status(&server, &client, &user).await["wallet"], 600);
+}
+
+#[tokio::test]
+async fn test_large_withdrawal_needs_admin_approval() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+    let token = user.token.clone();
+
+    let req = cashier_post(
+        &server,
+        &client,
+        &token,
+        "/api/cashier/withdraw",
+        json!({ "amount": 800 }),
+    )
+    .await;
+    assert_eq!(req["requires_approval"], true);
+    let id = req["id"].as_str().unwrap().to_string();
+
+    // nothing happens without an operator
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    let pending: serde_json::Value = client
+        .get(server.url("/api/admin/cashier/requests?status=pending"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(pending.as_array().unwrap().len(), 1);
+    assert_eq!(pending[0]["id"], id.as_str());
+
+    let req = cashier_post(
+        &server,
+        &client,
+        ADMIN_TOKEN,
+        &format!("/api/admin/cashier/requests/{}/approve", id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(req["requires_approval"], false);
+
+    let req = wait_resolved(&server, &client, &token, &id).await;
+    assert_eq!(req["status"], "approved");
+    assert_eq!(status(&server, &client, &user).await["wallet"], 200);
+}
+
+#[tokio::test]
+async fn test_admin_reject_releases_hold() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let token = create_test_user(&server, &client).await.token;
+
+    let req = cashier_post(
+        &server,
+        &client,
+        &token,
+        "/api/cashier/withdraw",
+        json!({ "amount": 900 }),
+    )
+    .await;
+    let id = req["id"].as_str().unwrap().to_string();
+
+    let req = cashier_post(
+        &server,
+        &client,
+        ADMIN_TOKEN,
+        &format!("/api/admin/cashier/requests/{}/reject", id),
+        json!({ "reason": "kyc pending" }),
+    )
+    .await;
+    assert_eq!(req["status"], "rejected");
+    assert_eq!(req["reason"], "kyc pending");
+
+    // the whole wallet is spendable again
+    let response = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&token)
+        .json(&json!({ "ante": 900 }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_withdrawal_over_available_is_rejected() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let token = create_test_user(&server, &client).await.token;
+
+    let response = client
+        .post(server.url("/api/cashier/withdraw"))
+        .bearer_auth(&token)
+        .json(&json!({ "amount": 1001 }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    expect_error(response, 402, "insufficient_funds").await;
+}
+
+#[tokio::test]
+async fn test_provider_decline_releases_hold() {
+    let store: SharedStore = InMem::new_demo().into_shared();
+    let provider = MockProvider {
+        delay: Duration::from_millis(50),
+        decline_above: Some(Money::new(100)),
+    };
+    let cashier = Cashier::new(store.clone(), Arc::new(provider), Money::new(500));
+
+    let req = cashier
+        .withdraw("user1", Currency::Play, Money::new(300))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_user("user1").await.unwrap().held(Currency::Play),
+        Money::new(300)
+    );
+
+    let mut resolved = None;
+    for _ in 0..50 {
+        let r = store.get_cashier_request(&req.id).await.unwrap();
+        if r.status != CashierStatus::Pending {
+            resolved = Some(r);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let resolved = resolved.expect("request never resolved");
+    assert_eq!(resolved.status, CashierStatus::Rejected);
+
+    let user = store.get_user("user1").await.unwrap();
+    assert_eq!(user.held(Currency::Play), Money::ZERO);
+    assert_eq!(user.balance(Currency::Play), Money::new(1000));
+    assert!(store.reconcile().await.ok);
+}
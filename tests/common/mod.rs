@@ -1,21 +1,87 @@
-use poker_server::server::router;
-use poker_server::store::InMem;
+use axum::extract::Extension;
+use poker_server::cashier::{Cashier, MockProvider};
+use poker_server::config::GameConfig;
+use poker_server::models::ErrorBody;
+use poker_server::server::{router_with_cashier, router_with_config};
+use poker_server::store::{InMem, SharedSnapshots, SharedStore, Snapshots};
+use poker_server::wallet;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tower_http::cors::{Any, CorsLayer};
 
+/// Admin token every `TestServer` accepts on `/api/admin`.
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+
 pub struct TestServer {
     pub addr: SocketAddr,
+    #[allow(dead_code)]
+    pub store: SharedStore,
 }
 
 impl TestServer {
+    #[allow(dead_code)]
     pub async fn new() -> Self {
-        // Create in-memory store
-        let inmem = InMem::new_demo();
+        Self::with_config(GameConfig::default()).await
+    }
+
+    /// A server on `config`, which gets `ADMIN_TOKEN` set.
+    #[allow(dead_code)]
+    pub async fn with_config(config: GameConfig) -> Self {
+        Self::start(InMem::new_demo(), config, None, true).await
+    }
+
+    /// A server with no payment provider, as `main` runs by default.
+    #[allow(dead_code)]
+    pub async fn without_payments() -> Self {
+        Self::start(InMem::new_demo(), GameConfig::default(), None, false).await
+    }
+
+    /// A server whose admin snapshot routes save `mem` to `path`.
+    #[allow(dead_code)]
+    pub async fn with_snapshots(mem: InMem, path: &Path) -> Self {
+        let snapshots = Snapshots::new(mem.clone(), path);
+        Self::start(mem, GameConfig::default(), Some(snapshots), true).await
+    }
+
+    /// With `mock_payments`, deposits and withdrawals go through a
+    /// `MockProvider` like `main --mock-payments`.
+    async fn start(
+        inmem: InMem,
+        config: GameConfig,
+        snapshots: Option<SharedSnapshots>,
+        mock_payments: bool,
+    ) -> Self {
         let shared_store = inmem.into_shared();
 
         // Build the same app as in main.rs
-        let app = router(shared_store.clone())
+        let config = GameConfig {
+            admin_token: Some(ADMIN_TOKEN.to_string()),
+            ..config
+        };
+        if config.wallet_url.is_some() {
+            wallet::redeliver_credits_every(
+                shared_store.clone(),
+                wallet::from_config(&config, shared_store.clone()),
+                Duration::from_millis(config.wallet_credit_retry_ms),
+            );
+        }
+        let mut app = if mock_payments {
+            let cashier = Cashier::new(
+                shared_store.clone(),
+                Arc::new(MockProvider::default()),
+                config.withdrawal_approval_threshold,
+            );
+            router_with_cashier(shared_store.clone(), config, cashier)
+        } else {
+            router_with_config(shared_store.clone(), config)
+        };
+        if let Some(snapshots) = snapshots {
+            app = app.layer(Extension(snapshots));
+        }
+        let app = app
             .layer(tower_http::trace::TraceLayer::new_for_http())
             .layer(CorsLayer::new().allow_origin(Any).allow_methods(Any));
 
@@ -31,7 +97,10 @@ impl TestServer {
         // Give server time to start
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
 
-        TestServer { addr }
+        TestServer {
+            addr,
+            store: shared_store,
+        }
     }
 
     pub fn url(&self, path: &str) -> String {
@@ -43,3 +112,36 @@ impl TestServer {
 pub async fn make_client() -> reqwest::Client {
     reqwest::Client::new()
 }
+
+/// A freshly signed-up player: their id and an access token for them.
+#[allow(dead_code)]
+pub struct TestUser {
+    pub id: String,
+    pub token: String,
+}
+
+#[allow(dead_code)]
+pub async fn signup(server: &TestServer, client: &reqwest::Client, name: &str) -> TestUser {
+    let json: serde_json::Value = client
+        .post(server.url("/api/signup"))
+        .json(&serde_json::json!({ "name": name, "password": "secret" }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    TestUser {
+        id: json["id"].as_str().unwrap().to_string(),
+        token: json["access_token"].as_str().unwrap().to_string(),
+    }
+}
+
+/// Checks an error response's status and returns its body.
+#[allow(dead_code)]
+pub async fn expect_error(response: reqwest::Response, status: u16, code: &str) -> ErrorBody {
+    assert_eq!(response.status(), status);
+    let body: ErrorBody = response.json().await.expect("error body");
+    assert_eq!(body.code, code, "{}", body.message);
+    body
+}
@@ -0,0 +1,54 @@
+#![cfg(feature = "testing")]
+
+use poker_server::store::{InMem, JournalStore};
+use poker_server::testing::store_conformance;
+use std::path::PathBuf;
+
+/// A directory for one backend's files, removed on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("poker-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    fn file(&self, extension: &str) -> PathBuf {
+        self.0.join(format!("{}.{extension}", uuid::Uuid::new_v4()))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[tokio::test]
+async fn test_in_memory_store_conforms() {
+    store_conformance(|| async { InMem::new_demo().into_shared() }).await;
+}
+
+#[tokio::test]
+async fn test_journal_store_conforms() {
+    let dir = TempDir::new();
+    store_conformance(|| async {
+        JournalStore::open(dir.file("journal"))
+            .unwrap()
+            .into_shared()
+    })
+    .await;
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_sqlite_store_conforms() {
+    let dir = TempDir::new();
+    store_conformance(|| async {
+        poker_server::store::SqliteStore::open(dir.file("db"))
+            .unwrap()
+            .into_shared()
+    })
+    .await;
+}
@@ -0,0 +1,217 @@
+mod common;
+use common::*;
+use poker_server::game::{self, GameError};
+use poker_server::models::{Currency, NewRound, Paytable, PoolShare, Settlement};
+use poker_server::money::Money;
+use poker_server::store::{InMem, SharedStore, StoreError};
+use serde_json::json;
+use std::time::Duration;
+
+async fn create_test_user(server: &TestServer, client: &reqwest::Client) -> serde_json::Value {
+    client
+        .post(server.url("/api/signup"))
+        .json(&json!({
+            "name": "currency_user",
+            "password": "secret"
+        }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON")
+}
+
+async fn open_round(store: &SharedStore, currency: Currency) -> Result<String, StoreError> {
+    let mut deck = game::new_deck();
+    store
+        .start_round(NewRound {
+            user_id: "user1".to_string(),
+            currency,
+            ante: Money::new(10),
+            cards: game::deal_hand(&mut deck, 5),
+            max_active_rounds: 2,
+            paytable: Paytable::default(),
+            remote_wallet: false,
+        })
+        .await
+        .map(|r| r.round.id)
+}
+
+#[tokio::test]
+async fn test_signup_reports_wallets_by_currency() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+
+    let json = create_test_user(&server, &client).await;
+    assert_eq!(json["currency"], "PLAY");
+    assert_eq!(json["wallet"], 1000);
+    assert_eq!(json["wallets"], json!({ "PLAY": 1000 }));
+}
+
+#[tokio::test]
+async fn test_round_in_deposited_currency() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let json = create_test_user(&server, &client).await;
+    let user_id = json["id"].as_str().unwrap().to_string();
+    let token = json["access_token"].as_str().unwrap().to_string();
+
+    // no EUR yet
+    let response = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&token)
+        .json(&json!({ "currency": "EUR", "ante": 10 }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    expect_error(response, 402, "insufficient_funds").await;
+
+    let response = client
+        .post(server.url("/api/cashier/deposit"))
+        .bearer_auth(&token)
+        .json(&json!({ "currency": "EUR", "amount": 100 }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    let mut eur = serde_json::Value::Null;
+    for _ in 0..50 {
+        eur = client
+            .get(server.url(&format!("/api/status/{}?currency=EUR", user_id)))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .expect("Failed to send request")
+            .json()
+            .await
+            .expect("Failed to parse JSON");
+        if eur["wallet"] == 100 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(eur["currency"], "EUR");
+    assert_eq!(eur["wallet"], 100);
+
+    let json: serde_json::Value = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&token)
+        .json(&json!({ "currency": "EUR", "ante": 10 }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(json["currency"], "EUR");
+    assert_eq!(json["wallet"], 90);
+
+    let round: serde_json::Value = client
+        .get(server.url(&format!("/api/users/{}/active-rounds", user_id)))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(round["rounds"][0]["currency"], "EUR");
+
+    // play money is untouched
+    let play: serde_json::Value = client
+        .get(server.url(&format!("/api/status/{}", user_id)))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(play["currency"], "PLAY");
+    assert_eq!(play["wallet"], 1000);
+}
+
+#[tokio::test]
+async fn test_ante_limits_per_currency() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let token = create_test_user(&server, &client).await["access_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let start = |currency: &str, ante: i64| {
+        client
+            .post(server.url("/api/start"))
+            .bearer_auth(&token)
+            .json(&json!({ "currency": currency, "ante": ante }))
+            .send()
+    };
+
+    // above the USD maximum, regardless of balance
+    let response = start("USD", 600).await.expect("Failed to send request");
+    let body = expect_error(response, 400, "ante_out_of_range").await;
+    assert_eq!(body.details.unwrap()["max"], 500);
+
+    // the same ante is fine in play money
+    let response = start("PLAY", 600).await.expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    let response = start("GBP", 10).await.expect("Failed to send request");
+    assert_eq!(response.status(), 422);
+}
+
+#[tokio::test]
+async fn test_pools_are_separate_per_currency() {
+    let store = InMem::new_demo().into_shared();
+    store
+        .update_user_wallet("user1", Currency::Eur, Money::new(100))
+        .await
+        .unwrap();
+
+    // an empty EUR pool can't back a EUR round even though PLAY is full
+    store
+        .sub_from_win_pool(Currency::Eur, Money::new(50_000))
+        .await
+        .unwrap();
+    let err = open_round(&store, Currency::Eur).await.unwrap_err();
+    assert!(
+        matches!(err, StoreError::Game(GameError::PoolTooSmall { .. })),
+        "{err}"
+    );
+    store
+        .add_to_pools(Currency::Eur, Money::new(1_000), Money::ZERO)
+        .await
+        .unwrap();
+
+    let round_id = open_round(&store, Currency::Eur).await.unwrap();
+    let settled = store
+        .settle_round(Settlement {
+            user_id: "user1".to_string(),
+            round_id,
+            expected_draws: 0,
+            payout: Money::new(30),
+            share: PoolShare::default(),
+            folded: false,
+        })
+        .await
+        .unwrap();
+    assert_eq!(settled.wallet, Money::new(100 - 10 + 30));
+    assert_eq!(settled.pools.win_pool, Money::new(1_000 + 10 - 30));
+    assert_eq!(
+        store.get_pools(Currency::Play).await.win_pool,
+        Money::new(50_000)
+    );
+
+    let user = store.get_user("user1").await.unwrap();
+    assert_eq!(user.balance(Currency::Eur), Money::new(120));
+    assert_eq!(user.balance(Currency::Play), Money::new(1000));
+
+    let report = store.reconcile().await;
+    assert!(report.ok, "{report:?}");
+    assert_eq!(
+        report.totals_for(Currency::Eur).unwrap().external_in,
+        Money::new(100 + 50_000 - 50_000 + 1_000)
+    );
+}
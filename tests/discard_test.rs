@@ -1,24 +1,15 @@
 mod common;
 use common::*;
+use poker_server::models::Currency;
 use serde_json::json;
 
 async fn create_game(server: &TestServer, client: &reqwest::Client) -> (String, String) {
-    let response = client
-        .post(&server.url("/api/login"))
-        .json(&json!({
-            "name": "discard_test_user"
-        }))
-        .send()
-        .await
-        .expect("Failed to send request");
-
-    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
-    let user_id = json["id"].as_str().unwrap().to_string();
+    let token = signup(server, client, "discard_test_user").await.token;
 
     let response = client
-        .post(&server.url("/api/start"))
+        .post(server.url("/api/start"))
+        .bearer_auth(&token)
         .json(&json!({
-            "user_id": &user_id,
             "ante": 10
         }))
         .send()
@@ -28,19 +19,19 @@ async fn create_game(server: &TestServer, client: &reqwest::Client) -> (String,
     let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
     let round_id = json["round_id"].as_str().unwrap().to_string();
 
-    (user_id, round_id)
+    (token, round_id)
 }
 
 #[tokio::test]
 async fn test_discard_success() {
     let server = TestServer::new().await;
     let client = make_client().await;
-    let (user_id, round_id) = create_game(&server, &client).await;
+    let (token, round_id) = create_game(&server, &client).await;
 
     let response = client
-        .post(&server.url("/api/discard"))
+        .post(server.url("/api/discard"))
+        .bearer_auth(&token)
         .json(&json!({
-            "user_id": user_id,
             "round_id": round_id,
             "discard_indices": [0, 2]
         }))
@@ -60,12 +51,12 @@ async fn test_discard_success() {
 async fn test_discard_invalid_round() {
     let server = TestServer::new().await;
     let client = make_client().await;
-    let (user_id, _) = create_game(&server, &client).await;
+    let (token, _) = create_game(&server, &client).await;
 
     let response = client
-        .post(&server.url("/api/discard"))
+        .post(server.url("/api/discard"))
+        .bearer_auth(&token)
         .json(&json!({
-            "user_id": user_id,
             "round_id": "invalid-round-id",
             "discard_indices": [0, 1]
         }))
@@ -73,5 +64,33 @@ async fn test_discard_invalid_round() {
         .await
         .expect("Failed to send request");
 
-    assert_eq!(response.status(), 400);
+    expect_error(response, 404, "round_not_found").await;
+}
+
+#[tokio::test]
+async fn test_discard_rejects_bad_indices() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let (token, round_id) = create_game(&server, &client).await;
+
+    let oversized: Vec<usize> = (0..60).collect();
+    for indices in [json!([]), json!([5]), json!([1, 1]), json!(oversized)] {
+        let response = client
+            .post(server.url("/api/discard"))
+            .bearer_auth(&token)
+            .json(&json!({
+                "round_id": round_id,
+                "discard_indices": indices
+            }))
+            .send()
+            .await
+            .expect("Failed to send request");
+        expect_error(response, 400, "invalid_request").await;
+    }
+
+    // nothing was charged and the round is untouched
+    let round = server.store.get_round(&round_id).await.unwrap();
+    assert_eq!(round.draws_used, 0);
+    let user = server.store.get_user(&round.user_id).await.unwrap();
+    assert_eq!(user.balance(Currency::Play).minor(), 990);
 }
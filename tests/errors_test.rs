@@ -0,0 +1,92 @@
+mod common;
+use common::*;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_errors_have_a_code_and_status() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+
+    let response = client
+        .get(server.url("/api/status/nobody"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(
+        response.headers()["content-type"].to_str().unwrap(),
+        "application/json"
+    );
+    let body = expect_error(response, 401, "missing_token").await;
+    assert!(body.details.is_none());
+
+    let response = client
+        .get(server.url("/api/status/nobody"))
+        .bearer_auth("garbage")
+        .send()
+        .await
+        .expect("Failed to send request");
+    expect_error(response, 401, "invalid_token").await;
+
+    let response = client
+        .get(server.url("/api/admin/rounds/no-such-round"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("Failed to send request");
+    expect_error(response, 404, "round_not_found").await;
+}
+
+#[tokio::test]
+async fn test_other_players_rounds_are_refused() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let owner = signup(&server, &client, "errors_round_owner").await;
+    let other = signup(&server, &client, "errors_round_other").await;
+
+    let started: serde_json::Value = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&owner.token)
+        .json(&json!({ "ante": 10 }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+
+    let response = client
+        .post(server.url("/api/reveal"))
+        .bearer_auth(&other.token)
+        .json(&json!({ "round_id": started["round_id"] }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    expect_error(response, 403, "not_your_round").await;
+}
+
+#[tokio::test]
+async fn test_small_pool_reports_largest_ante() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "errors_small_pool").await;
+
+    // leave 100 in the play-money pool; the top multiplier is 50
+    let response = client
+        .post(server.url("/api/admin/pools/PLAY/win-pool/withdraw"))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&json!({ "amount": 49_900 }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&player.token)
+        .json(&json!({ "ante": 10 }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    let body = expect_error(response, 503, "pool_too_small").await;
+    assert_eq!(body.details.unwrap()["max_ante"], 2);
+}
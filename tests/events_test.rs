@@ -0,0 +1,213 @@
+mod common;
+use common::*;
+use poker_server::config::GameConfig;
+use poker_server::models::{PoolShare, Settlement};
+use poker_server::money::Money;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// A listener on `/api/events`.
+struct Events {
+    response: reqwest::Response,
+    buf: String,
+}
+
+impl Events {
+    async fn open(server: &TestServer, client: &reqwest::Client) -> Self {
+        let response = client
+            .get(server.url("/api/events"))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        Events {
+            response,
+            buf: String::new(),
+        }
+    }
+
+    /// The next event's name and data, skipping keep-alive comments.
+    async fn next(&mut self) -> (String, Value) {
+        loop {
+            if let Some(end) = self.buf.find("\n\n") {
+                let block: String = self.buf.drain(..end + 2).collect();
+                let mut name = None;
+                let mut data = String::new();
+                for line in block.lines() {
+                    if let Some(v) = line.strip_prefix("event:") {
+                        name = Some(v.trim().to_string());
+                    } else if let Some(v) = line.strip_prefix("data:") {
+                        data.push_str(v.trim_start());
+                    }
+                }
+                if let Some(name) = name {
+                    return (name, serde_json::from_str(&data).unwrap());
+                }
+                continue;
+            }
+            let chunk = tokio::time::timeout(Duration::from_secs(5), self.response.chunk())
+                .await
+                .expect("no event in time")
+                .expect("stream error")
+                .expect("stream ended");
+            self.buf.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+    }
+
+    /// The data of the next event called `name`.
+    async fn until(&mut self, name: &str) -> Value {
+        loop {
+            let (n, data) = self.next().await;
+            if n == name {
+                return data;
+            }
+        }
+    }
+}
+
+async fn top_up(server: &TestServer, client: &reqwest::Client, amount: i64) {
+    let response = client
+        .post(server.url("/api/admin/pools/PLAY/win-pool/top-up"))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&json!({ "amount": amount }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+/// Starts a round over HTTP and settles it in the store for `payout`, as
+/// a winning reveal would.
+async fn win(server: &TestServer, client: &reqwest::Client, player: &TestUser, payout: i64) {
+    let response = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&player.token)
+        .json(&json!({ "ante": 10 }))
+        .send()
+        .await
+        .unwrap();
+    let start: Value = response.json().await.unwrap();
+    server
+        .store
+        .settle_round(Settlement {
+            user_id: player.id.clone(),
+            round_id: start["round_id"].as_str().unwrap().to_string(),
+            expected_draws: 0,
+            payout: Money::new(payout),
+            share: PoolShare::default(),
+            folded: false,
+        })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_events_start_with_pools_and_announce_big_wins() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "events_winner").await;
+    let mut events = Events::open(&server, &client).await;
+
+    for currency in ["EUR", "USD", "PLAY"] {
+        let (name, data) = events.next().await;
+        assert_eq!(name, "pools");
+        assert_eq!(data["type"], "pools");
+        assert_eq!(data["currency"], currency);
+        assert_eq!(data["win_pool"], 50_000);
+    }
+
+    top_up(&server, &client, 500).await;
+    let pools = events.until("pools").await;
+    assert_eq!(pools["win_pool"], 50_500);
+
+    // twice the ante is nothing to shout about; 30 times is
+    win(&server, &client, &player, 20).await;
+    win(&server, &client, &player, 300).await;
+    let big_win = events.until("big_win").await;
+    assert_eq!(big_win["currency"], "PLAY");
+    assert_eq!(big_win["ante"], 10);
+    assert_eq!(big_win["payout"], 300);
+    assert_eq!(big_win["multiplier"], 30);
+}
+
+#[tokio::test]
+async fn test_events_coalesce_pool_updates() {
+    let server = TestServer::with_config(GameConfig {
+        events_coalesce_ms: 500,
+        ..GameConfig::default()
+    })
+    .await;
+    let client = make_client().await;
+    let mut events = Events::open(&server, &client).await;
+    for _ in 0..3 {
+        events.until("pools").await;
+    }
+
+    for _ in 0..10 {
+        top_up(&server, &client, 100).await;
+    }
+    // at most the first change on its own, then the rest in one go
+    let mut updates = 0;
+    loop {
+        let pools = events.until("pools").await;
+        updates += 1;
+        if pools["win_pool"] == 51_000 {
+            break;
+        }
+    }
+    assert!(updates <= 2, "{updates} updates for one burst");
+}
+
+#[tokio::test]
+async fn test_events_carry_maintenance_notices() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "events_player").await;
+    let mut events = Events::open(&server, &client).await;
+
+    let notice = json!({
+        "message": "Down for upgrades",
+        "starts_at": "2030-01-01T02:00:00Z",
+        "ends_at": "2030-01-01T03:00:00Z",
+    });
+    let response = client
+        .post(server.url("/api/admin/maintenance"))
+        .bearer_auth(&player.token)
+        .json(&notice)
+        .send()
+        .await
+        .unwrap();
+    expect_error(response, 403, "permission_denied").await;
+
+    let response = client
+        .post(server.url("/api/admin/maintenance"))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&json!({ "message": "  " }))
+        .send()
+        .await
+        .unwrap();
+    expect_error(response, 400, "invalid_request").await;
+
+    let response = client
+        .post(server.url("/api/admin/maintenance"))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&notice)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let posted = events.until("maintenance").await;
+    assert_eq!(posted["message"], "Down for upgrades");
+    assert_eq!(posted["ends_at"], "2030-01-01T03:00:00Z");
+
+    // later listeners see it after the pools
+    let mut late = Events::open(&server, &client).await;
+    for _ in 0..3 {
+        assert_eq!(late.next().await.0, "pools");
+    }
+    let (name, data) = late.next().await;
+    assert_eq!(name, "maintenance");
+    assert_eq!(data["message"], "Down for upgrades");
+}
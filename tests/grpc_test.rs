@@ -0,0 +1,293 @@
+mod common;
+use common::*;
+use poker_server::config::GameConfig;
+use poker_server::grpc::{
+    self,
+    pb::{self, poker_client::PokerClient},
+};
+use tokio::net::TcpListener;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Status};
+
+/// An HTTP server and a gRPC server on the same store and config, so
+/// tokens from one work on the other.
+async fn start() -> (TestServer, PokerClient<Channel>) {
+    let config = GameConfig::default();
+    let server = TestServer::with_config(config.clone()).await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(grpc::serve(
+        listener,
+        server.store.clone(),
+        config,
+        std::future::pending(),
+    ));
+    let client = PokerClient::connect(format!("http://{addr}"))
+        .await
+        .expect("Failed to connect");
+    (server, client)
+}
+
+fn authed<T>(token: &str, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert("authorization", format!("Bearer {token}").parse().unwrap());
+    request
+}
+
+fn expect_status(status: Status, code: Code, error_code: &str) {
+    assert_eq!(status.code(), code, "{status:?}");
+    assert_eq!(status.metadata().get("error-code").unwrap(), error_code);
+}
+
+#[tokio::test]
+async fn test_grpc_plays_a_round() {
+    let (server, mut grpc) = start().await;
+
+    let login = grpc
+        .sign_up(pb::SignUpRequest {
+            name: "grpc_player".to_string(),
+            password: "secret".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(login.currency, pb::Currency::Play as i32);
+    assert_eq!(login.wallet, 1000);
+    assert_eq!(login.wallets["PLAY"], 1000);
+    let user_id = login.id;
+    let token = login.tokens.unwrap().access_token;
+
+    let login = grpc
+        .sign_in(pb::SignInRequest {
+            name: "grpc_player".to_string(),
+            password: "secret".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(login.id, user_id);
+
+    let start = grpc
+        .start(authed(
+            &token,
+            pb::StartRequest {
+                currency: pb::Currency::Unspecified as i32,
+                ante: 10,
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(start.cards.len(), 5);
+    assert_eq!(start.wallet, 990);
+
+    // the HTTP API sees the same store
+    let client = make_client().await;
+    let status: serde_json::Value = client
+        .get(server.url(&format!("/api/status/{}", user_id)))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["wallet"], 990);
+
+    let discard = grpc
+        .discard(authed(
+            &token,
+            pb::DiscardRequest {
+                round_id: start.round_id.clone(),
+                discard_indices: vec![0, 1],
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(discard.wallet, 980);
+    assert_eq!(discard.cards.len(), 5);
+
+    let reveal = grpc
+        .reveal(authed(
+            &token,
+            pb::RevealRequest {
+                round_id: start.round_id.clone(),
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(reveal.wallet, 980 + reveal.payout);
+
+    let status = grpc
+        .status(authed(
+            &token,
+            pb::StatusRequest {
+                currency: pb::Currency::Eur as i32,
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(status.currency, pb::Currency::Eur as i32);
+    assert_eq!(status.wallet, 0);
+    assert_eq!(status.win_pool, 50_000);
+}
+
+#[tokio::test]
+async fn test_grpc_errors_carry_the_api_code() {
+    let (_server, mut grpc) = start().await;
+    let login = grpc
+        .sign_up(pb::SignUpRequest {
+            name: "grpc_errors".to_string(),
+            password: "secret".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let token = login.tokens.unwrap().access_token;
+
+    let err = grpc
+        .sign_up(pb::SignUpRequest {
+            name: "grpc_errors".to_string(),
+            password: "other".to_string(),
+        })
+        .await
+        .unwrap_err();
+    expect_status(err, Code::FailedPrecondition, "name_taken");
+
+    let err = grpc
+        .sign_in(pb::SignInRequest {
+            name: "grpc_errors".to_string(),
+            password: "wrong".to_string(),
+        })
+        .await
+        .unwrap_err();
+    expect_status(err, Code::Unauthenticated, "invalid_credentials");
+
+    let err = grpc
+        .status(Request::new(pb::StatusRequest::default()))
+        .await
+        .unwrap_err();
+    expect_status(err, Code::Unauthenticated, "missing_token");
+
+    let err = grpc
+        .start(authed(
+            &token,
+            pb::StartRequest {
+                currency: 0,
+                ante: 0,
+            },
+        ))
+        .await
+        .unwrap_err();
+    expect_status(err, Code::InvalidArgument, "invalid_ante");
+
+    let err = grpc
+        .reveal(authed(
+            &token,
+            pb::RevealRequest {
+                round_id: "no-such-round".to_string(),
+            },
+        ))
+        .await
+        .unwrap_err();
+    expect_status(err, Code::NotFound, "round_not_found");
+
+    let err = grpc
+        .status(authed(&token, pb::StatusRequest { currency: 99 }))
+        .await
+        .unwrap_err();
+    expect_status(err, Code::InvalidArgument, "invalid_request");
+}
+
+fn keyed<T>(token: &str, key: &str, message: T) -> Request<T> {
+    let mut request = authed(token, message);
+    request
+        .metadata_mut()
+        .insert("idempotency-key", key.parse().unwrap());
+    request
+}
+
+async fn grpc_signup(grpc: &mut PokerClient<Channel>, name: &str) -> String {
+    let login = grpc
+        .sign_up(pb::SignUpRequest {
+            name: name.to_string(),
+            password: "secret".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    login.tokens.unwrap().access_token
+}
+
+#[tokio::test]
+async fn test_grpc_idempotency_key_replays_the_first_reply() {
+    let (_server, mut grpc) = start().await;
+    let token = grpc_signup(&mut grpc, "grpc_idem").await;
+    let ante = pb::StartRequest {
+        currency: pb::Currency::Unspecified as i32,
+        ante: 10,
+    };
+
+    let first = grpc.start(keyed(&token, "k1", ante)).await.unwrap();
+    assert!(first.metadata().get("idempotent-replayed").is_none());
+    let retry = grpc.start(keyed(&token, "k1", ante)).await.unwrap();
+    assert_eq!(retry.metadata().get("idempotent-replayed").unwrap(), "true");
+    let (first, retry) = (first.into_inner(), retry.into_inner());
+    assert_eq!(retry.round_id, first.round_id);
+    assert_eq!(retry.wallet, 990);
+
+    // the ante was only taken once
+    let status = grpc
+        .status(authed(&token, pb::StatusRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(status.wallet, 990);
+
+    // the key can't be reused for another request
+    let err = grpc
+        .reveal(keyed(
+            &token,
+            "k1",
+            pb::RevealRequest {
+                round_id: first.round_id.clone(),
+            },
+        ))
+        .await
+        .unwrap_err();
+    expect_status(err, Code::InvalidArgument, "idempotency_key_reused");
+
+    // keys are per player
+    let other = grpc_signup(&mut grpc, "grpc_idem_other").await;
+    let theirs = grpc
+        .start(keyed(&other, "k1", ante))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_ne!(theirs.round_id, first.round_id);
+
+    // client errors are replayed too
+    let missing = pb::DiscardRequest {
+        round_id: "no-such-round".to_string(),
+        discard_indices: vec![0],
+    };
+    for replayed in [false, true] {
+        let err = grpc
+            .discard(keyed(&token, "k2", missing.clone()))
+            .await
+            .unwrap_err();
+        expect_status(err.clone(), Code::NotFound, "round_not_found");
+        assert_eq!(
+            err.metadata().get("idempotent-replayed").is_some(),
+            replayed
+        );
+    }
+
+    let err = grpc.start(keyed(&token, " ", ante)).await.unwrap_err();
+    expect_status(err, Code::InvalidArgument, "invalid_idempotency_key");
+}
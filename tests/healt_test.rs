@@ -7,7 +7,7 @@ async fn test_health_endpoint() {
     let client = make_client().await;
 
     let response = client
-        .get(&server.url("/"))
+        .get(server.url("/"))
         .send()
         .await
         .expect("Failed to send request");
@@ -0,0 +1,176 @@
+mod common;
+use common::*;
+use serde_json::json;
+
+async fn create_test_user(server: &TestServer, client: &reqwest::Client) -> TestUser {
+    let response = client
+        .post(server.url("/api/signup"))
+        .json(&json!({
+            "name": "idempotency_user",
+            "password": "secret"
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    TestUser {
+        id: json["id"].as_str().unwrap().to_string(),
+        token: json["access_token"].as_str().unwrap().to_string(),
+    }
+}
+
+async fn wallet(server: &TestServer, client: &reqwest::Client, user: &TestUser) -> i64 {
+    let json: serde_json::Value = client
+        .get(server.url(&format!("/api/status/{}", user.id)))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    json["wallet"].as_i64().unwrap()
+}
+
+#[tokio::test]
+async fn test_start_retry_replays_first_response() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+    let token = user.token.clone();
+
+    let send = || {
+        client
+            .post(server.url("/api/start"))
+            .header("Idempotency-Key", "start-1")
+            .bearer_auth(&token)
+            .json(&json!({
+                "ante": 10
+            }))
+            .send()
+    };
+
+    let first = send().await.expect("Failed to send request");
+    assert_eq!(first.status(), 200);
+    assert!(first.headers().get("idempotent-replayed").is_none());
+    let first: serde_json::Value = first.json().await.expect("Failed to parse JSON");
+
+    let second = send().await.expect("Failed to send request");
+    assert_eq!(second.status(), 200);
+    assert_eq!(second.headers()["idempotent-replayed"], "true");
+    let second: serde_json::Value = second.json().await.expect("Failed to parse JSON");
+
+    assert_eq!(first, second);
+    assert_eq!(wallet(&server, &client, &user).await, 990);
+}
+
+#[tokio::test]
+async fn test_discard_retry_charges_once() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+    let token = user.token.clone();
+
+    let started: serde_json::Value = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&token)
+        .json(&json!({
+            "ante": 10
+        }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+
+    for _ in 0..3 {
+        let response = client
+            .post(server.url("/api/discard"))
+            .header("Idempotency-Key", "discard-1")
+            .bearer_auth(&token)
+            .json(&json!({
+                "round_id": started["round_id"],
+                "discard_indices": [0, 1]
+            }))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), 200);
+    }
+
+    // 1000 - 10 ante - 10 for a single two-card discard
+    assert_eq!(wallet(&server, &client, &user).await, 980);
+}
+
+#[tokio::test]
+async fn test_key_reuse_with_different_body_is_rejected() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let token = create_test_user(&server, &client).await.token;
+
+    let response = client
+        .post(server.url("/api/start"))
+        .header("Idempotency-Key", "start-2")
+        .bearer_auth(&token)
+        .json(&json!({
+            "ante": 10
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .post(server.url("/api/start"))
+        .header("Idempotency-Key", "start-2")
+        .bearer_auth(&token)
+        .json(&json!({
+            "ante": 20
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 422);
+}
+
+#[tokio::test]
+async fn test_error_responses_are_replayed() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let token = create_test_user(&server, &client).await.token;
+
+    for _ in 0..2 {
+        let response = client
+            .post(server.url("/api/start"))
+            .header("Idempotency-Key", "start-3")
+            .bearer_auth(&token)
+            .json(&json!({
+                "ante": 5000
+            }))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), 400);
+    }
+}
+
+#[tokio::test]
+async fn test_oversized_body_is_refused() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = create_test_user(&server, &client).await;
+    let token = user.token.clone();
+
+    let response = client
+        .post(server.url("/api/start"))
+        .header("Idempotency-Key", "too-big")
+        .bearer_auth(&token)
+        .json(&json!({ "ante": 10, "padding": "x".repeat(100 * 1024) }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    expect_error(response, 413, "payload_too_large").await;
+    assert_eq!(wallet(&server, &client, &user).await, 1000);
+}
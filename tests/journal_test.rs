@@ -0,0 +1,345 @@
+use poker_server::game;
+use poker_server::models::{
+    CachedResponse, Currency, DiscardOp, IdempotencyClaim, NewRound, Paytable, RoundStatus,
+};
+use poker_server::money::Money;
+use poker_server::store::{JournalStore, SharedStore, StoreError};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// A journal path that's removed, with its snapshot, on drop.
+struct TempJournal(PathBuf);
+
+impl TempJournal {
+    fn new() -> Self {
+        TempJournal(std::env::temp_dir().join(format!("poker-{}.journal", uuid::Uuid::new_v4())))
+    }
+
+    fn snapshot(&self) -> PathBuf {
+        let mut path = self.0.clone().into_os_string();
+        path.push(".snapshot");
+        path.into()
+    }
+
+    fn open(&self) -> SharedStore {
+        JournalStore::open(&self.0).unwrap().into_shared()
+    }
+
+    /// The events in the journal, in order.
+    fn events(&self) -> Vec<serde_json::Value> {
+        self.lines()
+            .iter()
+            .map(|line| {
+                let (_, json) = line.split_once(' ').unwrap();
+                let mut entry: serde_json::Value = serde_json::from_str(json).unwrap();
+                entry["event"].take()
+            })
+            .collect()
+    }
+
+    fn lines(&self) -> Vec<String> {
+        std::fs::read_to_string(&self.0)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+}
+
+impl Drop for TempJournal {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+        let _ = std::fs::remove_file(self.snapshot());
+    }
+}
+
+/// Starts a round for `user1` and discards one card; returns the round id.
+async fn play(store: &SharedStore) -> String {
+    let mut deck = game::new_deck();
+    let started = store
+        .start_round(NewRound {
+            user_id: "user1".to_string(),
+            currency: Currency::Play,
+            ante: Money::new(10),
+            cards: game::deal_hand(&mut deck, 5),
+            max_active_rounds: 10,
+            paytable: Paytable::default(),
+            remote_wallet: false,
+        })
+        .await
+        .unwrap();
+    store
+        .apply_discard(DiscardOp {
+            user_id: "user1".to_string(),
+            round_id: started.round.id.clone(),
+            fee: Default::default(),
+            replacements: vec![(0, game::deal_hand(&mut deck, 1)[0])],
+        })
+        .await
+        .unwrap();
+    started.round.id
+}
+
+#[tokio::test]
+async fn test_state_is_replayed_from_journal() {
+    let journal = TempJournal::new();
+    let store = journal.open();
+    let user = store
+        .create_user_if_unique("journaled_player", "hash")
+        .await
+        .unwrap();
+    store
+        .adjust_wallet(&user.id, Currency::Play, Money::new(-100), "correction")
+        .await
+        .unwrap();
+    let round_id = play(&store).await;
+    let round = store.get_round(&round_id).await.unwrap();
+    let pools = store.get_pools(Currency::Play).await;
+    drop(store);
+
+    let events = journal.events();
+    let kinds: Vec<&str> = events
+        .iter()
+        .map(|event| event["type"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "created",
+            "user_registered",
+            "wallet_adjusted",
+            "round_started",
+            "cards_discarded"
+        ]
+    );
+    // events carry what the operation was given, not the rows it left
+    let discarded = &events[4];
+    assert_eq!(discarded["round_id"], round_id.as_str());
+    assert_eq!(discarded["replacements"][0][0], 0);
+    assert!(discarded.get("users").is_none());
+    assert_eq!(events[2]["delta"], -100);
+    assert_eq!(events[2]["reason"], "correction");
+
+    let store = journal.open();
+    let reopened = store.find_user_by_name("journaled_player").await.unwrap();
+    assert_eq!(reopened.balance(Currency::Play), Money::new(900));
+    let replayed = store.get_round(&round_id).await.unwrap();
+    assert_eq!(replayed.status, RoundStatus::Active);
+    assert_eq!(
+        format!("{:?}", replayed.cards),
+        format!("{:?}", round.cards)
+    );
+    assert_eq!(
+        store.get_pools(Currency::Play).await.reserved,
+        pools.reserved
+    );
+    assert!(store.reconcile().await.ok);
+
+    // appending carries on from the replayed state
+    play(&store).await;
+    drop(store);
+    let store = journal.open();
+    assert_eq!(store.get_active_rounds("user1").await.len(), 2);
+}
+
+#[tokio::test]
+async fn test_snapshot_bounds_replay() {
+    let journal = TempJournal::new();
+    let store = JournalStore::open_with(&journal.0, 3)
+        .unwrap()
+        .into_shared();
+    let round_id = play(&store).await;
+    let _ = play(&store).await;
+    drop(store);
+    assert!(journal.snapshot().exists());
+
+    // lines the snapshot covers are never read again
+    let mut data = std::fs::read(&journal.0).unwrap();
+    data[..8].copy_from_slice(b"xxxxxxxx");
+    std::fs::write(&journal.0, &data).unwrap();
+    let store = journal.open();
+    assert_eq!(store.get_round(&round_id).await.unwrap().draws_used, 1);
+    assert_eq!(store.get_active_rounds("user1").await.len(), 2);
+    drop(store);
+
+    // without the snapshot the damage is found
+    std::fs::remove_file(journal.snapshot()).unwrap();
+    assert!(JournalStore::open(&journal.0).is_err());
+}
+
+#[tokio::test]
+async fn test_torn_tail_is_dropped() {
+    let journal = TempJournal::new();
+    let store = journal.open();
+    let round_id = play(&store).await;
+    drop(store);
+    let len = std::fs::metadata(&journal.0).unwrap().len();
+
+    // a crash mid-append leaves half a line
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&journal.0)
+        .unwrap();
+    file.write_all(b"1c291ca3 {\"seq\":4,\"event\":{\"at\"")
+        .unwrap();
+    drop(file);
+
+    let store = journal.open();
+    assert_eq!(store.get_round(&round_id).await.unwrap().draws_used, 1);
+    assert_eq!(std::fs::metadata(&journal.0).unwrap().len(), len);
+    let round_id = play(&store).await;
+    drop(store);
+    let store = journal.open();
+    assert!(store.get_round(&round_id).await.is_some());
+}
+
+#[tokio::test]
+async fn test_damage_before_tail_is_an_error() {
+    let journal = TempJournal::new();
+    let store = journal.open();
+    play(&store).await;
+    drop(store);
+
+    let mut lines = journal.lines();
+    // flip a digit in the round-start line; its checksum no longer matches
+    lines[1] = lines[1].replacen("\"ante\":10", "\"ante\":90", 1);
+    std::fs::write(&journal.0, lines.join("\n") + "\n").unwrap();
+
+    let err = JournalStore::open(&journal.0).err().unwrap();
+    assert!(err.contains("damaged"), "{err}");
+}
+
+#[tokio::test]
+async fn test_failed_operations_are_not_journaled() {
+    let journal = TempJournal::new();
+    let store = journal.open();
+    let overdraw = store
+        .adjust_wallet("user1", Currency::Play, Money::new(-5000), "too much")
+        .await;
+    assert!(overdraw.is_err());
+    assert!(store.set_user_frozen("nobody", true).await.is_err());
+    drop(store);
+
+    let kinds: Vec<serde_json::Value> = journal
+        .events()
+        .into_iter()
+        .map(|e| e["type"].clone())
+        .collect();
+    assert_eq!(kinds, ["created"]);
+}
+
+#[tokio::test]
+async fn test_reused_refresh_token_revocation_is_journaled() {
+    let journal = TempJournal::new();
+    let store = journal.open();
+    let expires_at = chrono::Utc::now() + chrono::Duration::hours(1);
+    let session = store.create_session("user1", expires_at).await.unwrap();
+    store
+        .refresh_session(&session.id, 0, expires_at)
+        .await
+        .unwrap();
+    let reused = store.refresh_session(&session.id, 0, expires_at).await;
+    assert_eq!(reused.err(), Some(StoreError::RefreshTokenReused));
+    drop(store);
+
+    let kinds: Vec<serde_json::Value> = journal
+        .events()
+        .into_iter()
+        .map(|e| e["type"].clone())
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "created",
+            "session_opened",
+            "session_refreshed",
+            "session_revoked"
+        ]
+    );
+    let store = journal.open();
+    let session = store.get_session(&session.id).await.unwrap();
+    assert!(session.revoked);
+    assert_eq!(session.generation, 1);
+}
+
+#[tokio::test]
+async fn test_finished_idempotency_keys_survive_a_restart() {
+    let journal = TempJournal::new();
+    let ttl = chrono::Duration::hours(1);
+    let now = chrono::Utc::now();
+    let store = journal.open();
+    let before = journal.lines().len();
+
+    let claim = store.claim_idempotency("user1:a", "start", now, ttl).await;
+    assert!(matches!(claim, IdempotencyClaim::Claimed));
+    let response = CachedResponse {
+        status: 200,
+        content_type: Some("application/json".to_string()),
+        body: b"{}".to_vec(),
+    };
+    store.finish_idempotency("user1:a", Some(response)).await;
+    // a released key was never written
+    store.claim_idempotency("user1:b", "start", now, ttl).await;
+    store.finish_idempotency("user1:b", None).await;
+    assert_eq!(journal.lines().len(), before + 1);
+    assert_eq!(journal.events()[before]["type"], "idempotency_recorded");
+
+    // replays and mismatches aren't journaled
+    store.claim_idempotency("user1:a", "start", now, ttl).await;
+    store
+        .claim_idempotency("user1:a", "discard", now, ttl)
+        .await;
+    assert_eq!(journal.lines().len(), before + 1);
+    drop(store);
+
+    let store = journal.open();
+    match store.claim_idempotency("user1:a", "start", now, ttl).await {
+        IdempotencyClaim::Replay(cached) => assert_eq!(cached.body, b"{}"),
+        other => panic!("expected a replay, got {other:?}"),
+    }
+    assert!(matches!(
+        store.claim_idempotency("user1:b", "start", now, ttl).await,
+        IdempotencyClaim::Claimed
+    ));
+
+    // expiry is journaled once it drops the record
+    let later = now + ttl + chrono::Duration::seconds(1);
+    assert!(matches!(
+        store
+            .claim_idempotency("user1:a", "start", later, ttl)
+            .await,
+        IdempotencyClaim::Claimed
+    ));
+    assert_eq!(
+        journal.events().last().unwrap()["type"],
+        "idempotency_expired"
+    );
+}
+
+#[tokio::test]
+async fn test_snapshots_keep_idempotency_records() {
+    let journal = TempJournal::new();
+    let ttl = chrono::Duration::hours(1);
+    let now = chrono::Utc::now();
+    let store = JournalStore::open_with(&journal.0, 1)
+        .unwrap()
+        .into_shared();
+    store.claim_idempotency("user1:a", "start", now, ttl).await;
+    let response = CachedResponse {
+        status: 200,
+        content_type: None,
+        body: b"first".to_vec(),
+    };
+    store.finish_idempotency("user1:a", Some(response)).await;
+    drop(store);
+
+    // the lines the snapshot covers are damaged, so the record comes from it
+    let mut data = std::fs::read(&journal.0).unwrap();
+    data[..8].copy_from_slice(b"xxxxxxxx");
+    std::fs::write(&journal.0, &data).unwrap();
+    let store = journal.open();
+    match store.claim_idempotency("user1:a", "start", now, ttl).await {
+        IdempotencyClaim::Replay(cached) => assert_eq!(cached.body, b"first"),
+        other => panic!("expected a replay, got {other:?}"),
+    }
+}
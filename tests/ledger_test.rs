@@ -0,0 +1,126 @@
+mod common;
+use common::*;
+use poker_server::models::Currency;
+use poker_server::money::Money;
+use poker_server::store::InMem;
+use serde_json::json;
+
+async fn play_round(server: &TestServer, client: &reqwest::Client, name: &str, discard: bool) {
+    let json: serde_json::Value = client
+        .post(server.url("/api/signup"))
+        .json(&json!({
+            "name": name,
+            "password": "secret"
+        }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let token = json["access_token"].as_str().unwrap().to_string();
+
+    let json: serde_json::Value = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&token)
+        .json(&json!({
+            "ante": 10
+        }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let round_id = json["round_id"].as_str().unwrap().to_string();
+
+    if discard {
+        let response = client
+            .post(server.url("/api/discard"))
+            .bearer_auth(&token)
+            .json(&json!({
+                "round_id": &round_id,
+                "discard_indices": [0, 3, 4]
+            }))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), 200);
+    }
+
+    let response = client
+        .post(server.url("/api/reveal"))
+        .bearer_auth(&token)
+        .json(&json!({
+            "round_id": &round_id
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn test_reconcile_after_play() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+
+    for i in 0..10 {
+        play_round(&server, &client, &format!("ledger_user_{i}"), i % 2 == 0).await;
+    }
+
+    let response = client
+        .get(server.url("/api/admin/reconcile"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(json["ok"], true, "{json}");
+    let play = json["totals"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|t| t["currency"] == "PLAY")
+        .unwrap();
+    assert_eq!(play["ledger_total"], 0);
+    assert!(json["unbalanced_transactions"]
+        .as_array()
+        .unwrap()
+        .is_empty());
+    assert!(json["mismatches"].as_array().unwrap().is_empty());
+    // demo seed: 50_000 pool + user1's 1000, plus 1000 per signup
+    assert_eq!(play["external_in"], 50_000 + 1000 + 10 * 1000);
+    assert_eq!(play["money_in_game"], play["external_in"]);
+}
+
+#[tokio::test]
+async fn test_reconcile_after_adjustments() {
+    let store = InMem::new_demo().into_shared();
+
+    store
+        .update_user_wallet("user1", Currency::Play, Money::new(250))
+        .await
+        .unwrap();
+    store
+        .add_to_pools(Currency::Play, Money::new(100), Money::new(40))
+        .await
+        .unwrap();
+    store
+        .sub_from_win_pool(Currency::Play, Money::new(500))
+        .await
+        .unwrap();
+    assert!(store
+        .sub_from_win_pool(Currency::Play, Money::new(1_000_000))
+        .await
+        .is_err());
+
+    let report = store.reconcile().await;
+    assert!(report.ok, "{report:?}");
+    assert_eq!(
+        report.totals_for(Currency::Play).unwrap().external_in,
+        Money::new(250 + 50_000 + 100 + 40 - 500)
+    );
+}
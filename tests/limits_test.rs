@@ -0,0 +1,324 @@
+mod common;
+use chrono::{Duration, Utc};
+use common::*;
+use poker_server::game::GameError;
+use poker_server::limits;
+use poker_server::models::{
+    Currency, LimitChange, LimitPeriod, PlayBlock, PlayerLimits, SessionLimit, TransactionKind,
+    WalletTransaction,
+};
+use poker_server::money::Money;
+use poker_server::store::InMem;
+use serde_json::json;
+
+async fn post(
+    server: &TestServer,
+    client: &reqwest::Client,
+    token: &str,
+    path: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    client
+        .post(server.url(path))
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to send request")
+}
+
+#[tokio::test]
+async fn test_wager_limit_blocks_start_and_discard() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = signup(&server, &client, "wager_limit_user").await;
+    let token = user.token;
+
+    let response = post(
+        &server,
+        &client,
+        &token,
+        &format!("/api/users/{}/limits", user.id),
+        json!({ "limit": "wager", "period": "daily", "currency": "PLAY", "amount": 40 }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+
+    let start = || {
+        post(
+            &server,
+            &client,
+            &token,
+            "/api/start",
+            json!({ "ante": 20 }),
+        )
+    };
+    let response = start().await;
+    assert_eq!(response.status(), 200);
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    let round_id = json["round_id"].as_str().unwrap().to_string();
+
+    // 20 staked, a one-card discard costs 10 more
+    let discard = || {
+        post(
+            &server,
+            &client,
+            &token,
+            "/api/discard",
+            json!({ "round_id": &round_id, "discard_indices": [0] }),
+        )
+    };
+    assert_eq!(discard().await.status(), 200);
+    assert_eq!(discard().await.status(), 200);
+    let body = expect_error(discard().await, 403, "limit_reached").await;
+    assert!(
+        body.message.contains("wager limit of 40 PLAY per day"),
+        "{}",
+        body.message
+    );
+    let details = body.details.unwrap();
+    assert_eq!(details["kind"], "wager");
+    assert_eq!(details["amount"], 40);
+
+    let response = post(
+        &server,
+        &client,
+        &token,
+        "/api/fold",
+        json!({ "round_id": &round_id }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    expect_error(start().await, 403, "limit_reached").await;
+
+    // refused stakes never touch the wallet
+    let status: serde_json::Value = client
+        .get(server.url(&format!("/api/status/{}", user.id)))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(status["wallet"], 1000 - 40);
+}
+
+#[tokio::test]
+async fn test_raising_limit_waits_for_cooling_period() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = signup(&server, &client, "raise_limit_user").await;
+    let token = user.token;
+    let path = &format!("/api/users/{}/limits", user.id);
+    let loss = |amount: Option<i64>| {
+        json!({
+            "limit": "loss",
+            "period": "weekly",
+            "currency": "PLAY",
+            "amount": amount
+        })
+    };
+
+    let json: serde_json::Value = post(&server, &client, &token, path, loss(Some(100)))
+        .await
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(json["money"][0]["amount"], 100);
+
+    // raising (or removing) is queued
+    let json: serde_json::Value = post(&server, &client, &token, path, loss(None))
+        .await
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(json["money"][0]["amount"], 100);
+    assert_eq!(json["pending"].as_array().unwrap().len(), 1);
+
+    // lowering applies at once and cancels the queued raise
+    let json: serde_json::Value = post(&server, &client, &token, path, loss(Some(30)))
+        .await
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(json["money"][0]["amount"], 30);
+    assert_eq!(json["pending"].as_array().unwrap().len(), 0);
+
+    let response = post(
+        &server,
+        &client,
+        &token,
+        "/api/start",
+        json!({ "ante": 40 }),
+    )
+    .await;
+    expect_error(response, 403, "limit_reached").await;
+
+    let response = post(&server, &client, &token, path, loss(Some(0))).await;
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_queued_change_applies_once_due() {
+    let store = InMem::new_demo().into_shared();
+    let change = |amount| LimitChange::Wager {
+        period: LimitPeriod::Monthly,
+        currency: Currency::Eur,
+        amount: Some(Money::new(amount)),
+    };
+    store
+        .request_limit_change("user1", change(100), Duration::days(1))
+        .await
+        .unwrap();
+    let queued = store
+        .request_limit_change("user1", change(200), Duration::zero())
+        .await
+        .unwrap();
+    assert_eq!(queued.pending.len(), 1);
+
+    let limits = store.get_limits("user1").await.unwrap();
+    assert!(limits.pending.is_empty());
+    assert_eq!(limits.money[0].amount, Money::new(200));
+}
+
+#[tokio::test]
+async fn test_self_exclusion_blocks_play() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = signup(&server, &client, "excluded_user").await;
+    let token = user.token;
+
+    let response = post(
+        &server,
+        &client,
+        &token,
+        &format!("/api/users/{}/self-exclusion", user.id),
+        json!({ "days": 30 }),
+    )
+    .await;
+    assert_eq!(response.status(), 400);
+
+    let response = post(
+        &server,
+        &client,
+        &token,
+        &format!("/api/users/{}/self-exclusion", user.id),
+        json!({ "days": 180 }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+
+    let response = post(
+        &server,
+        &client,
+        &token,
+        "/api/start",
+        json!({ "ante": 10 }),
+    )
+    .await;
+    let body = expect_error(response, 403, "self_excluded").await;
+    assert!(body.details.unwrap()["until"].is_string());
+}
+
+#[tokio::test]
+async fn test_cool_off_cannot_be_shortened() {
+    let store = InMem::new_demo().into_shared();
+    let now = Utc::now();
+    let long = store
+        .block_play("user1", PlayBlock::CoolOff, now + Duration::days(10))
+        .await
+        .unwrap();
+    let short = store
+        .block_play("user1", PlayBlock::CoolOff, now + Duration::days(2))
+        .await
+        .unwrap();
+    assert_eq!(short.cool_off_until, long.cool_off_until);
+}
+
+#[test]
+fn test_session_limit_forces_break() {
+    let mut player = PlayerLimits {
+        session: Some(SessionLimit {
+            max_minutes: 60,
+            break_minutes: 15,
+        }),
+        ..Default::default()
+    };
+    let t0 = Utc::now();
+    let mut usage = limits::Usage::default();
+    let mut check = |p: &PlayerLimits, at| {
+        limits::check_stake(p, &mut usage, Currency::Play, Money::new(1), at)
+    };
+
+    // steady play, never idle for a full break
+    for minute in (0..60).step_by(10) {
+        let at = t0 + Duration::minutes(minute);
+        check(&player, at).unwrap();
+        limits::record_play(&mut player, at);
+    }
+    let err = check(&player, t0 + Duration::minutes(61)).unwrap_err();
+    assert!(matches!(err, GameError::SessionLimit { .. }), "{err}");
+    assert!(check(&player, t0 + Duration::minutes(64)).is_err());
+
+    // a full break since the last stake starts a fresh session
+    let at = t0 + Duration::minutes(65);
+    check(&player, at).unwrap();
+    limits::record_play(&mut player, at);
+    assert_eq!(player.session_started_at, Some(at));
+}
+
+#[test]
+fn test_usage_rolls_off_per_window() {
+    let t0 = Utc::now();
+    let tx = |days, kind, amount| WalletTransaction {
+        seq: 0,
+        user_id: "user1".to_string(),
+        currency: Currency::Play,
+        kind,
+        amount: Money::new(amount),
+        balance_after: Money::ZERO,
+        round_id: None,
+        note: None,
+        created_at: t0 + Duration::days(days),
+    };
+    let mut usage = limits::Usage::default();
+    usage.record(&tx(0, TransactionKind::Ante, -100));
+    usage.record(&tx(3, TransactionKind::Ante, -50));
+    usage.record(&tx(3, TransactionKind::Payout, 30));
+    usage.record(&tx(3, TransactionKind::Bonus, 1000));
+
+    let at = t0 + Duration::days(3);
+    assert_eq!(
+        usage.totals(Currency::Play, LimitPeriod::Daily, at),
+        (Money::new(50), Money::new(20))
+    );
+    assert_eq!(
+        usage.totals(Currency::Play, LimitPeriod::Weekly, at),
+        (Money::new(150), Money::new(120))
+    );
+    assert_eq!(
+        usage.totals(Currency::Eur, LimitPeriod::Weekly, at),
+        (Money::ZERO, Money::ZERO)
+    );
+    let at = t0 + Duration::days(8);
+    assert_eq!(
+        usage.totals(Currency::Play, LimitPeriod::Weekly, at),
+        (Money::new(50), Money::new(20))
+    );
+    assert_eq!(
+        usage.totals(Currency::Play, LimitPeriod::Monthly, at),
+        (Money::new(150), Money::new(120))
+    );
+    let at = t0 + Duration::days(40);
+    for period in [
+        LimitPeriod::Daily,
+        LimitPeriod::Weekly,
+        LimitPeriod::Monthly,
+    ] {
+        assert_eq!(
+            usage.totals(Currency::Play, period, at),
+            (Money::ZERO, Money::ZERO)
+        );
+    }
+}
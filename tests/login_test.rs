@@ -8,9 +8,10 @@ async fn test_login_success() {
     let client = make_client().await;
 
     let response = client
-        .post(&server.url("/api/login"))
+        .post(server.url("/api/signup"))
         .json(&json!({
-            "name": "test_user"
+            "name": "test_user",
+            "password": "secret"
         }))
         .send()
         .await
@@ -31,9 +32,10 @@ async fn test_login_duplicate_user() {
 
     // First login should succeed
     let response1 = client
-        .post(&server.url("/api/login"))
+        .post(server.url("/api/signup"))
         .json(&json!({
-            "name": "duplicate_user"
+            "name": "duplicate_user",
+            "password": "secret"
         }))
         .send()
         .await
@@ -42,12 +44,101 @@ async fn test_login_duplicate_user() {
 
     // Second login with same name should fail
     let response2 = client
-        .post(&server.url("/api/login"))
+        .post(server.url("/api/signup"))
         .json(&json!({
-            "name": "duplicate_user"
+            "name": "duplicate_user",
+            "password": "secret"
         }))
         .send()
         .await
         .expect("Failed to send request");
-    assert_eq!(response2.status(), 400);
+    expect_error(response2, 409, "name_taken").await;
+}
+
+async fn signin(
+    server: &TestServer,
+    client: &reqwest::Client,
+    name: &str,
+    password: &str,
+) -> reqwest::Response {
+    client
+        .post(server.url("/api/signin"))
+        .json(&json!({ "name": name, "password": password }))
+        .send()
+        .await
+        .expect("Failed to send request")
+}
+
+#[tokio::test]
+async fn test_wallet_persists_across_logins() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = signup(&server, &client, "returning_player").await;
+
+    let response = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&user.token)
+        .json(&json!({ "ante": 10 }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    for _ in 0..2 {
+        let json: serde_json::Value = signin(&server, &client, "returning_player", "secret")
+            .await
+            .json()
+            .await
+            .expect("Failed to parse JSON");
+        assert_eq!(json["id"], user.id.as_str());
+        assert_eq!(json["wallet"], 990);
+    }
+}
+
+#[tokio::test]
+async fn test_passwords_are_hashed() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = signup(&server, &client, "hashed_user").await;
+
+    let stored = server.store.get_user(&user.id).await.unwrap().password_hash;
+    assert!(stored.starts_with("$argon2id$"), "{stored}");
+    assert!(!stored.contains("secret"));
+
+    let response = signin(&server, &client, "hashed_user", "Secret").await;
+    assert_eq!(response.status(), 401);
+    let response = signin(&server, &client, "nobody", "secret").await;
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn test_legacy_plaintext_password_rehashed_on_login() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+
+    // the demo account predates hashing
+    assert_eq!(
+        server.store.get_user("user1").await.unwrap().password_hash,
+        "pass1"
+    );
+
+    assert_eq!(
+        signin(&server, &client, "user1", "wrong").await.status(),
+        401
+    );
+    assert_eq!(
+        signin(&server, &client, "user1", "pass1").await.status(),
+        200
+    );
+    let stored = server.store.get_user("user1").await.unwrap().password_hash;
+    assert!(stored.starts_with("$argon2id$"), "{stored}");
+
+    assert_eq!(
+        signin(&server, &client, "user1", "pass1").await.status(),
+        200
+    );
+    assert_eq!(
+        server.store.get_user("user1").await.unwrap().password_hash,
+        stored
+    );
 }
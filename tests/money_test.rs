@@ -0,0 +1,119 @@
+mod common;
+use common::*;
+use poker_server::game;
+use poker_server::models::{Currency, NewRound, Paytable};
+use poker_server::money::{Money, MoneyError, Rounding};
+use poker_server::store::{InMem, StoreError};
+use serde_json::json;
+
+#[test]
+fn test_mul_ratio_rounding() {
+    let half = |m: i64, r| Money::new(m).mul_ratio(1, 2, r).unwrap().minor();
+
+    assert_eq!(half(5, Rounding::Down), 2);
+    assert_eq!(half(5, Rounding::Up), 3);
+    assert_eq!(half(5, Rounding::HalfUp), 3);
+    assert_eq!(half(5, Rounding::HalfEven), 2);
+    assert_eq!(half(7, Rounding::HalfEven), 4);
+    assert_eq!(half(-5, Rounding::HalfUp), -3);
+    assert_eq!(half(-5, Rounding::Down), -2);
+
+    // exact results are never nudged
+    assert_eq!(half(6, Rounding::Up), 3);
+    assert_eq!(
+        Money::new(10).percent(25, Rounding::Down).unwrap(),
+        Money::new(2)
+    );
+    assert_eq!(
+        Money::new(10).percent(25, Rounding::HalfUp).unwrap(),
+        Money::new(3)
+    );
+}
+
+#[test]
+fn test_checked_arithmetic() {
+    let max = Money::new(i64::MAX);
+    assert!(max.try_add(Money::new(1)).is_err());
+    assert!(Money::new(i64::MIN).try_sub(Money::new(1)).is_err());
+    assert!(max.try_mul(2).is_err());
+    // the intermediate product may exceed i64 as long as the result fits
+    assert_eq!(max.mul_ratio(2, 2, Rounding::Down).unwrap(), max);
+    assert!(max.mul_ratio(3, 2, Rounding::Down).is_err());
+    assert!(Money::new(1).mul_ratio(1, 0, Rounding::Down).is_err());
+}
+
+#[test]
+fn test_money_serializes_as_minor_units() {
+    assert_eq!(serde_json::to_value(Money::new(250)).unwrap(), json!(250));
+    let m: Money = serde_json::from_value(json!(-7)).unwrap();
+    assert_eq!(m, Money::new(-7));
+}
+
+#[tokio::test]
+async fn test_start_rejects_overflowing_exposure() {
+    let store = InMem::new_demo().into_shared();
+    store
+        .update_user_wallet("user1", Currency::Play, Money::new(i64::MAX))
+        .await
+        .unwrap();
+
+    let mut deck = game::new_deck();
+    let err = store
+        .start_round(NewRound {
+            user_id: "user1".to_string(),
+            currency: Currency::Play,
+            ante: Money::new(i64::MAX / 2),
+            cards: game::deal_hand(&mut deck, 5),
+            max_active_rounds: 1,
+            paytable: Paytable::default(),
+            remote_wallet: false,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err, StoreError::from(MoneyError::Overflow));
+}
+
+#[tokio::test]
+async fn test_discard_fee_rounds_half_up() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+
+    let json: serde_json::Value = client
+        .post(server.url("/api/signup"))
+        .json(&json!({ "name": "money_user", "password": "secret" }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let token = json["access_token"].as_str().unwrap().to_string();
+
+    let json: serde_json::Value = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&token)
+        .json(&json!({ "ante": 5 }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let round_id = json["round_id"].as_str().unwrap().to_string();
+
+    // half of an ante of 5 is 2.5, charged as 3
+    let json: serde_json::Value = client
+        .post(server.url("/api/discard"))
+        .bearer_auth(&token)
+        .json(&json!({
+            "round_id": &round_id,
+            "discard_indices": [2]
+        }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(json["wallet"], 1000 - 5 - 3);
+}
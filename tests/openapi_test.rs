@@ -0,0 +1,114 @@
+mod common;
+use common::*;
+use poker_server::server::{openapi, router};
+use poker_server::store::InMem;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Routes served for the documentation itself rather than the API.
+const DOC_ROUTES: [&str; 4] = ["/openapi.json", "/docs", "/docs/", "/docs/{*rest}"];
+
+/// Methods per path in the OpenAPI document.
+fn spec_operations() -> BTreeMap<String, BTreeSet<String>> {
+    let spec = serde_json::to_value(openapi()).unwrap();
+    spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(path, item)| {
+            let methods = item
+                .as_object()
+                .unwrap()
+                .keys()
+                .filter(|k| ["get", "post", "put", "patch", "delete"].contains(&k.as_str()))
+                .map(|m| m.to_uppercase())
+                .collect();
+            (path.clone(), methods)
+        })
+        .collect()
+}
+
+/// A concrete URL path for a route template.
+fn fill(path: &str) -> String {
+    path.replace("{currency}", "PLAY")
+        .replace("{user_id}", "nobody")
+        .replace("{round_id}", "nothing")
+        .replace("{id}", "nothing")
+}
+
+#[tokio::test]
+async fn test_spec_covers_every_registered_route() {
+    // axum has no public way to list routes, but its Debug output names
+    // every path the router matches
+    let app = router(InMem::new_demo().into_shared());
+    let debug = format!("{app:?}");
+    let routes = debug.split("fallback_router").next().unwrap();
+    let registered: BTreeSet<String> = routes
+        .split('"')
+        .skip(1)
+        .step_by(2)
+        .filter(|s| s.starts_with('/') && !DOC_ROUTES.contains(s))
+        .map(String::from)
+        .collect();
+
+    let operations = spec_operations();
+    let documented: BTreeSet<String> = operations.keys().cloned().collect();
+    assert_eq!(registered, documented);
+
+    // a method a route doesn't take gets 405 and lists the ones it does
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    for (path, methods) in &operations {
+        let response = client
+            .delete(server.url(&fill(path)))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), 405, "{path}");
+        let allowed: BTreeSet<String> = response.headers()["allow"]
+            .to_str()
+            .unwrap()
+            .split(',')
+            .map(|m| m.trim().to_string())
+            .filter(|m| m != "HEAD")
+            .collect();
+        assert_eq!(&allowed, methods, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn test_spec_and_swagger_ui_are_served() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+
+    let response = client
+        .get(server.url("/openapi.json"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    let spec: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+    let start = &spec["paths"]["/api/start"]["post"];
+    assert_eq!(
+        start["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/StartRequest"
+    );
+    assert_eq!(start["security"][0]["bearer"], serde_json::json!([]));
+    assert!(spec["components"]["schemas"]["ErrorBody"].is_object());
+    assert!(spec["components"]["securitySchemes"]["bearer"].is_object());
+    // operator routes say which permission they need
+    let void = &spec["paths"]["/api/admin/rounds/{round_id}/void"]["post"];
+    assert!(void["description"]
+        .as_str()
+        .unwrap()
+        .contains("`adjust_wallets`"));
+    assert!(void["responses"]["403"].is_object());
+
+    let response = client
+        .get(server.url("/docs/"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert!(response.text().await.unwrap().contains("swagger-ui"));
+}
@@ -0,0 +1,198 @@
+mod common;
+use common::*;
+use poker_server::config::{PoolPolicy, PoolSource, PoolSplit};
+use poker_server::game;
+use poker_server::models::{Currency, NewRound, Paytable, PoolShare, RoundStatus, Settlement};
+use poker_server::money::Money;
+use poker_server::store::InMem;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+async fn start_round(
+    server: &TestServer,
+    client: &reqwest::Client,
+    name: &str,
+) -> (TestUser, String) {
+    let json: serde_json::Value = client
+        .post(server.url("/api/signup"))
+        .json(&json!({ "name": name, "password": "secret" }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let user = TestUser {
+        id: json["id"].as_str().unwrap().to_string(),
+        token: json["access_token"].as_str().unwrap().to_string(),
+    };
+
+    let json: serde_json::Value = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&user.token)
+        .json(&json!({ "ante": 40 }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    (user, json["round_id"].as_str().unwrap().to_string())
+}
+
+async fn status(
+    server: &TestServer,
+    client: &reqwest::Client,
+    user: &TestUser,
+) -> serde_json::Value {
+    client
+        .get(server.url(&format!("/api/status/{}", user.id)))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON")
+}
+
+#[test]
+fn test_policy_share_rounds_house_down() {
+    let policy = PoolPolicy {
+        fold: PoolSplit { house_percent: 33 },
+        win_pool_ceilings: BTreeMap::from([(Currency::Eur, Money::new(1_000))]),
+        ..PoolPolicy::default()
+    };
+
+    let share = policy
+        .share(PoolSource::Fold, Currency::Eur, Money::new(10))
+        .unwrap();
+    assert_eq!(share.house, Money::new(3));
+    assert_eq!(share.win_pool, Money::new(7));
+    assert_eq!(share.win_pool_ceiling, Some(Money::new(1_000)));
+
+    let share = policy
+        .share(PoolSource::LosingAnte, Currency::Play, Money::new(10))
+        .unwrap();
+    assert_eq!(share.house, Money::new(2));
+    assert_eq!(share.win_pool_ceiling, None);
+}
+
+#[tokio::test]
+async fn test_discard_fee_goes_to_pools() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let (user, round_id) = start_round(&server, &client, "discard_pool_user").await;
+    let before = status(&server, &client, &user).await;
+
+    let response = client
+        .post(server.url("/api/discard"))
+        .bearer_auth(&user.token)
+        .json(&json!({
+            "round_id": &round_id,
+            "discard_indices": [0, 1]
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    // fee of 40: 25% to the house, the rest to the win pool
+    let after = status(&server, &client, &user).await;
+    assert_eq!(after["wallet"], 1000 - 40 - 40);
+    assert_eq!(
+        after["house_profit"].as_i64().unwrap() - before["house_profit"].as_i64().unwrap(),
+        10
+    );
+    assert_eq!(
+        after["win_pool"].as_i64().unwrap() - before["win_pool"].as_i64().unwrap(),
+        30
+    );
+}
+
+#[tokio::test]
+async fn test_fold_forfeits_ante_to_pools() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let (user, round_id) = start_round(&server, &client, "fold_user").await;
+    let before = status(&server, &client, &user).await;
+
+    let response = client
+        .post(server.url("/api/fold"))
+        .bearer_auth(&user.token)
+        .json(&json!({ "round_id": &round_id }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(json["currency"], "PLAY");
+    assert_eq!(json["wallet"], 960);
+    assert_eq!(
+        json["house_profit"].as_i64().unwrap() - before["house_profit"].as_i64().unwrap(),
+        10
+    );
+    assert_eq!(
+        json["win_pool"].as_i64().unwrap() - before["win_pool"].as_i64().unwrap(),
+        30
+    );
+
+    // the round is over
+    let response = client
+        .post(server.url("/api/reveal"))
+        .bearer_auth(&user.token)
+        .json(&json!({ "round_id": &round_id }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    expect_error(response, 409, "round_not_active").await;
+    let active: serde_json::Value = client
+        .get(server.url(&format!("/api/users/{}/active-rounds", user.id)))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(active["rounds"], json!([]));
+}
+
+#[tokio::test]
+async fn test_win_pool_ceiling_overflows_to_house() {
+    let store = InMem::new_demo().into_shared();
+    let mut deck = game::new_deck();
+    let round = store
+        .start_round(NewRound {
+            user_id: "user1".to_string(),
+            currency: Currency::Play,
+            ante: Money::new(10),
+            cards: game::deal_hand(&mut deck, 5),
+            max_active_rounds: 1,
+            paytable: Paytable::default(),
+            remote_wallet: false,
+        })
+        .await
+        .unwrap()
+        .round;
+
+    let settled = store
+        .settle_round(Settlement {
+            user_id: "user1".to_string(),
+            round_id: round.id.clone(),
+            expected_draws: 0,
+            payout: Money::ZERO,
+            share: PoolShare {
+                win_pool: Money::new(8),
+                house: Money::new(2),
+                win_pool_ceiling: Some(Money::new(50_005)),
+            },
+            folded: true,
+        })
+        .await
+        .unwrap();
+    assert_eq!(settled.round.status, RoundStatus::Folded);
+    assert_eq!(settled.pools.win_pool, Money::new(50_005));
+    assert_eq!(settled.pools.house_profit, Money::new(5));
+    assert!(store.reconcile().await.ok);
+}
@@ -0,0 +1,229 @@
+mod common;
+use common::*;
+use serde_json::json;
+
+async fn grant(server: &TestServer, client: &reqwest::Client, user_id: &str, role: &str) {
+    let response = client
+        .post(server.url(&format!("/api/admin/users/{}/role", user_id)))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&json!({ "role": role }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+}
+
+async fn get(server: &TestServer, client: &reqwest::Client, token: &str, path: &str) -> u16 {
+    client
+        .get(server.url(path))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .status()
+        .as_u16()
+}
+
+async fn post(
+    server: &TestServer,
+    client: &reqwest::Client,
+    token: &str,
+    path: &str,
+    body: serde_json::Value,
+) -> u16 {
+    client
+        .post(server.url(path))
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn test_players_cannot_use_operator_api() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "plain_player").await;
+
+    assert_eq!(
+        get(&server, &client, &player.token, "/api/admin/users").await,
+        403
+    );
+    let status = post(
+        &server,
+        &client,
+        &player.token,
+        &format!("/api/admin/users/{}/role", player.id),
+        json!({ "role": "admin" }),
+    )
+    .await;
+    assert_eq!(status, 403);
+}
+
+#[tokio::test]
+async fn test_support_sees_users_but_moves_no_money() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let support = signup(&server, &client, "support_agent").await;
+    let player = signup(&server, &client, "supported_player").await;
+    grant(&server, &client, &support.id, "support").await;
+
+    let path = format!("/api/admin/users/{}", player.id);
+    assert_eq!(get(&server, &client, &support.token, &path).await, 200);
+    let status = post(
+        &server,
+        &client,
+        &support.token,
+        &format!("/api/admin/users/{}/freeze", player.id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(status, 200);
+
+    let status = post(
+        &server,
+        &client,
+        &support.token,
+        &format!("/api/admin/users/{}/adjust", player.id),
+        json!({ "currency": "PLAY", "amount": 10, "reason": "goodwill" }),
+    )
+    .await;
+    assert_eq!(status, 403);
+    assert_eq!(
+        get(&server, &client, &support.token, "/api/admin/pools").await,
+        403
+    );
+}
+
+#[tokio::test]
+async fn test_only_money_roles_void_rounds() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let support = signup(&server, &client, "void_support").await;
+    let finance = signup(&server, &client, "void_finance").await;
+    let player = signup(&server, &client, "voided_player").await;
+    grant(&server, &client, &support.id, "support").await;
+    grant(&server, &client, &finance.id, "finance").await;
+
+    let started: serde_json::Value = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&player.token)
+        .json(&json!({ "ante": 10 }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .unwrap();
+    let path = format!(
+        "/api/admin/rounds/{}/void",
+        started["round_id"].as_str().unwrap()
+    );
+    let body = json!({ "reason": "stuck" });
+
+    // a void refunds the stake, so it is money moving
+    let status = post(&server, &client, &support.token, &path, body.clone()).await;
+    assert_eq!(status, 403);
+    let status = post(&server, &client, &finance.token, &path, body).await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn test_finance_moves_money_but_not_paytables() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let finance = signup(&server, &client, "finance_officer").await;
+    let player = signup(&server, &client, "credited_player").await;
+    grant(&server, &client, &finance.id, "finance").await;
+
+    let status = post(
+        &server,
+        &client,
+        &finance.token,
+        &format!("/api/admin/users/{}/adjust", player.id),
+        json!({ "currency": "PLAY", "amount": 10, "reason": "goodwill" }),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        get(&server, &client, &finance.token, "/api/admin/pools").await,
+        200
+    );
+
+    let response = client
+        .put(server.url("/api/admin/paytable"))
+        .bearer_auth(&finance.token)
+        .json(&json!({
+            "jacks_or_better": 1, "two_pair": 2, "three_of_a_kind": 3, "straight": 5,
+            "flush": 6, "full_house": 9, "four_of_a_kind": 25, "straight_flush": 100
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 403);
+}
+
+#[tokio::test]
+async fn test_paytable_change_applies_to_new_rounds() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "paytable_player").await;
+    let put = |table: serde_json::Value| {
+        client
+            .put(server.url("/api/admin/paytable"))
+            .bearer_auth(ADMIN_TOKEN)
+            .json(&table)
+            .send()
+    };
+
+    // a stronger hand may not pay less than a weaker one
+    let response = put(json!({
+        "jacks_or_better": 1, "two_pair": 2, "three_of_a_kind": 3, "straight": 5,
+        "flush": 4, "full_house": 9, "four_of_a_kind": 25, "straight_flush": 50
+    }))
+    .await
+    .expect("Failed to send request");
+    assert_eq!(response.status(), 400);
+
+    let response = put(json!({
+        "jacks_or_better": 1, "two_pair": 2, "three_of_a_kind": 3, "straight": 5,
+        "flush": 6, "full_house": 9, "four_of_a_kind": 25, "straight_flush": 100
+    }))
+    .await
+    .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    let table: serde_json::Value = client
+        .get(server.url("/api/paytable"))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(table["straight_flush"], 100);
+
+    // the new top multiplier sets the reservation
+    let status = post(
+        &server,
+        &client,
+        &player.token,
+        "/api/start",
+        json!({ "ante": 10 }),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let pools: serde_json::Value = client
+        .get(server.url("/api/admin/pools"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(pools["PLAY"]["reserved"], 1000);
+}
@@ -0,0 +1,179 @@
+mod common;
+use common::*;
+use poker_server::game;
+use poker_server::models::{Currency, NewRound, Paytable, PoolShare, Settlement};
+use poker_server::money::Money;
+use poker_server::store::{InMem, SharedStore, StoreError};
+use serde_json::json;
+
+const TASKS: usize = 32;
+
+/// Demo store whose EUR pool holds exactly 1000, with user1 funded in EUR.
+async fn eur_store() -> SharedStore {
+    let store = InMem::new_demo().into_shared();
+    store
+        .update_user_wallet("user1", Currency::Eur, Money::new(1_000))
+        .await
+        .unwrap();
+    store
+        .sub_from_win_pool(Currency::Eur, Money::new(49_000))
+        .await
+        .unwrap();
+    store
+}
+
+async fn open_round(store: &SharedStore) -> Result<String, StoreError> {
+    let mut deck = game::new_deck();
+    store
+        .start_round(NewRound {
+            user_id: "user1".to_string(),
+            currency: Currency::Eur,
+            ante: Money::new(10),
+            cards: game::deal_hand(&mut deck, 5),
+            max_active_rounds: TASKS,
+            paytable: Paytable::default(),
+            remote_wallet: false,
+        })
+        .await
+        .map(|r| r.round.id)
+}
+
+#[tokio::test]
+async fn test_concurrent_starts_never_overcommit_pool() {
+    let store = eur_store().await;
+
+    let mut handles = Vec::new();
+    for _ in 0..TASKS {
+        let store = store.clone();
+        handles.push(tokio::spawn(async move { open_round(&store).await }));
+    }
+
+    let mut started = 0;
+    for h in handles {
+        if h.await.unwrap().is_ok() {
+            started += 1;
+        }
+    }
+    // each round reserves 10 * 50 of the 1000 in the pool
+    assert_eq!(started, 2);
+    let pools = store.get_pools(Currency::Eur).await;
+    assert_eq!(pools.reserved, Money::new(1_000));
+    assert_eq!(pools.available(), Money::ZERO);
+}
+
+#[tokio::test]
+async fn test_settle_releases_reservation() {
+    let store = eur_store().await;
+    let first = open_round(&store).await.unwrap();
+    let second = open_round(&store).await.unwrap();
+    assert!(open_round(&store).await.is_err());
+
+    // the full reserved payout is honoured, not refunded
+    let settled = store
+        .settle_round(Settlement {
+            user_id: "user1".to_string(),
+            round_id: first,
+            expected_draws: 0,
+            payout: Money::new(500),
+            share: PoolShare::default(),
+            folded: false,
+        })
+        .await
+        .unwrap();
+    assert!(!settled.refunded);
+    assert_eq!(settled.pools.win_pool, Money::new(1_000 + 10 - 500));
+    assert_eq!(settled.pools.reserved, Money::new(500));
+
+    // a loss frees the reservation and feeds the pool
+    let settled = store
+        .settle_round(Settlement {
+            user_id: "user1".to_string(),
+            round_id: second,
+            expected_draws: 0,
+            payout: Money::ZERO,
+            share: PoolShare {
+                win_pool: Money::new(10),
+                ..Default::default()
+            },
+            folded: false,
+        })
+        .await
+        .unwrap();
+    assert_eq!(settled.pools.reserved, Money::ZERO);
+    assert_eq!(settled.pools.available(), Money::new(520));
+    assert!(open_round(&store).await.is_ok());
+    assert!(store.reconcile().await.ok);
+}
+
+#[tokio::test]
+async fn test_reserved_funds_cannot_be_withdrawn() {
+    let store = eur_store().await;
+    open_round(&store).await.unwrap();
+
+    let err = store
+        .sub_from_win_pool(Currency::Eur, Money::new(501))
+        .await
+        .unwrap_err();
+    assert_eq!(err, StoreError::WinPoolShort);
+    store
+        .sub_from_win_pool(Currency::Eur, Money::new(500))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_status_reports_reserved_and_available() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+
+    let json: serde_json::Value = client
+        .post(server.url("/api/signup"))
+        .json(&json!({ "name": "reservation_user", "password": "secret" }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let user_id = json["id"].as_str().unwrap().to_string();
+    let token = json["access_token"].as_str().unwrap().to_string();
+
+    let before: serde_json::Value = client
+        .get(server.url(&format!("/api/status/{}", user_id)))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+
+    let response = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&token)
+        .json(&json!({ "ante": 10 }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    let after: serde_json::Value = client
+        .get(server.url(&format!("/api/status/{}", user_id)))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(after["win_pool"], before["win_pool"]);
+    assert_eq!(
+        after["win_pool_reserved"].as_i64().unwrap()
+            - before["win_pool_reserved"].as_i64().unwrap(),
+        500
+    );
+    assert_eq!(
+        after["win_pool_available"].as_i64().unwrap(),
+        after["win_pool"].as_i64().unwrap() - after["win_pool_reserved"].as_i64().unwrap()
+    );
+}
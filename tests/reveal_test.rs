@@ -4,21 +4,22 @@ use serde_json::json;
 
 async fn create_and_discard(server: &TestServer, client: &reqwest::Client) -> (String, String) {
     let response = client
-        .post(&server.url("/api/login"))
+        .post(server.url("/api/signup"))
         .json(&json!({
-            "name": "reveal_test_user"
+            "name": "reveal_test_user",
+            "password": "secret"
         }))
         .send()
         .await
         .expect("Failed to send request");
 
     let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
-    let user_id = json["id"].as_str().unwrap().to_string();
+    let token = json["access_token"].as_str().unwrap().to_string();
 
     let response = client
-        .post(&server.url("/api/start"))
+        .post(server.url("/api/start"))
+        .bearer_auth(&token)
         .json(&json!({
-            "user_id": &user_id,
             "ante": 10
         }))
         .send()
@@ -28,19 +29,19 @@ async fn create_and_discard(server: &TestServer, client: &reqwest::Client) -> (S
     let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
     let round_id = json["round_id"].as_str().unwrap().to_string();
 
-    (user_id, round_id)
+    (token, round_id)
 }
 
 #[tokio::test]
 async fn test_reveal_success() {
     let server = TestServer::new().await;
     let client = make_client().await;
-    let (user_id, round_id) = create_and_discard(&server, &client).await;
+    let (token, round_id) = create_and_discard(&server, &client).await;
 
     let response = client
-        .post(&server.url("/api/reveal"))
+        .post(server.url("/api/reveal"))
+        .bearer_auth(&token)
         .json(&json!({
-            "user_id": user_id,
             "round_id": round_id
         }))
         .send()
@@ -63,13 +64,13 @@ async fn test_reveal_success() {
 async fn test_reveal_twice_fails() {
     let server = TestServer::new().await;
     let client = make_client().await;
-    let (user_id, round_id) = create_and_discard(&server, &client).await;
+    let (token, round_id) = create_and_discard(&server, &client).await;
 
     // First reveal should succeed
     let response1 = client
-        .post(&server.url("/api/reveal"))
+        .post(server.url("/api/reveal"))
+        .bearer_auth(&token)
         .json(&json!({
-            "user_id": user_id,
             "round_id": round_id
         }))
         .send()
@@ -79,13 +80,13 @@ async fn test_reveal_twice_fails() {
 
     // Second reveal should fail
     let response2 = client
-        .post(&server.url("/api/reveal"))
+        .post(server.url("/api/reveal"))
+        .bearer_auth(&token)
         .json(&json!({
-            "user_id": user_id,
             "round_id": round_id
         }))
         .send()
         .await
         .expect("Failed to send request");
-    assert_eq!(response2.status(), 400);
+    expect_error(response2, 409, "round_not_active").await;
 }
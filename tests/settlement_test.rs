@@ -0,0 +1,240 @@
+mod common;
+use common::*;
+use poker_server::game;
+use poker_server::models::{
+    Currency, DiscardOp, NewRound, Paytable, PoolShare, RoundStatus, Settlement,
+};
+use poker_server::money::Money;
+use poker_server::store::{InMem, SharedStore, StoreError};
+use serde_json::json;
+
+const TASKS: usize = 32;
+
+async fn open_round(store: &SharedStore, max_active_rounds: usize) -> Result<String, StoreError> {
+    let mut deck = game::new_deck();
+    store
+        .start_round(NewRound {
+            user_id: "user1".to_string(),
+            currency: Currency::Play,
+            ante: Money::new(10),
+            cards: game::deal_hand(&mut deck, 5),
+            max_active_rounds,
+            paytable: Paytable::default(),
+            remote_wallet: false,
+        })
+        .await
+        .map(|r| r.round.id)
+}
+
+#[tokio::test]
+async fn test_concurrent_settle_pays_once() {
+    let store = InMem::new_demo().into_shared();
+    let round_id = open_round(&store, 1).await.unwrap();
+
+    let mut handles = Vec::new();
+    for _ in 0..TASKS {
+        let store = store.clone();
+        let round_id = round_id.clone();
+        handles.push(tokio::spawn(async move {
+            store
+                .settle_round(Settlement {
+                    user_id: "user1".to_string(),
+                    round_id,
+                    expected_draws: 0,
+                    payout: Money::new(30),
+                    share: PoolShare::default(),
+                    folded: false,
+                })
+                .await
+        }));
+    }
+
+    let mut settled = 0;
+    for h in handles {
+        if h.await.unwrap().is_ok() {
+            settled += 1;
+        }
+    }
+    assert_eq!(settled, 1);
+
+    let user = store.get_user("user1").await.unwrap();
+    assert_eq!(user.balance(Currency::Play), Money::new(1000 - 10 + 30));
+    // the forfeited ante lands in the pool that pays the win
+    assert_eq!(
+        store.get_pools(Currency::Play).await.win_pool,
+        Money::new(50_000 + 10 - 30)
+    );
+    let round = store.get_round(&round_id).await.unwrap();
+    assert_eq!(round.status, RoundStatus::Revealed);
+}
+
+#[tokio::test]
+async fn test_concurrent_start_respects_active_cap() {
+    let store = InMem::new_demo().into_shared();
+
+    let mut handles = Vec::new();
+    for _ in 0..TASKS {
+        let store = store.clone();
+        handles.push(tokio::spawn(async move { open_round(&store, 1).await }));
+    }
+
+    let mut started = 0;
+    for h in handles {
+        if h.await.unwrap().is_ok() {
+            started += 1;
+        }
+    }
+    assert_eq!(started, 1);
+    assert_eq!(
+        store
+            .get_user("user1")
+            .await
+            .unwrap()
+            .balance(Currency::Play),
+        Money::new(990)
+    );
+    assert_eq!(store.get_active_rounds("user1").await.len(), 1);
+}
+
+#[tokio::test]
+async fn test_settle_rejects_stale_hand() {
+    let store = InMem::new_demo().into_shared();
+    let round_id = open_round(&store, 1).await.unwrap();
+
+    store
+        .apply_discard(DiscardOp {
+            user_id: "user1".to_string(),
+            round_id: round_id.clone(),
+            fee: PoolShare {
+                win_pool: Money::new(5),
+                ..Default::default()
+            },
+            replacements: vec![(0, game::new_deck()[0])],
+        })
+        .await
+        .unwrap();
+
+    // outcome was computed before the discard landed
+    let res = store
+        .settle_round(Settlement {
+            user_id: "user1".to_string(),
+            round_id: round_id.clone(),
+            expected_draws: 0,
+            payout: Money::new(0),
+            share: PoolShare {
+                win_pool: Money::new(8),
+                house: Money::new(2),
+                win_pool_ceiling: None,
+            },
+            folded: false,
+        })
+        .await;
+    assert!(res.is_err());
+    assert_eq!(
+        store.get_round(&round_id).await.unwrap().status,
+        RoundStatus::Active
+    );
+}
+
+#[tokio::test]
+async fn test_concurrent_discards_never_overdraw() {
+    let store = InMem::new_demo().into_shared();
+    let round_id = open_round(&store, 1).await.unwrap();
+
+    let mut handles = Vec::new();
+    for _ in 0..TASKS {
+        let store = store.clone();
+        let round_id = round_id.clone();
+        handles.push(tokio::spawn(async move {
+            store
+                .apply_discard(DiscardOp {
+                    user_id: "user1".to_string(),
+                    round_id,
+                    fee: PoolShare {
+                        win_pool: Money::new(100),
+                        ..Default::default()
+                    },
+                    replacements: vec![(0, game::new_deck()[0])],
+                })
+                .await
+        }));
+    }
+
+    let mut applied = 0;
+    for h in handles {
+        if h.await.unwrap().is_ok() {
+            applied += 1;
+        }
+    }
+    // 990 left after the ante covers nine 100-credit discards
+    assert_eq!(applied, 9);
+    assert_eq!(
+        store
+            .get_user("user1")
+            .await
+            .unwrap()
+            .balance(Currency::Play),
+        Money::new(90)
+    );
+    assert_eq!(store.get_round(&round_id).await.unwrap().draws_used, 9);
+}
+
+#[tokio::test]
+async fn test_concurrent_reveal_requests_settle_once() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+
+    let json: serde_json::Value = client
+        .post(server.url("/api/signup"))
+        .json(&json!({
+            "name": "settlement_user",
+            "password": "secret"
+        }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let token = json["access_token"].as_str().unwrap().to_string();
+
+    let json: serde_json::Value = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&token)
+        .json(&json!({
+            "ante": 10
+        }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let round_id = json["round_id"].as_str().unwrap().to_string();
+
+    let mut handles = Vec::new();
+    for _ in 0..16 {
+        let client = client.clone();
+        let url = server.url("/api/reveal");
+        let token = token.clone();
+        let body = json!({ "round_id": &round_id });
+        handles.push(tokio::spawn(async move {
+            client
+                .post(url)
+                .bearer_auth(token)
+                .json(&body)
+                .send()
+                .await
+                .expect("Failed to send request")
+                .status()
+        }));
+    }
+
+    let mut ok = 0;
+    for h in handles {
+        if h.await.unwrap().is_success() {
+            ok += 1;
+        }
+    }
+    assert_eq!(ok, 1);
+}
@@ -0,0 +1,138 @@
+mod common;
+use common::*;
+use poker_server::game;
+use poker_server::models::{Currency, NewRound, Paytable, RoundStatus};
+use poker_server::money::Money;
+use poker_server::store::InMem;
+use std::path::PathBuf;
+
+/// A snapshot path that's removed on drop.
+struct TempSnapshot(PathBuf);
+
+impl TempSnapshot {
+    fn new() -> Self {
+        TempSnapshot(std::env::temp_dir().join(format!("poker-{}.json", uuid::Uuid::new_v4())))
+    }
+}
+
+impl Drop for TempSnapshot {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[tokio::test]
+async fn test_snapshot_restores_state() {
+    let file = TempSnapshot::new();
+    let mem = InMem::new_demo();
+    let store = mem.clone().into_shared();
+    let user = store
+        .create_user_if_unique("snapshot_player", "hash")
+        .await
+        .unwrap();
+    let mut deck = game::new_deck();
+    let started = store
+        .start_round(NewRound {
+            user_id: user.id.clone(),
+            currency: Currency::Play,
+            ante: Money::new(10),
+            cards: game::deal_hand(&mut deck, 5),
+            max_active_rounds: 1,
+            paytable: Paytable::default(),
+            remote_wallet: false,
+        })
+        .await
+        .unwrap();
+    let pools = store.get_pools(Currency::Play).await;
+    mem.save_snapshot(&file.0).unwrap();
+
+    let store = InMem::load_snapshot(&file.0).unwrap().into_shared();
+    let restored = store.find_user_by_name("snapshot_player").await.unwrap();
+    assert_eq!(restored.balance(Currency::Play), Money::new(990));
+    let round = store.get_round(&started.round.id).await.unwrap();
+    assert_eq!(round.status, RoundStatus::Active);
+    assert_eq!(
+        format!("{:?}", round.cards),
+        format!("{:?}", started.round.cards)
+    );
+    assert_eq!(
+        store.get_pools(Currency::Play).await.reserved,
+        pools.reserved
+    );
+    assert!(store.reconcile().await.ok);
+}
+
+#[tokio::test]
+async fn test_missing_snapshot_starts_from_demo_and_corrupt_one_fails() {
+    let file = TempSnapshot::new();
+    let store = InMem::load_snapshot(&file.0).unwrap().into_shared();
+    assert!(store.get_user("user1").await.is_some());
+
+    std::fs::write(&file.0, b"{\"users\":").unwrap();
+    assert!(InMem::load_snapshot(&file.0).is_err());
+}
+
+#[tokio::test]
+async fn test_admin_can_save_and_download_snapshot() {
+    let file = TempSnapshot::new();
+    let server = TestServer::with_snapshots(InMem::new_demo(), &file.0).await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "snapshot_http_player").await;
+
+    let res = client
+        .post(server.url("/api/admin/snapshot"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 200);
+    let saved: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(
+        saved["bytes"].as_u64().unwrap(),
+        std::fs::metadata(&file.0).unwrap().len()
+    );
+    let store = InMem::load_snapshot(&file.0).unwrap().into_shared();
+    assert!(store.get_user(&player.id).await.is_some());
+
+    let res = client
+        .get(server.url("/api/admin/snapshot"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 200);
+    assert!(res
+        .headers()
+        .get("content-disposition")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("attachment"));
+    let state: serde_json::Value = res.json().await.unwrap();
+    assert!(state["users"][&player.id].is_object());
+    // no credentials leave the server
+    assert!(state["users"][&player.id].get("password_hash").is_none());
+    assert!(state.get("sessions").is_none());
+
+    // players can't take a copy of everyone's password hashes
+    let res = client
+        .get(server.url("/api/admin/snapshot"))
+        .bearer_auth(&player.token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 403);
+}
+
+#[tokio::test]
+async fn test_snapshot_routes_need_a_snapshot_file() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let res = client
+        .post(server.url("/api/admin/snapshot"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 404);
+}
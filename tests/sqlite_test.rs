@@ -0,0 +1,197 @@
+#![cfg(feature = "sqlite")]
+
+use poker_server::game;
+use poker_server::models::{
+    Currency, DiscardOp, NewRound, Paytable, RoundStatus, TransactionFilter,
+};
+use poker_server::money::Money;
+use poker_server::store::{SharedStore, SqliteStore};
+use std::path::PathBuf;
+
+/// A database path that's removed, with its WAL files, on drop.
+struct TempDb(PathBuf);
+
+impl TempDb {
+    fn new() -> Self {
+        TempDb(std::env::temp_dir().join(format!("poker-{}.db", uuid::Uuid::new_v4())))
+    }
+
+    fn open(&self) -> SharedStore {
+        SqliteStore::open(&self.0).unwrap().into_shared()
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.0.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_state_survives_reopen() {
+    let db = TempDb::new();
+    let store = db.open();
+    let user = store
+        .create_user_if_unique("persistent_player", "hash")
+        .await
+        .unwrap();
+    store
+        .adjust_wallet(&user.id, Currency::Play, Money::new(-100), "correction")
+        .await
+        .unwrap();
+    let mut deck = game::new_deck();
+    let started = store
+        .start_round(NewRound {
+            user_id: user.id.clone(),
+            currency: Currency::Play,
+            ante: Money::new(10),
+            cards: game::deal_hand(&mut deck, 5),
+            max_active_rounds: 1,
+            paytable: Paytable::default(),
+            remote_wallet: false,
+        })
+        .await
+        .unwrap();
+    let discarded = store
+        .apply_discard(DiscardOp {
+            user_id: user.id.clone(),
+            round_id: started.round.id.clone(),
+            fee: Default::default(),
+            replacements: vec![(0, game::deal_hand(&mut deck, 1)[0])],
+        })
+        .await
+        .unwrap();
+    let pools = store.get_pools(Currency::Play).await;
+    drop(store);
+
+    let store = db.open();
+    let reopened = store.find_user_by_name("persistent_player").await.unwrap();
+    assert_eq!(reopened.id, user.id);
+    assert_eq!(reopened.balance(Currency::Play), Money::new(890));
+    let round = store.get_round(&started.round.id).await.unwrap();
+    assert_eq!(round.status, RoundStatus::Active);
+    assert_eq!(
+        format!("{:?}", round.cards),
+        format!("{:?}", discarded.round.cards)
+    );
+    assert_eq!(round.draws_used, 1);
+    assert_eq!(
+        store.get_pools(Currency::Play).await.reserved,
+        pools.reserved
+    );
+
+    let history = store
+        .list_transactions(
+            &user.id,
+            TransactionFilter {
+                limit: 10,
+                ..TransactionFilter::default()
+            },
+        )
+        .await;
+    assert_eq!(history.len(), 3);
+    assert_eq!(history[1].note.as_deref(), Some("correction"));
+    assert!(store.reconcile().await.ok);
+
+    // names stay unique across restarts
+    assert!(store
+        .create_user_if_unique("persistent_player", "hash")
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_schema_is_versioned_and_indexed() {
+    let db = TempDb::new();
+    drop(db.open());
+    // opening again finds nothing left to migrate
+    drop(db.open());
+
+    let conn = rusqlite::Connection::open(&db.0).unwrap();
+    let version: i64 = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .unwrap();
+    assert_eq!(version, 1);
+
+    // reads come from memory, so there are only the indices the schema
+    // itself needs
+    let mut stmt = conn
+        .prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'index' AND sql IS NOT NULL ORDER BY name",
+        )
+        .unwrap();
+    let indices: Vec<String> = stmt
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(indices, ["idempotency_created", "users_name"]);
+
+    let duplicate = conn.execute(
+        "INSERT INTO users (id, name, data) VALUES ('someone-else', 'user1', '{}')",
+        [],
+    );
+    assert!(duplicate.is_err());
+}
+
+#[tokio::test]
+async fn test_failed_write_is_undone() {
+    let db = TempDb::new();
+    let store = db.open();
+    let user = store
+        .create_user_if_unique("unlucky_player", "hash")
+        .await
+        .unwrap();
+    let new_round = || NewRound {
+        user_id: user.id.clone(),
+        currency: Currency::Play,
+        ante: Money::new(10),
+        cards: game::deal_hand(&mut game::new_deck(), 5),
+        max_active_rounds: 1,
+        paytable: Paytable::default(),
+        remote_wallet: false,
+    };
+
+    let conn = rusqlite::Connection::open(&db.0).unwrap();
+    conn.execute_batch(
+        "CREATE TRIGGER full BEFORE INSERT ON rounds BEGIN SELECT RAISE(ABORT, 'disk full'); END;",
+    )
+    .unwrap();
+    let pools = store.get_pools(Currency::Play).await;
+    assert!(store.start_round(new_round()).await.is_err());
+
+    // nothing of the round is left in memory
+    let unchanged = store.get_user(&user.id).await.unwrap();
+    assert_eq!(unchanged.balance(Currency::Play), Money::new(1000));
+    assert!(store.get_active_rounds(&user.id).await.is_empty());
+    assert_eq!(
+        store.get_pools(Currency::Play).await.reserved,
+        pools.reserved
+    );
+    let history = store
+        .list_transactions(
+            &user.id,
+            TransactionFilter {
+                limit: 10,
+                ..TransactionFilter::default()
+            },
+        )
+        .await;
+    // just the signup bonus
+    assert_eq!(history.len(), 1);
+    assert!(store.reconcile().await.ok);
+
+    // and the next write goes through as if it never happened
+    conn.execute_batch("DROP TRIGGER full;").unwrap();
+    let started = store.start_round(new_round()).await.unwrap();
+    drop(store);
+
+    let store = db.open();
+    assert_eq!(store.get_active_rounds(&user.id).await.len(), 1);
+    assert!(store.get_round(&started.round.id).await.is_some());
+    assert!(store.reconcile().await.ok);
+}
@@ -2,30 +2,16 @@ mod common;
 use common::*;
 use serde_json::json;
 
-async fn create_test_user(server: &TestServer, client: &reqwest::Client) -> String {
-    let response = client
-        .post(&server.url("/api/login"))
-        .json(&json!({
-            "name": "game_test_user"
-        }))
-        .send()
-        .await
-        .expect("Failed to send request");
-
-    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
-    json["id"].as_str().unwrap().to_string()
-}
-
 #[tokio::test]
 async fn test_start_game_success() {
     let server = TestServer::new().await;
     let client = make_client().await;
-    let user_id = create_test_user(&server, &client).await;
+    let user = signup(&server, &client, "game_test_user").await;
 
     let response = client
-        .post(&server.url("/api/start"))
+        .post(server.url("/api/start"))
+        .bearer_auth(&user.token)
         .json(&json!({
-            "user_id": user_id,
             "ante": 10
         }))
         .send()
@@ -45,12 +31,12 @@ async fn test_start_game_success() {
 async fn test_start_game_insufficient_wallet() {
     let server = TestServer::new().await;
     let client = make_client().await;
-    let user_id = create_test_user(&server, &client).await;
+    let user = signup(&server, &client, "game_test_user").await;
 
     let response = client
-        .post(&server.url("/api/start"))
+        .post(server.url("/api/start"))
+        .bearer_auth(&user.token)
         .json(&json!({
-            "user_id": user_id,
             "ante": 2000
         }))
         .send()
@@ -61,19 +47,18 @@ async fn test_start_game_insufficient_wallet() {
 }
 
 #[tokio::test]
-async fn test_start_game_invalid_user() {
+async fn test_start_game_without_token() {
     let server = TestServer::new().await;
     let client = make_client().await;
 
     let response = client
-        .post(&server.url("/api/start"))
+        .post(server.url("/api/start"))
         .json(&json!({
-            "user_id": "invalid-user-id",
             "ante": 10
         }))
         .send()
         .await
         .expect("Failed to send request");
 
-    assert_eq!(response.status(), 400);
+    assert_eq!(response.status(), 401);
 }
@@ -0,0 +1,203 @@
+mod common;
+use common::*;
+use serde_json::json;
+
+async fn play_round(server: &TestServer, client: &reqwest::Client) -> TestUser {
+    let json: serde_json::Value = client
+        .post(server.url("/api/signup"))
+        .json(&json!({
+            "name": "transactions_user",
+            "password": "secret"
+        }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let user_id = json["id"].as_str().unwrap().to_string();
+    let token = json["access_token"].as_str().unwrap().to_string();
+
+    let json: serde_json::Value = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&token)
+        .json(&json!({
+            "ante": 10
+        }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let round_id = json["round_id"].as_str().unwrap().to_string();
+
+    client
+        .post(server.url("/api/discard"))
+        .bearer_auth(&token)
+        .json(&json!({
+            "round_id": &round_id,
+            "discard_indices": [0, 1]
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    client
+        .post(server.url("/api/reveal"))
+        .bearer_auth(&token)
+        .json(&json!({
+            "round_id": &round_id
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    TestUser { id: user_id, token }
+}
+
+async fn list(
+    server: &TestServer,
+    client: &reqwest::Client,
+    token: &str,
+    path: &str,
+) -> serde_json::Value {
+    let response = client
+        .get(server.url(path))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    response.json().await.expect("Failed to parse JSON")
+}
+
+#[tokio::test]
+async fn test_transactions_history() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = play_round(&server, &client).await;
+
+    let json = list(
+        &server,
+        &client,
+        &user.token,
+        &format!("/api/users/{}/transactions", user.id),
+    )
+    .await;
+    let items = json["items"].as_array().unwrap();
+    assert!(json["next_cursor"].is_null());
+
+    // newest first; a winning hand adds a payout line on top
+    let kinds: Vec<&str> = items.iter().map(|t| t["kind"].as_str().unwrap()).collect();
+    let expected_tail = ["discard_fee", "ante", "bonus"];
+    assert!(kinds.ends_with(&expected_tail), "{kinds:?}");
+
+    let oldest = &items[items.len() - 1];
+    assert_eq!(oldest["amount"], 1000);
+    assert_eq!(oldest["balance_after"], 1000);
+    let ante = &items[items.len() - 2];
+    assert_eq!(ante["amount"], -10);
+    assert_eq!(ante["balance_after"], 990);
+    assert!(ante["round_id"].is_string());
+    let fee = &items[items.len() - 3];
+    assert_eq!(fee["amount"], -10);
+    assert_eq!(fee["balance_after"], 980);
+}
+
+#[tokio::test]
+async fn test_transactions_filter_by_type() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = play_round(&server, &client).await;
+
+    let json = list(
+        &server,
+        &client,
+        &user.token,
+        &format!("/api/users/{}/transactions?type=ante", user.id),
+    )
+    .await;
+    let items = json["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["kind"], "ante");
+}
+
+#[tokio::test]
+async fn test_transactions_filter_by_date() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = play_round(&server, &client).await;
+
+    let json = list(
+        &server,
+        &client,
+        &user.token,
+        &format!(
+            "/api/users/{}/transactions?from=2100-01-01T00:00:00Z",
+            user.id
+        ),
+    )
+    .await;
+    assert!(json["items"].as_array().unwrap().is_empty());
+
+    let json = list(
+        &server,
+        &client,
+        &user.token,
+        &format!(
+            "/api/users/{}/transactions?from=2000-01-01T00:00:00Z&to=2100-01-01T00:00:00Z",
+            user.id
+        ),
+    )
+    .await;
+    assert!(json["items"].as_array().unwrap().len() >= 3);
+}
+
+#[tokio::test]
+async fn test_transactions_cursor_pagination() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = play_round(&server, &client).await;
+
+    let all = list(
+        &server,
+        &client,
+        &user.token,
+        &format!("/api/users/{}/transactions", user.id),
+    )
+    .await;
+    let all = all["items"].as_array().unwrap().clone();
+
+    let mut paged = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let path = match &cursor {
+            Some(c) => format!("/api/users/{}/transactions?limit=1&cursor={}", user.id, c),
+            None => format!("/api/users/{}/transactions?limit=1", user.id),
+        };
+        let page = list(&server, &client, &user.token, &path).await;
+        paged.extend(page["items"].as_array().unwrap().iter().cloned());
+        match page["next_cursor"].as_str() {
+            Some(c) => cursor = Some(c.to_string()),
+            None => break,
+        }
+    }
+
+    assert_eq!(paged, all);
+}
+
+#[tokio::test]
+async fn test_transactions_invalid_cursor() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let user = play_round(&server, &client).await;
+
+    let response = client
+        .get(server.url(&format!("/api/users/{}/transactions?cursor=nope", user.id)))
+        .bearer_auth(&user.token)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 400);
+}
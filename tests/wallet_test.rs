@@ -0,0 +1,415 @@
+mod common;
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use common::*;
+use poker_server::config::GameConfig;
+use poker_server::models::{Card, Currency, DiscardOp, PoolShare, Suit};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// A stand-in for an operator's seamless wallet. Players start on 1000 and
+/// every transaction id is applied at most once.
+#[derive(Default)]
+struct Operator {
+    balances: HashMap<String, i64>,
+    /// Signed amount each applied transaction moved.
+    applied: HashMap<String, (String, i64)>,
+    rolled_back: Vec<String>,
+    /// Debit requests to answer with a 500 before handling any.
+    failing_debits: u32,
+    /// Debits are applied, then answered only after this long.
+    debit_delay: Option<Duration>,
+    debit_requests: u32,
+    /// Credit requests to answer with a 500 before handling any.
+    failing_credits: u32,
+    /// Every credit is answered with a 500 while this is set.
+    credits_down: bool,
+}
+
+impl Operator {
+    fn balance(&mut self, user_id: &str) -> i64 {
+        *self.balances.entry(user_id.to_string()).or_insert(1000)
+    }
+
+    fn apply(&mut self, tx: &Value, sign: i64) -> Result<i64, (StatusCode, String)> {
+        let id = tx["transaction_id"].as_str().unwrap().to_string();
+        let user = tx["user_id"].as_str().unwrap().to_string();
+        if self.applied.contains_key(&id) {
+            return Ok(self.balance(&user));
+        }
+        let amount = sign * tx["amount"].as_i64().unwrap();
+        let balance = self.balance(&user) + amount;
+        if balance < 0 {
+            return Err((StatusCode::PAYMENT_REQUIRED, "insufficient funds".into()));
+        }
+        self.balances.insert(user.clone(), balance);
+        self.applied.insert(id, (user, amount));
+        Ok(balance)
+    }
+}
+
+type SharedOperator = Arc<Mutex<Operator>>;
+
+async fn balance(State(op): State<SharedOperator>, Json(req): Json<Value>) -> Json<Value> {
+    let balance = op.lock().unwrap().balance(req["user_id"].as_str().unwrap());
+    Json(json!({ "balance": balance }))
+}
+
+async fn debit(
+    State(op): State<SharedOperator>,
+    Json(tx): Json<Value>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let (result, delay) = {
+        let mut op = op.lock().unwrap();
+        op.debit_requests += 1;
+        if op.failing_debits > 0 {
+            op.failing_debits -= 1;
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "try again".into()));
+        }
+        (op.apply(&tx, -1), op.debit_delay)
+    };
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+    result.map(|balance| Json(json!({ "balance": balance })))
+}
+
+async fn credit(
+    State(op): State<SharedOperator>,
+    Json(tx): Json<Value>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let mut op = op.lock().unwrap();
+    if op.credits_down {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "down".into()));
+    }
+    if op.failing_credits > 0 {
+        op.failing_credits -= 1;
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "try again".into()));
+    }
+    let balance = op.apply(&tx, 1)?;
+    Ok(Json(json!({ "balance": balance })))
+}
+
+async fn rollback(State(op): State<SharedOperator>, Json(req): Json<Value>) -> Json<Value> {
+    let mut op = op.lock().unwrap();
+    let id = req["transaction_id"].as_str().unwrap().to_string();
+    let user = req["user_id"].as_str().unwrap();
+    if let Some((_, amount)) = op.applied.get(&id).cloned() {
+        if !op.rolled_back.contains(&id) {
+            let balance = op.balance(user) - amount;
+            op.balances.insert(user.to_string(), balance);
+            op.rolled_back.push(id);
+        }
+    }
+    Json(json!({ "balance": op.balance(user) }))
+}
+
+/// Starts a mock operator and a game server using it as its wallet.
+async fn setup(operator: Operator) -> (TestServer, SharedOperator) {
+    setup_with(operator, GameConfig::default()).await
+}
+
+/// As `setup`, with the rest of the server's settings from `config`.
+async fn setup_with(operator: Operator, config: GameConfig) -> (TestServer, SharedOperator) {
+    let operator = Arc::new(Mutex::new(operator));
+    let app = Router::new()
+        .route("/wallet/balance", post(balance))
+        .route("/wallet/debit", post(debit))
+        .route("/wallet/credit", post(credit))
+        .route("/wallet/rollback", post(rollback))
+        .with_state(operator.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let server = TestServer::with_config(GameConfig {
+        wallet_url: Some(format!("http://{}/wallet", addr)),
+        wallet_timeout_ms: 300,
+        wallet_retries: 1,
+        ..config
+    })
+    .await;
+    (server, operator)
+}
+
+async fn start(server: &TestServer, client: &reqwest::Client, token: &str) -> reqwest::Response {
+    client
+        .post(server.url("/api/start"))
+        .bearer_auth(token)
+        .json(&json!({ "ante": 10 }))
+        .send()
+        .await
+        .expect("Failed to send request")
+}
+
+async fn get_json(server: &TestServer, client: &reqwest::Client, token: &str, path: &str) -> Value {
+    client
+        .get(server.url(path))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON")
+}
+
+/// The round left nothing behind locally: no active round, no reservation.
+async fn assert_released(server: &TestServer, client: &reqwest::Client, player: &TestUser) {
+    let response = client
+        .get(server.url(&format!("/api/users/{}/active-rounds", player.id)))
+        .bearer_auth(&player.token)
+        .send()
+        .await
+        .expect("Failed to send request");
+    let active: Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(active["rounds"], json!([]));
+    let pools = get_json(server, client, ADMIN_TOKEN, "/api/admin/pools").await;
+    assert_eq!(pools["PLAY"]["reserved"], 0);
+}
+
+#[tokio::test]
+async fn test_remote_round_debits_and_credits_operator() {
+    let (server, operator) = setup(Operator::default()).await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "remote_player").await;
+
+    let response = start(&server, &client, &player.token).await;
+    assert_eq!(response.status(), 200);
+    let started: Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(started["wallet"], 990);
+
+    let revealed: Value = client
+        .post(server.url("/api/reveal"))
+        .bearer_auth(&player.token)
+        .json(&json!({ "round_id": started["round_id"] }))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let payout = revealed["payout"].as_i64().unwrap();
+    assert_eq!(revealed["wallet"].as_i64().unwrap(), 990 + payout);
+    assert_eq!(operator.lock().unwrap().balance(&player.id), 990 + payout);
+
+    // the local wallet never moved, and the books still balance
+    let user = server.store.get_user(&player.id).await.unwrap();
+    assert_eq!(user.balance(Currency::Play).minor(), 1000);
+    let path = format!("/api/status/{}", player.id);
+    let status = get_json(&server, &client, &player.token, &path).await;
+    assert_eq!(status["wallet"].as_i64().unwrap(), 990 + payout);
+    let report = get_json(&server, &client, ADMIN_TOKEN, "/api/admin/reconcile").await;
+    assert_eq!(report["ok"], true);
+}
+
+#[tokio::test]
+async fn test_declined_debit_voids_round() {
+    let (server, operator) = setup(Operator::default()).await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "broke_remote_player").await;
+    operator
+        .lock()
+        .unwrap()
+        .balances
+        .insert(player.id.clone(), 5);
+
+    let response = start(&server, &client, &player.token).await;
+    let body = expect_error(response, 402, "wallet_declined").await;
+    assert!(
+        body.message.contains("insufficient funds"),
+        "{}",
+        body.message
+    );
+    assert_released(&server, &client, &player).await;
+    assert_eq!(operator.lock().unwrap().balance(&player.id), 5);
+}
+
+#[tokio::test]
+async fn test_transient_failure_is_retried_once() {
+    let (server, operator) = setup(Operator {
+        failing_debits: 1,
+        ..Operator::default()
+    })
+    .await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "retried_player").await;
+
+    let response = start(&server, &client, &player.token).await;
+    assert_eq!(response.status(), 200);
+    let mut op = operator.lock().unwrap();
+    assert_eq!(op.debit_requests, 2);
+    assert_eq!(op.applied.len(), 1);
+    assert_eq!(op.balance(&player.id), 990);
+}
+
+#[tokio::test]
+async fn test_timed_out_debit_is_rolled_back() {
+    let (server, operator) = setup(Operator {
+        debit_delay: Some(Duration::from_millis(1000)),
+        ..Operator::default()
+    })
+    .await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "slow_wallet_player").await;
+
+    // the operator took the ante but never answered in time
+    let response = start(&server, &client, &player.token).await;
+    assert_eq!(response.status(), 502);
+    assert_released(&server, &client, &player).await;
+
+    let mut op = operator.lock().unwrap();
+    assert_eq!(op.applied.len(), 1);
+    assert_eq!(op.rolled_back.len(), 1);
+    assert_eq!(op.balance(&player.id), 1000);
+}
+
+#[tokio::test]
+async fn test_start_dropped_mid_flight_replays_on_retry() {
+    let (server, operator) = setup(Operator {
+        debit_delay: Some(Duration::from_millis(200)),
+        ..Operator::default()
+    })
+    .await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "impatient_player").await;
+    let send = |timeout| {
+        client
+            .post(server.url("/api/start"))
+            .header("Idempotency-Key", "start-dropped")
+            .bearer_auth(&player.token)
+            .timeout(timeout)
+            .json(&json!({ "ante": 10 }))
+            .send()
+    };
+
+    // the client gives up while the operator is still debiting
+    let dropped = send(Duration::from_millis(50)).await;
+    assert!(dropped.unwrap_err().is_timeout());
+    tokio::time::sleep(Duration::from_millis(400)).await;
+
+    let response = send(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["idempotent-replayed"], "true");
+    let started: Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(started["wallet"], 990);
+
+    let mut op = operator.lock().unwrap();
+    assert_eq!(op.debit_requests, 1);
+    assert_eq!(op.balance(&player.id), 990);
+}
+
+/// Starts a round for `player` and swaps its hand for a flush, which pays
+/// six times the ante of 10.
+async fn start_winning_round(
+    server: &TestServer,
+    client: &reqwest::Client,
+    player: &TestUser,
+) -> String {
+    let started: Value = start(server, client, &player.token)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let round_id = started["round_id"].as_str().unwrap().to_string();
+    let flush = [2, 4, 6, 8, 10].map(|rank| Card {
+        rank,
+        suit: Suit::Hearts,
+    });
+    server
+        .store
+        .apply_discard(DiscardOp {
+            user_id: player.id.clone(),
+            round_id: round_id.clone(),
+            fee: PoolShare::default(),
+            replacements: flush.into_iter().enumerate().collect(),
+        })
+        .await
+        .unwrap();
+    round_id
+}
+
+#[tokio::test]
+async fn test_failed_payout_is_replayed_not_rerun() {
+    // the first attempt and its retry both fail
+    let (server, _operator) = setup(Operator {
+        failing_credits: 2,
+        ..Operator::default()
+    })
+    .await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "unpaid_player").await;
+    let round_id = start_winning_round(&server, &client, &player).await;
+    let reveal = || {
+        client
+            .post(server.url("/api/reveal"))
+            .header("Idempotency-Key", "reveal-unpaid")
+            .bearer_auth(&player.token)
+            .json(&json!({ "round_id": round_id }))
+            .send()
+    };
+
+    let response = reveal().await.unwrap();
+    assert_eq!(response.status(), 502);
+    assert!(response.headers().get("idempotent-replayed").is_none());
+
+    // the round is settled, so the retry gets the same answer back
+    let response = reveal().await.unwrap();
+    assert_eq!(response.status(), 502);
+    assert_eq!(response.headers()["idempotent-replayed"], "true");
+}
+
+#[tokio::test]
+async fn test_failed_payout_is_sent_again() {
+    // credits fail until the wallet is back, then go through
+    let (server, operator) = setup_with(
+        Operator {
+            credits_down: true,
+            ..Operator::default()
+        },
+        GameConfig {
+            wallet_credit_retry_ms: 200,
+            ..GameConfig::default()
+        },
+    )
+    .await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "late_paid_player").await;
+    let round_id = start_winning_round(&server, &client, &player).await;
+
+    let response = client
+        .post(server.url("/api/reveal"))
+        .bearer_auth(&player.token)
+        .json(&json!({ "round_id": round_id }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 502);
+    let pending = server.store.pending_wallet_credits().await;
+    assert_eq!(pending.len(), 1);
+    let payout_id = format!("{round_id}:payout");
+    assert_eq!(pending[0].transaction_id, payout_id);
+    assert!(!operator.lock().unwrap().applied.contains_key(&payout_id));
+
+    operator.lock().unwrap().credits_down = false;
+    let mut credited = None;
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        credited = operator.lock().unwrap().applied.get(&payout_id).cloned();
+        if credited.is_some() && server.store.pending_wallet_credits().await.is_empty() {
+            break;
+        }
+    }
+    let (user, amount) = credited.expect("payout sent again");
+    assert_eq!(user, player.id);
+    assert_eq!(amount, pending[0].amount.minor());
+    assert!(server.store.pending_wallet_credits().await.is_empty());
+    assert_eq!(
+        operator.lock().unwrap().balance(&player.id),
+        1000 - 10 + amount
+    );
+}
@@ -0,0 +1,276 @@
+mod common;
+use common::*;
+use futures_util::{SinkExt, StreamExt};
+use poker_server::config::GameConfig;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(server: &TestServer, token: &str) -> Socket {
+    let mut request = format!("ws://{}/ws", server.addr)
+        .into_client_request()
+        .unwrap();
+    request
+        .headers_mut()
+        .insert("authorization", format!("Bearer {token}").parse().unwrap());
+    let (mut socket, _) = tokio_tungstenite::connect_async(request)
+        .await
+        .expect("Failed to connect");
+    assert_eq!(next(&mut socket).await["type"], "ready");
+    socket
+}
+
+/// The next JSON message, skipping heartbeat frames.
+async fn next(socket: &mut Socket) -> Value {
+    loop {
+        let msg = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("no message in time")
+            .expect("connection closed")
+            .expect("websocket error");
+        match msg {
+            Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+            Message::Ping(_) | Message::Pong(_) => continue,
+            other => panic!("unexpected frame {other:?}"),
+        }
+    }
+}
+
+/// Sends `request` and returns the reply carrying its `id`, skipping any
+/// pushes that come first.
+async fn call(socket: &mut Socket, request: Value) -> Value {
+    let id = request["id"].clone();
+    socket
+        .send(Message::Text(request.to_string().into()))
+        .await
+        .unwrap();
+    loop {
+        let msg = next(socket).await;
+        if msg["id"] == id && ["result", "error", "pong"].contains(&msg["type"].as_str().unwrap()) {
+            return msg;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_ws_plays_a_round() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "ws_player").await;
+    let mut socket = connect(&server, &player.token).await;
+
+    let reply = call(
+        &mut socket,
+        json!({ "id": "1", "type": "start", "ante": 10 }),
+    )
+    .await;
+    assert_eq!(reply["type"], "result", "{reply}");
+    let start = &reply["result"];
+    assert_eq!(start["cards"].as_array().unwrap().len(), 5);
+    assert_eq!(start["wallet"], 990);
+    let round_id = start["round_id"].clone();
+    // the balance change is pushed as well
+    loop {
+        let msg = next(&mut socket).await;
+        if msg["type"] == "wallet" {
+            assert_eq!(msg["wallet"], 990);
+            break;
+        }
+    }
+
+    let reply = call(
+        &mut socket,
+        json!({ "id": "2", "type": "discard", "round_id": round_id, "discard_indices": [0, 1] }),
+    )
+    .await;
+    assert_eq!(reply["result"]["wallet"], 980, "{reply}");
+
+    let reply = call(
+        &mut socket,
+        json!({ "id": "3", "type": "reveal", "round_id": round_id }),
+    )
+    .await;
+    let reveal = &reply["result"];
+    assert_eq!(
+        reveal["wallet"],
+        980 + reveal["payout"].as_i64().unwrap(),
+        "{reply}"
+    );
+
+    // the same round over HTTP is finished too
+    let response = client
+        .post(server.url("/api/reveal"))
+        .bearer_auth(&player.token)
+        .json(&json!({ "round_id": round_id }))
+        .send()
+        .await
+        .unwrap();
+    expect_error(response, 409, "round_not_active").await;
+
+    let reply = call(
+        &mut socket,
+        json!({ "id": "4", "type": "status", "currency": "EUR" }),
+    )
+    .await;
+    assert_eq!(reply["result"]["currency"], "EUR");
+    assert_eq!(reply["result"]["wallet"], 0);
+    // asking about a currency subscribes to its updates
+    loop {
+        let msg = next(&mut socket).await;
+        if msg["type"] == "pools" && msg["currency"] == "EUR" {
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_ws_errors_carry_the_request_id() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "ws_errors").await;
+    let mut socket = connect(&server, &player.token).await;
+
+    let reply = call(
+        &mut socket,
+        json!({ "id": "a", "type": "reveal", "round_id": "no-such-round" }),
+    )
+    .await;
+    assert_eq!(reply["type"], "error");
+    assert_eq!(reply["error"]["code"], "round_not_found");
+
+    let reply = call(
+        &mut socket,
+        json!({ "id": "b", "type": "start", "ante": 0 }),
+    )
+    .await;
+    assert_eq!(reply["error"]["code"], "invalid_ante");
+
+    let reply = call(&mut socket, json!({ "id": "c", "type": "ping" })).await;
+    assert_eq!(reply["type"], "pong");
+
+    socket.send(Message::Text("not json".into())).await.unwrap();
+    let reply = loop {
+        let msg = next(&mut socket).await;
+        if msg["type"] == "error" {
+            break msg;
+        }
+    };
+    assert_eq!(reply["id"], Value::Null);
+    assert_eq!(reply["error"]["code"], "invalid_request");
+}
+
+#[tokio::test]
+async fn test_ws_needs_a_valid_token() {
+    let server = TestServer::new().await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "ws_auth").await;
+
+    let err = tokio_tungstenite::connect_async(format!("ws://{}/ws", server.addr))
+        .await
+        .unwrap_err();
+    match err {
+        WsError::Http(response) => assert_eq!(response.status(), 401),
+        other => panic!("expected a 401, got {other:?}"),
+    }
+
+    // browsers can't set headers on the upgrade, so a query token works too
+    let (mut socket, _) =
+        tokio_tungstenite::connect_async(format!("ws://{}/ws?token={}", server.addr, player.token))
+            .await
+            .expect("Failed to connect");
+    let ready = next(&mut socket).await;
+    assert_eq!(ready["type"], "ready");
+    assert_eq!(ready["user_id"], player.id.as_str());
+
+    // logging out ends the connection at the next request
+    let response = client
+        .post(server.url("/api/logout"))
+        .bearer_auth(&player.token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+    socket
+        .send(Message::Text(
+            json!({ "id": "x", "type": "ping" }).to_string().into(),
+        ))
+        .await
+        .unwrap();
+    let reply = loop {
+        let msg = next(&mut socket).await;
+        if msg["type"] == "error" {
+            break msg;
+        }
+    };
+    assert_eq!(reply["error"]["code"], "session_revoked");
+}
+
+#[tokio::test]
+async fn test_ws_pushes_pool_changes_and_heartbeats() {
+    let server = TestServer::with_config(GameConfig {
+        ws_heartbeat_secs: 1,
+        ws_update_interval_ms: 50,
+        ..GameConfig::default()
+    })
+    .await;
+    let client = make_client().await;
+    let player = signup(&server, &client, "ws_pushes").await;
+    let mut socket = connect(&server, &player.token).await;
+
+    // the current numbers come first
+    let mut win_pool = None;
+    while win_pool.is_none() {
+        let msg = next(&mut socket).await;
+        if msg["type"] == "pools" && msg["currency"] == "PLAY" {
+            win_pool = msg["win_pool"].as_i64();
+        }
+    }
+
+    let response = client
+        .post(server.url("/api/admin/pools/PLAY/win-pool/top-up"))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&json!({ "amount": 500 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    loop {
+        let msg = next(&mut socket).await;
+        if msg["type"] == "pools" {
+            assert_eq!(msg["win_pool"].as_i64(), win_pool.map(|w| w + 500));
+            break;
+        }
+    }
+
+    // so is a balance change made over HTTP
+    let response = client
+        .post(server.url("/api/start"))
+        .bearer_auth(&player.token)
+        .json(&json!({ "ante": 10 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    loop {
+        let msg = next(&mut socket).await;
+        if msg["type"] == "wallet" && msg["currency"] == "PLAY" {
+            assert_eq!(msg["wallet"], 990);
+            break;
+        }
+    }
+
+    let ping = tokio::time::timeout(Duration::from_secs(3), async {
+        loop {
+            if let Some(Ok(Message::Ping(_))) = socket.next().await {
+                return;
+            }
+        }
+    })
+    .await;
+    assert!(ping.is_ok(), "no heartbeat");
+}